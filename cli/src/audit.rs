@@ -0,0 +1,66 @@
+use crate::{failure, load_config};
+use libkuiper::audit;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct AuditArgs {
+    /// The audit log to print, instead of the one set in `kuiper.toml`.
+    path: Option<PathBuf>,
+    /// Only verify the log, without printing its entries.
+    #[arg(long)]
+    verify: bool,
+}
+
+/// Prints the entries of the audit log, and checks that none of them have been tampered with.
+pub fn audit(dir: &Path, AuditArgs { path, verify }: AuditArgs) -> bool {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let Some(config) = load_config(dir) else {
+                return false;
+            };
+            match config.audit_log() {
+                Some(path) => path.to_path_buf(),
+                None => {
+                    eprintln!("no audit_log is set in kuiper.toml, pass the path of a log");
+                    return false;
+                }
+            }
+        }
+    };
+    let entries = match audit::entries(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("failed to read audit log {path:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    if !verify {
+        for entry in &entries {
+            println!(
+                "{:>6}  {}  {:<12}  {:<7} {}  {}",
+                entry.seq(),
+                entry.time(),
+                entry.user(),
+                entry.method(),
+                entry.url(),
+                entry
+                    .status()
+                    .map_or_else(|| "-".to_string(), |status| status.to_string())
+            );
+        }
+    }
+    match audit::verify(&entries) {
+        Ok(()) => {
+            println!("verified {} entries of {path:?}", entries.len());
+            true
+        }
+        Err(e) => {
+            eprintln!("{path:?}: {e}");
+            failure::record_error(&e);
+            false
+        }
+    }
+}
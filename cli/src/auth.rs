@@ -0,0 +1,90 @@
+use crate::{data_dir, failure};
+use libkuiper::{Session, SessionStore};
+use std::path::PathBuf;
+
+#[derive(clap::Subcommand)]
+pub enum AuthCommand {
+    /// Show where the session is stored and which values it holds.
+    Status {
+        /// The session file, as given to `kuiper run --session`.
+        #[arg(long)]
+        session: Option<PathBuf>,
+    },
+    /// Remove every value stored in the session.
+    Logout {
+        /// The session file, as given to `kuiper run --session`.
+        #[arg(long)]
+        session: Option<PathBuf>,
+    },
+}
+
+pub fn auth(command: AuthCommand) -> bool {
+    match command {
+        AuthCommand::Status { session } => {
+            let Some(session) = load_session(session, false) else {
+                return false;
+            };
+            match session.store() {
+                SessionStore::Keyring => {
+                    println!(
+                        "session for {:?} is stored in the OS keyring",
+                        session.path()
+                    )
+                }
+                SessionStore::File => println!("session is stored in {:?}", session.path()),
+            }
+            let mut names: Vec<_> = session.captures().keys().collect();
+            names.sort();
+            if names.is_empty() && session.headers().is_empty() && session.cookies().is_empty() {
+                println!("no values stored");
+            }
+            for name in names {
+                println!("  {name}");
+            }
+            for (host, headers) in session.headers() {
+                for name in headers.keys() {
+                    println!("  header {name} for {host}");
+                }
+            }
+            for cookie in session.cookies().iter() {
+                println!("  cookie {} for {}", cookie.name(), cookie.domain());
+            }
+            true
+        }
+        AuthCommand::Logout { session } => {
+            let Some(mut session) = load_session(session, false) else {
+                return false;
+            };
+            match session.clear() {
+                Ok(()) => {
+                    println!("cleared session for {:?}", session.path());
+                    true
+                }
+                Err(e) => {
+                    eprintln!("failed to clear session {:?}: '{e}'", session.path());
+                    failure::record_error(&e);
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Loads the session stored for `path`, `session.json` in the data directory by default, from
+/// the OS keyring unless `plaintext` is set.
+pub(crate) fn load_session(path: Option<PathBuf>, plaintext: bool) -> Option<Session> {
+    let path = path.unwrap_or_else(|| data_dir().join("session.json"));
+    let session = if plaintext {
+        Session::load(&path)
+    } else {
+        Session::load_from_keyring(&path)
+    };
+    match session {
+        Ok(session) => Some(session),
+        Err(e) => {
+            eprintln!("failed to load session {path:?}: '{e}'");
+            failure::record_error(&e);
+            None
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    auth::load_session, data_dir, dns::CachingResolver, failure, interrupt, load_config,
+    locate_requests, send_request, BodyLimits, ConnectionArgs, Selection,
+};
+use libkuiper::{bench::BenchStats, Client, Context, Failure, Request};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(clap::Args)]
+pub struct BenchArgs {
+    path: String,
+    #[command(flatten)]
+    selection: Selection,
+    /// Total number of requests to send.
+    #[arg(short = 'n', long, default_value_t = 100)]
+    requests: usize,
+    /// Number of requests to send concurrently.
+    #[arg(short, long, default_value_t = 1)]
+    concurrency: usize,
+    /// Compare the results against this baseline: `last` for the previous run, a name given to
+    /// `--save-baseline`, or a path to a baseline file.
+    #[arg(long)]
+    baseline: Option<String>,
+    /// Save the results as a baseline with this name.
+    #[arg(long)]
+    save_baseline: Option<String>,
+    /// How much worse than the baseline, in percent, a metric may get before the run fails.
+    #[arg(long, default_value_t = 10.0)]
+    threshold: f64,
+    /// How long resolved addresses are reused, in seconds. By default hosts are only resolved
+    /// once.
+    #[arg(long, conflicts_with = "no_dns_cache")]
+    dns_ttl: Option<u64>,
+    /// Resolve hosts for every new connection instead of caching their addresses.
+    #[arg(long)]
+    no_dns_cache: bool,
+    /// Report the time spent on DNS lookups.
+    #[arg(long)]
+    report_dns: bool,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+}
+
+pub fn bench(
+    dir: &Path,
+    BenchArgs {
+        path,
+        selection,
+        requests,
+        concurrency,
+        baseline,
+        save_baseline,
+        threshold,
+        dns_ttl,
+        no_dns_cache,
+        report_dns,
+        connection,
+    }: BenchArgs,
+) -> bool {
+    let Some(mut request_paths) = locate_requests(dir, &path, selection) else {
+        return false;
+    };
+    if request_paths.len() > 1 {
+        eprintln!("kuiper bench sends a single request, pick one with --first or --index N");
+        failure::record(Failure::Resolve);
+        return false;
+    }
+    let request_path = request_paths.remove(0);
+    let Some(session) = load_session(None, false) else {
+        return false;
+    };
+    let Some(config) = load_config(dir) else {
+        return false;
+    };
+    let context = Context::from(&session)
+        .with_hosts(config.hosts().to_vec())
+        .with_uri_normalization(config.uri_normalization());
+    let request = match Request::find_with_context(&request_path, &context) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("failed to parse request with name: {request_path:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    if let Some(message) = request.deprecated() {
+        log::warn!("'{}' is deprecated: {message}", request.name());
+    }
+
+    let resolver = (!no_dns_cache).then(|| {
+        let resolver = CachingResolver::new(dns_ttl.map(Duration::from_secs));
+        // resolve the target up front, so that the first requests don't include the lookup
+        if let Some(host) = reqwest::Url::parse(request.uri())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        {
+            if let Err(e) = resolver.lookup(&host) {
+                log::warn!("failed to resolve '{host}': '{e}'");
+            }
+        }
+        resolver
+    });
+    let shared = resolver
+        .clone()
+        .map(|resolver| Arc::new(resolver) as Arc<dyn reqwest::dns::Resolve>);
+    let Some(client) = connection.build(&config, None, shared) else {
+        return false;
+    };
+    let stats = send_concurrently(&client, &request, requests, concurrency.max(1));
+    println!("{stats}");
+    if report_dns {
+        match &resolver {
+            Some(resolver) => {
+                let lookups = resolver.lookups();
+                let total: Duration = lookups.iter().sum();
+                println!(
+                    "dns:        {} lookups, {:.2}ms total",
+                    lookups.len(),
+                    total.as_secs_f64() * 1000.0
+                );
+            }
+            None => println!("dns:        not measured without the DNS cache"),
+        }
+    }
+    if interrupt::interrupted() {
+        // a partial run would skew comparisons with later runs
+        log::warn!("not comparing or saving baselines of an interrupted run");
+        return false;
+    }
+
+    let baselines = baseline_dir(&request_path);
+    let mut success = true;
+    if let Some(baseline) = baseline {
+        let baseline_path = resolve_baseline(&baselines, &baseline);
+        match BenchStats::load(&baseline_path) {
+            Ok(baseline_stats) => {
+                let regressions = stats.compare(&baseline_stats, threshold);
+                if regressions.is_empty() {
+                    println!("no regressions compared to baseline '{baseline}'");
+                }
+                for regression in &regressions {
+                    println!("{regression}");
+                }
+                if !regressions.is_empty() {
+                    failure::record(Failure::Assertion);
+                }
+                success = regressions.is_empty();
+            }
+            Err(e) => {
+                eprintln!("failed to load baseline {baseline_path:?}: '{e}'");
+                failure::record_error(&e);
+                success = false;
+            }
+        }
+    }
+
+    let names = std::iter::once("last".to_string()).chain(save_baseline);
+    for name in names {
+        let baseline_path = resolve_baseline(&baselines, &name);
+        if let Err(e) = stats.save(&baseline_path) {
+            eprintln!("failed to save baseline {baseline_path:?}: '{e}'");
+        }
+    }
+
+    success
+}
+
+/// Sends `request` `total` times from `concurrency` threads sharing `client`.
+fn send_concurrently(
+    client: &Client,
+    request: &Request,
+    total: usize,
+    concurrency: usize,
+) -> BenchStats {
+    let next = AtomicUsize::new(0);
+    let start = Instant::now();
+    let results: Vec<(Vec<Duration>, usize)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..concurrency)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut durations = Vec::new();
+                    let mut errors = 0;
+                    while !interrupt::interrupted() && next.fetch_add(1, Ordering::Relaxed) < total
+                    {
+                        match send_request(client, request, BodyLimits::default()) {
+                            Ok(response) if response.status() < 400 => {
+                                durations.push(response.duration())
+                            }
+                            Ok(_) | Err(_) => errors += 1,
+                        }
+                    }
+                    (durations, errors)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("bench worker panicked"))
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    let errors = results.iter().map(|(_, errors)| errors).sum();
+    let durations: Vec<_> = results.into_iter().flat_map(|(d, _)| d).collect();
+    BenchStats::new(&durations, errors, elapsed)
+}
+
+/// Baselines are stored per request, in a directory named after the request path.
+fn baseline_dir(request_path: &Path) -> PathBuf {
+    let key: String = request_path
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    data_dir().join("baselines").join(key)
+}
+
+fn resolve_baseline(baselines: &Path, baseline: &str) -> PathBuf {
+    let path = Path::new(baseline);
+    if path.is_file() {
+        path.to_path_buf()
+    } else {
+        baselines.join(format!("{baseline}.json"))
+    }
+}
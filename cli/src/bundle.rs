@@ -0,0 +1,88 @@
+use crate::failure;
+use libkuiper::bundle::Bundle;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Subcommand)]
+pub enum BundleCommand {
+    /// Pack the collection in a directory into a single bundle file, leaving out hidden and
+    /// `.env` files.
+    Pack {
+        dir: PathBuf,
+        /// Where to write the bundle, `<dir>.bundle.json` by default.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Unpack a bundle into a directory, without overwriting existing files.
+    Unpack {
+        bundle: PathBuf,
+        /// Directory to unpack into, the current directory by default.
+        into: Option<PathBuf>,
+    },
+}
+
+pub fn bundle(command: BundleCommand) -> bool {
+    match command {
+        BundleCommand::Pack { dir, output } => pack(&dir, output),
+        BundleCommand::Unpack { bundle, into } => unpack(&bundle, into),
+    }
+}
+
+fn pack(dir: &Path, output: Option<PathBuf>) -> bool {
+    let bundle = match Bundle::pack(dir) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            eprintln!("failed to pack {dir:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let output = output.unwrap_or_else(|| {
+        let name = dir
+            .canonicalize()
+            .ok()
+            .and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "collection".to_string());
+        PathBuf::from(format!("{name}.bundle.json"))
+    });
+
+    match bundle.save(&output) {
+        Ok(()) => {
+            println!("packed {} files into {output:?}", bundle.files().count());
+            true
+        }
+        Err(e) => {
+            eprintln!("failed to write bundle {output:?}: '{e}'");
+            failure::record_error(&e);
+            false
+        }
+    }
+}
+
+fn unpack(path: &Path, into: Option<PathBuf>) -> bool {
+    let bundle = match Bundle::load(path) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            eprintln!("failed to load bundle {path:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let into = into.unwrap_or_else(|| PathBuf::from("."));
+
+    match bundle.unpack(&into) {
+        Ok(written) => {
+            for path in written {
+                println!("{}", path.display());
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("failed to unpack {path:?} into {into:?}: '{e}'");
+            failure::record_error(&e);
+            false
+        }
+    }
+}
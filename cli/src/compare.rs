@@ -0,0 +1,147 @@
+use crate::{
+    auth::load_session, failure, load_config, locate_requests, run::send_in_environment,
+    ConnectionArgs, Selection,
+};
+use libkuiper::{diff, normalize::Normalizer, Failure, Response};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct CompareArgs {
+    path: String,
+    #[command(flatten)]
+    selection: Selection,
+    /// The environment to compare with, found the same way as with `kuiper run --env`.
+    #[arg(long)]
+    against: String,
+    /// The environment to compare `--against`, instead of only the environment variables of the
+    /// process.
+    #[arg(long)]
+    env: Option<String>,
+    /// Leave the value at this JSONPath out of the comparison, like a timestamp or a generated id.
+    /// Can be given several times.
+    #[arg(long)]
+    ignore: Vec<String>,
+    /// File where captured values are stored between runs, with a session of its own per
+    /// environment.
+    #[arg(long)]
+    session: Option<PathBuf>,
+    /// Store the sessions in plaintext files even if the OS keyring is available.
+    #[arg(long)]
+    plaintext_session: bool,
+    /// Decrypt this SOPS or age encrypted file, and make its values available through
+    /// `{{secret:NAME}}`.
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Sends a request in two environments, and prints the differences between the responses.
+///
+/// Returns `true` if the responses have the same status and body.
+pub fn compare(
+    dir: &Path,
+    CompareArgs {
+        path,
+        selection,
+        against,
+        env,
+        ignore,
+        session,
+        plaintext_session,
+        secrets,
+        connection,
+    }: CompareArgs,
+) -> bool {
+    let Some(mut request_paths) = locate_requests(dir, &path, selection) else {
+        return false;
+    };
+    if request_paths.len() > 1 {
+        eprintln!("kuiper compare sends a single request, pick one with --first or --index N");
+        failure::record(Failure::Resolve);
+        return false;
+    }
+    let request_path = request_paths.remove(0);
+    let Some(session) = load_session(session, plaintext_session) else {
+        return false;
+    };
+    let secrets = match secrets.as_deref().map(libkuiper::secrets::load).transpose() {
+        Ok(secrets) => secrets.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("failed to load secrets: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let Some(config) = load_config(dir) else {
+        return false;
+    };
+    // each environment connects with the settings it has in the config
+    let send = |env: Option<&str>| {
+        send_in_environment(
+            dir,
+            &request_path,
+            env,
+            &session,
+            &secrets,
+            &config,
+            &connection.client_in(&config, env)?,
+        )
+    };
+    let (Some(old), Some(new)) = (send(env.as_deref()), send(Some(&against))) else {
+        return false;
+    };
+    let normalizer = ignore
+        .into_iter()
+        .fold(Normalizer::new(), Normalizer::with_ignore);
+    let changes = match changes(&old, &new, &normalizer) {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("failed to compare the responses: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    println!("--- {}", env.as_deref().unwrap_or("process environment"));
+    println!("+++ {against}");
+    if old.status() != new.status() {
+        println!("~ status: {} -> {}", old.status(), new.status());
+    }
+    for change in &changes {
+        println!("{change}");
+    }
+    let same = old.status() == new.status() && changes.is_empty();
+    if same {
+        println!("no differences");
+    } else {
+        failure::record(Failure::Assertion);
+    }
+    same
+}
+
+/// Compares the bodies of `old` and `new` value by value if both are JSON, and line by line
+/// otherwise.
+fn changes(
+    old: &Response,
+    new: &Response,
+    normalizer: &Normalizer,
+) -> libkuiper::KuiperResult<Vec<String>> {
+    let parse = |body: &str| serde_json::from_str::<Value>(body).ok();
+    match (parse(old.body()), parse(new.body())) {
+        (Some(mut old), Some(mut new)) => {
+            normalizer.normalize(&mut old)?;
+            normalizer.normalize(&mut new)?;
+            Ok(diff::json(&old, &new)
+                .iter()
+                .map(ToString::to_string)
+                .collect())
+        }
+        _ => Ok(diff::lines(old.body(), new.body())
+            .into_iter()
+            .filter(|line| !matches!(line, diff::Line::Same(_)))
+            .map(|line| line.to_string())
+            .collect()),
+    }
+}
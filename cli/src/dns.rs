@@ -0,0 +1,129 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Resolved addresses keyed by host, along with when they were resolved.
+type Cache = HashMap<String, (Instant, Vec<SocketAddr>)>;
+
+/// A DNS resolver that remembers the addresses it resolved, so that many requests to the same
+/// host only cause a single lookup.
+///
+/// Clones share the same cache.
+#[derive(Clone, Debug, Default)]
+pub struct CachingResolver {
+    /// How long resolved addresses are reused, forever if `None`.
+    ttl: Option<Duration>,
+    cache: Arc<Mutex<Cache>>,
+    /// Time spent on every lookup that was not answered from the cache.
+    lookups: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl CachingResolver {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            ttl,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the addresses of `host`, looking them up if they are not cached or have expired.
+    pub fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        match self.cached(host) {
+            Some(addrs) => Ok(addrs),
+            None => self.resolve_uncached(host),
+        }
+    }
+
+    /// The cached addresses of `host`, unless they have expired.
+    fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let cache = self.cache.lock().unwrap();
+        let (resolved_at, addrs) = cache.get(host)?;
+        self.ttl
+            .is_none_or(|ttl| resolved_at.elapsed() < ttl)
+            .then(|| addrs.clone())
+    }
+
+    /// Looks up the addresses of `host` and caches them, which blocks until the system's
+    /// resolver answers.
+    fn resolve_uncached(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        let start = Instant::now();
+        let addrs: Vec<_> = (host, 0).to_socket_addrs()?.collect();
+        self.lookups.lock().unwrap().push(start.elapsed());
+        log::debug!("resolved '{host}' to {addrs:?}");
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), (Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+
+    /// The durations of all lookups that were not answered from the cache.
+    pub fn lookups(&self) -> Vec<Duration> {
+        self.lookups.lock().unwrap().clone()
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = match resolver.cached(name.as_str()) {
+                Some(addrs) => addrs,
+                // so that a slow lookup doesn't hold up the other requests of the runtime
+                None => {
+                    tokio::task::spawn_blocking(move || resolver.resolve_uncached(name.as_str()))
+                        .await??
+                }
+            };
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_test() {
+        let resolver = CachingResolver::new(None);
+        let addrs = resolver.lookup("localhost").unwrap();
+        assert!(!addrs.is_empty());
+        // clones share the cache
+        assert_eq!(resolver.clone().lookup("localhost").unwrap(), addrs);
+        assert_eq!(resolver.lookups().len(), 1);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let resolved: Vec<_> = runtime
+            .block_on(resolver.resolve("localhost".parse().unwrap()))
+            .unwrap()
+            .collect();
+        assert_eq!(resolved, addrs);
+        assert_eq!(resolver.lookups().len(), 1);
+        let resolved: Vec<_> = runtime
+            .block_on(resolver.resolve("127.0.0.1".parse().unwrap()))
+            .unwrap()
+            .collect();
+        assert_eq!(resolved, ["127.0.0.1:0".parse::<SocketAddr>().unwrap()]);
+        assert_eq!(resolver.lookups().len(), 2);
+    }
+
+    #[test]
+    fn ttl_test() {
+        let resolver = CachingResolver::new(Some(Duration::from_millis(50)));
+        resolver.lookup("localhost").unwrap();
+        resolver.lookup("localhost").unwrap();
+        assert_eq!(resolver.lookups().len(), 1);
+
+        std::thread::sleep(Duration::from_millis(60));
+        resolver.lookup("localhost").unwrap();
+        assert_eq!(resolver.lookups().len(), 2);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{load_config, run::load_environment, ConnectionArgs};
+use libkuiper::{
+    keyring,
+    plugins::{Capabilities, Plugins},
+    Client, Context, Request,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(clap::Args)]
+pub struct DoctorArgs {
+    /// Check the variables and hosts of requests in this environment, given as a path to an env
+    /// file or as NAME for `NAME.env`, `environments/NAME.env` or `environments/NAME.json`.
+    #[arg(long)]
+    env: Option<String>,
+    /// Check that this SOPS or age encrypted file can be decrypted, and provides every secret.
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+    /// Don't try to connect to the hosts of requests.
+    #[arg(long)]
+    offline: bool,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Counts the problems found while printing them.
+#[derive(Default)]
+struct Findings {
+    errors: usize,
+}
+
+impl Findings {
+    fn ok(&self, message: impl AsRef<str>) {
+        println!("  ok: {}", message.as_ref());
+    }
+
+    fn warning(&self, message: impl AsRef<str>, fix: impl AsRef<str>) {
+        println!("  warning: {}", message.as_ref());
+        println!("    fix: {}", fix.as_ref());
+    }
+
+    fn error(&mut self, message: impl AsRef<str>, fix: impl AsRef<str>) {
+        self.errors += 1;
+        println!("  error: {}", message.as_ref());
+        println!("    fix: {}", fix.as_ref());
+    }
+}
+
+/// Checks the configuration, requests, variables, hosts and keyring used when running requests
+/// in `dir`, and prints how to fix every problem found.
+pub fn doctor(
+    dir: &Path,
+    DoctorArgs {
+        env,
+        secrets,
+        offline,
+        connection,
+    }: DoctorArgs,
+) -> bool {
+    let mut findings = Findings::default();
+
+    println!("config");
+    let Some(config) = load_config(dir) else {
+        println!(
+            "    fix: make sure every kuiper.toml is valid TOML, and every kuiper.json valid JSON"
+        );
+        return false;
+    };
+    match config.path() {
+        Some(path) => findings.ok(format!("using {path:?}")),
+        None => findings.ok("no kuiper.toml or kuiper.json found, using the defaults"),
+    }
+
+    println!("plugins");
+    let plugins = check_plugins(dir, &mut findings);
+
+    println!("requests");
+    let requests = check_requests(dir, &mut findings);
+
+    println!("variables");
+    let env_name = env;
+    let env = match &env_name {
+        Some(name) => match load_environment(dir, name) {
+            Some(env) => {
+                findings.ok(format!("loaded environment '{name}'"));
+                env
+            }
+            None => {
+                findings.error(
+                    format!("environment '{name}' could not be loaded"),
+                    format!(
+                        "create {name}.env, environments/{name}.env or environments/{name}.json \
+                         next to your requests"
+                    ),
+                );
+                HashMap::new()
+            }
+        },
+        None => HashMap::new(),
+    };
+    let secrets = match &secrets {
+        Some(path) => match libkuiper::secrets::load(path) {
+            Ok(secrets) => {
+                findings.ok(format!("decrypted {path:?}"));
+                Some(secrets)
+            }
+            Err(e) => {
+                findings.error(
+                    format!("failed to decrypt {path:?}: {e}"),
+                    "install sops or age, and point KUIPER_AGE_IDENTITY or SOPS_AGE_KEY_FILE at \
+                     your key, or store your age identity in the keyring",
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let mut context = Context::new()
+        .with_env(env)
+        .with_secrets(secrets.clone().unwrap_or_default())
+        .with_hosts(config.hosts().to_vec())
+        .with_uri_normalization(config.uri_normalization());
+    for resolver in plugins.resolvers() {
+        context = context.with_resolver(resolver);
+    }
+    check_variables(&requests, &context, secrets.is_some(), &mut findings);
+
+    println!("hosts");
+    if offline {
+        findings.ok("skipped, running offline");
+    } else {
+        match connection.client_in(&config, env_name.as_deref()) {
+            Some(client) => check_hosts(&requests, &context, &client, &mut findings),
+            None => findings.error(
+                "failed to create an HTTP client",
+                "check the client settings in kuiper.toml",
+            ),
+        }
+    }
+
+    println!("keyring");
+    if keyring::is_available() {
+        findings.ok("sessions are stored in the OS keyring");
+    } else {
+        findings.warning(
+            "the OS keyring is not available, sessions are stored in plaintext files",
+            "install secret-tool (libsecret) on Linux, or run with --plaintext-session if plaintext \
+             sessions are fine",
+        );
+    }
+
+    println!();
+    if findings.errors == 0 {
+        println!("no problems found");
+    } else {
+        println!("{} problems found", findings.errors);
+    }
+    findings.errors == 0
+}
+
+/// Loads the plugins of the requests in `dir`, and lists what each adds.
+fn check_plugins(dir: &Path, findings: &mut Findings) -> Plugins {
+    let plugins = match Plugins::discover(dir) {
+        Ok(plugins) => plugins,
+        Err(e) => {
+            findings.error(
+                format!("failed to load plugins: {e}"),
+                "make sure every executable in plugins answers { \"call\": \"describe\" } with \
+                 a JSON object",
+            );
+            return Plugins::default();
+        }
+    };
+    if plugins.is_empty() {
+        findings.ok("no plugins found");
+    }
+    for plugin in plugins.iter() {
+        findings.ok(format!(
+            "plugin '{}' adds {}",
+            plugin.name(),
+            describe(plugin.capabilities())
+        ));
+    }
+    plugins
+}
+
+/// Lists what a plugin adds, like `namespaces vault, filters redact`.
+fn describe(capabilities: &Capabilities) -> String {
+    let added: Vec<_> = [
+        ("namespaces", capabilities.namespaces()),
+        ("auth schemes", capabilities.auth()),
+        ("filters", capabilities.filters()),
+        ("reporters", capabilities.reporters()),
+    ]
+    .into_iter()
+    .filter(|(_, names)| !names.is_empty())
+    .map(|(kind, names)| format!("{kind} {}", names.join(", ")))
+    .collect();
+    if added.is_empty() {
+        "nothing".to_string()
+    } else {
+        added.join("; ")
+    }
+}
+
+/// Parses every request below `dir` and resolves its dependencies, returning the paths of the
+/// requests that are valid.
+fn check_requests(dir: &Path, findings: &mut Findings) -> Vec<PathBuf> {
+    let paths = match Request::search_paths(dir, "") {
+        Ok(paths) => paths,
+        Err(e) => {
+            findings.error(
+                format!("failed to list requests in {dir:?}: {e}"),
+                "run kuiper doctor from, or pass -d with, the directory of your requests",
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut valid = Vec::new();
+    for path in paths {
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        if let Err(e) = Request::find_uninterpolated(&path) {
+            findings.error(
+                format!("{name}: {e}"),
+                "fix the JSON of the request, or of a headers.json, variables.json or auth.json above it",
+            );
+        } else if let Err(e) = Request::dependency_order(&path) {
+            findings.error(
+                format!("{name}: {e}"),
+                "make every depends_on point at an existing request, without cycles",
+            );
+        } else {
+            valid.push(path);
+        }
+    }
+    findings.ok(format!("{} requests are valid", valid.len()));
+    valid
+}
+
+/// Checks that every `env` and `secret` value used by `requests` is available.
+fn check_variables(
+    requests: &[PathBuf],
+    context: &Context,
+    has_secrets: bool,
+    findings: &mut Findings,
+) {
+    let mut missing_env = BTreeSet::new();
+    let mut missing_secrets = BTreeSet::new();
+    let mut missing_variables = BTreeSet::new();
+    for path in requests {
+        let Ok(request) = Request::find_uninterpolated(path) else {
+            continue;
+        };
+        for (namespace, name) in request.references() {
+            match namespace.as_str() {
+                "env" if !context.has_env(&name) => {
+                    missing_env.insert(name);
+                }
+                "secret" if !context.has_secret(&name) => {
+                    missing_secrets.insert(name);
+                }
+                "var" if !request.variables().contains_key(&name) => {
+                    missing_variables.insert(name);
+                }
+                _ => {}
+            }
+        }
+        for name in request.required() {
+            if !context.has_env(name) {
+                missing_env.insert(name.clone());
+            }
+        }
+    }
+
+    for name in &missing_env {
+        findings.error(
+            format!("{{{{env:{name}}}}} is not set"),
+            format!("add {name}=... to the env file of the environment, or export it"),
+        );
+    }
+    for name in &missing_secrets {
+        if has_secrets {
+            findings.error(
+                format!("{{{{secret:{name}}}}} is not in the secrets file"),
+                format!("add {name} to the secrets file with sops or age"),
+            );
+        } else {
+            findings.error(
+                format!("{{{{secret:{name}}}}} is used, but no secrets file was given"),
+                "pass the encrypted secrets file with --secrets",
+            );
+        }
+    }
+    for name in &missing_variables {
+        findings.error(
+            format!("{{{{var:{name}}}}} is not set"),
+            format!("add \"{name}\" to a variables.json above the requests that use it"),
+        );
+    }
+    if missing_env.is_empty() && missing_secrets.is_empty() && missing_variables.is_empty() {
+        findings.ok("every env, secret and var value used by the requests is available");
+    }
+}
+
+/// Connects to every host that `requests` are sent to, through the configured proxies.
+fn check_hosts(requests: &[PathBuf], context: &Context, client: &Client, findings: &mut Findings) {
+    let origins: BTreeSet<_> = requests
+        .iter()
+        .filter_map(|path| Request::find_with_context(path, context).ok())
+        .filter_map(|request| reqwest::Url::parse(request.uri()).ok())
+        .filter(|url| url.has_host())
+        .map(|url| url.origin().ascii_serialization())
+        .collect();
+
+    for origin in &origins {
+        if let Err(e) = client.check_host(origin) {
+            findings.warning(
+                format!("{origin}: {e}"),
+                "add the host to allowed_hosts in kuiper.toml if requests should be sent to it",
+            );
+            continue;
+        }
+        let result = client
+            .inner()
+            .head(origin)
+            .timeout(Duration::from_secs(10))
+            .send();
+        match result {
+            Ok(response) => findings.ok(format!(
+                "{origin} is reachable (status {})",
+                response.status().as_u16()
+            )),
+            Err(e) => {
+                let fix = if e.is_timeout() {
+                    "check that the host is up, and that a proxy in the hosts of kuiper.toml is \
+                     set if it is only reachable through one"
+                } else if e.is_connect() {
+                    "check the host name and port, the proxy in the hosts of kuiper.toml, and \
+                     that the server's certificate is trusted by your system"
+                } else {
+                    "check the uri of the requests sent to this host"
+                };
+                findings.error(format!("{origin}: {}", error_chain(&e)), fix);
+            }
+        }
+    }
+    if origins.is_empty() {
+        findings.ok("no hosts to check");
+    }
+}
+
+/// Formats `error` along with its sources, which hold the details of connection and TLS errors.
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+    message
+}
@@ -0,0 +1,219 @@
+use crate::{auth::load_session, locate_requests, run::load_environment, Selection};
+use libkuiper::{
+    lint::{self, Severity},
+    Context, Request,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::Path,
+    process::Command,
+};
+
+#[derive(clap::Args)]
+pub struct EditArgs {
+    /// Path of the request, or a term to search for it with.
+    request: String,
+    /// Check placeholders in, and send the request in, this environment, given as a path to an
+    /// env file or as NAME for `NAME.env`, `environments/NAME.env` or `environments/NAME.json`.
+    #[arg(long)]
+    env: Option<String>,
+    #[command(flatten)]
+    selection: Selection,
+}
+
+/// What to do once the request has been edited and checked.
+enum Action {
+    Send,
+    Edit,
+    Quit,
+}
+
+/// Opens a request in `$VISUAL` or `$EDITOR`, checks it every time the editor exits, and offers
+/// to send it, until the user quits. Succeeds if the last check, or the last send, did.
+pub fn edit(
+    dir: &Path,
+    EditArgs {
+        request,
+        env,
+        selection,
+    }: EditArgs,
+) -> bool {
+    let Some(mut paths) = locate_requests(dir, &request, selection) else {
+        return false;
+    };
+    if paths.len() > 1 {
+        eprintln!("can only edit one request at a time, pick one with --first or --index N");
+        return false;
+    }
+    let path = paths.remove(0);
+    let values = match &env {
+        Some(name) => match load_environment(dir, name) {
+            Some(values) => values,
+            None => return false,
+        },
+        None => HashMap::new(),
+    };
+    let session = load_session(None, false).unwrap_or_default();
+    let context = Context::from(&session).with_env(values);
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    loop {
+        if let Err(e) = open(&editor, &path) {
+            eprintln!("failed to edit {path:?} with '{editor}': {e}");
+            return false;
+        }
+        let errors = check(dir, &path, &context);
+        let mut passed = errors == 0;
+        let question = if errors == 0 {
+            "no problems found, [s]end, [e]dit or [q]uit? [s] ".to_string()
+        } else {
+            format!("{errors} errors found, [e]dit, [s]end anyway or [q]uit? [e] ")
+        };
+        loop {
+            let default = if errors == 0 {
+                Action::Send
+            } else {
+                Action::Edit
+            };
+            match ask(&question, default) {
+                Ok(Action::Send) => passed = send(dir, &path, env.as_deref()),
+                Ok(Action::Edit) => break,
+                Ok(Action::Quit) => return passed,
+                Err(e) => {
+                    eprintln!("failed to read the answer: {e}");
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// Runs `editor`, which may include arguments like `code --wait`, on `path` and waits for it to
+/// exit.
+fn open(editor: &str, path: &Path) -> io::Result<()> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program).args(words).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("the editor exited with {status}")))
+    }
+}
+
+/// Lints the request at `path` and checks that the placeholders it inherits from the
+/// `headers.json` and `auth.json` files above it, and its `required` values, are set. Prints
+/// every problem found, and returns the number of errors.
+fn check(dir: &Path, path: &Path, context: &Context) -> usize {
+    let name = path.strip_prefix(dir).unwrap_or(path).display().to_string();
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("{name}: failed to read the request: {e}");
+            return 1;
+        }
+    };
+
+    let diagnostics = lint::lint(path, &contents, context);
+    for diagnostic in &diagnostics {
+        eprintln!("{name}:{diagnostic}");
+    }
+    let mut errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity() == Severity::Error)
+        .count();
+    if errors > 0 {
+        return errors;
+    }
+
+    let request = match Request::find_uninterpolated(path) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("{name}: error: {e}");
+            return 1;
+        }
+    };
+    if let Err(e) = Request::dependency_order(path) {
+        eprintln!("{name}: error: {e}");
+        errors += 1;
+    }
+    // the placeholders in the file itself were checked by the linter
+    let own: Vec<_> = lint::placeholders(&contents)
+        .into_iter()
+        .map(|placeholder| {
+            (
+                placeholder.namespace().to_string(),
+                placeholder.name().to_string(),
+            )
+        })
+        .collect();
+    for (namespace, placeholder) in request.references() {
+        if own.contains(&(namespace.clone(), placeholder.clone())) {
+            continue;
+        }
+        match namespace.as_str() {
+            "env" if !context.has_env(&placeholder) => eprintln!(
+                "{name}: warning: {{{{env:{placeholder}}}}}, used by a headers.json or auth.json \
+                 above this file, is not set in the selected environment"
+            ),
+            "var"
+                if !context.has_variable(&placeholder)
+                    && !request.variables().contains_key(&placeholder) =>
+            {
+                eprintln!(
+                    "{name}: error: {{{{var:{placeholder}}}}}, used by a headers.json or \
+                     auth.json above this file, is not set in any variables.json"
+                );
+                errors += 1;
+            }
+            _ => {}
+        }
+    }
+    for required in request.required() {
+        if !context.has_env(required) {
+            eprintln!("{name}: warning: required value '{required}' is not set");
+        }
+    }
+    errors
+}
+
+/// Asks what to do next, taking an empty answer as `default` and the end of input as quitting.
+fn ask(question: &str, default: Action) -> io::Result<Action> {
+    loop {
+        eprint!("{question}");
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(Action::Quit);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "" => return Ok(default),
+            "s" | "send" => return Ok(Action::Send),
+            "e" | "edit" => return Ok(Action::Edit),
+            "q" | "quit" => return Ok(Action::Quit),
+            _ => {}
+        }
+    }
+}
+
+/// Sends the request at `path` with `kuiper run` from `dir`, and returns `true` if it passed.
+fn send(dir: &Path, path: &Path, env: Option<&str>) -> bool {
+    let status = std::env::current_exe().and_then(|exe| {
+        let mut command = Command::new(exe);
+        command.arg("-d").arg(dir).arg("run").arg(path);
+        if let Some(env) = env {
+            command.arg("--env").arg(env);
+        }
+        command.status()
+    });
+    match status {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("failed to send {path:?}: {e}");
+            false
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Exit codes for the classes of failures, so that scripts wrapping kuiper can react to them
+//! without parsing its output.
+//!
+//! Failures are recorded as they are reported, and the earliest class recorded during a run,
+//! the one closest to resolving requests, decides the exit code.
+
+use libkuiper::{Failure, KuiperError};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Exit code of a failure that wasn't classified, like invalid arguments.
+pub const GENERIC: u8 = 1;
+
+static RECORDED: AtomicU8 = AtomicU8::new(u8::MAX);
+
+/// The exit code of `failure`. These are documented in the README, and must not change.
+pub const fn exit_code(failure: Failure) -> u8 {
+    match failure {
+        Failure::Resolve => 2,
+        Failure::Interpolation => 3,
+        Failure::Network => 4,
+        Failure::Http => 5,
+        Failure::Assertion => 6,
+        Failure::Cancelled => 7,
+    }
+}
+
+/// Records that the run failed with `failure`.
+pub fn record(failure: Failure) {
+    RECORDED.fetch_min(exit_code(failure), Ordering::SeqCst);
+}
+
+/// Records that the run failed with `error`.
+pub fn record_error(error: &KuiperError) {
+    record(error.failure());
+}
+
+/// The exit code of a failed run, which is that of the earliest class of failure recorded.
+pub fn recorded() -> u8 {
+    match RECORDED.load(Ordering::SeqCst) {
+        u8::MAX => GENERIC,
+        code => code,
+    }
+}
@@ -0,0 +1,58 @@
+use crate::failure;
+use libkuiper::{
+    grep::{self, Scope},
+    Request,
+};
+use std::{collections::BTreeSet, path::Path};
+
+#[derive(clap::Args)]
+pub struct GrepArgs {
+    /// Regex to search for.
+    pattern: String,
+    /// Only search the `uri` of requests.
+    #[arg(long, conflicts_with = "header")]
+    uri_only: bool,
+    /// Only search the value of this header.
+    #[arg(long)]
+    header: Option<String>,
+}
+
+pub fn grep(
+    dir: &Path,
+    GrepArgs {
+        pattern,
+        uri_only,
+        header,
+    }: GrepArgs,
+) -> bool {
+    let scope = match header {
+        Some(header) => Scope::Header(header),
+        None if uri_only => Scope::Uri,
+        None => Scope::All,
+    };
+
+    match grep::grep(dir, &pattern, &scope) {
+        Ok(matches) => {
+            for m in &matches {
+                let path = m.path().strip_prefix(dir).unwrap_or(m.path());
+                println!("{}:{}: {}", path.display(), m.line(), m.text());
+            }
+            let paths: BTreeSet<_> = matches.iter().map(|m| m.path()).collect();
+            for path in paths {
+                if let Some(message) = Request::find_uninterpolated(path)
+                    .ok()
+                    .and_then(|request| request.deprecated().map(str::to_string))
+                {
+                    let path = path.strip_prefix(dir).unwrap_or(path);
+                    log::warn!("{} is deprecated: {message}", path.display());
+                }
+            }
+            !matches.is_empty()
+        }
+        Err(e) => {
+            eprintln!("failed to search {dir:?}: '{e}'");
+            failure::record_error(&e);
+            false
+        }
+    }
+}
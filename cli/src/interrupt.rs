@@ -0,0 +1,106 @@
+//! Handling of SIGINT and SIGTERM.
+//!
+//! The first signal only sets a flag, so that batch runs and benchmarks can stop sending new
+//! requests and still report what they collected so far. Clients stop reading response bodies
+//! once it is set, and delays before retries are cut short. A second signal exits right away,
+//! without waiting for requests that are in flight.
+
+use crate::failure;
+use libkuiper::Failure;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Exit code used when kuiper stopped because it was interrupted.
+pub const EXIT_CODE: u8 = failure::exit_code(Failure::Cancelled);
+
+/// How often [`sleep`] checks whether kuiper was interrupted.
+const SLEEP_SLICE: Duration = Duration::from_millis(50);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the signal handlers.
+pub fn install() {
+    #[cfg(unix)]
+    // SAFETY: `handle` only touches an atomic and calls `_exit`, both of which are
+    // async-signal-safe.
+    unsafe {
+        let handler: extern "C" fn(libc::c_int) = handle;
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+/// Returns `true` once a SIGINT or SIGTERM has been received.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// The flag that is set once a SIGINT or SIGTERM has been received, to cancel clients with.
+pub fn flag() -> &'static AtomicBool {
+    &INTERRUPTED
+}
+
+/// Sleeps for `duration`, or until a SIGINT or SIGTERM is received, and returns `false` in the
+/// latter case.
+pub fn sleep(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    while !interrupted() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(SLEEP_SLICE));
+    }
+    false
+}
+
+/// The exit code of a run that succeeded if `success`, which is [`EXIT_CODE`] if it was
+/// interrupted, whether it succeeded or not.
+pub fn exit_code(success: bool) -> u8 {
+    if interrupted() {
+        EXIT_CODE
+    } else if success {
+        0
+    } else {
+        failure::recorded()
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `_exit` is async-signal-safe.
+        unsafe { libc::_exit(EXIT_CODE as libc::c_int) }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use libkuiper::KuiperError;
+
+    #[test]
+    fn interrupt_test() {
+        assert_eq!(EXIT_CODE, 7);
+        assert_eq!(exit_code(true), 0);
+        assert!(sleep(Duration::from_millis(10)));
+
+        // the first signal only sets the flag
+        handle(libc::SIGINT);
+        assert!(interrupted());
+        assert!(flag().load(Ordering::SeqCst));
+        let start = Instant::now();
+        assert!(!sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(exit_code(true), EXIT_CODE);
+        assert_eq!(exit_code(false), EXIT_CODE);
+        // requests cut short by the flag fail as cancelled
+        failure::record_error(&KuiperError::Cancelled);
+        assert_eq!(failure::recorded(), EXIT_CODE);
+
+        INTERRUPTED.store(false, Ordering::SeqCst);
+    }
+}
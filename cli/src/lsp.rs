@@ -0,0 +1,387 @@
+use crate::{
+    auth::load_session,
+    run::{environment_path, read_environment},
+};
+use libkuiper::{
+    envfile,
+    lint::{self, Severity},
+    watch::Watch,
+    Context, Request, Session,
+};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(clap::Args)]
+pub struct LspArgs {
+    /// Check and resolve placeholders in this environment, given as a path to an env file or as
+    /// NAME for `NAME.env`, `environments/NAME.env` or `environments/NAME.json`.
+    #[arg(long)]
+    env: Option<String>,
+    /// Accepted for editors that pass it, since stdin and stdout are the only transport.
+    #[arg(long = "stdio", action = clap::ArgAction::SetTrue, hide = true)]
+    _stdio: bool,
+}
+
+/// The command of the code action that runs a request.
+const RUN_COMMAND: &str = "kuiper.run";
+
+/// Serves the Language Server Protocol over stdin and stdout until the client exits.
+pub fn lsp(dir: &Path, LspArgs { env, .. }: LspArgs) -> bool {
+    let (env, env_path) = match &env {
+        Some(name) => {
+            let Some(path) = environment_path(dir, name) else {
+                return false;
+            };
+            match read_environment(&path) {
+                Some(env) => (env, Some(path)),
+                None => return false,
+            }
+        }
+        None => (HashMap::new(), None),
+    };
+    let session = load_session(None, false).unwrap_or_default();
+    let mut server = Server {
+        context: Context::from(&session).with_env(env.clone()),
+        session,
+        env,
+        watch: Watch::new(env_path),
+        documents: HashMap::new(),
+        shut_down: false,
+    };
+
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return server.shut_down,
+            Err(e) => {
+                log::error!("failed to read message: '{e}'");
+                return false;
+            }
+        };
+        if message["method"] == "exit" {
+            return server.shut_down;
+        }
+        server.reload();
+        for reply in server.handle(&message) {
+            if let Err(e) = write_message(&mut output, &reply) {
+                log::error!("failed to write message: '{e}'");
+                return false;
+            }
+        }
+    }
+}
+
+struct Server {
+    context: Context,
+    session: Session,
+    /// Values of the selected environment, passed on to the requests that are run.
+    env: HashMap<String, String>,
+    /// The env file of the selected environment, which is read again when it changes.
+    watch: Watch,
+    /// The contents of the open documents, by URI.
+    documents: HashMap<String, String>,
+    shut_down: bool,
+}
+
+impl Server {
+    /// Reads the env file again if it changed since it was last read, so that the next
+    /// diagnostics, hovers and runs use its new values.
+    fn reload(&mut self) {
+        for path in self.watch.changed() {
+            // an editor may remove the file before writing it again
+            let Some(env) = read_environment(&path) else {
+                continue;
+            };
+            let changed = envfile::changes(&self.env, &env);
+            if changed.is_empty() {
+                continue;
+            }
+            log::info!("reloaded {path:?}, changed: {}", changed.join(", "));
+            self.context = Context::from(&self.session).with_env(env.clone());
+            self.env = env;
+        }
+    }
+
+    /// Handles a request or notification, and returns the messages to send in reply.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "codeActionProvider": true,
+                    "executeCommandProvider": { "commands": [RUN_COMMAND] }
+                },
+                "serverInfo": { "name": "kuiper", "version": env!("CARGO_PKG_VERSION") }
+            }),
+            "shutdown" => {
+                self.shut_down = true;
+                Value::Null
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                return self.update(document["uri"].as_str(), document["text"].as_str());
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                return self.update(params["textDocument"]["uri"].as_str(), text);
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                return vec![diagnostics(uri, Vec::new())];
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/codeAction" => {
+                let uri = &params["textDocument"]["uri"];
+                if uri.as_str().is_some_and(Request::is_request_file) {
+                    json!([{
+                        "title": "Run request",
+                        "kind": "source",
+                        "command": {
+                            "title": "Run request",
+                            "command": RUN_COMMAND,
+                            "arguments": [uri]
+                        }
+                    }])
+                } else {
+                    json!([])
+                }
+            }
+            "workspace/executeCommand" if params["command"] == RUN_COMMAND => {
+                let mut replies = self.run(params["arguments"][0].as_str().unwrap_or_default());
+                replies.extend(id.map(|id| json!({ "jsonrpc": "2.0", "id": id, "result": null })));
+                return replies;
+            }
+            method => {
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("unsupported method '{method}'") }
+                    })],
+                    // notifications that aren't handled can be ignored
+                    None => Vec::new(),
+                };
+            }
+        };
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+
+    /// Stores the new contents of a document and returns its diagnostics.
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> Vec<Value> {
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return Vec::new();
+        };
+        self.documents.insert(uri.to_string(), text.to_string());
+        let Some(path) = to_path(uri) else {
+            return Vec::new();
+        };
+        let lines: Vec<_> = text.lines().collect();
+        let found = lint::lint(&path, text, &self.context)
+            .into_iter()
+            .map(|diagnostic| {
+                let line = lines.get(diagnostic.line()).copied().unwrap_or_default();
+                json!({
+                    "range": range(diagnostic.line(), line, diagnostic.start(), diagnostic.end()),
+                    "severity": match diagnostic.severity() {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "source": "kuiper",
+                    "message": diagnostic.message()
+                })
+            })
+            .collect();
+        vec![diagnostics(uri, found)]
+    }
+
+    /// Describes the placeholder, or the origin of the header, under the cursor.
+    fn hover(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let (Some(contents), Some(line_idx), Some(character)) = (
+            self.documents.get(uri),
+            params["position"]["line"].as_u64(),
+            params["position"]["character"].as_u64(),
+        ) else {
+            return Value::Null;
+        };
+        let line_idx = line_idx as usize;
+        let line = contents.lines().nth(line_idx).unwrap_or_default();
+        let offset = to_byte(line, character as usize);
+
+        if let Some(placeholder) = lint::placeholder_at(contents, line_idx, offset) {
+            let variables = to_path(uri)
+                .and_then(|path| Request::find_variables(path).ok())
+                .unwrap_or_default();
+            let context = self.context.clone().with_variables(variables);
+            return json!({
+                "contents": { "kind": "markdown", "value": placeholder.describe(&context) },
+                "range": range(line_idx, line, placeholder.start(), placeholder.end())
+            });
+        }
+
+        // the origins of headers are only known for the file as it is saved
+        let Some((start, end)) = quoted_at(line, offset) else {
+            return Value::Null;
+        };
+        let name = &line[start..end];
+        let Some(request) = to_path(uri).and_then(|path| Request::find_uninterpolated(path).ok())
+        else {
+            return Value::Null;
+        };
+        match (request.header_origin(name), request.headers().get(name)) {
+            (Some(origin), Some(value)) => json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": match value {
+                        Some(value) => format!("header `{name}: {value}`, set by {origin}"),
+                        None => format!("header `{name}`, removed by {origin}"),
+                    }
+                },
+                "range": range(line_idx, line, start, end)
+            }),
+            _ => Value::Null,
+        }
+    }
+
+    /// Runs the request at `uri` with `kuiper run`, and reports the result to the client.
+    fn run(&self, uri: &str) -> Vec<Value> {
+        let Some(path) = to_path(uri) else {
+            return vec![show_message(1, format!("not a request file: {uri}"))];
+        };
+        let output = std::env::current_exe().and_then(|exe| {
+            Command::new(exe)
+                .arg("run")
+                .arg(&path)
+                .envs(&self.env)
+                .output()
+        });
+        match output {
+            Ok(output) => {
+                let log = String::from_utf8_lossy(&output.stdout).into_owned()
+                    + &String::from_utf8_lossy(&output.stderr);
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                vec![
+                    json!({
+                        "jsonrpc": "2.0",
+                        "method": "window/logMessage",
+                        "params": { "type": 4, "message": log }
+                    }),
+                    if output.status.success() {
+                        show_message(3, format!("{name} passed"))
+                    } else {
+                        show_message(1, format!("{name} failed, see the output for details"))
+                    },
+                ]
+            }
+            Err(e) => vec![show_message(1, format!("failed to run {path:?}: {e}"))],
+        }
+    }
+}
+
+fn diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics }
+    })
+}
+
+fn show_message(kind: u8, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "window/showMessage",
+        "params": { "type": kind, "message": message }
+    })
+}
+
+/// An LSP range on `line`, from the byte offsets `start` to `end`.
+fn range(line_idx: usize, line: &str, start: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": line_idx, "character": to_utf16(line, start) },
+        "end": { "line": line_idx, "character": to_utf16(line, end) }
+    })
+}
+
+/// Converts a byte offset in `line` to the UTF-16 offset that LSP positions use.
+fn to_utf16(line: &str, byte: usize) -> usize {
+    line.char_indices()
+        .take_while(|(idx, _)| *idx < byte)
+        .map(|(_, c)| c.len_utf16())
+        .sum()
+}
+
+/// Converts a UTF-16 offset in `line` to a byte offset.
+fn to_byte(line: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (idx, c) in line.char_indices() {
+        if units >= utf16 {
+            return idx;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// The byte range of the contents of the JSON string around `offset` in `line`, if any.
+fn quoted_at(line: &str, offset: usize) -> Option<(usize, usize)> {
+    let quotes: Vec<_> = line.match_indices('"').map(|(idx, _)| idx).collect();
+    quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0] + 1, pair[1]))
+        .find(|(start, end)| (*start..=*end).contains(&offset))
+}
+
+fn to_path(uri: &str) -> Option<PathBuf> {
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+/// Reads a message framed by a `Content-Length` header, or `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
@@ -1,21 +1,312 @@
-use clap::Parser;
-use libkuiper::Request;
-use reqwest::Method;
-use std::{path::PathBuf, str::FromStr};
+use audit::AuditArgs;
+use auth::AuthCommand;
+use bench::BenchArgs;
+use bundle::BundleCommand;
+use clap::{Parser, Subcommand};
+use compare::CompareArgs;
+use doctor::DoctorArgs;
+use edit::EditArgs;
+use grep::GrepArgs;
+use libkuiper::{
+    audit::AuditLog, convert, graphql, observer::Event, ratelimit::RateLimiter, sse,
+    tls::TlsBackend, Attempt, BodyLimit, Client, ClientConfig, Config, Failure, KuiperError,
+    KuiperResult, Request, Response, TlsVersion,
+};
+use lsp::LspArgs;
+use new::NewArgs;
+use normalize::NormalizeArgs;
+use remote::RemoteCommand;
+use replay::ReplayArgs;
+use reqwest::dns::Resolve;
+use run::RunArgs;
+use stats::StatsArgs;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use test::TestArgs;
+
+mod audit;
+mod auth;
+mod bench;
+mod bundle;
+mod compare;
+mod dns;
+mod doctor;
+mod edit;
+mod failure;
+mod grep;
+mod interrupt;
+mod lsp;
+mod new;
+mod normalize;
+mod remote;
+mod replay;
+mod report;
+mod run;
+mod stats;
+mod test;
 
 #[derive(clap::Parser)]
 struct Args {
-    path: String,
-    #[arg(short)]
+    #[command(subcommand)]
+    command: Command,
+    #[arg(short, global = true)]
     env_file: Option<PathBuf>,
     /// Specify this argument to start request evaluation from this directory.
-    #[arg(short)]
+    #[arg(short, global = true)]
     dir: Option<PathBuf>,
 }
 
-fn main() {
+#[derive(Subcommand)]
+enum Command {
+    /// Send a request, after sending the requests it depends on.
+    Run(Box<RunArgs>),
+    /// Send every request in a collection, or only the ones affected by changes since a git
+    /// revision.
+    Test(Box<TestArgs>),
+    /// Send a request in two environments and print the differences between the responses.
+    Compare(CompareArgs),
+    /// Send a request repeatedly and report latency and throughput.
+    Bench(BenchArgs),
+    /// Send a request from the history, or recorded in an exchange saved with
+    /// `--save-exchange`, again.
+    Replay(ReplayArgs),
+    /// Search the contents of request files.
+    Grep(GrepArgs),
+    /// Summarize a collection, and list what in it is unused, undocumented or untested.
+    Stats(StatsArgs),
+    /// Share a collection of requests as a single file.
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+    /// Inspect or clear the stored session.
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+    /// Check that config, requests, variables, hosts and the keyring are set up correctly.
+    Doctor(DoctorArgs),
+    /// Serve diagnostics, hovers and a code action to run requests to editors, over the Language
+    /// Server Protocol on stdin and stdout.
+    Lsp(LspArgs),
+    /// Open a request in `$EDITOR`, check it every time it is saved and closed, and offer to send
+    /// it.
+    Edit(EditArgs),
+    /// Create a set of related requests for a resource from a template.
+    New(NewArgs),
+    /// Sort the keys, normalize the numbers and mask values of a JSON file, to prepare it as a
+    /// fixture.
+    Normalize(NormalizeArgs),
+    /// Print and verify the audit log of sent requests.
+    Audit(AuditArgs),
+    /// Manage git repositories that requests can be run from.
+    Remote {
+        #[command(subcommand)]
+        command: RemoteCommand,
+    },
+}
+
+/// How a request is picked when the search term given for it matches several requests.
+#[derive(clap::Args, Clone, Copy, Debug, Default)]
+pub(crate) struct Selection {
+    /// Use the first of several matching requests, in the order they are listed in.
+    #[arg(long, conflicts_with_all = ["index", "all"])]
+    first: bool,
+    /// Use the Nth of several matching requests, counting from 1.
+    #[arg(long, conflicts_with = "all")]
+    index: Option<usize>,
+    /// Use every matching request.
+    #[arg(long)]
+    all: bool,
+}
+
+/// Limits on how much of a response body is read, and how it is printed.
+#[derive(clap::Args, Clone, Copy, Debug, Default)]
+pub(crate) struct BodyLimits {
+    /// Abort when a response body is larger than this many bytes.
+    #[arg(long)]
+    max_response_size: Option<u64>,
+    /// Truncate response bodies larger than `--max-response-size` instead of aborting.
+    #[arg(long, requires = "max_response_size")]
+    truncate: bool,
+    /// Only print the first N bytes of response bodies.
+    #[arg(long)]
+    head_bytes: Option<usize>,
+    /// Convert JSON response bodies to this format before printing them.
+    #[arg(long, value_enum)]
+    output_format: Option<OutputFormat>,
+    /// Print the events of `text/event-stream` responses as they arrive, instead of the whole
+    /// body once the stream has ended.
+    #[arg(long)]
+    stream: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum OutputFormat {
+    Yaml,
+    /// Only for objects. Keys with `null` values are left out.
+    Toml,
+    /// Only for arrays of objects without nested arrays or objects, with a column per key.
+    Csv,
+}
+
+impl OutputFormat {
+    fn convert(self, body: &str) -> KuiperResult<String> {
+        let value = serde_json::from_str(body)?;
+        match self {
+            OutputFormat::Yaml => convert::to_yaml(&value),
+            OutputFormat::Toml => convert::to_toml(&value),
+            OutputFormat::Csv => convert::to_csv(&value),
+        }
+    }
+}
+
+impl BodyLimits {
+    fn body_limit(&self) -> Option<BodyLimit> {
+        let limit = BodyLimit::new(self.max_response_size?);
+        Some(if self.truncate {
+            limit.truncating()
+        } else {
+            limit
+        })
+    }
+}
+
+/// How connections to servers are made and reused.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub(crate) struct ConnectionArgs {
+    /// Open a new connection for every request instead of reusing idle ones.
+    #[arg(long, conflicts_with = "pool_size")]
+    no_keepalive: bool,
+    /// Maximum number of idle connections kept open per host.
+    #[arg(long)]
+    pool_size: Option<usize>,
+    /// Close connections that have been idle for this many milliseconds.
+    #[arg(long)]
+    pool_idle_timeout_ms: Option<u64>,
+    /// Whether to disable Nagle's algorithm on connections.
+    #[arg(long)]
+    tcp_nodelay: Option<bool>,
+    /// Send TCP keepalive probes on idle connections at this interval, in milliseconds.
+    #[arg(long)]
+    tcp_keepalive_ms: Option<u64>,
+    /// Give up connecting to a server after this many milliseconds.
+    #[arg(long)]
+    connect_timeout_ms: Option<u64>,
+    /// Refuse to connect with a TLS version older than this one.
+    #[arg(long)]
+    min_tls_version: Option<TlsVersion>,
+    /// Refuse to connect with a TLS version newer than this one.
+    #[arg(long)]
+    max_tls_version: Option<TlsVersion>,
+    /// Connect with this TLS library, `native-tls` or `rustls`.
+    #[arg(long)]
+    tls_backend: Option<TlsBackend>,
+    /// Send requests to this host even if the `allowed_hosts` or `denied_hosts` of `kuiper.toml`
+    /// don't allow it. Can be given several times.
+    #[arg(long)]
+    allow_host: Vec<String>,
+    /// Send requests over HTTP/3 (QUIC), to servers known to support it. Needs kuiper to be built
+    /// with the `http3` feature.
+    #[arg(long)]
+    http3: bool,
+}
+
+impl ConnectionArgs {
+    /// Builds the client shared by every request in a run, so that connections are kept alive
+    /// and reused between requests to the same host.
+    pub(crate) fn client(&self, config: &Config) -> Option<Client> {
+        self.build(config, None, None)
+    }
+
+    /// Like [`ConnectionArgs::client`], with the connection settings that `config` has for the
+    /// environment `env`, if any.
+    pub(crate) fn client_in(&self, config: &Config, env: Option<&str>) -> Option<Client> {
+        self.build(config, env, None)
+    }
+
+    /// Builds a client with the settings and host proxies in `config`, where settings are
+    /// overridden by those of the environment `env` and then by the ones given as flags, that
+    /// resolves hosts with `resolver` if given.
+    pub(crate) fn build(
+        &self,
+        config: &Config,
+        env: Option<&str>,
+        resolver: Option<Arc<dyn Resolve>>,
+    ) -> Option<Client> {
+        let flags = ClientConfig::default()
+            .with_pool_max_idle_per_host(self.no_keepalive.then_some(0).or(self.pool_size))
+            .with_pool_idle_timeout(self.pool_idle_timeout_ms.map(Duration::from_millis))
+            .with_tcp_nodelay(self.tcp_nodelay)
+            .with_tcp_keepalive(self.tcp_keepalive_ms.map(Duration::from_millis))
+            .with_connect_timeout(self.connect_timeout_ms.map(Duration::from_millis))
+            .with_min_tls_version(self.min_tls_version)
+            .with_max_tls_version(self.max_tls_version)
+            .with_tls_backend(self.tls_backend)
+            .with_http3(self.http3.then_some(true));
+        let environment = env.and_then(|name| Some((name, config.environment(name)?)));
+        let settings = environment
+            .map_or(*config.client(), |(_, environment)| {
+                config.client().merge(*environment.client())
+            })
+            .merge(flags);
+
+        let mut builder = reqwest::blocking::Client::builder();
+        if let Some((name, environment)) = environment {
+            builder = match environment.configure(builder, settings.tls_backend()) {
+                Ok(builder) => builder,
+                Err(e) => {
+                    eprintln!("failed to apply the settings of environment '{name}': '{e}'");
+                    failure::record_error(&e);
+                    return None;
+                }
+            };
+        }
+        if let Some(proxy) = libkuiper::hosts::proxy(config.hosts()) {
+            builder = builder.proxy(proxy);
+        }
+        let host_policy = config
+            .host_policy()
+            .clone()
+            .with_exceptions(self.allow_host.clone());
+        let mut client = match Client::from_builder(builder, &settings, resolver) {
+            Ok(client) => client
+                .with_host_policy(host_policy)
+                .with_rate_limiter(RateLimiter::new(
+                    data_dir().join("ratelimit.json"),
+                    config.hosts().to_vec(),
+                ))
+                .with_cancel_flag(interrupt::flag()),
+            Err(e) => {
+                eprintln!("failed to create HTTP client: '{e}'");
+                // the client is only built from settings
+                failure::record(Failure::Resolve);
+                return None;
+            }
+        };
+        if let Some(path) = config.audit_log() {
+            match AuditLog::open(path) {
+                Ok(log) => client = client.with_audit_log(log),
+                Err(e) => {
+                    eprintln!("failed to open audit log {path:?}: '{e}'");
+                    failure::record_error(&e);
+                    return None;
+                }
+            }
+        }
+        Some(client)
+    }
+}
+
+fn main() -> ExitCode {
     let Args {
-        path,
+        command,
         env_file,
         dir,
     } = Args::parse();
@@ -25,72 +316,291 @@ fn main() {
             Ok(env_file_path) => dotenv::from_path(env_file_path).unwrap(),
             Err(e) => {
                 eprintln!("failed to read env file: '{}'", e);
-                return;
+                return ExitCode::from(failure::exit_code(Failure::Resolve));
             }
         }
     }
 
-    let mut file_path = PathBuf::new();
-    file_path.push(&path);
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "info,kuiper_lib=trace");
+    }
+    pretty_env_logger::init_timed();
+    interrupt::install();
 
     let dir = dir.unwrap_or(std::env::current_dir().expect("should be able to read current dir"));
-    file_path = dir.join(file_path);
 
-    match file_path.canonicalize() {
-        Ok(existing_path) => {
-            if std::env::var("RUST_LOG").is_err() {
-                std::env::set_var("RUST_LOG", "info,kuiper_lib=trace");
-            }
+    let success = match command {
+        Command::Run(args) => run::run(&dir, *args),
+        Command::Test(args) => test::test(&dir, *args),
+        Command::Compare(args) => compare::compare(&dir, args),
+        Command::Bench(args) => bench::bench(&dir, args),
+        Command::Replay(args) => replay::replay(&dir, args),
+        Command::Grep(args) => grep::grep(&dir, args),
+        Command::Stats(args) => stats::stats(&dir, args),
+        Command::Bundle { command } => bundle::bundle(command),
+        Command::Auth { command } => auth::auth(command),
+        Command::Doctor(args) => doctor::doctor(&dir, args),
+        Command::Lsp(args) => lsp::lsp(&dir, args),
+        Command::Edit(args) => edit::edit(&dir, args),
+        Command::New(args) => new::new(&dir, args),
+        Command::Normalize(args) => normalize::normalize(args),
+        Command::Remote { command } => remote::remote(command),
+        Command::Audit(args) => audit::audit(&dir, args),
+    };
 
-            pretty_env_logger::init_timed();
-            match libkuiper::Request::find(existing_path.clone()) {
-                Ok(request) => {
-                    send_request(&request);
-                }
-                Err(e) => {
-                    eprintln!("failed to parse request with name: {existing_path:?}: '{e}'");
-                }
-            }
+    if interrupt::interrupted() {
+        eprintln!("interrupted, results are incomplete");
+    }
+    ExitCode::from(interrupt::exit_code(success))
+}
+
+/// Resolves `path` relative to `dir`, falling back to searching `dir` for a single request whose
+/// path contains `path`. If several requests match, `selection` picks one or all of them.
+///
+/// Paths like `remote:path/to/request` are resolved in the checkout of that remote instead.
+pub(crate) fn locate_requests(
+    dir: &Path,
+    path: &str,
+    selection: Selection,
+) -> Option<Vec<PathBuf>> {
+    if let Some(remote) = remote::resolve(path) {
+        let (dir, path) = remote?;
+        return locate_requests(&dir, path, selection);
+    }
+
+    for candidate in [dir.join(path), dir.join(format!("{path}.kuiper"))] {
+        if let Ok(existing_path) = candidate.canonicalize() {
+            return Some(vec![existing_path]);
+        }
+    }
+
+    // try searching instead of finding
+    let mut m = match Request::search_paths(dir, path) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("failed to search for '{path}' in {dir:?}: '{e}'");
+            failure::record_error(&e);
+            return None;
+        }
+    };
+    match selection {
+        _ if m.is_empty() => {
+            eprintln!("no request found for that term '{}'", path);
+            failure::record(Failure::Resolve);
+            None
         }
-        Err(_) => {
-            // try searching instead of finding
-            let mut m = Request::search(dir, &path).expect("failed to search");
-            if m.is_empty() {
-                eprintln!("no request found for that term '{}'", path);
-            } else if m.len() > 1 {
+        _ if m.len() == 1 || selection.all => Some(m),
+        Selection { first: true, .. } => Some(vec![m.remove(0)]),
+        Selection {
+            index: Some(index), ..
+        } => {
+            if (1..=m.len()).contains(&index) {
+                Some(vec![m.remove(index - 1)])
+            } else {
                 eprintln!(
-                    "multiple candidate requests for term '{}': [{}]",
-                    path,
-                    m.iter().map(|r| r.name()).collect::<Vec<_>>().join(", ")
+                    "--index {index} is out of range, there are {} candidate requests for term '{path}'",
+                    m.len()
                 );
-            } else {
-                let request = m.remove(0);
-                send_request(&request);
+                failure::record(Failure::Resolve);
+                None
+            }
+        }
+        _ => {
+            failure::record(Failure::Resolve);
+            eprintln!(
+                "multiple candidate requests for term '{path}', pick one with --first or --index N, or run them all with --all:"
+            );
+            for (idx, path) in m.iter().enumerate() {
+                match Request::find_uninterpolated(path)
+                    .ok()
+                    .and_then(|request| request.deprecated().map(str::to_string))
+                {
+                    Some(message) => {
+                        eprintln!("{:>4}. {} (deprecated: {message})", idx + 1, path.display())
+                    }
+                    None => eprintln!("{:>4}. {}", idx + 1, path.display()),
+                }
             }
+            None
         }
     }
 }
 
-fn send_request(req: &Request) {
-    let client = reqwest::blocking::Client::new();
-    let mut request = client.request(Method::from_str(req.method()).unwrap(), req.uri());
-    for (name, value) in req.headers() {
-        if let Some(v) = value {
-            request = request.header(name, v);
+/// Loads the config closest to `dir`, falling back to the user config in `$XDG_CONFIG_HOME/kuiper`
+/// or `~/.config/kuiper`.
+pub(crate) fn load_config(dir: &Path) -> Option<Config> {
+    let user_config = Config::find(&config_dir());
+    let config = Config::discover(dir).and_then(|config| match user_config {
+        Some(path) => Ok(config.with_fallback(Config::load(path)?)),
+        None => Ok(config),
+    });
+    match config {
+        Ok(config) => Some(config),
+        Err(e) => {
+            eprintln!("failed to load config: '{e}'");
+            failure::record_error(&e);
+            None
         }
     }
+}
+
+/// Returns `$XDG_CONFIG_HOME/kuiper`, falling back to `~/.config/kuiper`.
+pub(crate) fn config_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+        .join("kuiper")
+}
 
-    if let Some(body) = req.body() {
-        request = request.json(body);
+/// Returns `$XDG_DATA_HOME/kuiper`, falling back to `~/.local/share/kuiper`.
+pub(crate) fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_default()
+        .join("kuiper")
+}
+
+/// Sends `req` with `client`, reading no more of the response body than `limits` allow.
+pub(crate) fn send_request(
+    client: &Client,
+    req: &Request,
+    limits: BodyLimits,
+) -> KuiperResult<Response> {
+    let result = if limits.stream {
+        client.send_streaming(req, limits.body_limit(), &mut |event| println!("{event}"))
+    } else {
+        client.send_with_limit(req, limits.body_limit())
+    };
+    if let Err(KuiperError::HostNotAllowed(_)) = &result {
+        log::warn!("pass --allow-host to send '{}' anyway", req.name());
     }
+    result
+}
 
-    request = request.query(&req.params().iter().collect::<Vec<_>>());
+/// Sends `req`, retrying up to `retries` times if it can't be sent or gets a 5xx response.
+///
+/// Every delay before a retry is randomized by up to `jitter` percent. With a `deadline`, each
+/// attempt times out when it is reached, and retries that can't start before it are given up.
+pub(crate) fn send_with_retries(
+    client: &Client,
+    req: &Request,
+    retries: u32,
+    delay: Duration,
+    jitter: u32,
+    deadline: Option<Instant>,
+    limits: BodyLimits,
+) -> KuiperResult<Response> {
+    let mut attempt = 0;
+    let mut attempts = Vec::new();
+    loop {
+        let result = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let mut req = req.clone();
+                // timeouts are kept in whole milliseconds
+                req.override_timeout(left.max(Duration::from_millis(1)));
+                send_request(client, &req, limits)
+            }
+            None => send_request(client, req, limits),
+        };
+        let retryable = match &result {
+            Ok(response) => response.status() >= 500,
+            Err(
+                KuiperError::ResponseTooLarge(_)
+                | KuiperError::HostNotAllowed(_)
+                | KuiperError::Cancelled,
+            ) => false,
+            Err(_) => true,
+        };
+        if !retryable || attempt >= retries || interrupt::interrupted() {
+            return result.map(|response| response.with_earlier_attempts(attempts));
+        }
 
-    let request = request.build().unwrap();
+        let backoff = with_jitter(delay * 2u32.saturating_pow(attempt), jitter);
+        if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+            log::warn!(
+                "not retrying '{}', the next attempt would start after its deadline",
+                req.name()
+            );
+            return result.map(|response| response.with_earlier_attempts(attempts));
+        }
+        let reason = match result {
+            Ok(response) => {
+                attempts.extend_from_slice(response.attempts());
+                format!("got status {}", response.status())
+            }
+            Err(e) => e.to_string(),
+        };
+        attempt += 1;
+        client.notify(&Event::RetryScheduled {
+            request: req.name().to_string(),
+            attempt,
+            delay: backoff,
+            reason: reason.clone(),
+        });
+        attempts.push(Attempt::Retry {
+            reason,
+            delay_ms: backoff.as_millis() as u64,
+        });
+        log::warn!(
+            "attempt {attempt} of '{}' failed, retrying in {}ms",
+            req.name(),
+            backoff.as_millis()
+        );
+        if !interrupt::sleep(backoff) {
+            return Err(KuiperError::Cancelled);
+        }
+    }
+}
 
-    let response = client.execute(request).unwrap();
+/// Randomizes `delay` by up to `percent` percent in either direction, so that clients that
+/// failed at the same time don't retry at the same time.
+fn with_jitter(delay: Duration, percent: u32) -> Duration {
+    if percent == 0 {
+        return delay;
+    }
+    // a randomly keyed hasher is random enough for spreading out retries
+    let random = RandomState::new().build_hasher().finish();
+    let factor =
+        1.0 + f64::from(percent.min(100)) / 100.0 * ((random % 2001) as f64 / 1000.0 - 1.0);
+    delay.mul_f64(factor)
+}
 
+pub(crate) fn print_response(req: &Request, response: &Response, limits: BodyLimits) {
     println!("{}", req.name());
     println!("{}", response.status());
-    println!("{}", response.text().unwrap());
+    if let Some(timings) = response.timings() {
+        log::debug!("timings: {timings}");
+    }
+    let converted = limits
+        .output_format
+        .and_then(|format| match format.convert(response.body()) {
+            Ok(converted) => Some(converted),
+            Err(e) => {
+                log::warn!("printing the body of '{}' as is: '{e}'", req.name());
+                None
+            }
+        });
+    let body = converted.as_deref().unwrap_or(response.body());
+    // the events have been printed as they arrived
+    let streamed = limits.stream && response.content_type().is_some_and(sse::is_event_stream);
+    match limits.head_bytes {
+        _ if streamed => {}
+        Some(head_bytes) if head_bytes < body.len() => {
+            let end = (0..=head_bytes)
+                .rev()
+                .find(|idx| body.is_char_boundary(*idx))
+                .unwrap_or(0);
+            println!("{}", &body[..end]);
+            println!("... ({} more bytes)", body.len() - end);
+        }
+        _ => println!("{}", body),
+    }
+    if req.graphql().is_some() {
+        for error in graphql::errors(response.body()) {
+            eprintln!("GraphQL error: {error}");
+        }
+    }
 }
@@ -0,0 +1,66 @@
+use crate::{config_dir, failure};
+use libkuiper::templates::{self, Template};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct NewArgs {
+    /// Name of the template, either a directory in `~/.config/kuiper/templates/` or a built-in
+    /// template.
+    #[arg(short, long, default_value = "crud")]
+    template: String,
+    /// Name of the resource, which replaces `{{resource}}` in the paths and contents of the
+    /// template files.
+    #[arg(short, long)]
+    resource: String,
+    /// Directory to create the requests in. Defaults to a directory named after the resource.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn new(
+    dir: &Path,
+    NewArgs {
+        template,
+        resource,
+        output,
+    }: NewArgs,
+) -> bool {
+    let user_template = config_dir().join("templates").join(&template);
+    let loaded = if user_template.is_dir() {
+        match Template::load(&user_template) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("failed to load template {user_template:?}: '{e}'");
+                failure::record_error(&e);
+                return false;
+            }
+        }
+    } else if let Some(builtin) = Template::builtin(&template) {
+        builtin
+    } else {
+        eprintln!(
+            "no template named '{template}' in {:?}, and the built-in templates are: {}",
+            user_template.parent().unwrap_or(&user_template),
+            templates::BUILTIN.join(", ")
+        );
+        return false;
+    };
+
+    let output = output.unwrap_or_else(|| dir.join(&resource));
+    match loaded.generate(&resource, &output) {
+        Ok(paths) => {
+            for path in paths {
+                println!(
+                    "created {}",
+                    path.strip_prefix(dir).unwrap_or(&path).display()
+                );
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("failed to generate requests from template '{template}': '{e}'");
+            failure::record_error(&e);
+            false
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::failure;
+use libkuiper::normalize::Normalizer;
+use std::{fs, path::PathBuf};
+
+#[derive(clap::Args)]
+pub struct NormalizeArgs {
+    /// JSON file to normalize.
+    file: PathBuf,
+    /// Replace the value at this JSONPath with `<masked>`. Can be given several times.
+    #[arg(long)]
+    mask: Vec<String>,
+    /// Leave out the value at this JSONPath. Can be given several times.
+    #[arg(long)]
+    ignore: Vec<String>,
+    /// Overwrite the file instead of printing the normalized JSON.
+    #[arg(short, long)]
+    in_place: bool,
+}
+
+pub fn normalize(
+    NormalizeArgs {
+        file,
+        mask,
+        ignore,
+        in_place,
+    }: NormalizeArgs,
+) -> bool {
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("failed to read {file:?}: '{e}'");
+            return false;
+        }
+    };
+    let normalizer = mask
+        .into_iter()
+        .fold(Normalizer::new(), Normalizer::with_mask);
+    let normalizer = ignore.into_iter().fold(normalizer, Normalizer::with_ignore);
+    let normalized = match normalizer.normalize_json(&contents) {
+        Ok(normalized) => normalized,
+        Err(e) => {
+            eprintln!("failed to normalize {file:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    if in_place {
+        if let Err(e) = fs::write(&file, normalized + "\n") {
+            eprintln!("failed to write {file:?}: '{e}'");
+            return false;
+        }
+    } else {
+        println!("{normalized}");
+    }
+    true
+}
@@ -0,0 +1,147 @@
+use crate::{data_dir, failure};
+use libkuiper::remote::Remotes;
+use std::{path::PathBuf, process::Command};
+
+#[derive(clap::Subcommand)]
+pub enum RemoteCommand {
+    /// Add a git repository of requests, which can then be run with `kuiper run NAME:path`.
+    Add { name: String, url: String },
+    /// Remove a remote and its cached checkout.
+    Remove { name: String },
+    /// List all remotes.
+    List,
+    /// Fetch the latest version of a remote, or of every remote if no name is given.
+    Update { name: Option<String> },
+}
+
+pub fn remote(command: RemoteCommand) -> bool {
+    let Some(mut remotes) = load() else {
+        return false;
+    };
+
+    match command {
+        RemoteCommand::Add { name, url } => {
+            if let Err(e) = remotes.add(&name, &url) {
+                eprintln!("failed to add remote: '{e}'");
+                failure::record_error(&e);
+                return false;
+            }
+            save(&remotes) && checkout(&name, &url).is_some()
+        }
+        RemoteCommand::Remove { name } => {
+            if remotes.remove(&name).is_none() {
+                eprintln!("no remote named '{name}'");
+                return false;
+            }
+            let dir = checkout_dir(&name);
+            if dir.exists() {
+                if let Err(e) = std::fs::remove_dir_all(&dir) {
+                    eprintln!("failed to remove checkout {dir:?}: '{e}'");
+                }
+            }
+            save(&remotes)
+        }
+        RemoteCommand::List => {
+            for (name, url) in remotes.iter() {
+                println!("{name}\t{url}");
+            }
+            true
+        }
+        RemoteCommand::Update { name } => {
+            let mut success = true;
+            for (remote, url) in remotes.iter() {
+                if name.as_ref().is_none_or(|name| name == remote) {
+                    success &= checkout(remote, url).is_some();
+                }
+            }
+            if let Some(name) = name.filter(|name| remotes.get(name).is_none()) {
+                eprintln!("no remote named '{name}'");
+                return false;
+            }
+            success
+        }
+    }
+}
+
+/// Splits `remote:path/to/request` into the checkout of the remote, updated to its latest
+/// version, and the path within it.
+///
+/// Returns `None` if `path` does not start with the name of a remote, and `Some(None)` if it does
+/// but the remote could not be checked out.
+pub(crate) fn resolve(path: &str) -> Option<Option<(PathBuf, &str)>> {
+    let remotes = load()?;
+    let (name, path) = remotes.split(path)?;
+    let url = remotes.get(name).expect("split only returns known remotes");
+    Some(checkout(name, url).map(|dir| (dir, path)))
+}
+
+fn load() -> Option<Remotes> {
+    match Remotes::load(data_dir().join("remotes.json")) {
+        Ok(remotes) => Some(remotes),
+        Err(e) => {
+            eprintln!("failed to load remotes: '{e}'");
+            failure::record_error(&e);
+            None
+        }
+    }
+}
+
+fn save(remotes: &Remotes) -> bool {
+    match remotes.save() {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("failed to save remotes {:?}: '{e}'", remotes.path());
+            failure::record_error(&e);
+            false
+        }
+    }
+}
+
+fn checkout_dir(name: &str) -> PathBuf {
+    data_dir().join("remotes").join(name)
+}
+
+/// Clones the remote, or pulls the latest changes if it has been cloned before, and returns the
+/// directory of the checkout.
+///
+/// If pulling fails, the previously cached checkout is used.
+fn checkout(name: &str, url: &str) -> Option<PathBuf> {
+    let dir = checkout_dir(name);
+    if dir.join(".git").exists() {
+        if !git(Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["pull", "--ff-only", "--quiet"]))
+        {
+            log::warn!("failed to update remote '{name}', using cached version");
+        }
+        return Some(dir);
+    }
+
+    log::info!("cloning remote '{name}' from {url}");
+    if let Some(parent) = dir.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            eprintln!("failed to create {parent:?}: '{e}'");
+            return None;
+        }
+    }
+    if git(Command::new("git")
+        .args(["clone", "--depth", "1", "--quiet", url])
+        .arg(&dir))
+    {
+        Some(dir)
+    } else {
+        eprintln!("failed to clone remote '{name}' from {url}");
+        None
+    }
+}
+
+fn git(command: &mut Command) -> bool {
+    match command.status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("failed to run git: '{e}'");
+            false
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{
+    auth::load_session, data_dir, failure, load_config, print_response, send_request, BodyLimits,
+    ConnectionArgs,
+};
+use libkuiper::{history::History, Context, Exchange, Request, Response};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct ReplayArgs {
+    /// Path to an exchange saved with `--save-exchange`, or the id of an entry in the history.
+    /// Lists the history when left out.
+    exchange: Option<String>,
+    /// Decrypt this SOPS or age encrypted file, for the `{{secret:NAME}}` values that were
+    /// redacted from the history entry.
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+    #[command(flatten)]
+    limits: BodyLimits,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// The history of requests sent by `kuiper run`.
+pub(crate) fn history() -> History {
+    History::open(data_dir().join("history"))
+}
+
+pub fn replay(
+    dir: &Path,
+    ReplayArgs {
+        exchange,
+        secrets,
+        limits,
+        connection,
+    }: ReplayArgs,
+) -> bool {
+    match exchange {
+        None => list_history(),
+        Some(path) if Path::new(&path).is_file() => {
+            replay_exchange(dir, Path::new(&path), limits, connection)
+        }
+        Some(id) => replay_history(dir, &id, secrets.as_deref(), limits, connection),
+    }
+}
+
+fn list_history() -> bool {
+    let entries = match history().entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("failed to read the history: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    for entry in &entries {
+        println!(
+            "{:<24}{:<32}{:<8}{}",
+            entry.id(),
+            entry.sent_at(),
+            entry
+                .status()
+                .map_or_else(|| "-".to_string(), |status| status.to_string()),
+            entry.request_name()
+        );
+    }
+    if entries.is_empty() {
+        println!("no requests have been sent yet");
+    }
+    true
+}
+
+fn replay_exchange(
+    dir: &Path,
+    path: &Path,
+    limits: BodyLimits,
+    connection: ConnectionArgs,
+) -> bool {
+    let exchange = match Exchange::load(path) {
+        Ok(exchange) => exchange,
+        Err(e) => {
+            eprintln!("failed to load exchange {path:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    let environment = exchange.environment();
+    println!(
+        "replaying exchange recorded at {} with kuiper {} on {}/{}",
+        environment.recorded_at(),
+        environment.kuiper_version(),
+        environment.os(),
+        environment.arch()
+    );
+    let sent = send(dir, &exchange.request(), limits, connection).is_some();
+    if sent {
+        println!(
+            "recorded status was {}, took {}ms",
+            exchange.response().status(),
+            exchange.response().duration().as_millis()
+        );
+    }
+    sent
+}
+
+/// Interpolates the request of the history entry `id` with the values recorded in it, and sends
+/// it again. Values that were redacted are resolved from the session, `secrets` and the
+/// environment as usual.
+fn replay_history(
+    dir: &Path,
+    id: &str,
+    secrets: Option<&Path>,
+    limits: BodyLimits,
+    connection: ConnectionArgs,
+) -> bool {
+    let entry = match history().load(id) {
+        Ok(entry) => entry,
+        Err(e) => {
+            eprintln!("failed to load history entry: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let Some(session) = load_session(None, false) else {
+        return false;
+    };
+    let secrets = match secrets.map(libkuiper::secrets::load).transpose() {
+        Ok(secrets) => secrets.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("failed to load secrets: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let Some(config) = load_config(dir) else {
+        return false;
+    };
+
+    println!(
+        "replaying '{}' as it was sent at {}",
+        entry.request_name(),
+        entry.sent_at()
+    );
+    for placeholder in entry.snapshot().redacted() {
+        log::info!("{{{{{placeholder}}}}} was redacted, using its current value");
+    }
+    let context = Context::from(&session)
+        .with_secrets(secrets)
+        .with_hosts(config.hosts().to_vec())
+        .with_uri_normalization(config.uri_normalization())
+        .with_snapshot(entry.snapshot());
+    let request = match Request::find_with_context(entry.request_name(), &context) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!(
+                "failed to parse request with name: {:?}: '{e}'",
+                entry.request_name()
+            );
+            failure::record_error(&e);
+            return false;
+        }
+    };
+    let sent = send(dir, &request, limits, connection).is_some();
+    if let (true, Some(status)) = (sent, entry.status()) {
+        println!("recorded status was {status}");
+    }
+    sent
+}
+
+/// Sends `request` with a client configured for `dir`, and prints its response.
+fn send(
+    dir: &Path,
+    request: &Request,
+    limits: BodyLimits,
+    connection: ConnectionArgs,
+) -> Option<Response> {
+    let config = load_config(dir)?;
+    let client = connection.client(&config)?;
+    match send_request(&client, request, limits) {
+        Ok(response) => {
+            print_response(request, &response, limits);
+            Some(response)
+        }
+        Err(e) => {
+            eprintln!("failed to send request '{}': '{e}'", request.name());
+            failure::record_error(&e);
+            None
+        }
+    }
+}
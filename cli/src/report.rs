@@ -0,0 +1,171 @@
+use libkuiper::{Attempt, Timings};
+use serde_json::json;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// The result of sending a single request, as shown in summaries.
+#[derive(Clone)]
+pub struct Record {
+    pub name: String,
+    pub status: Option<u16>,
+    pub duration: Option<Duration>,
+    /// Failed assertions, or the error that prevented the request from being sent.
+    pub failures: Vec<String>,
+    /// The redirects, retries, token fetches and poll attempts before the response.
+    pub attempts: Vec<Attempt>,
+    /// How long each phase of getting the response took.
+    pub timings: Option<Timings>,
+}
+
+impl Record {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Reporter {
+    /// Emit GitHub Actions error annotations, and append a summary to `$GITHUB_STEP_SUMMARY`.
+    Github,
+}
+
+/// Reports `records` with `reporter`, and writes a markdown summary to `summary` if given.
+pub fn emit(records: &[Record], reporter: Option<Reporter>, summary: Option<&Path>) {
+    if let Some(Reporter::Github) = reporter {
+        for record in records.iter().filter(|record| !record.passed()) {
+            println!("{}", github_annotation(record));
+        }
+        if let Some(step_summary) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+            if let Err(e) = append(Path::new(&step_summary), &markdown(records)) {
+                eprintln!("failed to write GitHub step summary: '{e}'");
+            }
+        }
+    }
+
+    if let Some(summary) = summary {
+        if let Err(e) = std::fs::write(summary, markdown(records)) {
+            eprintln!("failed to write summary {summary:?}: '{e}'");
+        }
+    }
+}
+
+fn append(path: &Path, contents: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+/// Formats a failed record as a GitHub Actions `::error` workflow command.
+fn github_annotation(record: &Record) -> String {
+    let file = std::env::current_dir()
+        .ok()
+        .and_then(|dir| {
+            Path::new(&record.name)
+                .strip_prefix(dir)
+                .ok()
+                .map(|p| p.display().to_string())
+        })
+        .unwrap_or_else(|| record.name.clone());
+    format!(
+        "::error file={},title={}::{}",
+        escape_property(&file),
+        escape_property(&format!("kuiper: {}", record.name)),
+        escape_data(&record.failures.join("\n"))
+    )
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Renders `records` as JSON, for `--json`.
+pub fn json(records: &[Record]) -> String {
+    serde_json::to_string_pretty(&results(records)).expect("results should serialize to JSON")
+}
+
+/// The results of `records`, as printed by `--json` and given to the reporters of plugins.
+pub fn results(records: &[Record]) -> serde_json::Value {
+    let passed = records.iter().filter(|record| record.passed()).count();
+    let results: Vec<_> = records
+        .iter()
+        .map(|record| {
+            json!({
+                "name": record.name,
+                "passed": record.passed(),
+                "status": record.status,
+                "duration_ms": record.duration.map(|duration| duration.as_millis() as u64),
+                "failures": record.failures,
+                "attempts": record.attempts,
+                "timings": record.timings.as_ref().map(timings),
+            })
+        })
+        .collect();
+    json!({
+        "passed": passed,
+        "failed": records.len() - passed,
+        "results": results,
+    })
+}
+
+/// The phases of `timings` in milliseconds, with `null` for the DNS lookup and connecting when
+/// an open connection was reused.
+fn timings(timings: &Timings) -> serde_json::Value {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    json!({
+        "dns_ms": timings.dns().map(ms),
+        "connect_ms": timings.connect().map(ms),
+        "first_byte_ms": ms(timings.time_to_first_byte()),
+        "download_ms": ms(timings.download()),
+    })
+}
+
+/// Renders `records` as a markdown table followed by the details of every failure.
+pub fn markdown(records: &[Record]) -> String {
+    let passed = records.iter().filter(|record| record.passed()).count();
+    let mut out = String::from("## kuiper results\n\n");
+    out.push_str(&format!(
+        "{} passed, {} failed\n\n",
+        passed,
+        records.len() - passed
+    ));
+    out.push_str("| request | status | time | result |\n| --- | --- | --- | --- |\n");
+    for record in records {
+        out.push_str(&format!(
+            "| `{}` | {} | {} | {} |\n",
+            record.name,
+            record
+                .status
+                .map_or_else(|| "-".to_string(), |s| s.to_string()),
+            record
+                .duration
+                .map_or_else(|| "-".to_string(), |d| format!("{}ms", d.as_millis())),
+            if record.passed() {
+                "✅ passed"
+            } else {
+                "❌ failed"
+            }
+        ));
+    }
+
+    let failed: Vec<_> = records.iter().filter(|record| !record.passed()).collect();
+    if !failed.is_empty() {
+        out.push_str("\n### Failures\n");
+        for record in failed {
+            out.push_str(&format!("\n`{}`\n\n", record.name));
+            for failure in &record.failures {
+                out.push_str(&format!("- {failure}\n"));
+            }
+        }
+    }
+
+    out
+}
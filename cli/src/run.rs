@@ -0,0 +1,1355 @@
+use crate::{
+    auth::load_session,
+    failure, interrupt, load_config, locate_requests, print_response, replay,
+    report::{self, Record, Reporter},
+    send_with_retries, BodyLimits, ConnectionArgs, Selection,
+};
+use libkuiper::{
+    dataset, diff, envfile, history::History, hosts::HostDefaults, plugins::Plugins,
+    poll::Condition, seed::Seed, uri::UriNormalization, AssertionOutcome, Attempt, Client, Config,
+    Context, Exchange, Failure, KuiperError, KuiperResult, Request, Response, Session,
+    SessionStore,
+};
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(clap::Args)]
+pub struct RunArgs {
+    path: String,
+    #[command(flatten)]
+    selection: Selection,
+    #[command(flatten)]
+    options: RunOptions,
+}
+
+/// How requests are sent and their results reported, by `kuiper run` and `kuiper test`.
+#[derive(clap::Args)]
+pub struct RunOptions {
+    /// Only send the request itself, without resolving its `depends_on` first.
+    #[arg(long)]
+    no_deps: bool,
+    /// Set a header of the request, but not of its dependencies, as `Name: value`. An empty value
+    /// removes the header, even if it is set in a `headers.json`.
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    headers: Vec<(String, Option<String>)>,
+    /// Set a query param of the request, but not of its dependencies, as `name=value`. An empty
+    /// value removes the param.
+    #[arg(short = 'p', long = "param", value_parser = parse_param)]
+    params: Vec<(String, Option<String>)>,
+    /// Send the request, but not its dependencies, again until this condition on its response
+    /// body holds, like `$.status == 'done'`. Uses the interval and timeout of the `poll` block
+    /// of the request if it has one.
+    #[arg(long)]
+    poll_until: Option<Condition>,
+    /// File where captured values are stored between runs. The values are kept in the OS keyring
+    /// when it is available, in an entry named after this file.
+    #[arg(long)]
+    session: Option<PathBuf>,
+    /// Store the session in a plaintext file even if the OS keyring is available.
+    #[arg(long)]
+    plaintext_session: bool,
+    /// Decrypt this SOPS or age encrypted file, and make its values available through
+    /// `{{secret:NAME}}`.
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+    /// Run the commands of `{{cmd:COMMAND}}` values and use what they print. Only use this with
+    /// requests you trust, since they can run any command.
+    #[arg(long)]
+    allow_cmd: bool,
+    /// Send the request once per row in this `.csv` or `.json` file, using `{{data:COLUMN}}`.
+    #[arg(long)]
+    data: Option<PathBuf>,
+    /// Interpolate `{{env:...}}` with the values of this environment before the environment
+    /// variables of the process. An environment is an env file, given as a path or as NAME for
+    /// `NAME.env`, `environments/NAME.env` or `environments/NAME.json` in the request directory.
+    #[arg(long, conflicts_with = "envs")]
+    env: Option<String>,
+    /// Set `{{env:NAME}}` and `{{var:NAME}}` as `NAME=value`, taking precedence over the
+    /// environment, the environment variables of the process and `variables.json`.
+    #[arg(long = "var", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+    /// Send the request in each of these comma-separated environments, and compare the responses.
+    #[arg(long, value_delimiter = ',', conflicts_with = "data")]
+    envs: Vec<String>,
+    /// Send the rows of `--data` concurrently.
+    #[arg(long, requires = "data")]
+    parallel: bool,
+    /// Report results in a format understood by a CI system.
+    #[arg(long, value_enum)]
+    reporter: Option<Reporter>,
+    /// Report results with this reporter of a plugin, and print what it makes of them.
+    #[arg(long)]
+    plugin_reporter: Option<String>,
+    /// Pass the body of every response through this filter of a plugin before it is printed,
+    /// checked and captured from. Can be given several times, to apply filters in order.
+    #[arg(long = "filter")]
+    filters: Vec<String>,
+    /// Write a markdown summary of the results to this file.
+    #[arg(long)]
+    summary: Option<PathBuf>,
+    /// Print the results as JSON, including the redirects, retries, token fetches and poll
+    /// attempts before each response, instead of the responses.
+    #[arg(long, conflicts_with = "envs")]
+    json: bool,
+    /// Save every exchange (the sent request and its response) as a file in this directory.
+    #[arg(long)]
+    save_exchange: Option<PathBuf>,
+    /// Retry requests that fail to send or get a 5xx response this many times.
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Delay before the first retry, doubled for every following retry.
+    #[arg(long, default_value_t = 500)]
+    retry_delay_ms: u64,
+    /// Randomize every delay before a retry by up to this many percent.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=100))]
+    retry_jitter: u32,
+    /// Retry non-idempotent requests even if their `Idempotency-Key` header has been removed.
+    #[arg(long)]
+    force_retry: bool,
+    /// The most time every request may take together with its dependencies, like `10s`. The time
+    /// left is shared evenly by the requests that haven't been sent yet, unless they set a
+    /// `timeout_ms` of their own.
+    #[arg(long, value_parser = parse_deadline)]
+    deadline: Option<Duration>,
+    /// Write the values captured by the requests in this run to this env file.
+    #[arg(long)]
+    capture_to: Option<PathBuf>,
+    /// Warn about headers that override a different value set in an ancestor directory.
+    #[arg(long)]
+    warn_shadowing: bool,
+    /// Replace the `body_file` golden files of the requests in this run with the bodies of their
+    /// responses.
+    #[arg(long)]
+    update_golden: bool,
+    /// Generate `{{expr:uuid}}` and random values from this seed, and freeze `{{expr:now}}`, so
+    /// that every run sends the same requests.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// The time `{{expr:now}}` is frozen at with `--seed`, instead of `2000-01-01T00:00:00Z`.
+    #[arg(long, requires = "seed")]
+    now: Option<jiff::Timestamp>,
+    #[command(flatten)]
+    limits: BodyLimits,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+}
+
+pub fn run(
+    dir: &Path,
+    RunArgs {
+        path,
+        selection,
+        options,
+    }: RunArgs,
+) -> bool {
+    let Some(request_paths) = locate_requests(dir, &path, selection) else {
+        return false;
+    };
+    run_requests(dir, request_paths, HashMap::new(), options)
+}
+
+/// Sends the requests at `request_paths`, each after the requests it depends on, with
+/// `variables` as values of `{{var:NAME}}`, which `--var` takes precedence over.
+pub fn run_requests(
+    dir: &Path,
+    request_paths: Vec<PathBuf>,
+    variables: HashMap<String, String>,
+    RunOptions {
+        no_deps,
+        headers,
+        params,
+        poll_until,
+        session,
+        plaintext_session,
+        secrets,
+        allow_cmd,
+        data,
+        env,
+        vars,
+        envs,
+        parallel,
+        reporter,
+        plugin_reporter,
+        filters,
+        summary,
+        json,
+        save_exchange,
+        retries,
+        retry_delay_ms,
+        retry_jitter,
+        force_retry,
+        deadline,
+        capture_to,
+        warn_shadowing,
+        update_golden,
+        seed,
+        now,
+        limits,
+        connection,
+    }: RunOptions,
+) -> bool {
+    let mut runs = Vec::with_capacity(request_paths.len());
+    for request_path in request_paths {
+        let mut order = if no_deps {
+            vec![request_path]
+        } else {
+            match Request::dependency_order(&request_path) {
+                Ok(order) => order,
+                Err(e) => {
+                    eprintln!("failed to resolve dependencies of {request_path:?}: '{e}'");
+                    failure::record_error(&e);
+                    return false;
+                }
+            }
+        };
+        let target = order
+            .pop()
+            .expect("dependency order should end with the request itself");
+        runs.push((order, target));
+    }
+
+    let Some(session) = load_session(session, plaintext_session) else {
+        return false;
+    };
+    let env_name = env;
+    let (env, session) = match &env_name {
+        Some(name) => match (load_environment(dir, name), env_session(&session, name)) {
+            (Some(env), Some(session)) => (env, session),
+            _ => return false,
+        },
+        None => (HashMap::new(), session),
+    };
+
+    let secrets = match secrets.as_deref().map(libkuiper::secrets::load).transpose() {
+        Ok(secrets) => secrets.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("failed to load secrets: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    let Some(config) = load_config(dir) else {
+        return false;
+    };
+    let Some(mut client) = connection.client_in(&config, env_name.as_deref()) else {
+        return false;
+    };
+    if config.cookies() {
+        client = client.with_cookie_jar(session.cookies().clone());
+    }
+    let Some(secrets) = environment_secrets(&config, env_name.as_deref(), &secrets) else {
+        return false;
+    };
+    // every environment of `--envs` connects with settings, and has secrets, of its own
+    let mut env_clients = HashMap::new();
+    let mut env_secrets = HashMap::new();
+    for name in &envs {
+        let (Some(client), Some(secrets)) = (
+            connection.client_in(&config, Some(name)),
+            environment_secrets(&config, Some(name), &secrets),
+        ) else {
+            return false;
+        };
+        env_clients.insert(name.clone(), client);
+        env_secrets.insert(name.clone(), secrets);
+    }
+    let seed = seed.map(|seed| {
+        let seed = Seed::new(seed);
+        now.map_or(seed, |now| seed.with_now(now))
+    });
+    // seeds are derived from paths relative to the collection, which are the same on every machine
+    let root = config
+        .path()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .or_else(|| dir.canonicalize().ok())
+        .unwrap_or_else(|| dir.to_path_buf());
+    let plugins = match Plugins::discover(dir) {
+        Ok(plugins) => plugins,
+        Err(e) => {
+            eprintln!("failed to load plugins: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    let mut runner = Runner {
+        client: client.with_plugins(plugins.clone()),
+        env_clients,
+        env_secrets,
+        hosts: config.hosts().to_vec(),
+        uri_normalization: config.uri_normalization(),
+        seed: seed.map(|seed| (seed, root)),
+        session,
+        session_headers: config.session_headers().to_vec(),
+        secrets,
+        env,
+        vars: vars.into_iter().collect(),
+        variables,
+        prompts: HashMap::new(),
+        allow_cmd,
+        plugins,
+        filters,
+        save_exchange,
+        history: Some(replay::history()),
+        retry: Retry {
+            retries,
+            delay: Duration::from_millis(retry_delay_ms),
+            jitter: retry_jitter,
+            force: force_retry,
+        },
+        budget: deadline.map(Budget::new),
+        // events printed as they arrive would end up in the middle of the JSON
+        limits: BodyLimits {
+            stream: limits.stream && !json,
+            ..limits
+        },
+        quiet: json,
+        warn_shadowing,
+        update_golden,
+        overrides: Overrides {
+            headers,
+            params,
+            poll_until,
+        },
+        captured: HashMap::new(),
+        target_response: None,
+        records: Vec::new(),
+    };
+    let mut success = true;
+    for (order, target) in &runs {
+        if interrupt::interrupted() {
+            break;
+        }
+        success &= if envs.is_empty() {
+            runner.run_all(order, target, data.as_deref(), parallel)
+        } else {
+            runner.run_matrix(dir, &envs, order, target)
+        };
+    }
+
+    report::emit(&runner.records, reporter, summary.as_deref());
+    if json {
+        println!("{}", report::json(&runner.records));
+    }
+    if let Some(capture_to) = capture_to {
+        let values = runner
+            .captured
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        if let Err(e) = envfile::write(&capture_to, values) {
+            eprintln!("failed to write captures to {capture_to:?}: '{e}'");
+            failure::record_error(&e);
+            success = false;
+        }
+    }
+    if let Some(name) = plugin_reporter {
+        let results = report::results(&runner.records);
+        match runner
+            .plugins
+            .reporter(&name)
+            .and_then(|plugin| plugin.report(&name, &results))
+        {
+            Ok(output) => println!("{output}"),
+            Err(e) => {
+                eprintln!("failed to report results with '{name}': '{e}'");
+                failure::record_error(&e);
+                success = false;
+            }
+        }
+    }
+    success
+}
+
+/// State shared by every request sent during a single run.
+#[derive(Clone)]
+struct Runner {
+    client: Client,
+    /// The clients of the environments of `--envs`, by name.
+    env_clients: HashMap<String, Client>,
+    /// The secrets of the environments of `--envs`, by name.
+    env_secrets: HashMap<String, HashMap<String, String>>,
+    hosts: Vec<HostDefaults>,
+    uri_normalization: UriNormalization,
+    /// The seed of the run, and the directory that the paths of requests are made relative to
+    /// when deriving a seed for each of them.
+    seed: Option<(Seed, PathBuf)>,
+    session: Session,
+    /// Response headers that are kept in the session, and sent with later requests to the same
+    /// host.
+    session_headers: Vec<String>,
+    secrets: HashMap<String, String>,
+    /// Values of the selected environment, used before the environment variables of the process.
+    env: HashMap<String, String>,
+    /// Values given with `--var`, used before the environment and `variables.json`.
+    vars: HashMap<String, String>,
+    /// Values of `{{var:NAME}}`, like the variables of a suite, used before `variables.json`.
+    variables: HashMap<String, String>,
+    /// Answers to the prompts of the requests sent so far, so that each is only asked once.
+    prompts: HashMap<String, String>,
+    allow_cmd: bool,
+    /// The plugins of the collection, which add namespaces, filters and reporters.
+    plugins: Plugins,
+    /// The filters of plugins that response bodies are passed through, in order.
+    filters: Vec<String>,
+    save_exchange: Option<PathBuf>,
+    /// Where every sent request is recorded, with the values it was interpolated with.
+    history: Option<History>,
+    retry: Retry,
+    /// The time left for the requests of the current flow, with `--deadline`.
+    budget: Option<Budget>,
+    limits: BodyLimits,
+    /// Leaves responses and assertion outcomes out of the output, for results that are printed
+    /// at the end of the run instead.
+    quiet: bool,
+    warn_shadowing: bool,
+    update_golden: bool,
+    overrides: Overrides,
+    /// Values captured by, or already in the session for, the requests sent so far.
+    captured: HashMap<String, String>,
+    /// The last response to a request that was not sent as a dependency.
+    target_response: Option<Response>,
+    records: Vec<Record>,
+}
+
+/// Headers and params set with `--header` and `--param`, where `None` removes them, and the
+/// condition set with `--poll-until`.
+#[derive(Clone, Default)]
+struct Overrides {
+    headers: Vec<(String, Option<String>)>,
+    params: Vec<(String, Option<String>)>,
+    poll_until: Option<Condition>,
+}
+
+impl Overrides {
+    fn apply(&self, request: &mut Request) {
+        for (name, value) in &self.headers {
+            request.override_header(name, value.clone());
+        }
+        for (name, value) in &self.params {
+            request.override_param(name, value.clone());
+        }
+        if let Some(until) = &self.poll_until {
+            request.override_poll_until(until.clone());
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Retry {
+    retries: u32,
+    delay: Duration,
+    /// How many percent every delay is randomized by.
+    jitter: u32,
+    force: bool,
+}
+
+impl Retry {
+    /// Returns how many times `request` may be retried.
+    ///
+    /// Non-idempotent requests get an `Idempotency-Key` header attached, so that every attempt is
+    /// recognizable as the same logical request, and are not retried at all if the header has
+    /// been removed unless retries are forced.
+    fn retries_for(&self, request: &mut Request) -> u32 {
+        if self.retries == 0 || request.is_idempotent() || request.ensure_idempotency_key() {
+            self.retries
+        } else if self.force {
+            log::warn!("retrying '{}' without an idempotency key", request.name());
+            self.retries
+        } else {
+            log::warn!(
+                "not retrying '{}' without an idempotency key, use --force-retry to retry anyway",
+                request.name()
+            );
+            0
+        }
+    }
+}
+
+/// The time that a request and its dependencies, a flow, may take together, and where it went.
+#[derive(Clone)]
+struct Budget {
+    deadline: Duration,
+    start: Instant,
+    /// How many requests of the flow haven't been sent yet.
+    steps_left: usize,
+    /// The requests sent so far, how long each took and how many times it was retried.
+    spent: Vec<(String, Duration, usize)>,
+}
+
+impl Budget {
+    fn new(deadline: Duration) -> Self {
+        Self {
+            deadline,
+            start: Instant::now(),
+            steps_left: 0,
+            spent: Vec::new(),
+        }
+    }
+
+    /// Starts over for a flow of `steps` requests.
+    fn restart(&mut self, steps: usize) {
+        self.start = Instant::now();
+        self.steps_left = steps;
+        self.spent.clear();
+    }
+
+    fn left(&self) -> Duration {
+        self.deadline.saturating_sub(self.start.elapsed())
+    }
+
+    /// When `request` has to be done by: its own timeout, or an even share of the time left,
+    /// but never after the deadline of the flow.
+    fn deadline_for(&self, request: &Request) -> Instant {
+        let left = self.left();
+        let share = request
+            .timeout()
+            .unwrap_or(left / self.steps_left.max(1) as u32);
+        Instant::now() + share.min(left)
+    }
+
+    fn spend(&mut self, name: &str, spent: Duration, retries: usize) {
+        self.steps_left = self.steps_left.saturating_sub(1);
+        self.spent.push((name.to_string(), spent, retries));
+    }
+
+    /// Describes where the time went, request by request.
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "deadline of {}ms exceeded after {}ms:",
+            self.deadline.as_millis(),
+            self.start.elapsed().as_millis()
+        );
+        let width = self.spent.iter().map(|(name, ..)| name.len()).max();
+        for (name, spent, retries) in &self.spent {
+            summary.push_str(&format!(
+                "\n  {name:<width$}  {:>6}ms",
+                spent.as_millis(),
+                width = width.unwrap_or_default()
+            ));
+            if *retries > 0 {
+                summary.push_str(&format!(" ({retries} retries)"));
+            }
+        }
+        if self.steps_left > 0 {
+            summary.push_str(&format!("\n  {} requests not sent", self.steps_left));
+        }
+        summary
+    }
+}
+
+impl Runner {
+    /// Sends `request` with retries, and again until its `poll` condition holds if it has one.
+    fn send(&self, request: &mut Request) -> KuiperResult<Response> {
+        let retries = self.retry.retries_for(request);
+        let request = &*request;
+        let deadline = self
+            .budget
+            .as_ref()
+            .map(|budget| budget.deadline_for(request));
+        let send = || {
+            send_with_retries(
+                &self.client,
+                request,
+                retries,
+                self.retry.delay,
+                self.retry.jitter,
+                deadline,
+                self.limits,
+            )
+        };
+        match request.poll() {
+            Some(poll) => poll.run(send, |attempt, response| {
+                log::info!(
+                    "attempt {attempt} of '{}' got {}, '{}' does not hold yet",
+                    request.name(),
+                    response.status(),
+                    poll.until()
+                );
+                !interrupt::interrupted()
+            }),
+            None => send(),
+        }
+    }
+
+    /// Sends the dependencies in `order`, and then `target`, either once or once for every row
+    /// in the dataset at `data`.
+    ///
+    /// With a budget, the flow stops once its deadline has passed, and a summary of where the
+    /// time went is printed.
+    fn run_all(
+        &mut self,
+        order: &[PathBuf],
+        target: &Path,
+        data: Option<&Path>,
+        parallel: bool,
+    ) -> bool {
+        if let Some(budget) = &mut self.budget {
+            budget.restart(order.len() + 1);
+        }
+        let success = order.iter().all(|step| {
+            !interrupt::interrupted()
+                && self.within_budget(step)
+                && self.send_and_capture(step, true)
+        }) && !interrupt::interrupted()
+            && self.within_budget(target)
+            && match data {
+                Some(data) => self.run_dataset(target, data, parallel),
+                None => self.send_and_capture(target, false),
+            };
+        if let Some(budget) = &self.budget {
+            if !success && budget.left().is_zero() {
+                eprintln!("{}", budget.summary());
+            }
+        }
+        success
+    }
+
+    /// Returns `false`, and records the request at `path` as failed, if the deadline of the
+    /// flow has passed before it could be sent.
+    fn within_budget(&mut self, path: &Path) -> bool {
+        let Some(budget) = &self.budget else {
+            return true;
+        };
+        if !budget.left().is_zero() {
+            return true;
+        }
+        let error = KuiperError::DeadlineExceeded(budget.deadline);
+        eprintln!("not sending {path:?}: '{error}'");
+        self.records
+            .push(error_record(path.display().to_string(), error));
+        false
+    }
+
+    /// Sends the dependencies in `order` and `target` in every environment in `envs`, each with
+    /// a session of its own, and compares the responses to `target` with the response in the
+    /// first environment.
+    fn run_matrix(
+        &mut self,
+        dir: &Path,
+        envs: &[String],
+        order: &[PathBuf],
+        target: &Path,
+    ) -> bool {
+        let mut results = Vec::with_capacity(envs.len());
+        let mut success = true;
+        for name in envs {
+            if interrupt::interrupted() {
+                break;
+            }
+            let (Some(env), Some(session)) = (
+                load_environment(dir, name),
+                env_session(&self.session, name),
+            ) else {
+                success = false;
+                continue;
+            };
+
+            println!("[{name}]");
+            let mut runner = Runner {
+                client: self.env_clients.get(name).unwrap_or(&self.client).clone(),
+                secrets: self.env_secrets.get(name).unwrap_or(&self.secrets).clone(),
+                env,
+                session,
+                captured: HashMap::new(),
+                target_response: None,
+                records: Vec::new(),
+                ..self.clone()
+            };
+            success &= runner.run_all(order, target, None, false);
+            self.records
+                .extend(runner.records.into_iter().map(|mut record| {
+                    record.name = format!("{} [{name}]", record.name);
+                    record
+                }));
+            results.push((name.as_str(), runner.target_response));
+        }
+
+        print_comparison(&results);
+        success
+    }
+
+    /// Sends the request at `path`, checks its `expect` block and stores the values it captures
+    /// in the session.
+    ///
+    /// Returns `false` if sending or capturing failed, or if an assertion did not hold.
+    fn send_and_capture(&mut self, path: &Path, skip_if_satisfied: bool) -> bool {
+        let mut request = match self.find_request(path, None) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("failed to parse request with name: {path:?}: '{e}'");
+                failure::record_error(&e);
+                return false;
+            }
+        };
+
+        if !skip_if_satisfied {
+            self.overrides.apply(&mut request);
+        }
+        self.report_warnings(&request);
+
+        if skip_if_satisfied && self.session.satisfies(&request) {
+            log::info!(
+                "skipping '{}', its captures are already in the session",
+                request.name()
+            );
+            if let Some(budget) = &mut self.budget {
+                budget.spend(request.name(), Duration::ZERO, 0);
+            }
+            for name in request.captures().keys() {
+                if let Some(value) = self.session.get(name) {
+                    self.captured.insert(name.clone(), value.to_string());
+                }
+            }
+            return true;
+        }
+
+        self.session.attach_headers(&mut request);
+        let start = Instant::now();
+        let result = self.send(&mut request);
+        if let Some(budget) = &mut self.budget {
+            let retries = result.as_ref().map_or(0, |response| {
+                response
+                    .attempts()
+                    .iter()
+                    .filter(|attempt| matches!(attempt, Attempt::Retry { .. }))
+                    .count()
+            });
+            budget.spend(request.name(), start.elapsed(), retries);
+        }
+        self.record_history(&request, &result);
+        let response = match result.and_then(|response| self.filter(response)) {
+            Ok(response) => {
+                if !self.quiet {
+                    print_response(&request, &response, self.limits);
+                }
+                response
+            }
+            Err(e) => {
+                eprintln!("failed to send request '{}': '{e}'", request.name());
+                self.records
+                    .push(error_record(request.name().to_string(), e));
+                return false;
+            }
+        };
+        self.save_exchange(&request, &response);
+        let kept_headers = self
+            .session
+            .keep_headers(&request, &self.session_headers, &response);
+        let kept_cookies = self
+            .client
+            .cookie_jar()
+            .is_some_and(|jar| self.session.set_cookies(jar));
+        if kept_headers || kept_cookies {
+            if let Err(e) = self.session.save() {
+                eprintln!("failed to save session {:?}: '{e}'", self.session.path());
+            }
+        }
+        if !skip_if_satisfied {
+            self.target_response = Some(response.clone());
+        }
+        if self.update_golden {
+            if let Some(expect) = request.expect() {
+                match expect.update_golden(&response) {
+                    Ok(Some(golden)) => log::info!("updated golden file {golden:?}"),
+                    Ok(None) => {}
+                    Err(e) => eprintln!(
+                        "failed to update golden file of '{}': '{e}'",
+                        request.name()
+                    ),
+                }
+            }
+        }
+
+        let record = match check_expectations(&request, &response) {
+            Ok(outcomes) => {
+                if !self.quiet {
+                    for outcome in &outcomes {
+                        println!("{outcome}");
+                    }
+                }
+                response_record(request.name().to_string(), &response, &outcomes)
+            }
+            Err(e) => {
+                eprintln!(
+                    "failed to check expectations of '{}': '{e}'",
+                    request.name()
+                );
+                error_record(request.name().to_string(), e)
+            }
+        };
+        let passed = record.passed();
+        self.records.push(record);
+
+        match request.capture_values(&response) {
+            Ok(captured) if !captured.is_empty() => {
+                self.session.extend_values(captured.clone());
+                // values other than strings are captured as JSON
+                for name in captured.keys() {
+                    let value = &self.session.captures()[name];
+                    self.captured.insert(name.clone(), value.clone());
+                }
+                if let Err(e) = self.session.save() {
+                    eprintln!("failed to save session {:?}: '{e}'", self.session.path());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("failed to capture values from '{}': '{e}'", request.name());
+                failure::record_error(&e);
+                return false;
+            }
+        }
+
+        passed
+    }
+
+    /// Passes the body of `response` through the filters of the run, in order.
+    fn filter(&self, mut response: Response) -> KuiperResult<Response> {
+        for name in &self.filters {
+            let body = self.plugins.filter(name)?.filter(name, &response)?;
+            response = response.with_body(body);
+        }
+        Ok(response)
+    }
+
+    /// Sends the request at `path` once for every row in the dataset at `data`, and prints a
+    /// table with the result of each row.
+    ///
+    /// Rows that have not been sent when the run is interrupted are left out of the table.
+    fn run_dataset(&mut self, path: &Path, data: &Path, parallel: bool) -> bool {
+        let rows = match dataset::load(data) {
+            Ok(rows) => rows,
+            Err(e) => {
+                eprintln!("failed to load dataset {data:?}: '{e}'");
+                failure::record_error(&e);
+                return false;
+            }
+        };
+
+        if let Some(row) = rows.first() {
+            if let Ok(mut request) = self.find_request(path, Some(row)) {
+                self.overrides.apply(&mut request);
+                self.report_warnings(&request);
+            }
+        }
+
+        let this = &*self;
+        type RowResult = KuiperResult<(Response, Vec<AssertionOutcome>)>;
+        let send_row = |row: dataset::Row| -> RowResult {
+            let context = this.context(path, Some(&row));
+            let mut request = Request::find_with_context(path, &context)?;
+            this.overrides.apply(&mut request);
+            let result = this.send(&mut request);
+            this.record_history(&request, &result);
+            let response = result.and_then(|response| this.filter(response))?;
+            this.save_exchange(&request, &response);
+            let outcomes = check_expectations(&request, &response)?;
+            Ok((response, outcomes))
+        };
+        let send_row = &send_row;
+
+        let send_rows = |rows: &[dataset::Row], offset: usize| -> Vec<(usize, RowResult)> {
+            rows.iter()
+                .cloned()
+                .enumerate()
+                .take_while(|_| !interrupt::interrupted())
+                .map(|(idx, row)| (offset + idx, send_row(row)))
+                .collect()
+        };
+        let send_rows = &send_rows;
+
+        let results: Vec<_> = if parallel {
+            let workers = thread::available_parallelism().map_or(4, |n| n.get());
+            let chunk_size = rows.len().div_ceil(workers).max(1);
+            thread::scope(|s| {
+                let handles: Vec<_> = rows
+                    .chunks(chunk_size)
+                    .enumerate()
+                    .map(|(idx, chunk)| s.spawn(move || send_rows(chunk, idx * chunk_size)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("dataset worker panicked"))
+                    .collect()
+            })
+        } else {
+            send_rows(&rows, 0)
+        };
+
+        if !self.quiet {
+            println!("{:<6}{:<8}{:<10}assertions", "row", "status", "time");
+        }
+        let mut success = true;
+        for (idx, result) in results {
+            let name = format!("{} [row {idx}]", path.display());
+            let record = match result {
+                Ok((response, outcomes)) if !self.quiet => {
+                    let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+                    println!(
+                        "{:<6}{:<8}{:<10}{}",
+                        idx,
+                        response.status(),
+                        format!("{}ms", response.duration().as_millis()),
+                        if failed == 0 {
+                            "ok".to_string()
+                        } else {
+                            format!("{failed} failed")
+                        }
+                    );
+                    response_record(name, &response, &outcomes)
+                }
+                Ok((response, outcomes)) => response_record(name, &response, &outcomes),
+                Err(e) => {
+                    if !self.quiet {
+                        println!("{:<6}error: {e}", idx);
+                    }
+                    error_record(name, e)
+                }
+            };
+            success &= record.passed();
+            self.records.push(record);
+        }
+
+        success
+    }
+
+    /// Finds the request at `path`, interpolated with `row` if given.
+    ///
+    /// When running in a terminal, values that the request requires but that are not set, and
+    /// its `{{prompt:NAME}}` and `{{prompt_secret:NAME}}` values, are prompted for, and kept for
+    /// the rest of the run.
+    fn find_request(&mut self, path: &Path, row: Option<&dataset::Row>) -> KuiperResult<Request> {
+        let result = match Request::find_with_context(path, &self.context(path, row)) {
+            Err(KuiperError::MissingRequired(names)) if io::stdin().is_terminal() => {
+                for name in names {
+                    let value = prompt(&name)?;
+                    self.env.insert(name, value);
+                }
+                Request::find_with_context(path, &self.context(path, row))
+            }
+            result => result,
+        };
+        match result {
+            Err(KuiperError::MissingPrompts(prompts)) if io::stdin().is_terminal() => {
+                for asked in prompts {
+                    let value = if asked.is_secret() {
+                        prompt_hidden(asked.name())?
+                    } else {
+                        prompt(asked.name())?
+                    };
+                    self.prompts.insert(asked.name().to_string(), value);
+                }
+                Request::find_with_context(path, &self.context(path, row))
+            }
+            result => result,
+        }
+    }
+
+    /// The context that the request at `path` is interpolated with, for `row` if given.
+    fn context(&self, path: &Path, row: Option<&dataset::Row>) -> Context {
+        let mut env = self.env.clone();
+        env.extend(self.vars.clone());
+        let mut variables = self.variables.clone();
+        variables.extend(self.vars.clone());
+        let mut context = Context::from(&self.session)
+            .with_secrets(self.secrets.clone())
+            .with_env(env)
+            .with_variables(variables)
+            .with_prompts(self.prompts.clone())
+            .with_commands_allowed(self.allow_cmd)
+            .with_hosts(self.hosts.clone())
+            .with_uri_normalization(self.uri_normalization);
+        for resolver in self.plugins.resolvers() {
+            context = context.with_resolver(resolver);
+        }
+        if let Some((seed, root)) = &self.seed {
+            let mut key = path
+                .strip_prefix(root)
+                .unwrap_or(path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if let Some(row) = row {
+                let mut columns: Vec<_> = row.iter().collect();
+                columns.sort();
+                for (column, value) in columns {
+                    key.push_str(&format!("\n{column}={value}"));
+                }
+            }
+            context = context.with_seed(seed.derive(&key));
+        }
+        match row {
+            Some(row) => context.with_data(row.clone()),
+            None => context,
+        }
+    }
+
+    fn report_warnings(&self, request: &Request) {
+        if let Some(message) = request.deprecated() {
+            log::warn!("'{}' is deprecated: {message}", request.name());
+        }
+        for (name, value) in request.headers() {
+            match (request.header_origin(name), value) {
+                (Some(origin), Some(_)) => log::debug!("header '{name}' was set by {origin}"),
+                (Some(origin), None) => log::debug!("header '{name}' was removed by {origin}"),
+                (None, _) => {}
+            }
+        }
+        if self.warn_shadowing {
+            for warning in request.warnings() {
+                log::warn!("{warning}");
+            }
+        }
+    }
+
+    fn record_history(&self, request: &Request, result: &KuiperResult<Response>) {
+        if let Some(history) = &self.history {
+            let status = result.as_ref().ok().map(Response::status);
+            match history.record(request, status) {
+                Ok(entry) => log::info!("recorded '{}' as '{}'", request.name(), entry.id()),
+                Err(e) => log::warn!(
+                    "failed to record '{}' in the history: '{e}'",
+                    request.name()
+                ),
+            }
+        }
+    }
+
+    fn save_exchange(&self, request: &Request, response: &Response) {
+        if let Some(dir) = &self.save_exchange {
+            match Exchange::new(request, response).save_in(dir) {
+                Ok(path) => log::info!("saved exchange to {path:?}"),
+                Err(e) => eprintln!("failed to save exchange in {dir:?}: '{e}'"),
+            }
+        }
+    }
+}
+
+/// Sends the request at `path`, after its dependencies, in the environment `env`, or with only the
+/// environment variables of the process if `None`, without printing the responses. Returns the
+/// response to the request, or `None` if it could not be sent.
+pub(crate) fn send_in_environment(
+    dir: &Path,
+    path: &Path,
+    env: Option<&str>,
+    session: &Session,
+    secrets: &HashMap<String, String>,
+    config: &Config,
+    client: &Client,
+) -> Option<Response> {
+    let mut order = match Request::dependency_order(path) {
+        Ok(order) => order,
+        Err(e) => {
+            eprintln!("failed to resolve dependencies of {path:?}: '{e}'");
+            failure::record_error(&e);
+            return None;
+        }
+    };
+    let target = order
+        .pop()
+        .expect("dependency order should end with the request itself");
+    let secrets = environment_secrets(config, env, secrets)?;
+    let (env, session) = match env {
+        Some(name) => (load_environment(dir, name)?, env_session(session, name)?),
+        None => (HashMap::new(), session.clone()),
+    };
+    let plugins = match Plugins::discover(dir) {
+        Ok(plugins) => plugins,
+        Err(e) => {
+            eprintln!("failed to load plugins: '{e}'");
+            failure::record_error(&e);
+            return None;
+        }
+    };
+
+    let mut runner = Runner {
+        client: client.clone().with_plugins(plugins.clone()),
+        env_clients: HashMap::new(),
+        env_secrets: HashMap::new(),
+        hosts: config.hosts().to_vec(),
+        uri_normalization: config.uri_normalization(),
+        seed: None,
+        session,
+        session_headers: config.session_headers().to_vec(),
+        secrets,
+        env,
+        vars: HashMap::new(),
+        variables: HashMap::new(),
+        prompts: HashMap::new(),
+        allow_cmd: false,
+        plugins,
+        filters: Vec::new(),
+        save_exchange: None,
+        history: None,
+        retry: Retry {
+            retries: 0,
+            delay: Duration::ZERO,
+            jitter: 0,
+            force: false,
+        },
+        budget: None,
+        limits: BodyLimits::default(),
+        quiet: true,
+        warn_shadowing: false,
+        update_golden: false,
+        overrides: Overrides::default(),
+        captured: HashMap::new(),
+        target_response: None,
+        records: Vec::new(),
+    };
+    runner.run_all(&order, &target, None, false);
+    runner.target_response
+}
+
+/// Fetches the secrets of the environment `name` from the cloud secret managers it has in the
+/// config, and adds `secrets`, which take precedence. Prints why and returns `None` if a secret
+/// could not be fetched.
+fn environment_secrets(
+    config: &Config,
+    name: Option<&str>,
+    secrets: &HashMap<String, String>,
+) -> Option<HashMap<String, String>> {
+    let Some(environment) = name.and_then(|name| config.environment(name)) else {
+        return Some(secrets.clone());
+    };
+    match libkuiper::secrets::fetch(environment.secrets()) {
+        Ok(mut fetched) => {
+            fetched.extend(secrets.clone());
+            Some(fetched)
+        }
+        Err(e) => {
+            eprintln!(
+                "failed to fetch the secrets of '{}': '{e}'",
+                name.unwrap_or_default()
+            );
+            failure::record_error(&e);
+            None
+        }
+    }
+}
+
+/// Reads the env file of the environment `name`, which is either `name` itself, or `NAME.env`,
+/// `environments/NAME.env` or `environments/NAME.json` in `dir`.
+pub(crate) fn load_environment(dir: &Path, name: &str) -> Option<HashMap<String, String>> {
+    read_environment(&environment_path(dir, name)?)
+}
+
+/// The env file of the environment `name`, as found by [`load_environment`].
+pub(crate) fn environment_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let candidates = [
+        PathBuf::from(name),
+        dir.join(format!("{name}.env")),
+        dir.join("environments").join(format!("{name}.env")),
+        dir.join("environments").join(format!("{name}.json")),
+    ];
+    let path = candidates.into_iter().find(|path| path.is_file());
+    if path.is_none() {
+        eprintln!("no env file found for environment '{name}'");
+        failure::record(Failure::Resolve);
+    }
+    path
+}
+
+/// Reads the env file at `path`, which is read as JSON if it has a `.json` extension.
+pub(crate) fn read_environment(path: &Path) -> Option<HashMap<String, String>> {
+    let result = if path.extension().is_some_and(|ext| ext == "json") {
+        envfile::read_json(path)
+    } else {
+        envfile::read(path)
+    };
+    match result {
+        Ok(env) => Some(env),
+        Err(e) => {
+            eprintln!("failed to read env file {path:?}: '{e}'");
+            failure::record_error(&e);
+            None
+        }
+    }
+}
+
+/// Loads the session of the environment `env`, stored next to `session` with the name of the
+/// environment added, so that values captured in one environment are not sent to another.
+fn env_session(session: &Session, env: &str) -> Option<Session> {
+    let path = session.path().with_extension(format!("{env}.json"));
+    load_session(Some(path), session.store() == SessionStore::File)
+}
+
+/// Prints the status, time and body of the response in every environment side by side, with
+/// the differences between each body and the body in the first environment.
+fn print_comparison(results: &[(&str, Option<Response>)]) {
+    let Some((reference_name, reference)) = results.first() else {
+        return;
+    };
+    let reference_body = reference
+        .as_ref()
+        .map(|response| diff::normalize_json(response.body()));
+
+    println!();
+    println!("{:<16}{:<8}{:<10}body", "env", "status", "time");
+    let mut diffs = Vec::new();
+    for (name, response) in results {
+        let Some(response) = response else {
+            println!("{name:<16}not sent");
+            continue;
+        };
+        let body = diff::normalize_json(response.body());
+        let summary = match &reference_body {
+            Some(reference_body) if name != reference_name => {
+                let lines: Vec<_> = diff::lines(reference_body, &body)
+                    .into_iter()
+                    .filter(|line| !matches!(line, diff::Line::Same(_)))
+                    .map(|line| line.to_string())
+                    .collect();
+                if lines.is_empty() {
+                    "same".to_string()
+                } else {
+                    let summary = format!("{} lines differ from {reference_name}", lines.len());
+                    diffs.push((name, lines));
+                    summary
+                }
+            }
+            _ => "-".to_string(),
+        };
+        println!(
+            "{:<16}{:<8}{:<10}{}",
+            name,
+            response.status(),
+            format!("{}ms", response.duration().as_millis()),
+            summary
+        );
+    }
+
+    for (name, lines) in diffs {
+        println!();
+        println!("--- {reference_name}");
+        println!("+++ {name}");
+        for line in lines {
+            println!("{line}");
+        }
+    }
+}
+
+/// The record of a response, whose failed outcomes are recorded as an HTTP failure if it has an
+/// error status, and as an assertion failure otherwise.
+fn response_record(name: String, response: &Response, outcomes: &[AssertionOutcome]) -> Record {
+    if outcomes.iter().any(|outcome| !outcome.passed()) {
+        failure::record(if response.status() >= 400 {
+            Failure::Http
+        } else {
+            Failure::Assertion
+        });
+    }
+    Record {
+        name,
+        status: Some(response.status()),
+        duration: Some(response.duration()),
+        failures: outcomes
+            .iter()
+            .filter(|outcome| !outcome.passed())
+            .map(ToString::to_string)
+            .collect(),
+        attempts: response.attempts().to_vec(),
+        timings: response.timings().copied(),
+    }
+}
+
+fn error_record(name: String, error: KuiperError) -> Record {
+    failure::record_error(&error);
+    Record {
+        name,
+        status: None,
+        duration: None,
+        failures: vec![error.to_string()],
+        attempts: Vec::new(),
+        timings: None,
+    }
+}
+
+/// Parses a `--header` value, `Name: value`.
+fn parse_header(s: &str) -> Result<(String, Option<String>), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'Name: value', got '{s}'"))?;
+    let value = value.trim();
+    Ok((
+        name.trim().to_string(),
+        (!value.is_empty()).then(|| value.to_string()),
+    ))
+}
+
+/// Parses a `--param` value, `name=value`.
+fn parse_param(s: &str) -> Result<(String, Option<String>), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected 'name=value', got '{s}'"))?;
+    Ok((
+        name.to_string(),
+        (!value.is_empty()).then(|| value.to_string()),
+    ))
+}
+
+/// Parses a `--deadline` value, like `10s`.
+fn parse_deadline(s: &str) -> Result<Duration, String> {
+    libkuiper::poll::parse_duration(s).map_err(|e| e.to_string())
+}
+
+/// Parses a `--var` value, `NAME=value`, where the value may be empty.
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected 'NAME=value', got '{s}'")),
+    }
+}
+
+/// Asks for the value of `name` on the terminal.
+fn prompt(name: &str) -> io::Result<String> {
+    eprint!("{name}: ");
+    io::stderr().flush()?;
+    let mut value = String::new();
+    io::stdin().read_line(&mut value)?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Asks for the value of `name` on the terminal, without showing what is typed.
+fn prompt_hidden(name: &str) -> io::Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+
+        let fd = io::stdin().as_raw_fd();
+        // SAFETY: `termios` is plain data, and is filled in by `tcgetattr` before it is used.
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } == 0 {
+            let echoing = termios;
+            termios.c_lflag &= !libc::ECHO;
+            termios.c_lflag |= libc::ECHONL;
+            // SAFETY: `fd` is the terminal that `termios` was read from.
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) };
+            let value = prompt(name);
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &echoing) };
+            return value;
+        }
+    }
+    log::warn!("the answer to '{name}' will be shown as it is typed");
+    prompt(name)
+}
+
+fn check_expectations(
+    request: &Request,
+    response: &Response,
+) -> KuiperResult<Vec<AssertionOutcome>> {
+    let mut outcomes = match request.expect() {
+        Some(expect) => expect.evaluate(response)?,
+        None => Vec::new(),
+    };
+    outcomes.extend(request.run_hooks(response));
+    Ok(outcomes)
+}
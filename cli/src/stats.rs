@@ -0,0 +1,81 @@
+use crate::failure;
+use libkuiper::stats::{self, Definition};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args)]
+pub struct StatsArgs {
+    /// The collection to summarize, instead of the current directory (or `-d`).
+    path: Option<PathBuf>,
+    /// How many of the largest bodies to list.
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+}
+
+/// Prints statistics about the requests in the collection, and what in it is unused,
+/// undocumented or untested.
+pub fn stats(dir: &Path, StatsArgs { path, top }: StatsArgs) -> bool {
+    let root = path.map_or_else(|| dir.to_path_buf(), |path| dir.join(path));
+    let stats = match stats::stats(&root) {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("failed to read {root:?}: '{e}'");
+            failure::record_error(&e);
+            return false;
+        }
+    };
+
+    println!("{} requests", stats.requests());
+    print_counts("methods", stats.methods());
+    print_counts("hosts", stats.hosts());
+    print_counts("tags", stats.tags());
+
+    print_paths("invalid files", stats.invalid());
+    print_definitions("unused variables", stats.unused_variables());
+    print_definitions("headers never overridden", stats.unoverridden_headers());
+    if !stats.bodies().is_empty() {
+        println!("largest bodies");
+        for (path, len) in stats.bodies().iter().take(top) {
+            println!("  {}: {len} bytes", path.display());
+        }
+    }
+    print_paths("missing descriptions", stats.missing_descriptions());
+    print_paths("missing expectations", stats.missing_expectations());
+
+    true
+}
+
+fn print_counts(title: &str, counts: &BTreeMap<String, usize>) {
+    if counts.is_empty() {
+        return;
+    }
+    // the most common first
+    let mut counts: Vec<_> = counts.iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    println!("{title}");
+    for (name, count) in counts {
+        println!("  {name}: {count}");
+    }
+}
+
+fn print_definitions(title: &str, definitions: &[Definition]) {
+    if definitions.is_empty() {
+        return;
+    }
+    println!("{title} ({})", definitions.len());
+    for definition in definitions {
+        println!("  {}: {}", definition.path().display(), definition.name());
+    }
+}
+
+fn print_paths(title: &str, paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    println!("{title} ({})", paths.len());
+    for path in paths {
+        println!("  {}", path.display());
+    }
+}
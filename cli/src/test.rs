@@ -0,0 +1,185 @@
+use crate::{
+    failure, load_config,
+    run::{self, RunOptions},
+};
+use libkuiper::{affected, Failure, Request};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(clap::Args)]
+pub struct TestArgs {
+    /// The collection to run, instead of the current directory (or `-d`).
+    path: Option<PathBuf>,
+    /// Only run the requests affected by the files changed since this git revision, like
+    /// `origin/main`, including changes that are not committed yet.
+    #[arg(long)]
+    changed_since: Option<String>,
+    /// Only run the requests of this suite of `kuiper.toml`, in its order and with its variables.
+    #[arg(long)]
+    suite: Option<String>,
+    #[command(flatten)]
+    options: RunOptions,
+}
+
+/// Runs every request in the collection, or only the ones of a suite, or only the ones affected
+/// by changes since a revision.
+pub fn test(
+    dir: &Path,
+    TestArgs {
+        path,
+        changed_since,
+        suite,
+        options,
+    }: TestArgs,
+) -> bool {
+    let root = path.map_or_else(|| dir.to_path_buf(), |path| dir.join(path));
+    let suite = match &suite {
+        Some(name) => {
+            let Some(config) = load_config(&root) else {
+                return false;
+            };
+            let Some(suite) = config.suite(name) else {
+                eprintln!("no suite named '{name}' in kuiper.toml");
+                failure::record(Failure::Resolve);
+                return false;
+            };
+            match suite.requests() {
+                Ok(requests) => Some((requests, suite.variables().clone())),
+                Err(e) => {
+                    eprintln!("failed to find the requests of suite '{name}': '{e}'");
+                    failure::record_error(&e);
+                    return false;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let requests = match &changed_since {
+        Some(revision) => changed_files(&root, revision).and_then(|changed| {
+            match affected::affected_requests(&root, &changed) {
+                Ok(requests) => Some(requests),
+                Err(e) => {
+                    eprintln!("failed to find the requests affected by the changes: '{e}'");
+                    failure::record_error(&e);
+                    None
+                }
+            }
+        }),
+        None => match Request::search_paths(&root, "") {
+            Ok(requests) => Some(requests),
+            Err(e) => {
+                eprintln!("failed to search for requests in {root:?}: '{e}'");
+                failure::record_error(&e);
+                None
+            }
+        },
+    };
+    let Some(mut requests) = requests else {
+        return false;
+    };
+    let variables = match suite {
+        Some((suite_requests, variables)) => {
+            // the suite keeps its order, but only runs what is in the collection and affected
+            let selected: HashSet<_> = requests
+                .iter()
+                .filter_map(|path| path.canonicalize().ok())
+                .collect();
+            requests = suite_requests
+                .into_iter()
+                .filter(|path| {
+                    path.canonicalize()
+                        .is_ok_and(|path| selected.contains(&path))
+                })
+                .collect();
+            variables
+        }
+        None => HashMap::new(),
+    };
+
+    match &changed_since {
+        Some(revision) if requests.is_empty() => {
+            println!("no requests are affected by the changes since '{revision}'");
+            return true;
+        }
+        Some(revision) => log::info!(
+            "running {} requests affected by the changes since '{revision}'",
+            requests.len()
+        ),
+        None => log::info!("running {} requests", requests.len()),
+    }
+    run::run_requests(&root, requests, variables, options)
+}
+
+/// The files changed since `revision`, committed or not, and the files git doesn't track yet, as
+/// absolute paths.
+fn changed_files(dir: &Path, revision: &str) -> Option<Vec<PathBuf>> {
+    let top = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let top = PathBuf::from(top.trim());
+    let top = top.canonicalize().unwrap_or(top);
+    // changes on `revision` since the branch was made are not changes of the branch
+    let changed = git(
+        dir,
+        &["diff", "-z", "--name-only", "--merge-base", revision],
+    )?;
+    let untracked = git(
+        dir,
+        &[
+            "ls-files",
+            "-z",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+        ],
+    )?;
+    Some(
+        changed
+            .split('\0')
+            .chain(untracked.split('\0'))
+            .filter(|path| !path.is_empty())
+            .map(|path| top.join(path))
+            .collect(),
+    )
+}
+
+/// Runs git in `dir` and returns what it prints.
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    match Command::new("git").arg("-C").arg(dir).args(args).output() {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        Ok(output) => {
+            eprintln!(
+                "git {} failed: '{}'",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            failure::record(Failure::Resolve);
+            None
+        }
+        Err(e) => {
+            eprintln!("failed to run git: '{e}'");
+            failure::record(Failure::Resolve);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Args, Command};
+    use clap::Parser;
+
+    #[test]
+    fn changed_since_test() {
+        let args = Args::try_parse_from(["kuiper", "test", "--changed-since", "main"]).unwrap();
+        let Command::Test(args) = args.command else {
+            panic!("`kuiper test` should run the test command");
+        };
+        assert_eq!(args.changed_since.as_deref(), Some("main"));
+        assert_eq!(args.suite, None);
+    }
+}
@@ -0,0 +1,290 @@
+//! Finding the requests that changed files affect, so that a pipeline only has to run those.
+
+use crate::{lint, KuiperResult, Request};
+use log::warn;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The files that apply to every request in the directory they are in and below it.
+const INHERITED: [&str; 5] = [
+    "headers.json",
+    "variables.json",
+    "auth.json",
+    "kuiper.toml",
+    "kuiper.json",
+];
+
+/// The requests under `root` that are affected by the `changed` files, which are absolute paths,
+/// like the paths listed by `git diff --name-only` joined to the top level of the repository.
+///
+/// A request is affected if its own file changed, a `headers.json`, `variables.json`,
+/// `auth.json`, `kuiper.toml` or `kuiper.json` in its directory or above it changed, a file it
+/// reads changed, like a `{{file:PATH}}` of the request or of a `headers.json` above it, a file
+/// its body is read from, the query file of a GraphQL request, the `.proto` files or descriptor
+/// set of a gRPC call or its golden file, or if a request it depends on is affected.
+pub fn affected_requests(
+    root: impl AsRef<Path>,
+    changed: &[PathBuf],
+) -> KuiperResult<Vec<PathBuf>> {
+    let root = root.as_ref().canonicalize()?;
+    let changed: BTreeSet<PathBuf> = changed.iter().map(|path| normalize(path)).collect();
+    let inherited: Vec<&Path> = changed
+        .iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| INHERITED.contains(&name))
+        })
+        .filter_map(|path| path.parent())
+        .collect();
+
+    let mut placeholders = HashMap::new();
+    let mut dependencies = BTreeMap::new();
+    let mut affected = BTreeSet::new();
+    for path in Request::search_paths(&root, "")? {
+        let dir = path.parent().unwrap_or(&root).to_path_buf();
+        let request = match Request::find_uninterpolated(&path) {
+            Ok(request) => request,
+            Err(e) => {
+                // it is run anyway if it changed, and fails to tell what is wrong with it
+                warn!("only checking whether {path:?} itself changed: '{e}'");
+                if changed.contains(&path) {
+                    affected.insert(path);
+                }
+                continue;
+            }
+        };
+
+        let mut inputs = vec![path.clone()];
+        inputs.extend(body_files(&request).into_iter().map(|file| dir.join(file)));
+        inputs.extend(
+            request
+                .expect()
+                .and_then(|expect| expect.body_file())
+                .map(|file| dir.join(file)),
+        );
+        inputs.extend(files(&path, &dir, &mut placeholders));
+        // files of a `headers.json` are relative to it, and those of an `auth.json` to the request
+        for ancestor in path.ancestors().skip(1) {
+            inputs.extend(files(
+                &ancestor.join("headers.json"),
+                ancestor,
+                &mut placeholders,
+            ));
+            inputs.extend(files(&ancestor.join("auth.json"), &dir, &mut placeholders));
+        }
+
+        // the `.proto` files a gRPC call imports can be anywhere in its import paths
+        let import_paths = proto_import_paths(&request, &dir);
+        if inputs
+            .iter()
+            .any(|input| changed.contains(&normalize(input)))
+            || changed.iter().any(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "proto")
+                    && import_paths.iter().any(|dir| path.starts_with(dir))
+            })
+            || inherited
+                .iter()
+                .any(|inherited| path.starts_with(inherited))
+        {
+            affected.insert(path.clone());
+        }
+        dependencies.insert(path, request.dependencies().unwrap_or_default());
+    }
+
+    // requests are affected by the requests they depend on, which may depend on others in turn
+    loop {
+        let dependents: Vec<_> = dependencies
+            .iter()
+            .filter(|(path, depends_on)| {
+                !affected.contains(*path) && depends_on.iter().any(|d| affected.contains(d))
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+
+    Ok(affected.into_iter().collect())
+}
+
+/// The files that the body of `request` is read from, relative to its directory.
+fn body_files(request: &Request) -> Vec<&Path> {
+    let mut files = vec![];
+    files.extend(request.body_ndjson_file());
+    files.extend(request.body_file_binary());
+    files.extend(request.graphql().and_then(|graphql| graphql.query_file()));
+    if let Some(grpc) = request.grpc() {
+        files.extend(grpc.descriptor_set());
+        files.extend(grpc.proto());
+    }
+    files.extend(
+        request
+            .multipart()
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, part)| part.file()),
+    );
+    files
+}
+
+/// The directories that the `.proto` file of the gRPC call of `request` imports other files from,
+/// which are its `import_paths`, or else the directory of the file itself.
+fn proto_import_paths(request: &Request, dir: &Path) -> Vec<PathBuf> {
+    let Some(proto) = request.grpc().and_then(|grpc| grpc.proto()) else {
+        return Vec::new();
+    };
+    let import_paths = request
+        .grpc()
+        .map(|grpc| grpc.import_paths())
+        .unwrap_or_default();
+    if import_paths.is_empty() {
+        dir.join(proto)
+            .parent()
+            .map(normalize)
+            .into_iter()
+            .collect()
+    } else {
+        import_paths
+            .iter()
+            .map(|path| normalize(&dir.join(path)))
+            .collect()
+    }
+}
+
+/// The `{{file:PATH}}` values in the file at `path`, relative to `dir`. The placeholders of every
+/// file are kept in `placeholders`, since the same `headers.json` is above many requests.
+fn files(
+    path: &Path,
+    dir: &Path,
+    placeholders: &mut HashMap<PathBuf, Vec<String>>,
+) -> Vec<PathBuf> {
+    let names = placeholders.entry(path.to_path_buf()).or_insert_with(|| {
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        lint::placeholders(&contents)
+            .into_iter()
+            // files named after other values can't be known without interpolating
+            .filter(|p| p.namespace() == "file" && !p.name().contains("{{"))
+            .map(|p| p.name().to_string())
+            .collect()
+    });
+    names.iter().map(|name| dir.join(name)).collect()
+}
+
+/// Removes the `.` and `..` components of `path`, without looking at the file system, since
+/// changed files may have been deleted.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn affected_requests_test() {
+        let root = Path::new("../requests").canonicalize().unwrap();
+        let affected = |changed: &[&str]| {
+            let changed: Vec<_> = changed.iter().map(|path| root.join(path)).collect();
+            affected_requests(&root, &changed)
+                .unwrap()
+                .into_iter()
+                .map(|path| path.strip_prefix(&root).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            affected(&["dependencies/login.kuiper"]),
+            vec![
+                "dependencies/login.kuiper",
+                "dependencies/profile.kuiper",
+                "dependencies/settings.kuiper"
+            ]
+        );
+        assert_eq!(
+            affected(&["subdir/headers.json"]),
+            vec![
+                "subdir/request_in_subdir.kuiper",
+                "subdir/unset/unset.kuiper"
+            ]
+        );
+        assert_eq!(
+            affected(&["files/key.pem", "shared/./client.txt"]),
+            vec!["files/upload.kuiper", "shared/users/get_user.kuiper"]
+        );
+        // paths are compared without looking at the file system
+        assert_eq!(
+            affected(&["stats/refunds/../variables.json"]),
+            vec![
+                "stats/ledger.kuiper",
+                "stats/list_payments.kuiper",
+                "stats/refunds/create_refund.kuiper"
+            ]
+        );
+        assert!(affected(&["README.md", "subdir/notes.txt"]).is_empty());
+    }
+
+    /// The requests under `requests` affected by changes to the `changed` files in it.
+    fn affected_by(changed: &str) -> Vec<String> {
+        let root = Path::new("../requests").canonicalize().unwrap();
+        affected_requests(&root, &[root.join(changed)])
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn graphql_query_file_test() {
+        assert_eq!(
+            affected_by("graphql/get_user.graphql"),
+            vec!["graphql/get_user.kuiper"]
+        );
+    }
+
+    #[test]
+    fn grpc_proto_test() {
+        assert_eq!(
+            affected_by("grpc/orders.proto"),
+            vec!["grpc/get_order.kuiper"]
+        );
+        // files it imports are found in its import paths
+        assert_eq!(
+            affected_by("grpc/shop/v1/money.proto"),
+            vec!["grpc/get_order.kuiper"]
+        );
+    }
+
+    #[test]
+    fn body_file_binary_test() {
+        assert_eq!(
+            affected_by("binary/avatar.png"),
+            vec!["binary/upload_avatar.kuiper"]
+        );
+    }
+
+    #[test]
+    fn multipart_file_test() {
+        assert_eq!(
+            affected_by("multipart/report.csv"),
+            vec!["multipart/upload_report.kuiper"]
+        );
+    }
+}
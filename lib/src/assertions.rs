@@ -0,0 +1,1044 @@
+use crate::{jsonpath, normalize::Normalizer, KuiperError, KuiperResult, Response};
+use regex::Regex;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The `expect` (or `assertions`) block of a request, describing what a successful response
+/// looks like.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Expect {
+    #[serde(default)]
+    status: Option<u16>,
+    /// Assertions on header values, by header name. A `null` value asserts that the header is
+    /// absent.
+    #[serde(default)]
+    headers: HashMap<String, Option<HeaderAssertion>>,
+    #[serde(default)]
+    body: Vec<BodyAssertion>,
+    /// A golden file that the body must equal, relative to the request file.
+    #[serde(default)]
+    body_file: Option<PathBuf>,
+    /// JSONPaths that are masked (`mask`) or left out (`ignore`) when comparing a JSON body with
+    /// `body_file`, and when updating it.
+    #[serde(flatten)]
+    normalizer: Normalizer,
+    /// The number of bytes in the body.
+    #[serde(default)]
+    body_length: Option<u64>,
+    /// The hex-encoded SHA-256 digest of the body.
+    #[serde(default)]
+    body_sha256: Option<String>,
+    /// The type of file the body is, recognized by its first bytes.
+    #[serde(default)]
+    body_type: Option<BodyType>,
+    #[serde(default)]
+    max_duration_ms: Option<u64>,
+}
+
+/// An assertion on the value found at `path` in a JSON response body.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BodyAssertion {
+    path: String,
+    /// Inverts the result of the predicate.
+    #[serde(default)]
+    not: bool,
+    /// The type the value must have, see [`ValueType`].
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    value_type: Option<ValueType>,
+    #[serde(flatten)]
+    predicate: Predicate,
+}
+
+/// An assertion on the value of a header.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum HeaderAssertion {
+    /// The value matches this regex.
+    Pattern(String),
+    /// The value equals this value exactly.
+    Equals { equals: String },
+    /// The value matches this regex, like [`HeaderAssertion::Pattern`].
+    Matches { matches: String },
+}
+
+impl HeaderAssertion {
+    fn to_assertion(&self, name: &str) -> Assertion {
+        match self {
+            HeaderAssertion::Equals { equals } => Assertion::HeaderEq {
+                name: name.to_string(),
+                value: equals.clone(),
+            },
+            HeaderAssertion::Pattern(pattern) | HeaderAssertion::Matches { matches: pattern } => {
+                Assertion::HeaderRegex {
+                    name: name.to_string(),
+                    pattern: pattern.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// A single check of a response, with the same semantics as the `expect` block of a request,
+/// for embedding kuiper in other test harnesses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Assertion {
+    /// The status of the response is this status.
+    StatusEq(u16),
+    /// The header `name` has exactly this value.
+    HeaderEq { name: String, value: String },
+    /// The value of the header `name` matches the regex `pattern`.
+    HeaderRegex { name: String, pattern: String },
+    /// The response has no header `name`.
+    HeaderAbsent(String),
+    /// The value at the JSONPath `path` of the JSON body has the type `value_type`, if it is set,
+    /// and satisfies `predicate`, or doesn't if `not` is set.
+    JsonPathMatches {
+        path: String,
+        value_type: Option<ValueType>,
+        predicate: Predicate,
+        not: bool,
+    },
+    /// The body is this many bytes long.
+    BodyLength(u64),
+    /// The body has this hex-encoded SHA-256 digest, compared case-insensitively.
+    BodySha256(String),
+    /// The body starts with the magic bytes of this type of file.
+    BodyType(BodyType),
+    /// The response took at most this long, redirects included.
+    MaxDuration(Duration),
+}
+
+impl Assertion {
+    /// Evaluates the assertion against `response`.
+    ///
+    /// Returns an error if the assertion itself is invalid, e.g. has a malformed regex or
+    /// JSONPath. A failed assertion is reported in the returned outcome.
+    pub fn evaluate(&self, response: &Response) -> KuiperResult<AssertionOutcome> {
+        self.evaluate_with(response, &OnceCell::new())
+    }
+
+    /// Like [`Assertion::evaluate`], parsing the body into `body` only if it wasn't yet, so
+    /// that it is parsed once for every assertion on it.
+    fn evaluate_with(
+        &self,
+        response: &Response,
+        body: &OnceCell<Option<Value>>,
+    ) -> KuiperResult<AssertionOutcome> {
+        match self {
+            Assertion::StatusEq(expected) => {
+                let actual = response.status();
+                Ok(AssertionOutcome::new(
+                    format!("status is {expected}"),
+                    (actual != *expected).then(|| format!("got {actual}")),
+                ))
+            }
+            Assertion::HeaderEq { name, value } => {
+                let actual = response.header(name);
+                Ok(header_outcome(
+                    format!("header '{name}' equals '{value}'"),
+                    actual,
+                    actual.map(|actual| actual == value),
+                ))
+            }
+            Assertion::HeaderRegex { name, pattern } => {
+                let regex = Regex::new(pattern)
+                    .map_err(|_| KuiperError::InvalidRegex(pattern.to_string()))?;
+                let actual = response.header(name);
+                Ok(header_outcome(
+                    format!("header '{name}' matches '{pattern}'"),
+                    actual,
+                    actual.map(|actual| regex.is_match(actual)),
+                ))
+            }
+            Assertion::HeaderAbsent(name) => Ok(AssertionOutcome::new(
+                format!("header '{name}' is absent"),
+                response.header(name).map(|value| format!("got '{value}'")),
+            )),
+            Assertion::JsonPathMatches {
+                path,
+                value_type,
+                predicate,
+                not,
+            } => {
+                let body = body.get_or_init(|| serde_json::from_str(response.body()).ok());
+                evaluate_json_path(path, *value_type, predicate, *not, body.as_ref())
+            }
+            Assertion::BodyLength(expected) => {
+                let actual = response.bytes().len() as u64;
+                Ok(AssertionOutcome::new(
+                    format!("body is {expected} bytes long"),
+                    (actual != *expected).then(|| format!("got {actual} bytes")),
+                ))
+            }
+            Assertion::BodySha256(expected) => {
+                let actual: String = digest(&SHA256, response.bytes())
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+                Ok(AssertionOutcome::new(
+                    format!("body has SHA-256 digest {expected}"),
+                    (!actual.eq_ignore_ascii_case(expected.trim()))
+                        .then(|| format!("got {actual}")),
+                ))
+            }
+            Assertion::BodyType(expected) => {
+                let bytes = response.bytes();
+                let failure = match BodyType::sniff(bytes) {
+                    Some(actual) if actual == *expected => None,
+                    Some(actual) => Some(format!("got {actual}")),
+                    None if bytes.is_empty() => Some("body is empty".to_string()),
+                    None => {
+                        let start: Vec<_> = bytes
+                            .iter()
+                            .take(8)
+                            .map(|byte| format!("{byte:02x}"))
+                            .collect();
+                        Some(format!("got unknown bytes {}", start.join(" ")))
+                    }
+                };
+                Ok(AssertionOutcome::new(
+                    format!("body is {expected}"),
+                    failure,
+                ))
+            }
+            Assertion::MaxDuration(max) => {
+                let actual = response.duration().as_millis();
+                Ok(AssertionOutcome::new(
+                    format!("duration is at most {}ms", max.as_millis()),
+                    (actual > max.as_millis()).then(|| format!("took {actual}ms")),
+                ))
+            }
+        }
+    }
+}
+
+fn header_outcome(
+    description: String,
+    actual: Option<&str>,
+    holds: Option<bool>,
+) -> AssertionOutcome {
+    AssertionOutcome::new(
+        description,
+        match (holds, actual) {
+            (Some(true), _) => None,
+            (_, Some(value)) => Some(format!("got '{value}'")),
+            (_, None) => Some("header is missing".to_string()),
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Predicate {
+    /// The path exists (`true`) or does not exist (`false`).
+    Exists(bool),
+    /// The value equals this value.
+    Equals(Value),
+    /// The array, object or string has this many elements.
+    Length(usize),
+    /// The array contains this element, or the string contains this substring.
+    Contains(Value),
+    /// The number is greater than this number.
+    Gt(Number),
+    /// The number is greater than or equal to this number.
+    Gte(Number),
+    /// The number is less than this number.
+    Lt(Number),
+    /// The number is less than or equal to this number.
+    Lte(Number),
+}
+
+/// The JSON type of a value. Strings that encode a number or boolean, like `"42"` or `"true"`,
+/// are converted to it when the type is `number`, `integer` or `boolean`, since some APIs
+/// serialize numbers as strings. Values are never converted otherwise.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    String,
+    Number,
+    /// A number without a fractional part.
+    Integer,
+    Boolean,
+    Array,
+    Object,
+    Null,
+}
+
+impl ValueType {
+    /// The type of `value`, which is `Number` for every number.
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::String(_) => ValueType::String,
+            Value::Number(_) => ValueType::Number,
+            Value::Bool(_) => ValueType::Boolean,
+            Value::Array(_) => ValueType::Array,
+            Value::Object(_) => ValueType::Object,
+            Value::Null => ValueType::Null,
+        }
+    }
+
+    /// `value` as this type, converting string-encoded numbers and booleans, or `None` if it
+    /// doesn't have this type.
+    fn coerce(self, value: &Value) -> Option<Value> {
+        let integer = |n: &Number| n.is_i64() || n.is_u64();
+        match (self, value) {
+            (ValueType::Number | ValueType::Integer, Value::String(s)) => s
+                .parse::<Number>()
+                .ok()
+                .filter(|n| self == ValueType::Number || integer(n))
+                .map(Value::Number),
+            (ValueType::Integer, Value::Number(n)) => integer(n).then(|| value.clone()),
+            (ValueType::Boolean, Value::String(s)) => match s.as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => (ValueType::of(value) == self).then(|| value.clone()),
+        }
+    }
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::String => write!(f, "a string"),
+            ValueType::Number => write!(f, "a number"),
+            ValueType::Integer => write!(f, "an integer"),
+            ValueType::Boolean => write!(f, "a boolean"),
+            ValueType::Array => write!(f, "an array"),
+            ValueType::Object => write!(f, "an object"),
+            ValueType::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// A type of binary file, written as its media type, like `image/png`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    #[serde(rename = "image/png")]
+    Png,
+    #[serde(rename = "image/jpeg")]
+    Jpeg,
+    #[serde(rename = "image/gif")]
+    Gif,
+    #[serde(rename = "image/webp")]
+    Webp,
+    #[serde(rename = "application/pdf")]
+    Pdf,
+    #[serde(rename = "application/zip")]
+    Zip,
+    #[serde(rename = "application/gzip")]
+    Gzip,
+    #[serde(rename = "application/wasm")]
+    Wasm,
+}
+
+impl BodyType {
+    const ALL: [BodyType; 8] = [
+        BodyType::Png,
+        BodyType::Jpeg,
+        BodyType::Gif,
+        BodyType::Webp,
+        BodyType::Pdf,
+        BodyType::Zip,
+        BodyType::Gzip,
+        BodyType::Wasm,
+    ];
+
+    /// The type of file that `bytes` is, by its magic bytes.
+    fn sniff(bytes: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|body_type| body_type.matches(bytes))
+    }
+
+    fn matches(self, bytes: &[u8]) -> bool {
+        match self {
+            BodyType::Png => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+            BodyType::Jpeg => bytes.starts_with(&[0xff, 0xd8, 0xff]),
+            BodyType::Gif => bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a"),
+            BodyType::Webp => bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WEBP"[..]),
+            BodyType::Pdf => bytes.starts_with(b"%PDF-"),
+            // empty archives and archives split into parts start differently
+            BodyType::Zip => [b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"]
+                .iter()
+                .any(|magic| bytes.starts_with(*magic)),
+            BodyType::Gzip => bytes.starts_with(&[0x1f, 0x8b]),
+            BodyType::Wasm => bytes.starts_with(b"\0asm"),
+        }
+    }
+}
+
+impl Display for BodyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = serde_json::to_value(self).unwrap_or_default();
+        write!(f, "{}", name.as_str().unwrap_or_default())
+    }
+}
+
+/// The result of evaluating a single assertion against a response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssertionOutcome {
+    description: String,
+    failure: Option<String>,
+}
+
+impl AssertionOutcome {
+    pub(crate) fn new(description: String, failure: Option<String>) -> Self {
+        Self {
+            description,
+            failure,
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Describes why the assertion failed, or `None` if it passed.
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl Display for AssertionOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.failure {
+            None => write!(f, "ok: {}", self.description),
+            Some(failure) => write!(f, "FAILED: {} ({failure})", self.description),
+        }
+    }
+}
+
+impl Expect {
+    /// Evaluates every assertion against `response`.
+    ///
+    /// Returns an error if the block itself is invalid, e.g. contains a malformed regex or
+    /// JSONPath. Failed assertions are reported in the returned outcomes.
+    pub fn evaluate(&self, response: &Response) -> KuiperResult<Vec<AssertionOutcome>> {
+        let body = OnceCell::new();
+        let mut outcomes = self
+            .assertions()
+            .iter()
+            .map(|assertion| assertion.evaluate_with(response, &body))
+            .collect::<KuiperResult<Vec<_>>>()?;
+
+        if let Some(golden) = &self.body_file {
+            // the golden file is compared before the duration is checked
+            let idx = outcomes.len() - usize::from(self.max_duration_ms.is_some());
+            outcomes.insert(idx, self.evaluate_golden(golden, response)?);
+        }
+
+        Ok(outcomes)
+    }
+
+    /// The assertions of the block, in the order they are evaluated in, without the comparison
+    /// with the golden file.
+    pub fn assertions(&self) -> Vec<Assertion> {
+        let mut assertions: Vec<_> = self.status.map(Assertion::StatusEq).into_iter().collect();
+
+        let mut headers: Vec<_> = self.headers.iter().collect();
+        headers.sort_by_key(|(name, _)| name.as_str());
+        assertions.extend(
+            headers
+                .into_iter()
+                .map(|(name, assertion)| match assertion {
+                    None => Assertion::HeaderAbsent(name.clone()),
+                    Some(assertion) => assertion.to_assertion(name),
+                }),
+        );
+
+        assertions.extend(
+            self.body
+                .iter()
+                .map(|assertion| Assertion::JsonPathMatches {
+                    path: assertion.path.clone(),
+                    value_type: assertion.value_type,
+                    predicate: assertion.predicate.clone(),
+                    not: assertion.not,
+                }),
+        );
+
+        assertions.extend(self.body_length.map(Assertion::BodyLength));
+        assertions.extend(self.body_sha256.clone().map(Assertion::BodySha256));
+        assertions.extend(self.body_type.map(Assertion::BodyType));
+        assertions.extend(
+            self.max_duration_ms
+                .map(|ms| Assertion::MaxDuration(Duration::from_millis(ms))),
+        );
+        assertions
+    }
+
+    /// The golden file of the body, if there is one.
+    pub fn body_file(&self) -> Option<&Path> {
+        self.body_file.as_deref()
+    }
+
+    /// Replaces the golden file with the body of `response`, if there is a golden file, and
+    /// returns its path. JSON bodies are normalized and pretty-printed, so that changes are easy
+    /// to review.
+    pub fn update_golden(&self, response: &Response) -> KuiperResult<Option<&Path>> {
+        let Some(golden) = &self.body_file else {
+            return Ok(None);
+        };
+        let mut contents = self.normalizer.normalize_body(response.body())?;
+        if serde_json::from_str::<Value>(response.body()).is_ok() {
+            contents.push('\n');
+        }
+        if let Some(parent) = golden.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(golden, contents)?;
+        Ok(Some(golden))
+    }
+
+    /// Makes the golden file path relative to `dir`, the directory of the request file.
+    pub(crate) fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(golden) = &mut self.body_file {
+            *golden = dir.join(&*golden);
+        }
+    }
+
+    fn evaluate_golden(
+        &self,
+        golden: &Path,
+        response: &Response,
+    ) -> KuiperResult<AssertionOutcome> {
+        let description = format!("body matches golden file {golden:?}");
+        let expected = match fs::read_to_string(golden) {
+            Ok(expected) => expected,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(AssertionOutcome::new(
+                    description,
+                    Some(
+                        "golden file does not exist, run with --update-golden to create it"
+                            .to_string(),
+                    ),
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let failure = match (
+            serde_json::from_str::<Value>(&expected),
+            serde_json::from_str::<Value>(response.body()),
+        ) {
+            (Ok(mut expected), Ok(mut actual)) => {
+                self.normalizer.normalize(&mut expected)?;
+                self.normalizer.normalize(&mut actual)?;
+                first_difference(&expected, &actual, "$")
+            }
+            _ => (expected != response.body()).then(|| "body differs".to_string()),
+        };
+        Ok(AssertionOutcome::new(description, failure))
+    }
+}
+
+/// Describes the first place where `actual` differs from `expected`, which are both found at
+/// `path`.
+fn first_difference(expected: &Value, actual: &Value, path: &str) -> Option<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual) => {
+                        if let Some(difference) = first_difference(expected, actual, &path) {
+                            return Some(difference);
+                        }
+                    }
+                    None => return Some(format!("'{path}' is missing")),
+                }
+            }
+            actual
+                .keys()
+                .find(|key| !expected.contains_key(*key))
+                .map(|key| format!("'{path}.{key}' is unexpected"))
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() != actual.len() => {
+            Some(format!(
+                "'{path}' has {} elements, expected {}",
+                actual.len(),
+                expected.len()
+            ))
+        }
+        (Value::Array(expected), Value::Array(actual)) => expected
+            .iter()
+            .zip(actual)
+            .enumerate()
+            .find_map(|(idx, (expected, actual))| {
+                first_difference(expected, actual, &format!("{path}[{idx}]"))
+            }),
+        _ if expected == actual => None,
+        _ => Some(format!("'{path}' is {actual}, expected {expected}")),
+    }
+}
+
+/// Evaluates an assertion on the value at `path` of `body`, which is `None` if the body is not
+/// JSON. A value of the wrong type fails the assertion whether or not it is inverted.
+fn evaluate_json_path(
+    path: &str,
+    value_type: Option<ValueType>,
+    predicate: &Predicate,
+    not: bool,
+    body: Option<&Value>,
+) -> KuiperResult<AssertionOutcome> {
+    let mut description = format!("'{path}' {}{predicate}", if not { "not " } else { "" });
+    if let Some(value_type) = value_type {
+        description.push_str(&format!(" as {value_type}"));
+    }
+    let Some(body) = body else {
+        return Ok(AssertionOutcome::new(
+            description,
+            Some("response body is not JSON".to_string()),
+        ));
+    };
+
+    let actual = jsonpath::select(body, path)?;
+    let coerced = match (value_type, actual) {
+        (Some(value_type), Some(value)) => match value_type.coerce(value) {
+            Some(coerced) => Some(coerced),
+            None => {
+                let failure = format!(
+                    "got {value} ({}), expected {value_type}",
+                    ValueType::of(value)
+                );
+                return Ok(AssertionOutcome::new(description, Some(failure)));
+            }
+        },
+        _ => actual.cloned(),
+    };
+    let holds = predicate.holds(coerced.as_ref());
+    let failure = (holds == not).then(|| match actual {
+        Some(value) if not => format!("got {value}"),
+        Some(value) => predicate.failure(value, value_type.is_none()),
+        None => "path does not exist".to_string(),
+    });
+    Ok(AssertionOutcome::new(description, failure))
+}
+
+impl Predicate {
+    fn holds(&self, actual: Option<&Value>) -> bool {
+        match (self, actual) {
+            (Predicate::Exists(exists), actual) => actual.is_some() == *exists,
+            (_, None) => false,
+            (Predicate::Equals(expected), Some(actual)) => equals(actual, expected),
+            (Predicate::Length(expected), Some(actual)) => match actual {
+                Value::Array(values) => values.len() == *expected,
+                Value::Object(map) => map.len() == *expected,
+                Value::String(s) => s.chars().count() == *expected,
+                _ => false,
+            },
+            (Predicate::Contains(expected), Some(actual)) => match (actual, expected) {
+                (Value::Array(values), expected) => values.contains(expected),
+                (Value::String(s), Value::String(expected)) => s.contains(expected.as_str()),
+                _ => false,
+            },
+            (Predicate::Gt(n) | Predicate::Gte(n) | Predicate::Lt(n) | Predicate::Lte(n), _) => {
+                let (Some(actual), Some(expected)) = (actual.and_then(Value::as_f64), n.as_f64())
+                else {
+                    return false;
+                };
+                match self {
+                    Predicate::Gt(_) => actual > expected,
+                    Predicate::Gte(_) => actual >= expected,
+                    Predicate::Lt(_) => actual < expected,
+                    _ => actual <= expected,
+                }
+            }
+        }
+    }
+
+    /// Why `actual`, which the predicate doesn't hold for, is wrong. Without an explicit type,
+    /// strings that would hold as a number or boolean point to the `type` that converts them.
+    fn failure(&self, actual: &Value, untyped: bool) -> String {
+        let expected = match self {
+            Predicate::Equals(expected) => format!("{expected} ({})", ValueType::of(expected)),
+            Predicate::Gt(n) => format!("a number greater than {n}"),
+            Predicate::Gte(n) => format!("a number of at least {n}"),
+            Predicate::Lt(n) => format!("a number less than {n}"),
+            Predicate::Lte(n) => format!("a number of at most {n}"),
+            _ => return format!("got {actual}"),
+        };
+        let mut failure = format!(
+            "got {actual} ({}), expected {expected}",
+            ValueType::of(actual)
+        );
+        let hint = [ValueType::Number, ValueType::Boolean]
+            .into_iter()
+            .find(|value_type| {
+                value_type
+                    .coerce(actual)
+                    .is_some_and(|coerced| self.holds(Some(&coerced)))
+            });
+        if let (true, Value::String(_), Some(value_type)) = (untyped, actual, hint) {
+            let name = serde_json::to_value(value_type).unwrap_or_default();
+            failure.push_str(&format!(", set \"type\": {name} to convert it"));
+        }
+        failure
+    }
+}
+
+/// Whether `actual` equals `expected`, comparing numbers by value, so that `1.0` equals `1`.
+fn equals(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a == b || a.as_f64() == b.as_f64(),
+        _ => actual == expected,
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Predicate::Exists(true) => write!(f, "exists"),
+            Predicate::Exists(false) => write!(f, "does not exist"),
+            Predicate::Equals(value) => write!(f, "equals {value}"),
+            Predicate::Length(len) => write!(f, "has length {len}"),
+            Predicate::Contains(value) => write!(f, "contains {value}"),
+            Predicate::Gt(n) => write!(f, "is greater than {n}"),
+            Predicate::Gte(n) => write!(f, "is at least {n}"),
+            Predicate::Lt(n) => write!(f, "is less than {n}"),
+            Predicate::Lte(n) => write!(f, "is at most {n}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn evaluate_test() {
+        let expect: Expect = serde_json::from_value(json!({
+            "status": 200,
+            "headers": {
+                "Content-Type": "^application/json",
+                "Cache-Control": { "equals": "no-store" },
+                "Vary": { "matches": "(?i)accept" },
+                "x-debug": null
+            },
+            "body": [
+                { "path": "$.id", "exists": true },
+                { "path": "$.error", "exists": false },
+                { "path": "$.name", "equals": "alice" },
+                { "path": "$.name", "equals": "bob", "not": true },
+                { "path": "$.tags", "length": 2 },
+                { "path": "$.tags", "contains": "admin" }
+            ],
+            "max_duration_ms": 100
+        }))
+        .unwrap();
+
+        let response = Response::new(
+            200,
+            vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("cache-control".to_string(), "no-store".to_string()),
+                ("vary".to_string(), "Accept-Encoding".to_string()),
+            ],
+            json!({ "id": 1, "name": "alice", "tags": ["admin", "user"] }).to_string(),
+            Duration::from_millis(50),
+        );
+        let outcomes = expect.evaluate(&response).unwrap();
+        assert_eq!(outcomes.len(), 12);
+        assert!(outcomes.iter().all(|o| o.passed()), "{:?}", outcomes);
+
+        let response = Response::new(
+            404,
+            vec![
+                ("X-Debug".to_string(), "1".to_string()),
+                ("Cache-Control".to_string(), "no-store, private".to_string()),
+            ],
+            json!({ "error": "not found" }).to_string(),
+            Duration::from_millis(150),
+        );
+        let failed: Vec<_> = expect
+            .evaluate(&response)
+            .unwrap()
+            .into_iter()
+            .filter(|o| !o.passed())
+            .map(|o| o.description().to_string())
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                "status is 200",
+                "header 'Cache-Control' equals 'no-store'",
+                "header 'Content-Type' matches '^application/json'",
+                "header 'Vary' matches '(?i)accept'",
+                "header 'x-debug' is absent",
+                "'$.id' exists",
+                "'$.error' does not exist",
+                "'$.name' equals \"alice\"",
+                "'$.tags' has length 2",
+                "'$.tags' contains \"admin\"",
+                "duration is at most 100ms",
+            ]
+        );
+    }
+
+    #[test]
+    fn assertion_test() {
+        let response = Response::new(
+            201,
+            vec![("Location".to_string(), "/users/42".to_string())],
+            json!({ "id": 42 }).to_string(),
+            Duration::from_millis(20),
+        );
+        let outcomes: Vec<_> = [
+            Assertion::StatusEq(201),
+            Assertion::HeaderRegex {
+                name: "location".to_string(),
+                pattern: "^/users/\\d+$".to_string(),
+            },
+            Assertion::JsonPathMatches {
+                path: "$.id".to_string(),
+                value_type: None,
+                predicate: Predicate::Equals(json!(42)),
+                not: false,
+            },
+            Assertion::MaxDuration(Duration::from_millis(10)),
+        ]
+        .iter()
+        .map(|assertion| assertion.evaluate(&response).unwrap())
+        .collect();
+        assert_eq!(
+            outcomes.iter().map(|o| o.passed()).collect::<Vec<_>>(),
+            vec![true, true, true, false]
+        );
+        assert_eq!(outcomes[3].failure(), Some("took 20ms"));
+        assert!(matches!(
+            Assertion::HeaderRegex {
+                name: "Location".to_string(),
+                pattern: "(".to_string()
+            }
+            .evaluate(&response),
+            Err(KuiperError::InvalidRegex(_))
+        ));
+
+        // an `expect` block is the same assertions
+        let expect: Expect = serde_json::from_value(json!({
+            "status": 201,
+            "headers": { "Location": { "equals": "/users/42" }, "X-Debug": null },
+            "body": [{ "path": "$.id", "exists": true, "not": true }],
+            "max_duration_ms": 10
+        }))
+        .unwrap();
+        assert_eq!(
+            expect.assertions(),
+            vec![
+                Assertion::StatusEq(201),
+                Assertion::HeaderEq {
+                    name: "Location".to_string(),
+                    value: "/users/42".to_string()
+                },
+                Assertion::HeaderAbsent("X-Debug".to_string()),
+                Assertion::JsonPathMatches {
+                    path: "$.id".to_string(),
+                    value_type: None,
+                    predicate: Predicate::Exists(true),
+                    not: true
+                },
+                Assertion::MaxDuration(Duration::from_millis(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn typed_assertion_test() {
+        let expect: Expect = serde_json::from_value(json!({
+            "body": [
+                { "path": "$.count", "gte": 10 },
+                { "path": "$.count", "gte": 10, "type": "number" },
+                { "path": "$.total", "equals": 12.5, "type": "number" },
+                { "path": "$.page", "lt": 3, "type": "integer" },
+                { "path": "$.active", "equals": true },
+                { "path": "$.active", "equals": true, "type": "boolean" },
+                { "path": "$.name", "exists": true, "type": "string" },
+                { "path": "$.ratio", "equals": 1 }
+            ]
+        }))
+        .unwrap();
+        let response = |body: serde_json::Value| {
+            Response::new(200, Vec::new(), body.to_string(), Duration::ZERO)
+        };
+        let failures = |body| {
+            expect
+                .evaluate(&response(body))
+                .unwrap()
+                .into_iter()
+                .filter_map(|o| o.failure().map(|f| format!("{}: {f}", o.description())))
+                .collect::<Vec<_>>()
+        };
+
+        let passed: Vec<String> = Vec::new();
+        assert_eq!(
+            failures(json!({
+                "count": 10, "total": 12.5, "page": 2, "active": true, "name": "a", "ratio": 1.0
+            })),
+            passed
+        );
+        assert_eq!(
+            failures(json!({
+                "count": "12", "total": "12.50", "page": "1.5", "active": "true", "name": 7,
+                "ratio": 1.5
+            })),
+            vec![
+                "'$.count' is at least 10: got \"12\" (a string), expected a number of at least \
+                 10, set \"type\": \"number\" to convert it",
+                "'$.page' is less than 3 as an integer: got \"1.5\" (a string), expected an \
+                 integer",
+                "'$.active' equals true: got \"true\" (a string), expected true (a boolean), set \
+                 \"type\": \"boolean\" to convert it",
+                "'$.name' exists as a string: got 7 (a number), expected a string",
+                "'$.ratio' equals 1: got 1.5 (a number), expected 1 (a number)",
+            ]
+        );
+        assert_eq!(
+            failures(json!({
+                "count": 9, "total": 12.5, "page": 3, "active": "yes", "name": "a", "ratio": 1
+            })),
+            vec![
+                "'$.count' is at least 10: got 9 (a number), expected a number of at least 10",
+                "'$.count' is at least 10 as a number: got 9 (a number), expected a number of at \
+                 least 10",
+                "'$.page' is less than 3 as an integer: got 3 (a number), expected a number less \
+                 than 3",
+                "'$.active' equals true: got \"yes\" (a string), expected true (a boolean)",
+                "'$.active' equals true as a boolean: got \"yes\" (a string), expected a boolean",
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_assertion_test() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\xff".to_vec();
+        let response =
+            |body: &[u8]| Response::from_bytes(200, Vec::new(), body.to_vec(), Duration::ZERO);
+        assert_eq!(response(&png).bytes(), png.as_slice());
+        assert_eq!(response(b"{}").body(), "{}");
+
+        let expect: Expect = serde_json::from_value(json!({
+            "body_length": 17,
+            "body_sha256": "3F4D4E5E1C7B1D6D1C2A6F9E6D6E4A3C9B8B2E6E0A0B7C7E1E2F3A4B5C6D7E8F",
+            "body_type": "image/png"
+        }))
+        .unwrap();
+        let sha256 = digest(&SHA256, &png)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let outcomes = expect.evaluate(&response(&png)).unwrap();
+        assert_eq!(
+            outcomes
+                .iter()
+                .map(|o| o.failure().map(str::to_string))
+                .collect::<Vec<_>>(),
+            vec![None, Some(format!("got {sha256}")), None]
+        );
+        assert!(Assertion::BodySha256(sha256.to_uppercase())
+            .evaluate(&response(&png))
+            .unwrap()
+            .passed());
+
+        let failures = |body: &[u8]| {
+            expect
+                .evaluate(&response(body))
+                .unwrap()
+                .into_iter()
+                .filter_map(|o| o.failure().map(|f| format!("{}: {f}", o.description())))
+                .skip(2)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            failures(b"%PDF-1.7\n"),
+            vec!["body is image/png: got application/pdf"]
+        );
+        assert_eq!(
+            failures(b"<!DOCTYPE html>"),
+            vec!["body is image/png: got unknown bytes 3c 21 44 4f 43 54 59 50"]
+        );
+        assert_eq!(failures(b""), vec!["body is image/png: body is empty"]);
+        assert_eq!(
+            BodyType::sniff(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(BodyType::Webp)
+        );
+        assert_eq!(BodyType::sniff(b"PK\x05\x06"), Some(BodyType::Zip));
+        assert!(serde_json::from_value::<Expect>(json!({ "body_type": "image/bmp" })).is_err());
+    }
+
+    #[test]
+    fn golden_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-golden-{}", uuid::Uuid::new_v4()));
+        let mut expect: Expect = serde_json::from_value(json!({
+            "body_file": "golden/user.json",
+            "ignore": ["$.updated_at"]
+        }))
+        .unwrap();
+        expect.resolve_paths(&dir);
+        let response = |body: serde_json::Value| {
+            Response::new(200, Vec::new(), body.to_string(), Duration::ZERO)
+        };
+
+        let outcomes = expect.evaluate(&response(json!({ "id": 42 }))).unwrap();
+        assert!(!outcomes[0].passed());
+
+        let golden = expect
+            .update_golden(&response(
+                json!({ "id": 42, "tags": ["a"], "updated_at": 1 }),
+            ))
+            .unwrap();
+        assert_eq!(golden, Some(dir.join("golden/user.json").as_path()));
+
+        let failures = |body| {
+            expect
+                .evaluate(&response(body))
+                .unwrap()
+                .into_iter()
+                .filter_map(|o| o.failure().map(str::to_string))
+                .collect::<Vec<_>>()
+        };
+        let same: Vec<String> = Vec::new();
+        assert_eq!(
+            failures(json!({ "id": 42, "tags": ["a"], "updated_at": 2 })),
+            same
+        );
+        assert_eq!(
+            failures(json!({ "id": 43, "tags": ["a"] })),
+            vec!["'$.id' is 43, expected 42"]
+        );
+        assert_eq!(
+            failures(json!({ "id": 42, "tags": [] })),
+            vec!["'$.tags' has 0 elements, expected 1"]
+        );
+        assert_eq!(
+            failures(json!({ "id": 42, "tags": ["a"], "name": "x" })),
+            vec!["'$.name' is unexpected"]
+        );
+        assert_eq!(
+            failures(json!({ "tags": ["a"] })),
+            vec!["'$.id' is missing"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
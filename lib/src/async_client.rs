@@ -0,0 +1,149 @@
+use crate::{
+    auth::Auth,
+    client::{build_request, check_pin, configure, limit_body, response_headers},
+    hosts::HostPolicy,
+    BodyLimit, ClientConfig, KuiperError, KuiperResult, Request, Response, Timings,
+};
+use std::{fs, io::Read, path::Path, time::Instant};
+
+/// An HTTP client that sends requests without blocking a thread, for embedding kuiper in
+/// tokio-based services and test harnesses.
+///
+/// Unlike [`Client`](crate::Client), it doesn't keep requests within the rate limits of their
+/// hosts, doesn't measure DNS lookups or connecting apart from the time to the first byte, doesn't
+/// report events to observers, can't send requests authenticated with OAuth client credentials or
+/// plugins or make gRPC calls, and reads `body_ndjson_file`, `body_file_binary` and `multipart`
+/// bodies into memory instead of streaming them. Those files are read on tokio's blocking thread
+/// pool, so that a large file doesn't hold up the other tasks of the runtime.
+#[derive(Clone, Debug)]
+pub struct AsyncClient {
+    inner: reqwest::Client,
+    host_policy: HostPolicy,
+    /// Whether requests are sent over HTTP/3.
+    http3: bool,
+}
+
+impl AsyncClient {
+    pub fn new(config: &ClientConfig) -> KuiperResult<Self> {
+        Self::from_builder(reqwest::Client::builder(), config)
+    }
+
+    /// Applies `config` to `builder`, which may have been customized beforehand, for example
+    /// with a proxy.
+    pub fn from_builder(
+        builder: reqwest::ClientBuilder,
+        config: &ClientConfig,
+    ) -> KuiperResult<Self> {
+        Ok(Self {
+            inner: configure!(builder, config).build()?,
+            host_policy: HostPolicy::default(),
+            http3: config.http3(),
+        })
+    }
+
+    /// Refuses to send requests to hosts that `policy` doesn't allow.
+    pub fn with_host_policy(mut self, policy: HostPolicy) -> Self {
+        self.host_policy = policy;
+        self
+    }
+
+    /// The underlying `reqwest` client.
+    pub fn inner(&self) -> &reqwest::Client {
+        &self.inner
+    }
+
+    /// Sends `request` and reads its response, after checking that its host is allowed.
+    pub async fn send(&self, request: &Request) -> KuiperResult<Response> {
+        self.send_with_limit(request, None).await
+    }
+
+    /// Like [`AsyncClient::send`], without reading more of the response body into memory than
+    /// `limit` allows.
+    pub async fn send_with_limit(
+        &self,
+        request: &Request,
+        limit: Option<BodyLimit>,
+    ) -> KuiperResult<Response> {
+        self.host_policy.check(request.uri())?;
+        match request.auth() {
+            Some(Auth::OauthClientCredentials { .. }) => {
+                return Err(KuiperError::AuthFailed(
+                    "OAuth client credentials are not supported by the async client".to_string(),
+                ))
+            }
+            Some(Auth::Plugin { .. }) => {
+                return Err(KuiperError::AuthFailed(
+                    "plugin auth is not supported by the async client".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        if request.grpc().is_some() {
+            return Err(KuiperError::GrpcFailed(
+                "gRPC calls are not supported by the async client".to_string(),
+            ));
+        }
+
+        let mut built = if reads_files(request) {
+            let (client, request) = (self.inner.clone(), request.clone());
+            tokio::task::spawn_blocking(move || -> KuiperResult<_> {
+                Ok(build_request!(client, &request, read_file, read_sized))
+            })
+            .await
+            .map_err(|e| KuiperError::IoError(std::io::Error::other(e)))??
+        } else {
+            build_request!(self.inner, request, read_file, read_sized)
+        };
+
+        if self.http3 {
+            *built.version_mut() = reqwest::Version::HTTP_3;
+        }
+        let start = Instant::now();
+        let mut response = self.inner.execute(built).await?;
+        let time_to_first_byte = start.elapsed();
+        check_pin(request, response.url(), response.extensions().get())?;
+        let status = response.status().as_u16();
+        let headers = response_headers(response.headers());
+
+        let download_start = Instant::now();
+        let body = match limit {
+            None => response.bytes().await?.to_vec(),
+            Some(limit) => {
+                if !limit.truncate()
+                    && response
+                        .content_length()
+                        .is_some_and(|len| len > limit.max())
+                {
+                    return Err(KuiperError::ResponseTooLarge(limit.max()));
+                }
+                let mut body = Vec::new();
+                while let Some(chunk) = response.chunk().await? {
+                    body.extend_from_slice(&chunk);
+                    if body.len() as u64 > limit.max() {
+                        break;
+                    }
+                }
+                limit_body(request, body, limit)?
+            }
+        };
+        let timings = Timings::new(None, time_to_first_byte, download_start.elapsed());
+        Ok(Response::from_bytes(status, headers, body, start.elapsed()).with_timings(timings))
+    }
+}
+
+/// Returns `true` if building `request` reads files, which blocks.
+fn reads_files(request: &Request) -> bool {
+    request.body_ndjson_file().is_some()
+        || request.body_file_binary().is_some()
+        || request.multipart().is_some()
+}
+
+fn read_file(path: &Path) -> KuiperResult<reqwest::Body> {
+    Ok(fs::read(path)?.into())
+}
+
+fn read_sized(mut reader: Box<dyn Read + Send>, length: u64) -> KuiperResult<reqwest::Body> {
+    let mut body = Vec::with_capacity(length as usize);
+    reader.read_to_end(&mut body)?;
+    Ok(body.into())
+}
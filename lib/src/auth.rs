@@ -0,0 +1,236 @@
+//! Authentication shared by the requests in a directory, set in an `auth.json` or in the `auth`
+//! block of a request.
+
+use crate::{KuiperError, KuiperResult};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+
+/// Tokens are fetched again this long before they expire, so that they don't expire in flight.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// `Authorization: Bearer TOKEN`.
+    Bearer { token: String },
+    /// `Authorization: Basic ...`.
+    Basic { username: String, password: String },
+    /// The key in a header, `X-API-Key` unless another one is given.
+    ApiKey {
+        key: String,
+        #[serde(default = "default_api_key_header")]
+        header: String,
+    },
+    /// A bearer token fetched from `token_url` with the OAuth 2.0 client credentials grant when
+    /// the request is sent.
+    OauthClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+    },
+    /// The headers that the auth scheme `scheme` of a plugin adds, given `params` and the
+    /// request, for example to sign it.
+    Plugin {
+        scheme: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        params: BTreeMap<String, String>,
+    },
+    /// No authentication, even if an `auth.json` above the request sets some.
+    None,
+}
+
+fn default_api_key_header() -> String {
+    DEFAULT_API_KEY_HEADER.to_string()
+}
+
+impl Auth {
+    /// The values of the auth, which may contain placeholders.
+    pub fn values(&self) -> Vec<&str> {
+        match self {
+            Auth::Bearer { token } => vec![token],
+            Auth::Basic { username, password } => vec![username, password],
+            Auth::ApiKey { key, header } => vec![key, header],
+            Auth::OauthClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+            } => [token_url, client_id, client_secret]
+                .into_iter()
+                .chain(scope)
+                .map(String::as_str)
+                .collect(),
+            Auth::Plugin { params, .. } => params.values().map(String::as_str).collect(),
+            Auth::None => Vec::new(),
+        }
+    }
+
+    /// Replaces every value of the auth with the result of `interpolate`.
+    pub(crate) fn interpolate(
+        &mut self,
+        mut interpolate: impl FnMut(&str) -> KuiperResult<String>,
+    ) -> KuiperResult<()> {
+        let values: Vec<&mut String> = match self {
+            Auth::Bearer { token } => vec![token],
+            Auth::Basic { username, password } => vec![username, password],
+            Auth::ApiKey { key, header } => vec![key, header],
+            Auth::OauthClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+            } => [token_url, client_id, client_secret]
+                .into_iter()
+                .chain(scope)
+                .collect(),
+            Auth::Plugin { params, .. } => params.values_mut().collect(),
+            Auth::None => Vec::new(),
+        };
+        for value in values {
+            *value = interpolate(value)?;
+        }
+        Ok(())
+    }
+
+    /// The header that the auth adds to requests, if it is known without sending anything.
+    pub fn header(&self) -> Option<(String, String)> {
+        match self {
+            Auth::Bearer { token } => {
+                Some(("Authorization".to_string(), format!("Bearer {token}")))
+            }
+            Auth::Basic { username, password } => Some((
+                "Authorization".to_string(),
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{username}:{password}"))
+                ),
+            )),
+            Auth::ApiKey { key, header } => Some((header.clone(), key.clone())),
+            Auth::OauthClientCredentials { .. } | Auth::Plugin { .. } | Auth::None => None,
+        }
+    }
+}
+
+/// The token url, client id and scope that a token was fetched for.
+type TokenKey = (String, String, Option<String>);
+
+/// Tokens fetched with the client credentials grant, with when they expire, if they do.
+#[derive(Debug, Default)]
+pub(crate) struct TokenCache {
+    tokens: Mutex<HashMap<TokenKey, (String, Option<Instant>)>>,
+}
+
+impl TokenCache {
+    /// Returns the cached token for `token_url`, `client_id` and `scope` if it has not expired,
+    /// or fetches a new one with `fetch`, which returns the body of the token response.
+    pub(crate) fn token(
+        &self,
+        token_url: &str,
+        client_id: &str,
+        scope: Option<&str>,
+        fetch: impl FnOnce() -> KuiperResult<String>,
+    ) -> KuiperResult<String> {
+        let key = (
+            token_url.to_string(),
+            client_id.to_string(),
+            scope.map(str::to_string),
+        );
+        let mut tokens = self.tokens.lock().unwrap();
+        if let Some((token, expires)) = tokens.get(&key) {
+            if expires.is_none_or(|expires| Instant::now() < expires) {
+                return Ok(token.clone());
+            }
+        }
+
+        let (token, expires_in) = parse_token_response(&fetch()?)?;
+        let expires = expires_in.map(|expires_in| {
+            Instant::now() + Duration::from_secs(expires_in).saturating_sub(EXPIRY_MARGIN)
+        });
+        tokens.insert(key, (token.clone(), expires));
+        Ok(token)
+    }
+}
+
+/// Reads the access token and how many seconds it is valid for from a token response.
+fn parse_token_response(body: &str) -> KuiperResult<(String, Option<u64>)> {
+    let response: serde_json::Value = serde_json::from_str(body)
+        .map_err(|_| KuiperError::AuthFailed("the token response is not JSON".to_string()))?;
+    let token = response["access_token"].as_str().ok_or_else(|| {
+        KuiperError::AuthFailed("the token response has no access_token".to_string())
+    })?;
+    Ok((token.to_string(), response["expires_in"].as_u64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn header_test() {
+        let auth: Auth =
+            serde_json::from_str(r#"{ "type": "basic", "username": "ada", "password": "pw" }"#)
+                .unwrap();
+        assert_eq!(
+            auth.header(),
+            Some(("Authorization".to_string(), "Basic YWRhOnB3".to_string()))
+        );
+        let auth: Auth = serde_json::from_str(r#"{ "type": "api_key", "key": "k" }"#).unwrap();
+        assert_eq!(
+            auth.header(),
+            Some(("X-API-Key".to_string(), "k".to_string()))
+        );
+        let mut auth: Auth = serde_json::from_str(
+            r#"{ "type": "oauth_client_credentials", "token_url": "{{env:URL}}", "client_id": "id", "client_secret": "s" }"#,
+        )
+        .unwrap();
+        assert_eq!(auth.header(), None);
+        auth.interpolate(|value| Ok(value.replace("{{env:URL}}", "http://localhost/token")))
+            .unwrap();
+        assert_eq!(auth.values()[0], "http://localhost/token");
+
+        let mut auth: Auth = serde_json::from_str(
+            r#"{ "type": "plugin", "scheme": "hmac", "params": { "key_id": "{{env:KEY_ID}}" } }"#,
+        )
+        .unwrap();
+        assert_eq!(auth.header(), None);
+        auth.interpolate(|value| Ok(value.replace("{{env:KEY_ID}}", "k1")))
+            .unwrap();
+        assert_eq!(auth.values(), vec!["k1"]);
+    }
+
+    #[test]
+    fn token_cache_test() {
+        let cache = TokenCache::default();
+        let token = cache
+            .token("url", "id", None, || {
+                Ok(r#"{ "access_token": "a", "expires_in": 3600 }"#.to_string())
+            })
+            .unwrap();
+        assert_eq!(token, "a");
+        let token = cache
+            .token("url", "id", None, || unreachable!("the token is cached"))
+            .unwrap();
+        assert_eq!(token, "a");
+
+        // tokens that expire within the margin are fetched every time
+        let fetch = || Ok(r#"{ "access_token": "b", "expires_in": 10 }"#.to_string());
+        assert_eq!(cache.token("url", "other", None, fetch).unwrap(), "b");
+        let fetch = || Ok(r#"{ "access_token": "c" }"#.to_string());
+        assert_eq!(cache.token("url", "other", None, fetch).unwrap(), "c");
+
+        assert!(matches!(
+            cache.token("url", "id", Some("read"), || Ok("{}".to_string())),
+            Err(KuiperError::AuthFailed(_))
+        ));
+    }
+}
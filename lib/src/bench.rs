@@ -0,0 +1,230 @@
+use crate::KuiperResult;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, fs, path::Path, time::Duration};
+
+/// Latency and throughput statistics for a benchmark run.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BenchStats {
+    requests: usize,
+    errors: usize,
+    mean_ms: f64,
+    p50_ms: f64,
+    p95_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+    /// Requests per second.
+    throughput: f64,
+}
+
+/// A metric that got worse than allowed compared to a baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regression {
+    metric: &'static str,
+    baseline: f64,
+    current: f64,
+}
+
+impl BenchStats {
+    /// Computes statistics from the durations of successful requests, the number of failed
+    /// requests, and the total time the run took.
+    pub fn new(durations: &[Duration], errors: usize, elapsed: Duration) -> Self {
+        let mut millis: Vec<f64> = durations.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        millis.sort_by(f64::total_cmp);
+        let requests = millis.len() + errors;
+        let mean_ms = if millis.is_empty() {
+            0.0
+        } else {
+            millis.iter().sum::<f64>() / millis.len() as f64
+        };
+        let throughput = if elapsed.is_zero() {
+            0.0
+        } else {
+            requests as f64 / elapsed.as_secs_f64()
+        };
+
+        Self {
+            requests,
+            errors,
+            mean_ms,
+            p50_ms: percentile(&millis, 50.0),
+            p95_ms: percentile(&millis, 95.0),
+            p99_ms: percentile(&millis, 99.0),
+            max_ms: millis.last().copied().unwrap_or(0.0),
+            throughput,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> KuiperResult<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> KuiperResult<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        self.mean_ms
+    }
+
+    pub fn p50_ms(&self) -> f64 {
+        self.p50_ms
+    }
+
+    pub fn p95_ms(&self) -> f64 {
+        self.p95_ms
+    }
+
+    pub fn p99_ms(&self) -> f64 {
+        self.p99_ms
+    }
+
+    pub fn max_ms(&self) -> f64 {
+        self.max_ms
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.throughput
+    }
+
+    /// Returns the metrics that regressed by more than `threshold_percent` compared to
+    /// `baseline`. Latencies regress when they grow, throughput when it shrinks.
+    pub fn compare(&self, baseline: &BenchStats, threshold_percent: f64) -> Vec<Regression> {
+        let factor = threshold_percent / 100.0;
+        let latencies = [
+            ("mean", baseline.mean_ms, self.mean_ms),
+            ("p50", baseline.p50_ms, self.p50_ms),
+            ("p95", baseline.p95_ms, self.p95_ms),
+            ("p99", baseline.p99_ms, self.p99_ms),
+        ];
+
+        let mut regressions: Vec<_> = latencies
+            .into_iter()
+            .filter(|(_, baseline, current)| *current > baseline * (1.0 + factor))
+            .map(|(metric, baseline, current)| Regression {
+                metric,
+                baseline,
+                current,
+            })
+            .collect();
+        if self.throughput < baseline.throughput * (1.0 - factor) {
+            regressions.push(Regression {
+                metric: "throughput",
+                baseline: baseline.throughput,
+                current: self.throughput,
+            });
+        }
+
+        regressions
+    }
+}
+
+impl Display for BenchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "requests:   {} ({} errors)", self.requests, self.errors)?;
+        writeln!(f, "mean:       {:.2}ms", self.mean_ms)?;
+        writeln!(f, "p50:        {:.2}ms", self.p50_ms)?;
+        writeln!(f, "p95:        {:.2}ms", self.p95_ms)?;
+        writeln!(f, "p99:        {:.2}ms", self.p99_ms)?;
+        writeln!(f, "max:        {:.2}ms", self.max_ms)?;
+        write!(f, "throughput: {:.2} req/s", self.throughput)
+    }
+}
+
+impl Regression {
+    pub fn metric(&self) -> &str {
+        self.metric
+    }
+
+    pub fn baseline(&self) -> f64 {
+        self.baseline
+    }
+
+    pub fn current(&self) -> f64 {
+        self.current
+    }
+
+    /// The relative change from the baseline, in percent.
+    pub fn change_percent(&self) -> f64 {
+        if self.baseline == 0.0 {
+            0.0
+        } else {
+            (self.current - self.baseline) / self.baseline * 100.0
+        }
+    }
+}
+
+impl Display for Regression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} regressed from {:.2} to {:.2} ({:+.1}%)",
+            self.metric,
+            self.baseline,
+            self.current,
+            self.change_percent()
+        )
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn stats_test() {
+        let durations: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        let stats = BenchStats::new(&durations, 0, Duration::from_secs(10));
+        assert_eq!(stats.requests(), 100);
+        assert_eq!(stats.p50_ms(), 50.0);
+        assert_eq!(stats.p95_ms(), 95.0);
+        assert_eq!(stats.p99_ms(), 99.0);
+        assert_eq!(stats.max_ms(), 100.0);
+        assert_eq!(stats.mean_ms(), 50.5);
+        assert_eq!(stats.throughput(), 10.0);
+    }
+
+    #[test]
+    fn compare_test() {
+        let durations: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        let baseline = BenchStats::new(&durations, 0, Duration::from_secs(10));
+
+        let similar: Vec<_> = durations
+            .iter()
+            .map(|d| *d + Duration::from_millis(1))
+            .collect();
+        let current = BenchStats::new(&similar, 0, Duration::from_secs(10));
+        assert!(current.compare(&baseline, 10.0).is_empty());
+
+        let slower: Vec<_> = durations.iter().map(|d| *d * 2).collect();
+        let current = BenchStats::new(&slower, 0, Duration::from_secs(20));
+        let metrics: Vec<_> = current
+            .compare(&baseline, 10.0)
+            .iter()
+            .map(|r| r.metric().to_string())
+            .collect();
+        assert_eq!(metrics, vec!["mean", "p50", "p95", "p99", "throughput"]);
+    }
+}
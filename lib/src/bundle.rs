@@ -0,0 +1,217 @@
+use crate::{KuiperError, KuiperResult};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, File},
+    io::BufReader,
+    path::{Component, Path, PathBuf},
+};
+
+/// A collection of requests packed into a single file, so that it can be shared.
+///
+/// Bundles contain every file in the collection, such as requests, `headers.json`, datasets and
+/// images. Hidden files, `.env` files and `environments` directories are left out, since they
+/// usually contain secrets.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Bundle {
+    kuiper_version: String,
+    /// File contents keyed by their path relative to the collection root, using `/` as the
+    /// separator.
+    files: BTreeMap<String, String>,
+    /// The base64 contents of files that are not text, like images for binary bodies, keyed
+    /// like `files`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    binary_files: BTreeMap<String, String>,
+}
+
+impl Bundle {
+    /// Packs the collection in `root`.
+    pub fn pack(root: impl AsRef<Path>) -> KuiperResult<Self> {
+        let root = root.as_ref();
+        let mut files = BTreeMap::new();
+        let mut binary_files = BTreeMap::new();
+        let mut dirs = VecDeque::new();
+        dirs.push_back(root.to_path_buf());
+        while let Some(dir) = dirs.pop_front() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if is_excluded(&path) {
+                    trace!("leaving '{path:?}' out of bundle");
+                } else if path.is_dir() {
+                    dirs.push_back(path);
+                } else if path.is_file() {
+                    let relative = path
+                        .strip_prefix(root)
+                        .map_err(|_| KuiperError::PathError)?;
+                    let key = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    match String::from_utf8(fs::read(&path)?) {
+                        Ok(contents) => files.insert(key, contents),
+                        Err(e) => binary_files.insert(key, STANDARD.encode(e.into_bytes())),
+                    };
+                }
+            }
+        }
+
+        Ok(Self {
+            kuiper_version: env!("CARGO_PKG_VERSION").to_string(),
+            files,
+            binary_files,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> KuiperResult<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> KuiperResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Writes the files in the bundle to `root`, and returns their paths.
+    ///
+    /// Fails without writing anything if a file would end up outside `root`, or if it already
+    /// exists.
+    pub fn unpack(&self, root: impl AsRef<Path>) -> KuiperResult<Vec<PathBuf>> {
+        let root = root.as_ref();
+        let mut targets = Vec::with_capacity(self.files.len() + self.binary_files.len());
+        let binary_files = self.binary_files.iter().map(|(key, contents)| {
+            STANDARD
+                .decode(contents)
+                .map(|contents| (key, contents))
+                .map_err(|e| KuiperError::InvalidBundle(format!("'{key}' is not base64: {e}")))
+        });
+        let files = self
+            .files
+            .iter()
+            .map(|(key, contents)| Ok((key, contents.clone().into_bytes())))
+            .chain(binary_files);
+        for file in files {
+            let (key, contents) = file?;
+            let relative = Path::new(key);
+            if !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Err(KuiperError::InvalidBundle(format!(
+                    "path '{key}' is not inside the collection"
+                )));
+            }
+            let target = root.join(relative);
+            if target.exists() {
+                return Err(KuiperError::InvalidBundle(format!(
+                    "'{}' already exists",
+                    target.display()
+                )));
+            }
+            targets.push((target, contents));
+        }
+
+        let mut written = Vec::with_capacity(targets.len());
+        for (target, contents) in targets {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, contents)?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+
+    pub fn kuiper_version(&self) -> &str {
+        &self.kuiper_version
+    }
+
+    /// Paths of the files in the bundle, relative to the collection root.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files
+            .keys()
+            .chain(self.binary_files.keys())
+            .map(String::as_str)
+    }
+}
+
+/// Returns `true` if `path` is left out of bundles: hidden files, `.env` files, and
+/// `environments` directories, whose env files can be JSON as well.
+fn is_excluded(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    name.starts_with('.')
+        || path.extension().is_some_and(|ext| ext == "env")
+        || (name == "environments" && path.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn pack_unpack_test() {
+        let bundle = Bundle::pack("../requests").unwrap();
+        let files: Vec<_> = bundle.files().collect();
+        assert!(files.contains(&"request_in_root.kuiper"));
+        assert!(files.contains(&"subdir/headers.json"));
+        assert!(files.contains(&"data/users.csv"));
+        assert!(!files.contains(&"example.env"));
+
+        let dir = std::env::temp_dir().join(format!("kuiper-bundle-{}", uuid::Uuid::new_v4()));
+        let written = bundle.unpack(&dir).unwrap();
+        assert_eq!(written.len(), files.len());
+        assert_eq!(
+            fs::read_to_string(dir.join("subdir/request_in_subdir.kuiper")).unwrap(),
+            fs::read_to_string("../requests/subdir/request_in_subdir.kuiper").unwrap()
+        );
+        assert!(matches!(
+            bundle.unpack(&dir),
+            Err(KuiperError::InvalidBundle(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn environments_and_binary_files_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-bundle-{}", uuid::Uuid::new_v4()));
+        let collection = dir.join("collection");
+        fs::create_dir_all(collection.join("environments")).unwrap();
+        fs::write(
+            collection.join("environments/prod.json"),
+            r#"{"TOKEN":"secret"}"#,
+        )
+        .unwrap();
+        fs::write(collection.join("environments/staging.env"), "TOKEN=secret").unwrap();
+        fs::write(collection.join("upload.kuiper"), "{}").unwrap();
+        let avatar = [0x89, b'P', b'N', b'G', 0xff, 0x00];
+        fs::write(collection.join("avatar.png"), avatar).unwrap();
+
+        let bundle = Bundle::pack(&collection).unwrap();
+        let files: Vec<_> = bundle.files().collect();
+        assert_eq!(files, vec!["upload.kuiper", "avatar.png"]);
+
+        let unpacked = dir.join("unpacked");
+        bundle.unpack(&unpacked).unwrap();
+        assert_eq!(fs::read(unpacked.join("avatar.png")).unwrap(), avatar);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unpack_outside_root_test() {
+        let bundle = Bundle {
+            kuiper_version: env!("CARGO_PKG_VERSION").to_string(),
+            files: BTreeMap::from([("../escaped.kuiper".to_string(), String::new())]),
+            binary_files: BTreeMap::new(),
+        };
+        assert!(matches!(
+            bundle.unpack(std::env::temp_dir()),
+            Err(KuiperError::InvalidBundle(_))
+        ));
+    }
+}
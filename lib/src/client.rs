@@ -0,0 +1,2113 @@
+use crate::{
+    audit::AuditLog,
+    auth::{Auth, TokenCache},
+    cookies::CookieJar,
+    grpc,
+    hosts::HostPolicy,
+    observer::{Event, Observer, Observers},
+    plugins::Plugins,
+    ratelimit::RateLimiter,
+    sse,
+    tls::TlsBackend,
+    Attempt, HeaderMap, KuiperError, KuiperResult, Request, Response, Timings,
+};
+use log::{error, info, warn};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{HeaderValue, COOKIE, LOCATION, SET_COOKIE},
+    redirect,
+    tls::TlsInfo,
+    Method,
+};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "rustls")]
+use std::collections::HashSet;
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    fs::File,
+    future::Future,
+    io::{BufRead, BufReader, Read},
+    net::ToSocketAddrs,
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{self, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// How many redirects are followed before the redirect itself is returned, like `reqwest` does.
+const MAX_REDIRECTS: usize = 10;
+
+/// The content type of `body_ndjson_file` bodies, unless the request sets one of its own.
+pub(crate) const NDJSON: &str = "application/x-ndjson";
+
+/// The content type of `body_raw` bodies, unless the request sets one of its own.
+pub(crate) const TEXT: &str = "text/plain; charset=utf-8";
+
+/// The content type of `body_file_binary` bodies and files in multipart bodies, unless the
+/// request sets one of its own.
+pub(crate) const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Headers that are not sent along when a request is redirected to another origin.
+const CREDENTIAL_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// A version of the TLS protocol.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls1_0,
+    #[serde(rename = "1.1")]
+    Tls1_1,
+    #[serde(rename = "1.2")]
+    Tls1_2,
+    #[serde(rename = "1.3")]
+    Tls1_3,
+}
+
+impl TlsVersion {
+    pub(crate) fn to_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            TlsVersion::Tls1_0 => reqwest::tls::Version::TLS_1_0,
+            TlsVersion::Tls1_1 => reqwest::tls::Version::TLS_1_1,
+            TlsVersion::Tls1_2 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls1_3 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = KuiperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.0" => Ok(TlsVersion::Tls1_0),
+            "1.1" => Ok(TlsVersion::Tls1_1),
+            "1.2" => Ok(TlsVersion::Tls1_2),
+            "1.3" => Ok(TlsVersion::Tls1_3),
+            _ => Err(KuiperError::InvalidTlsVersion(s.to_string())),
+        }
+    }
+}
+
+impl Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TlsVersion::Tls1_0 => "1.0",
+                TlsVersion::Tls1_1 => "1.1",
+                TlsVersion::Tls1_2 => "1.2",
+                TlsVersion::Tls1_3 => "1.3",
+            }
+        )
+    }
+}
+
+/// Settings for the connections a [`Client`] makes, read from the `client` section of
+/// `kuiper.toml`. Settings that are not given use the defaults of `reqwest`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// Maximum number of idle connections kept per host, `0` disables keep-alive.
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout_ms: Option<u64>,
+    tcp_nodelay: Option<bool>,
+    tcp_keepalive_ms: Option<u64>,
+    connect_timeout_ms: Option<u64>,
+    min_tls_version: Option<TlsVersion>,
+    max_tls_version: Option<TlsVersion>,
+    /// The TLS library that connections are made with, native-tls unless set.
+    tls_backend: Option<TlsBackend>,
+    /// Whether requests are sent over HTTP/3, which needs the `http3` feature.
+    http3: Option<bool>,
+}
+
+impl ClientConfig {
+    /// Returns a config with the settings of `other`, falling back to the settings of `self`
+    /// where `other` does not specify them.
+    pub fn merge(self, other: ClientConfig) -> Self {
+        Self {
+            pool_max_idle_per_host: other.pool_max_idle_per_host.or(self.pool_max_idle_per_host),
+            pool_idle_timeout_ms: other.pool_idle_timeout_ms.or(self.pool_idle_timeout_ms),
+            tcp_nodelay: other.tcp_nodelay.or(self.tcp_nodelay),
+            tcp_keepalive_ms: other.tcp_keepalive_ms.or(self.tcp_keepalive_ms),
+            connect_timeout_ms: other.connect_timeout_ms.or(self.connect_timeout_ms),
+            min_tls_version: other.min_tls_version.or(self.min_tls_version),
+            max_tls_version: other.max_tls_version.or(self.max_tls_version),
+            tls_backend: other.tls_backend.or(self.tls_backend),
+            http3: other.http3.or(self.http3),
+        }
+    }
+
+    pub fn with_pool_max_idle_per_host(mut self, max: Option<usize>) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    pub fn with_pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout_ms = timeout.map(|t| t.as_millis() as u64);
+        self
+    }
+
+    pub fn with_tcp_nodelay(mut self, nodelay: Option<bool>) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    pub fn with_tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive_ms = interval.map(|i| i.as_millis() as u64);
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout_ms = timeout.map(|t| t.as_millis() as u64);
+        self
+    }
+
+    pub fn with_min_tls_version(mut self, version: Option<TlsVersion>) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    pub fn with_max_tls_version(mut self, version: Option<TlsVersion>) -> Self {
+        self.max_tls_version = version;
+        self
+    }
+
+    pub fn with_tls_backend(mut self, backend: Option<TlsBackend>) -> Self {
+        self.tls_backend = backend;
+        self
+    }
+
+    pub fn with_http3(mut self, http3: Option<bool>) -> Self {
+        self.http3 = http3;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(&self) -> Option<usize> {
+        self.pool_max_idle_per_host
+    }
+
+    pub fn pool_idle_timeout(&self) -> Option<Duration> {
+        self.pool_idle_timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn tcp_nodelay(&self) -> Option<bool> {
+        self.tcp_nodelay
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive_ms.map(Duration::from_millis)
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn min_tls_version(&self) -> Option<TlsVersion> {
+        self.min_tls_version
+    }
+
+    pub fn max_tls_version(&self) -> Option<TlsVersion> {
+        self.max_tls_version
+    }
+
+    pub fn tls_backend(&self) -> TlsBackend {
+        self.tls_backend.unwrap_or_default()
+    }
+
+    pub fn http3(&self) -> bool {
+        self.http3.unwrap_or(false)
+    }
+}
+
+/// A limit on how many bytes of a response body are read into memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BodyLimit {
+    max: u64,
+    truncate: bool,
+}
+
+impl BodyLimit {
+    /// Fails requests whose response body is larger than `max` bytes.
+    pub fn new(max: u64) -> Self {
+        Self {
+            max,
+            truncate: false,
+        }
+    }
+
+    /// Truncates response bodies to the limit instead of failing.
+    pub fn truncating(mut self) -> Self {
+        self.truncate = true;
+        self
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn truncate(&self) -> bool {
+        self.truncate
+    }
+}
+
+/// Applies a [`ClientConfig`] to a blocking or async `reqwest` client builder, which share the
+/// names of their methods but not a trait. Fails if the TLS backend can't be used as configured.
+macro_rules! configure {
+    ($builder:expr, $config:expr) => {{
+        let mut builder = $builder;
+        let config: &ClientConfig = $config;
+        // the certificates of servers are checked against the pins of requests
+        builder = builder.tls_info(true);
+        if let Some(max) = config.pool_max_idle_per_host() {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = config.pool_idle_timeout() {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(nodelay) = config.tcp_nodelay() {
+            builder = builder.tcp_nodelay(nodelay);
+        }
+        if let Some(interval) = config.tcp_keepalive() {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(timeout) = config.connect_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+        if config.tls_backend() == $crate::tls::TlsBackend::Rustls {
+            #[cfg(feature = "rustls")]
+            {
+                $crate::tls::install_provider();
+                builder = builder.use_rustls_tls();
+                for certificate in $crate::tls::system_roots()? {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            #[cfg(not(feature = "rustls"))]
+            return Err($crate::KuiperError::UnsupportedTls(
+                "kuiper was built without the `rustls` feature".to_string(),
+            ));
+        }
+        if config.http3() {
+            // servers are not asked whether they speak HTTP/3 first, with `Alt-Svc`
+            #[cfg(feature = "http3")]
+            {
+                builder = builder.http3_prior_knowledge();
+            }
+            #[cfg(not(feature = "http3"))]
+            return Err($crate::KuiperError::UnsupportedHttp3(
+                "kuiper was built without the `http3` feature".to_string(),
+            ));
+        }
+        let (min, max) = $crate::tls::versions(
+            config.tls_backend(),
+            config.min_tls_version(),
+            config.max_tls_version(),
+        )?;
+        if let Some(version) = min {
+            builder = builder.min_tls_version(version.to_reqwest());
+        }
+        if let Some(version) = max {
+            builder = builder.max_tls_version(version.to_reqwest());
+        }
+        builder
+    }};
+}
+
+/// Builds the `reqwest` request for a [`Request`] with a blocking or async `reqwest` client, using
+/// `$file_body` to turn the path of a body file into a body, and `$sized_body` to turn a reader of
+/// a known length into one.
+macro_rules! build_request {
+    ($client:expr, $request:expr, $file_body:expr, $sized_body:expr) => {{
+        let request: &Request = $request;
+        let mut builder = $client.request($crate::client::method(request)?, request.uri());
+        for (name, value) in request.headers() {
+            // the content type of a multipart body has to name its boundary
+            if request.multipart().is_some() && name.eq_ignore_ascii_case("content-type") {
+                continue;
+            }
+            if let Some(value) = value {
+                builder = builder.header(name, value);
+            }
+        }
+        let has_content_type = request
+            .headers()
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case("content-type"));
+        if let Some(body) = request.body() {
+            builder = builder.json(body);
+        } else if let Some(path) = request.body_ndjson_file() {
+            if !has_content_type {
+                builder = builder.header(reqwest::header::CONTENT_TYPE, $crate::client::NDJSON);
+            }
+            builder = builder.body($file_body(path)?);
+        } else if let Some(body) = request.body_raw() {
+            if !has_content_type {
+                let content_type = request.content_type().unwrap_or($crate::client::TEXT);
+                builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+            }
+            builder = builder.body(body.to_string());
+        } else if let Some(form) = request.form() {
+            builder = builder.form(form);
+        } else if let Some(parts) = request.multipart() {
+            let body = $crate::multipart::encode(parts)?;
+            builder = builder.header(reqwest::header::CONTENT_TYPE, &body.content_type);
+            builder = builder.body($sized_body(body.reader, body.length)?);
+        } else if let Some(path) = request.body_file_binary() {
+            if !has_content_type {
+                let content_type = request
+                    .content_type()
+                    .unwrap_or($crate::client::OCTET_STREAM);
+                builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+            }
+            let file = std::fs::File::open(path)?;
+            let length = file.metadata()?.len();
+            builder = builder.body($sized_body(Box::new(file), length)?);
+        } else if let Some(graphql) = request.graphql() {
+            builder = builder.json(&graphql.body()?);
+        } else if let Some(call) = request.jsonrpc() {
+            builder = builder.json(&call.body());
+        }
+        if let Some(timeout) = request.timeout() {
+            builder = builder.timeout(timeout);
+        }
+        builder
+            .query(&request.params().iter().collect::<Vec<_>>())
+            .build()?
+    }};
+}
+
+#[cfg(feature = "async")]
+pub(crate) use {build_request, configure};
+
+/// An HTTP client whose connections are pooled and reused across every request it sends.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: reqwest::blocking::Client,
+    #[cfg(feature = "rustls")]
+    config: ClientConfig,
+    /// The origins whose TLS session has been logged, shared by clones of the client.
+    #[cfg(feature = "rustls")]
+    tls_origins: Arc<Mutex<HashSet<String>>>,
+    /// Time spent resolving hosts by every request sent so far.
+    dns: Arc<Mutex<Duration>>,
+    /// Time spent opening connections, resolving hosts included, by every request sent so far.
+    connect: Arc<Mutex<Duration>>,
+    host_policy: HostPolicy,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Once set, requests are no longer sent and response bodies no longer read.
+    cancel_flag: Option<&'static AtomicBool>,
+    audit_log: Option<Arc<AuditLog>>,
+    /// Tokens fetched for requests with OAuth client credentials, shared by clones of the client.
+    tokens: Arc<TokenCache>,
+    observers: Observers,
+    /// The cookies that responses set, shared by clones of the client, if it keeps them.
+    cookies: Option<Arc<Mutex<CookieJar>>>,
+    /// The plugins that add the auth schemes of `plugin` auth.
+    plugins: Plugins,
+    /// Whether requests are sent over HTTP/3.
+    http3: bool,
+}
+
+impl Client {
+    pub fn new(config: &ClientConfig) -> KuiperResult<Self> {
+        Self::from_builder(reqwest::blocking::Client::builder(), config, None)
+    }
+
+    /// Applies `config` to `builder`, which may have been customized beforehand, for example
+    /// with a proxy. Hosts are resolved with `resolver`, or the system resolver if `None`.
+    pub fn from_builder(
+        mut builder: reqwest::blocking::ClientBuilder,
+        config: &ClientConfig,
+        resolver: Option<Arc<dyn Resolve>>,
+    ) -> KuiperResult<Self> {
+        let dns = Arc::new(Mutex::new(Duration::ZERO));
+        let observers = Observers::default();
+        builder = builder.dns_resolver(Arc::new(TimedResolver {
+            inner: resolver,
+            spent: dns.clone(),
+            observers: observers.clone(),
+        }));
+        let connect = Arc::new(Mutex::new(Duration::ZERO));
+        builder = builder.connector_layer(TimedConnectLayer {
+            spent: connect.clone(),
+        });
+        // redirects are followed by `send_with_limit`, so that every hop can be reported
+        builder = configure!(builder, config).redirect(redirect::Policy::none());
+
+        Ok(Self {
+            inner: builder.build()?,
+            #[cfg(feature = "rustls")]
+            config: *config,
+            #[cfg(feature = "rustls")]
+            tls_origins: Arc::default(),
+            dns,
+            connect,
+            host_policy: HostPolicy::default(),
+            rate_limiter: None,
+            audit_log: None,
+            cancel_flag: None,
+            tokens: Arc::default(),
+            observers,
+            cookies: None,
+            plugins: Plugins::default(),
+            http3: config.http3(),
+        })
+    }
+
+    /// Reports the [`Event`]s of every request sent with the client, or any of its clones, to
+    /// `observer`.
+    pub fn with_observer(self, observer: impl Observer + 'static) -> Self {
+        self.observers.add(Arc::new(observer));
+        self
+    }
+
+    /// Reports `event` to the observers of the client. Runners that send requests with the
+    /// client use this to report what happens around sending them, like retries.
+    pub fn notify(&self, event: &Event) {
+        self.observers.notify(event);
+    }
+
+    /// Keeps requests within the per-host rate limits of `limiter`.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    /// Authenticates requests with `plugin` auth with the auth schemes that `plugins` add.
+    pub fn with_plugins(mut self, plugins: Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    pub fn plugins(&self) -> &Plugins {
+        &self.plugins
+    }
+
+    /// Records every request sent with [`Client::send`] in `log`.
+    pub fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit_log = Some(Arc::new(log));
+        self
+    }
+
+    /// Waits until a request to `uri` fits within the rate limit of its host, if it has one, and
+    /// returns how long that took.
+    pub fn throttle(&self, uri: &str) -> KuiperResult<Duration> {
+        match &self.rate_limiter {
+            Some(limiter) => limiter.acquire(uri),
+            None => Ok(Duration::ZERO),
+        }
+    }
+
+    /// Fails requests with [`KuiperError::Cancelled`] once `flag` is set, like by a signal
+    /// handler. Requests are not sent anymore, and bodies that are being read stop at the next
+    /// chunk, but a request that is waiting for its response headers keeps waiting for them.
+    pub fn with_cancel_flag(mut self, flag: &'static AtomicBool) -> Self {
+        self.cancel_flag = Some(flag);
+        self
+    }
+
+    /// Refuses to send requests to hosts that `policy` doesn't allow.
+    pub fn with_host_policy(mut self, policy: HostPolicy) -> Self {
+        self.host_policy = policy;
+        self
+    }
+
+    /// Keeps the cookies that responses set in a jar that starts out as `jar`, and sends them
+    /// with later requests, unless a request sets its own `Cookie` header.
+    pub fn with_cookie_jar(mut self, jar: CookieJar) -> Self {
+        self.cookies = Some(Arc::new(Mutex::new(jar)));
+        self
+    }
+
+    /// The cookies kept so far, if the client keeps them.
+    pub fn cookie_jar(&self) -> Option<CookieJar> {
+        self.cookies
+            .as_ref()
+            .map(|cookies| cookies.lock().unwrap().clone())
+    }
+
+    /// Returns an error if the host policy doesn't allow sending a request to `uri`.
+    pub fn check_host(&self, uri: &str) -> KuiperResult<()> {
+        self.host_policy.check(uri)
+    }
+
+    /// The underlying `reqwest` client.
+    pub fn inner(&self) -> &reqwest::blocking::Client {
+        &self.inner
+    }
+
+    /// Sends `request` and reads its response, after checking that its host is allowed and
+    /// waiting until it fits within the rate limit of its host.
+    ///
+    /// Redirects are followed, and listed in the [`attempts`](Response::attempts) of the
+    /// response along with the token fetched for the request if one was.
+    pub fn send(&self, request: &Request) -> KuiperResult<Response> {
+        self.send_with_limit(request, None)
+    }
+
+    /// Like [`Client::send`], without reading more of the response body into memory than
+    /// `limit` allows.
+    pub fn send_with_limit(
+        &self,
+        request: &Request,
+        limit: Option<BodyLimit>,
+    ) -> KuiperResult<Response> {
+        self.send_notifying(request, limit, None)
+    }
+
+    /// Like [`Client::send_with_limit`], calling `on_event` with every event of a
+    /// `text/event-stream` response as soon as it arrives, instead of only once the stream has
+    /// ended. The response still has the whole stream as its body.
+    pub fn send_streaming(
+        &self,
+        request: &Request,
+        limit: Option<BodyLimit>,
+        on_event: &mut dyn FnMut(&sse::Event),
+    ) -> KuiperResult<Response> {
+        self.send_notifying(request, limit, Some(on_event))
+    }
+
+    fn send_notifying(
+        &self,
+        request: &Request,
+        limit: Option<BodyLimit>,
+        on_event: Option<&mut dyn FnMut(&sse::Event)>,
+    ) -> KuiperResult<Response> {
+        let start = Instant::now();
+        let result = self.send_and_follow(request, limit, on_event);
+        self.notify(&Event::Completed {
+            request: request.name().to_string(),
+            status: result.as_ref().ok().map(Response::status),
+            duration: start.elapsed(),
+        });
+        result
+    }
+
+    fn send_and_follow(
+        &self,
+        request: &Request,
+        limit: Option<BodyLimit>,
+        on_event: Option<&mut dyn FnMut(&sse::Event)>,
+    ) -> KuiperResult<Response> {
+        if is_cancelled(self.cancel_flag) {
+            return Err(KuiperError::Cancelled);
+        }
+        self.check_host(request.uri())?;
+        let mut attempts = Vec::new();
+        let authorized;
+        let request = match request.auth() {
+            Some(Auth::OauthClientCredentials { .. }) => {
+                authorized = self.authorize(request, &mut attempts)?;
+                &authorized
+            }
+            Some(Auth::Plugin { scheme, params }) => {
+                let headers = self
+                    .plugins
+                    .auth(scheme)?
+                    .authenticate(scheme, params, request)?;
+                let mut request = request.clone();
+                for (name, value) in headers {
+                    request.set_auth_header(&name, value);
+                }
+                authorized = request;
+                &authorized
+            }
+            _ => request,
+        };
+        match self.throttle(request.uri()) {
+            Ok(delay) if !delay.is_zero() => info!(
+                "waited {}ms before sending '{}' to stay within its rate limit",
+                delay.as_millis(),
+                request.name()
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("failed to update the rate limit ledger: '{e}'"),
+        }
+
+        if let Some(call) = request.grpc() {
+            self.notify(&Event::SendStarted {
+                request: request.name().to_string(),
+                method: "gRPC".to_string(),
+                uri: request.uri().to_string(),
+            });
+            let result = grpc::call(request, call);
+            self.audit(request, result.as_ref().ok().map(Response::status));
+            return result.map(|response| response.with_earlier_attempts(attempts));
+        }
+
+        let start = Instant::now();
+        let mut current = Cow::Borrowed(request);
+        let mut redirects = 0;
+        let (response, timings) = loop {
+            self.notify(&Event::SendStarted {
+                request: request.name().to_string(),
+                method: current.method().to_string(),
+                uri: current.uri().to_string(),
+            });
+            let mut built = build_request!(self.inner, &current, stream_file, stream_sized);
+            self.attach_cookies(&mut built);
+            let result = self.execute(built);
+            let status = result.as_ref().ok().map(|(response, _)| response.status());
+            self.audit(&current, status.map(|status| status.as_u16()));
+            let (response, timings) = result?;
+            // cookies set by a redirect, like that of a login form, are kept too
+            self.store_cookies(&response);
+            check_pin(&current, response.url(), response.extensions().get())?;
+            let Some(next) = follow(&current, &response) else {
+                break (response, timings);
+            };
+            if redirects == MAX_REDIRECTS {
+                warn!(
+                    "not following more than {MAX_REDIRECTS} redirects of '{}'",
+                    request.name()
+                );
+                break (response, timings);
+            }
+            self.check_host(next.uri())?;
+            attempts.push(Attempt::Redirect {
+                status: response.status().as_u16(),
+                from: response.url().to_string(),
+                to: next.uri().to_string(),
+            });
+            redirects += 1;
+            current = Cow::Owned(next);
+        };
+        let status = response.status().as_u16();
+        let headers = response_headers(response.headers());
+        let download_start = Instant::now();
+        let streams_events = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(sse::is_event_stream);
+        let body = match on_event {
+            Some(on_event) if streams_events => read_events(
+                request,
+                response,
+                limit,
+                &self.observers,
+                self.cancel_flag,
+                on_event,
+            )?,
+            _ => read_body(request, response, limit, &self.observers, self.cancel_flag)?,
+        };
+        let timings = timings.with_download(download_start.elapsed());
+        Ok(Response::from_bytes(status, headers, body, start.elapsed())
+            .with_timings(timings)
+            .with_earlier_attempts(attempts))
+    }
+
+    /// Adds the kept cookies for the url of `request` to it, unless it has a `Cookie` header.
+    fn attach_cookies(&self, request: &mut reqwest::blocking::Request) {
+        let Some(cookies) = &self.cookies else {
+            return;
+        };
+        if request.headers().contains_key(COOKIE) {
+            return;
+        }
+        let header = cookies.lock().unwrap().header(request.url());
+        if let Some(value) = header.and_then(|header| HeaderValue::from_str(&header).ok()) {
+            request.headers_mut().insert(COOKIE, value);
+        }
+    }
+
+    /// Keeps the cookies that `response` sets, if the client keeps them.
+    fn store_cookies(&self, response: &reqwest::blocking::Response) {
+        if let Some(cookies) = &self.cookies {
+            let set_cookies = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok());
+            cookies.lock().unwrap().store(response.url(), set_cookies);
+        }
+    }
+
+    /// Records that `request` was sent, and got a response with `status` if it did, in the audit
+    /// log if there is one.
+    fn audit(&self, request: &Request, status: Option<u16>) {
+        if let Some(log) = &self.audit_log {
+            if let Err(e) = log.record(request, status) {
+                error!(
+                    "failed to record '{}' in the audit log {:?}: '{e}'",
+                    request.name(),
+                    log.path()
+                );
+            }
+        }
+    }
+
+    /// Returns `request` with a bearer token from its OAuth client credentials, fetching one if
+    /// none has been fetched yet or the last one has expired, which is added to `attempts`.
+    fn authorize(&self, request: &Request, attempts: &mut Vec<Attempt>) -> KuiperResult<Request> {
+        let Some(Auth::OauthClientCredentials {
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+        }) = request.auth()
+        else {
+            return Ok(request.clone());
+        };
+        let token = self
+            .tokens
+            .token(token_url, client_id, scope.as_deref(), || {
+                self.check_host(token_url)?;
+                info!(
+                    "fetching a token for '{}' from '{token_url}'",
+                    request.name()
+                );
+                let mut form = vec![
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret),
+                ];
+                if let Some(scope) = scope {
+                    form.push(("scope", scope));
+                }
+                let response = self.inner.post(token_url).form(&form).send()?;
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(KuiperError::AuthFailed(format!(
+                        "'{token_url}' responded with {}",
+                        status.as_u16()
+                    )));
+                }
+                attempts.push(Attempt::AuthRefresh {
+                    token_url: token_url.clone(),
+                });
+                Ok(response.text()?)
+            })?;
+        let mut request = request.clone();
+        request.set_auth_header("Authorization", format!("Bearer {token}"));
+        Ok(request)
+    }
+
+    /// Sends `request` and returns the response as soon as its headers have arrived, along with
+    /// how long that took. The download time of the returned timings is left at zero, for the
+    /// caller to set once it has read the body.
+    ///
+    /// The DNS and connect times include lookups and connections for other requests sent at the
+    /// same time with this client.
+    pub fn execute(
+        &self,
+        mut request: reqwest::blocking::Request,
+    ) -> reqwest::Result<(reqwest::blocking::Response, Timings)> {
+        if let Some(version) = self.version() {
+            *request.version_mut() = version;
+        }
+        let dns_before = *self.dns.lock().unwrap();
+        let connect_before = *self.connect.lock().unwrap();
+        let start = Instant::now();
+        let response = self.inner.execute(request)?;
+        let elapsed = start.elapsed();
+        let dns = *self.dns.lock().unwrap() - dns_before;
+        // the connector resolves the host before it connects
+        let opening = *self.connect.lock().unwrap() - connect_before;
+        let connect = opening.saturating_sub(dns);
+        let timings = Timings::new(
+            (!dns.is_zero()).then_some(dns),
+            elapsed.saturating_sub(opening),
+            Duration::ZERO,
+        )
+        .with_connect((!opening.is_zero()).then_some(connect));
+        #[cfg(feature = "rustls")]
+        self.log_tls_session(response.url());
+        Ok((response, timings))
+    }
+
+    /// Logs the TLS version and cipher suite negotiated with the origin of `url` the first time a
+    /// request is sent to it, when connections are made with rustls and debug logging is on.
+    #[cfg(feature = "rustls")]
+    fn log_tls_session(&self, url: &reqwest::Url) {
+        if url.scheme() != "https"
+            || self.config.tls_backend() != TlsBackend::Rustls
+            || !log::log_enabled!(log::Level::Debug)
+        {
+            return;
+        }
+        let origin = url.origin().ascii_serialization();
+        if !self.tls_origins.lock().unwrap().insert(origin.clone()) {
+            return;
+        }
+        match crate::tls::negotiate(url, &self.config) {
+            Ok(session) => log::debug!("negotiated {session} with {origin}"),
+            Err(e) => log::debug!("failed to check the TLS session with {origin}: '{e}'"),
+        }
+    }
+
+    /// The HTTP version that requests are sent with, HTTP/3 if `http3` is set and whatever the
+    /// server supports otherwise.
+    pub fn version(&self) -> Option<reqwest::Version> {
+        self.http3.then_some(reqwest::Version::HTTP_3)
+    }
+}
+
+/// Returns the request to send after `response` to `request`, if it is a redirect.
+///
+/// Like browsers do, a `301`, `302` or `303` turns anything but a `HEAD` into a `GET` without a
+/// body, and credentials are not sent along to another origin.
+fn follow(request: &Request, response: &reqwest::blocking::Response) -> Option<Request> {
+    let status = response.status().as_u16();
+    if !matches!(status, 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let to = response.url().join(location).ok()?;
+
+    let mut next = request.clone();
+    if matches!(status, 301..=303) && !request.method.eq_ignore_ascii_case("HEAD") {
+        next.method = "GET".to_string();
+        next.body = None;
+        next.body_ndjson_file = None;
+        next.body_raw = None;
+        next.form = None;
+        next.multipart = None;
+        next.body_file_binary = None;
+        next.graphql = None;
+        next.jsonrpc = None;
+        next.headers
+            .retain(|name, _| !name.eq_ignore_ascii_case("content-type"));
+    }
+    if to.origin() != response.url().origin() {
+        next.pin_sha256 = None;
+        next.headers.retain(|name, _| {
+            !CREDENTIAL_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header))
+        });
+    }
+    // the location has the query of its own
+    next.uri = to.to_string();
+    next.params.clear();
+    Some(next)
+}
+
+/// Fails if `request` pins a certificate fingerprint, and the certificate the server at `url`
+/// presented doesn't have it.
+pub(crate) fn check_pin(
+    request: &Request,
+    url: &reqwest::Url,
+    tls_info: Option<&TlsInfo>,
+) -> KuiperResult<()> {
+    let Some(pin) = request.pin_sha256() else {
+        return Ok(());
+    };
+    let host = url.host_str().unwrap_or_default().to_string();
+    let Some(certificate) = tls_info.and_then(TlsInfo::peer_certificate) else {
+        error!("'{host}' was not reached over TLS, so its certificate can't be checked");
+        return Err(KuiperError::CertificatePinMismatch(host));
+    };
+    let fingerprint = fingerprint(certificate);
+    if fingerprint != normalize_fingerprint(pin) {
+        error!("the certificate of '{host}' has the fingerprint {fingerprint}, expected {pin}");
+        return Err(KuiperError::CertificatePinMismatch(host));
+    }
+    Ok(())
+}
+
+/// The SHA-256 fingerprint of a DER encoded certificate, in lowercase hex.
+fn fingerprint(certificate: &[u8]) -> String {
+    digest(&SHA256, certificate)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Lowercases a fingerprint and removes its separators, as in the `AB:CD:...` printed by
+/// `openssl x509 -fingerprint -sha256`.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .trim()
+        .trim_start_matches("sha256/")
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Streams the file at `path` as a chunked body, without reading it into memory.
+fn stream_file(path: &Path) -> KuiperResult<reqwest::blocking::Body> {
+    Ok(reqwest::blocking::Body::new(File::open(path)?))
+}
+
+/// Streams an encoded multipart body with a `Content-Length`, reading its files as they are sent.
+fn stream_sized(
+    reader: Box<dyn Read + Send>,
+    length: u64,
+) -> KuiperResult<reqwest::blocking::Body> {
+    Ok(reqwest::blocking::Body::sized(reader, length))
+}
+
+/// Returns `true` if `cancel_flag` is set.
+fn is_cancelled(cancel_flag: Option<&AtomicBool>) -> bool {
+    cancel_flag.is_some_and(|flag| flag.load(Ordering::SeqCst))
+}
+
+/// Reads the body of `response`, without reading more than `limit` allows into memory, until
+/// `cancel_flag` is set.
+fn read_body(
+    request: &Request,
+    mut response: reqwest::blocking::Response,
+    limit: Option<BodyLimit>,
+    observers: &Observers,
+    cancel_flag: Option<&AtomicBool>,
+) -> KuiperResult<Vec<u8>> {
+    let BodyLimit { max, truncate } = match limit {
+        Some(limit) => limit,
+        None if observers.is_empty() && cancel_flag.is_none() => {
+            return Ok(response.bytes()?.to_vec())
+        }
+        None => BodyLimit::new(u64::MAX),
+    };
+
+    let total = response.content_length();
+    if !truncate && total.is_some_and(|len| len > max) {
+        return Err(KuiperError::ResponseTooLarge(max));
+    }
+
+    // read in chunks, so that observers can follow along and reading can be cancelled
+    let mut body = Vec::new();
+    let mut chunk = vec![0; 64 * 1024];
+    let mut reader = (&mut response).take(max.saturating_add(1));
+    loop {
+        if is_cancelled(cancel_flag) {
+            return Err(KuiperError::Cancelled);
+        }
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        body.extend_from_slice(&chunk[..read]);
+        observers.notify(&Event::BytesReceived {
+            request: request.name().to_string(),
+            received: body.len() as u64,
+            total,
+        });
+    }
+    limit_body(request, body, BodyLimit { max, truncate })
+}
+
+/// Reads the body of a `text/event-stream` response line by line, calling `on_event` with every
+/// event as soon as its blank line has been read. Once the body is longer than `limit` allows,
+/// reading stops, and once `cancel_flag` is set, it fails.
+fn read_events(
+    request: &Request,
+    response: reqwest::blocking::Response,
+    limit: Option<BodyLimit>,
+    observers: &Observers,
+    cancel_flag: Option<&AtomicBool>,
+    on_event: &mut dyn FnMut(&sse::Event),
+) -> KuiperResult<Vec<u8>> {
+    let limit = limit.unwrap_or(BodyLimit::new(u64::MAX));
+    let total = response.content_length();
+    let mut reader = BufReader::new(response.take(limit.max.saturating_add(1)));
+    let mut parser = sse::Parser::default();
+    let mut body = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        if is_cancelled(cancel_flag) {
+            return Err(KuiperError::Cancelled);
+        }
+        line.clear();
+        let read = match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        body.extend_from_slice(&line[..read]);
+        if !observers.is_empty() {
+            observers.notify(&Event::BytesReceived {
+                request: request.name().to_string(),
+                received: body.len() as u64,
+                total,
+            });
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Some(event) = parser.line(text.trim_end_matches(['\r', '\n'])) {
+            on_event(&event);
+        }
+    }
+    limit_body(request, body, limit)
+}
+
+/// Fails, or truncates, `body` if it is longer than `limit`.
+pub(crate) fn limit_body(
+    request: &Request,
+    mut body: Vec<u8>,
+    BodyLimit { max, truncate }: BodyLimit,
+) -> KuiperResult<Vec<u8>> {
+    if body.len() as u64 > max {
+        if !truncate {
+            return Err(KuiperError::ResponseTooLarge(max));
+        }
+        warn!(
+            "response body of '{}' is larger than {max} bytes, truncating it",
+            request.name()
+        );
+        body.truncate(max as usize);
+    }
+
+    Ok(body)
+}
+
+pub(crate) fn method(request: &Request) -> KuiperResult<Method> {
+    Method::from_bytes(request.method().as_bytes())
+        .map_err(|_| KuiperError::InvalidMethod(request.method().to_string()))
+}
+
+pub(crate) fn response_headers(headers: &reqwest::header::HeaderMap) -> HeaderMap {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Resolves hosts with another resolver, or the system resolver, and adds up the time spent.
+struct TimedResolver {
+    inner: Option<Arc<dyn Resolve>>,
+    spent: Arc<Mutex<Duration>>,
+    observers: Observers,
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.observers.notify(&Event::ResolveStarted {
+            host: name.as_str().to_string(),
+        });
+        let inner = self.inner.clone();
+        let spent = self.spent.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs: Addrs = match inner {
+                Some(inner) => inner.resolve(name).await?,
+                // getaddrinfo blocks, so it runs where it doesn't hold up the runtime
+                None => {
+                    let addrs: Vec<_> = tokio::task::spawn_blocking(move || {
+                        (name.as_str(), 0)
+                            .to_socket_addrs()
+                            .map(Iterator::collect::<Vec<_>>)
+                    })
+                    .await??;
+                    Box::new(addrs.into_iter())
+                }
+            };
+            *spent.lock().unwrap() += start.elapsed();
+            Ok(addrs)
+        })
+    }
+}
+
+/// Wraps the connector of the HTTP client, which resolves the host, connects and does the TLS
+/// handshake, and adds up the time spent opening connections.
+#[derive(Clone)]
+struct TimedConnectLayer {
+    spent: Arc<Mutex<Duration>>,
+}
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect {
+            inner,
+            spent: self.spent.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TimedConnect<S> {
+    inner: S,
+    spent: Arc<Mutex<Duration>>,
+}
+
+impl<S, R> Service<R> for TimedConnect<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, destination: R) -> Self::Future {
+        let connecting = self.inner.call(destination);
+        let spent = self.spent.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let connection = connecting.await;
+            *spent.lock().unwrap() += start.elapsed();
+            connection
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+    use test_log::test;
+
+    /// Answers `count` requests with a body of `body`, and returns the address to send them to.
+    fn serve(count: usize, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 201 Created\r\nLocation: /orders/7\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn send_test() {
+        let addr = serve(3, r#"{"id":7}"#);
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "{addr}/orders", "method": "POST", "body": {{}} }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+
+        let response = client.send(&request).unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.header("location"), Some("/orders/7"));
+        assert_eq!(response.body(), r#"{"id":7}"#);
+        assert!(response.timings().unwrap().connect().is_some());
+
+        assert!(matches!(
+            client.send_with_limit(&request, Some(BodyLimit::new(4))),
+            Err(KuiperError::ResponseTooLarge(4))
+        ));
+        let truncated = client
+            .send_with_limit(&request, Some(BodyLimit::new(4).truncating()))
+            .unwrap();
+        assert_eq!(truncated.body(), r#"{"id"#);
+    }
+
+    #[test]
+    fn redirect_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let (lines, received) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 303 See Other\r\nLocation: /orders/7?view=full\r\n",
+                "HTTP/1.1 200 OK\r\n",
+            ];
+            for (stream, response) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                lines.send(line.trim_end().to_string()).unwrap();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "{response}Content-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "{addr}/orders", "method": "POST", "params": {{ "dry_run": "true" }} }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+
+        let response = client.send(&request).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.attempts(),
+            &[Attempt::Redirect {
+                status: 303,
+                from: format!("{addr}/orders?dry_run=true"),
+                to: format!("{addr}/orders/7?view=full"),
+            }]
+        );
+        let lines: Vec<_> = received.try_iter().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "POST /orders?dry_run=true HTTP/1.1",
+                "GET /orders/7?view=full HTTP/1.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn cookie_jar_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let (cookies, received) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 302 Found\r\nLocation: /me\r\nSet-Cookie: session=abc; Path=/; HttpOnly\r\n",
+                "HTTP/1.1 200 OK\r\nSet-Cookie: theme=dark; Path=/\r\n",
+                "HTTP/1.1 200 OK\r\n",
+            ];
+            for (stream, response) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                let mut cookie = None;
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(value) = line.strip_prefix("cookie: ") {
+                        cookie = Some(value.trim_end().to_string());
+                    }
+                    line.clear();
+                }
+                cookies.send(cookie).unwrap();
+                write!(
+                    stream,
+                    "{response}Content-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+        let login: Request =
+            serde_json::from_str(&format!(r#"{{ "uri": "{addr}/login", "method": "POST" }}"#))
+                .unwrap();
+        let own: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "{addr}/me", "method": "GET", "headers": {{ "Cookie": "session=mine" }} }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap()
+        .with_cookie_jar(CookieJar::default());
+
+        client.send(&login).unwrap();
+        client.send(&own).unwrap();
+        let cookies: Vec<_> = received.try_iter().collect();
+        assert_eq!(
+            cookies,
+            vec![
+                None,
+                // the cookie set by the redirect is sent to where it leads
+                Some("session=abc".to_string()),
+                Some("session=mine".to_string()),
+            ]
+        );
+        assert_eq!(client.cookie_jar().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn oauth_client_credentials_test() {
+        // the token is fetched once, and reused for the second request
+        let addr = serve(3, r#"{"access_token":"t","expires_in":3600}"#);
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "{addr}/orders", "method": "GET", "auth": {{ "type": "oauth_client_credentials", "token_url": "{addr}/token", "client_id": "id", "client_secret": "secret" }} }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let mut attempts = Vec::new();
+        assert_eq!(
+            client.authorize(&request, &mut attempts).unwrap().headers()["Authorization"]
+                .as_deref(),
+            Some("Bearer t")
+        );
+        assert_eq!(
+            attempts,
+            vec![Attempt::AuthRefresh {
+                token_url: format!("{addr}/token")
+            }]
+        );
+        let response = client.send(&request).unwrap();
+        assert_eq!(response.status(), 201);
+        assert!(response.attempts().is_empty());
+        assert_eq!(client.send(&request).unwrap().status(), 201);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn send_async_test() {
+        use crate::AsyncClient;
+
+        let addr = serve(3, r#"{"id":7}"#);
+        let request: Request =
+            serde_json::from_str(&format!(r#"{{ "uri": "{addr}/orders", "method": "GET" }}"#))
+                .unwrap();
+        let client = AsyncClient::from_builder(
+            reqwest::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+        )
+        .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let response = runtime.block_on(client.send(&request)).unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.body(), r#"{"id":7}"#);
+
+        let truncated = runtime
+            .block_on(client.send_with_limit(&request, Some(BodyLimit::new(2).truncating())))
+            .unwrap();
+        assert_eq!(truncated.body(), r#"{""#);
+
+        // files are read on the blocking pool, and failing to read them fails the request
+        let file =
+            std::env::temp_dir().join(format!("kuiper-async-{}.ndjson", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "{}\n").unwrap();
+        let upload = |path: &std::path::Path| -> Request {
+            serde_json::from_value(serde_json::json!({
+                "uri": format!("{addr}/orders"),
+                "method": "POST",
+                "body_ndjson_file": path,
+            }))
+            .unwrap()
+        };
+        let response = runtime.block_on(client.send(&upload(&file))).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(response.status(), 201);
+        assert!(matches!(
+            runtime.block_on(client.send(&upload(&file))),
+            Err(KuiperError::IoError(_))
+        ));
+
+        let denied = AsyncClient::new(&ClientConfig::default())
+            .unwrap()
+            .with_host_policy(
+                serde_json::from_str(r#"{ "allowed_hosts": ["example.com"] }"#).unwrap(),
+            );
+        assert!(matches!(
+            runtime.block_on(denied.send(&request)),
+            Err(KuiperError::HostNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn cancel_flag_test() {
+        static CANCELLED: AtomicBool = AtomicBool::new(false);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n0123456789"
+            )
+            .unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(100));
+            let _ = write!(stream, "0123456789");
+        });
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "http://{addr}/", "method": "GET" }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap()
+        .with_cancel_flag(&CANCELLED)
+        // cancelled while the body is being read
+        .with_observer(|event: &Event| {
+            if let Event::BytesReceived { .. } = event {
+                CANCELLED.store(true, Ordering::SeqCst);
+            }
+        });
+        assert!(matches!(client.send(&request), Err(KuiperError::Cancelled)));
+        // and not sent at all once cancelled
+        let start = Instant::now();
+        assert!(matches!(client.send(&request), Err(KuiperError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn ndjson_body_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-ndjson-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let events = "{\"id\":1}\n{\"id\":2}\n";
+        std::fs::write(dir.join("events.ndjson"), events).unwrap();
+
+        // echoes the headers that describe the body, and the body itself
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut echo = String::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") || lower.starts_with("transfer-encoding") {
+                    echo.push_str(&lower);
+                }
+                line.clear();
+            }
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let size = usize::from_str_radix(line.trim(), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).unwrap();
+                if size == 0 {
+                    break;
+                }
+                echo.push_str(std::str::from_utf8(&chunk[..size]).unwrap());
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{echo}",
+                echo.len()
+            )
+            .unwrap();
+        });
+
+        std::fs::write(
+            dir.join("ingest.kuiper"),
+            format!(
+                r#"{{ "uri": "http://{addr}/events", "method": "POST", "body_ndjson_file": "events.ndjson" }}"#
+            ),
+        )
+        .unwrap();
+        let request = Request::find_uninterpolated(dir.join("ingest.kuiper")).unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        assert_eq!(
+            response.body(),
+            format!("content-type: application/x-ndjson\r\ntransfer-encoding: chunked\r\n{events}")
+        );
+
+        std::fs::write(
+            dir.join("conflicting.kuiper"),
+            r#"{ "uri": "http://localhost", "method": "POST", "body": {}, "body_ndjson_file": "events.ndjson" }"#,
+        )
+        .unwrap();
+        let result = Request::find_uninterpolated(dir.join("conflicting.kuiper"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(KuiperError::ConflictingBodies(_))));
+    }
+
+    #[test]
+    fn raw_body_test() {
+        // echoes the content type and the body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut echo = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") {
+                    echo.push_str(&lower);
+                } else if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            echo.push_str(std::str::from_utf8(&body).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{echo}",
+                echo.len()
+            )
+            .unwrap();
+        });
+
+        let context = crate::Context::new().with_env(std::collections::HashMap::from([(
+            "QUOTES_URL".to_string(),
+            format!("http://{addr}"),
+        )]));
+        let request =
+            Request::find_with_context("../requests/xml/get_quote.kuiper", &context).unwrap();
+        assert_eq!(request.content_type(), Some("text/xml; charset=utf-8"));
+        assert!(request.body().is_none());
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        assert!(response
+            .body()
+            .starts_with("content-type: text/xml; charset=utf-8\r\n<?xml"));
+        assert!(response.body().contains("<Symbol>ACME</Symbol>"));
+
+        let dir = std::env::temp_dir().join(format!("kuiper-raw-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("conflicting.kuiper"),
+            r#"{ "uri": "http://localhost", "method": "POST", "body": {}, "body_raw": "a" }"#,
+        )
+        .unwrap();
+        let result = Request::find_uninterpolated(dir.join("conflicting.kuiper"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            result,
+            Err(KuiperError::ConflictingBodies(fields)) if fields == ["body", "body_raw"]
+        ));
+    }
+
+    #[test]
+    fn form_body_test() {
+        // echoes the content type and the body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut echo = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") {
+                    echo.push_str(&lower);
+                } else if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            echo.push_str(std::str::from_utf8(&body).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{echo}",
+                echo.len()
+            )
+            .unwrap();
+        });
+
+        let context = crate::Context::new().with_env(std::collections::HashMap::from([(
+            "TOKEN_URL".to_string(),
+            format!("http://{addr}"),
+        )]));
+        let request =
+            Request::find_with_context("../requests/form/token.kuiper", &context).unwrap();
+        assert_eq!(
+            request.form().unwrap().get("client_id").map(String::as_str),
+            Some("kuiper-cli")
+        );
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        // the fields are sent in the order of their names, and encoded
+        assert_eq!(
+            response.body(),
+            "content-type: application/x-www-form-urlencoded\r\n\
+             client_id=kuiper-cli&grant_type=client_credentials&scope=read+write"
+        );
+    }
+
+    #[test]
+    fn multipart_body_test() {
+        // echoes the content type and the body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut echo = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") {
+                    echo.push_str(&line);
+                } else if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            echo.push_str(std::str::from_utf8(&body).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{echo}",
+                echo.len()
+            )
+            .unwrap();
+        });
+
+        let context = crate::Context::new().with_env(std::collections::HashMap::from([(
+            "UPLOAD_URL".to_string(),
+            format!("http://{addr}"),
+        )]));
+        let request =
+            Request::find_with_context("../requests/multipart/upload_report.kuiper", &context)
+                .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        let (content_type, body) = response.body().split_once("\r\n").unwrap();
+        let boundary = content_type
+            .strip_prefix("content-type: multipart/form-data; boundary=")
+            .unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\n\
+                 Report for Q3\r\n--{boundary}\r\nContent-Disposition: form-data; \
+                 name=\"report\"; filename=\"report.csv\"\r\nContent-Type: text/csv\r\n\r\n\
+                 quarter,revenue\nQ3,1200\n\r\n--{boundary}--\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn binary_body_test() {
+        // echoes the headers that describe the body, and the body itself
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_type = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") {
+                    content_type = lower.clone();
+                } else if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut echo = format!("{content_type}length: {length}\r\n").into_bytes();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            echo.extend_from_slice(&body);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                echo.len()
+            )
+            .unwrap();
+            stream.write_all(&echo).unwrap();
+        });
+
+        let context = crate::Context::new().with_env(std::collections::HashMap::from([(
+            "AVATARS_URL".to_string(),
+            format!("http://{addr}"),
+        )]));
+        let request =
+            Request::find_with_context("../requests/binary/upload_avatar.kuiper", &context)
+                .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        let png = std::fs::read("../requests/binary/avatar.png").unwrap();
+        // the file is sent with its length rather than in chunks
+        let mut expected =
+            format!("content-type: image/png\r\nlength: {}\r\n", png.len()).into_bytes();
+        expected.extend_from_slice(&png);
+        assert_eq!(response.bytes(), expected);
+    }
+
+    #[test]
+    fn graphql_body_test() {
+        // echoes the content type and the body
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut echo = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-type") {
+                    echo.push_str(&lower);
+                } else if let Some(value) = lower.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            echo.push_str(std::str::from_utf8(&body).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{echo}",
+                echo.len()
+            )
+            .unwrap();
+        });
+
+        let context = crate::Context::new().with_env(std::collections::HashMap::from([(
+            "GRAPHQL_URL".to_string(),
+            format!("http://{addr}"),
+        )]));
+        let request =
+            Request::find_with_context("../requests/graphql/get_user.kuiper", &context).unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let response = client.send(&request).unwrap();
+        let (content_type, body) = response.body().split_once("\r\n").unwrap();
+        assert_eq!(content_type, "content-type: application/json");
+        // the query is read from its file, and the variable holding a number stays a number
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(body).unwrap(),
+            serde_json::json!({
+                "query": std::fs::read_to_string("../requests/graphql/get_user.graphql").unwrap(),
+                "variables": { "id": 42, "withPosts": true }
+            })
+        );
+    }
+
+    #[test]
+    fn streaming_test() {
+        // sends the second event only once the first one has been received
+        let (received, wait) = std::sync::mpsc::channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n\
+                 event: started\ndata: 1\n\n"
+            )
+            .unwrap();
+            stream.flush().unwrap();
+            let second = match wait.recv_timeout(Duration::from_secs(5)) {
+                Ok(()) => "streamed",
+                Err(_) => "buffered",
+            };
+            write!(stream, "data: {second}\n\n").unwrap();
+        });
+
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "http://{addr}/events", "method": "GET" }}"#
+        ))
+        .unwrap();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        let mut events = Vec::new();
+        let response = client
+            .send_streaming(&request, None, &mut |event| {
+                events.push(event.to_string());
+                let _ = received.send(());
+            })
+            .unwrap();
+        assert_eq!(events, vec!["[started] 1", "streamed"]);
+        assert_eq!(
+            response.body(),
+            "event: started\ndata: 1\n\ndata: streamed\n\n"
+        );
+    }
+
+    #[test]
+    fn pin_test() {
+        assert_eq!(
+            normalize_fingerprint(" AB:cd:01 "),
+            normalize_fingerprint("abcd01")
+        );
+        assert_eq!(
+            fingerprint(b"certificate"),
+            normalize_fingerprint(&hex_pairs(fingerprint(b"certificate")))
+        );
+
+        // a host reached without TLS has no certificate to check
+        let addr = serve(1, "{}");
+        let hosts: Vec<crate::hosts::HostDefaults> =
+            serde_json::from_str(r#"[{ "pattern": "127.0.0.1", "pin_sha256": "AB:CD" }]"#).unwrap();
+        let mut request: Request =
+            serde_json::from_str(&format!(r#"{{ "uri": "{addr}/", "method": "GET" }}"#)).unwrap();
+        request.add_host_defaults(&crate::Context::new().with_hosts(hosts));
+        assert_eq!(request.pin_sha256(), Some("AB:CD"));
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            client.send(&request),
+            Err(KuiperError::CertificatePinMismatch(host)) if host == "127.0.0.1"
+        ));
+    }
+
+    #[test]
+    fn timeout_test() {
+        // accepts the connection, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let _stream = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(5));
+        });
+        let mut request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "http://{addr}/", "method": "GET", "timeout_ms": 2000 }}"#
+        ))
+        .unwrap();
+        assert_eq!(request.timeout(), Some(Duration::from_secs(2)));
+        request.override_timeout(Duration::from_millis(50));
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        assert!(client.send(&request).is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Formats a hex fingerprint like `openssl` does, as `AB:CD:...`.
+    fn hex_pairs(fingerprint: String) -> String {
+        fingerprint
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    #[test]
+    fn observer_test() {
+        let addr = serve(1, r#"{"id":7}"#);
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{ "uri": "{addr}/orders", "method": "POST", "body": {{}} }}"#
+        ))
+        .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        let client = Client::from_builder(
+            reqwest::blocking::Client::builder().no_proxy(),
+            &ClientConfig::default(),
+            None,
+        )
+        .unwrap()
+        .with_observer(move |event: &Event| observed.lock().unwrap().push(event.clone()));
+        client.send(&request).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3, "{events:?}");
+        assert_eq!(
+            events[0],
+            Event::SendStarted {
+                request: String::new(),
+                method: "POST".to_string(),
+                uri: format!("{addr}/orders"),
+            }
+        );
+        assert_eq!(
+            events[1],
+            Event::BytesReceived {
+                request: String::new(),
+                received: 8,
+                total: Some(8),
+            }
+        );
+        assert!(matches!(
+            events[2],
+            Event::Completed {
+                status: Some(201),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn merge_test() {
+        let file = ClientConfig::default()
+            .with_pool_max_idle_per_host(Some(32))
+            .with_connect_timeout(Some(Duration::from_secs(5)));
+        let flags = ClientConfig::default()
+            .with_pool_max_idle_per_host(Some(0))
+            .with_tcp_nodelay(Some(true));
+
+        let merged = file.merge(flags);
+        assert_eq!(merged.pool_max_idle_per_host(), Some(0));
+        assert_eq!(merged.connect_timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(merged.tcp_nodelay(), Some(true));
+        assert_eq!(merged.tcp_keepalive(), None);
+    }
+
+    #[test]
+    fn tls_version_test() {
+        let config: ClientConfig =
+            serde_json::from_str(r#"{ "min_tls_version": "1.2", "max_tls_version": "1.3" }"#)
+                .unwrap();
+        assert_eq!(config.min_tls_version(), Some(TlsVersion::Tls1_2));
+        assert_eq!(config.max_tls_version(), Some(TlsVersion::Tls1_3));
+        assert_eq!("1.1".parse::<TlsVersion>().unwrap(), TlsVersion::Tls1_1);
+        assert!(matches!(
+            "1.4".parse::<TlsVersion>(),
+            Err(KuiperError::InvalidTlsVersion(_))
+        ));
+
+        // a maximum of 1.3 is no limit, but native-tls can't require it
+        assert!(Client::new(&config).is_ok());
+        let tls_1_3 = config.with_min_tls_version(Some(TlsVersion::Tls1_3));
+        assert!(matches!(
+            Client::new(&tls_1_3),
+            Err(KuiperError::UnsupportedTls(_))
+        ));
+        let rustls = tls_1_3.with_tls_backend(Some(TlsBackend::Rustls));
+        #[cfg(feature = "rustls")]
+        assert!(Client::new(&rustls).is_ok());
+        #[cfg(not(feature = "rustls"))]
+        assert!(matches!(
+            Client::new(&rustls),
+            Err(KuiperError::UnsupportedTls(_))
+        ));
+    }
+
+    /// Connects to the destination it was given after a delay.
+    struct SlowConnector(Duration);
+
+    impl Service<&'static str> for SlowConnector {
+        type Response = &'static str;
+        type Error = std::io::Error;
+        type Future = Pin<Box<dyn Future<Output = std::io::Result<&'static str>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut task::Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, destination: &'static str) -> Self::Future {
+            let delay = self.0;
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                Ok(destination)
+            })
+        }
+    }
+
+    #[test]
+    fn timed_connect_test() {
+        let spent = Arc::new(Mutex::new(Duration::ZERO));
+        let mut connector = TimedConnectLayer {
+            spent: spent.clone(),
+        }
+        .layer(SlowConnector(Duration::from_millis(20)));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let connection = runtime.block_on(connector.call("example.com:443"));
+        assert_eq!(connection.unwrap(), "example.com:443");
+        runtime.block_on(connector.call("example.com:443")).unwrap();
+        assert!(*spent.lock().unwrap() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn http3_test() {
+        let config: ClientConfig = serde_json::from_str(r#"{ "http3": true }"#).unwrap();
+        assert!(config.http3());
+        assert!(!config
+            .merge(ClientConfig::default().with_http3(Some(false)))
+            .http3());
+        #[cfg(feature = "http3")]
+        assert!(Client::new(&config).is_ok());
+        #[cfg(not(feature = "http3"))]
+        assert!(matches!(
+            Client::new(&config),
+            Err(crate::KuiperError::UnsupportedHttp3(_))
+        ));
+    }
+}
@@ -0,0 +1,468 @@
+use crate::{
+    client::ClientConfig,
+    hosts::{HostDefaults, HostPolicy},
+    secrets::CloudSecret,
+    suite::Suite,
+    tls::TlsBackend,
+    uri::UriNormalization,
+    KuiperError, KuiperResult,
+};
+use log::trace;
+use reqwest::{blocking::ClientBuilder, Certificate, Identity, Proxy};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// The name of the config file of a collection.
+pub const CONFIG_FILE_NAME: &str = "kuiper.toml";
+/// The name of the JSON config file, which is read in directories without a `kuiper.toml`.
+pub const JSON_CONFIG_FILE_NAME: &str = "kuiper.json";
+
+/// Settings for a collection of requests, read from a `kuiper.toml` file, or a `kuiper.json`
+/// with the same fields.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    client: ClientConfig,
+    /// Defaults for requests to hosts matching a pattern, in order of precedence.
+    #[serde(default)]
+    hosts: Vec<HostDefaults>,
+    #[serde(flatten)]
+    host_policy: HostPolicy,
+    #[serde(default)]
+    normalize_uri: UriNormalization,
+    /// Record every sent request in this audit log, relative to the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audit_log: Option<PathBuf>,
+    /// Response headers that are kept in the session, and sent with later requests to the same
+    /// host, like CSRF tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    session_headers: Vec<String>,
+    /// Keep the cookies that responses set in the session, and send them with later requests,
+    /// like a browser does.
+    #[serde(default)]
+    cookies: bool,
+    /// Connection settings of environments, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    environments: BTreeMap<String, EnvironmentConfig>,
+    /// Named groups of requests, whose patterns are relative to the config file.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    suites: BTreeMap<String, Suite>,
+}
+
+impl Config {
+    /// Loads the config at `path`, which is read as TOML if its name ends with `.toml`, and as
+    /// JSON otherwise.
+    pub fn load(path: impl AsRef<Path>) -> KuiperResult<Self> {
+        let path = path.as_ref();
+        let mut config: Self = if path.extension().is_some_and(|ext| ext == "toml") {
+            let contents = fs::read_to_string(path)?;
+            toml::from_str(&contents).map_err(|e| crate::toml_error(&contents, e))?
+        } else {
+            serde_json::from_reader(BufReader::new(File::open(path)?))?
+        };
+        config.path = Some(path.to_path_buf());
+        if let (Some(audit_log), Some(dir)) = (&mut config.audit_log, path.parent()) {
+            *audit_log = dir.join(&*audit_log);
+        }
+        if let Some(dir) = path.parent() {
+            for environment in config.environments.values_mut() {
+                environment.resolve_paths(dir);
+            }
+            config.suites = std::mem::take(&mut config.suites)
+                .into_iter()
+                .map(|(name, suite)| (name, suite.with_root(dir)))
+                .collect();
+        }
+        trace!("loaded config at '{path:?}'");
+        Ok(config)
+    }
+
+    /// Loads the config closest to `dir`, looking in `dir` and then in each of its ancestors,
+    /// or returns the default config if there is none.
+    pub fn discover(dir: impl AsRef<Path>) -> KuiperResult<Self> {
+        match dir.as_ref().ancestors().find_map(Self::find) {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The config file in `dir`, which is its `kuiper.toml`, or else its `kuiper.json`.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        [CONFIG_FILE_NAME, JSON_CONFIG_FILE_NAME]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// The file the config was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn client(&self) -> &ClientConfig {
+        &self.client
+    }
+
+    pub fn hosts(&self) -> &[HostDefaults] {
+        &self.hosts
+    }
+
+    pub fn host_policy(&self) -> &HostPolicy {
+        &self.host_policy
+    }
+
+    /// How the uris of requests are normalized once they are interpolated.
+    pub fn uri_normalization(&self) -> UriNormalization {
+        self.normalize_uri
+    }
+
+    /// The audit log that every sent request is recorded in, if any.
+    pub fn audit_log(&self) -> Option<&Path> {
+        self.audit_log.as_deref()
+    }
+
+    /// The response headers that are kept in the session, and sent with later requests to the
+    /// same host.
+    pub fn session_headers(&self) -> &[String] {
+        &self.session_headers
+    }
+
+    /// Whether cookies that responses set are kept in the session.
+    pub fn cookies(&self) -> bool {
+        self.cookies
+    }
+
+    /// The connection settings of the environment `name`, which is either the name of an entry
+    /// of `environments`, or the path of an env file whose name without its extension is.
+    pub fn environment(&self, name: &str) -> Option<&EnvironmentConfig> {
+        self.environments.get(name).or_else(|| {
+            let stem = Path::new(name).file_stem()?.to_str()?;
+            self.environments.get(stem)
+        })
+    }
+
+    /// The suite `name`, if there is one.
+    pub fn suite(&self, name: &str) -> Option<&Suite> {
+        self.suites.get(name)
+    }
+
+    /// The suites, by name.
+    pub fn suites(&self) -> &BTreeMap<String, Suite> {
+        &self.suites
+    }
+
+    /// Combines this config with `fallback`, like a user-wide config, where settings from this
+    /// config win and its host defaults take precedence over those of `fallback`. The allowed and
+    /// denied hosts of both are combined.
+    pub fn with_fallback(self, fallback: Config) -> Self {
+        let mut hosts = self.hosts;
+        hosts.extend(fallback.hosts);
+        Self {
+            path: self.path.or(fallback.path),
+            client: fallback.client.merge(self.client),
+            hosts,
+            host_policy: self.host_policy.merge(fallback.host_policy),
+            normalize_uri: if self.normalize_uri == UriNormalization::default() {
+                fallback.normalize_uri
+            } else {
+                self.normalize_uri
+            },
+            audit_log: self.audit_log.or(fallback.audit_log),
+            session_headers: {
+                let mut session_headers = self.session_headers;
+                for name in fallback.session_headers {
+                    if !session_headers
+                        .iter()
+                        .any(|existing| existing.eq_ignore_ascii_case(&name))
+                    {
+                        session_headers.push(name);
+                    }
+                }
+                session_headers
+            },
+            cookies: self.cookies || fallback.cookies,
+            environments: {
+                let mut environments = fallback.environments;
+                environments.extend(self.environments);
+                environments
+            },
+            suites: {
+                let mut suites = fallback.suites;
+                suites.extend(self.suites);
+                suites
+            },
+        }
+    }
+}
+
+/// Connection settings of an environment, read from the `environments` section of
+/// `kuiper.toml`, which apply to every request sent in that environment, like one behind an mTLS
+/// proxy. Paths are relative to the config file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvironmentConfig {
+    /// Settings that take precedence over the `client` section of the config.
+    #[serde(flatten)]
+    client: ClientConfig,
+    /// Sends every request through this proxy, instead of the proxies of the `hosts` section.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proxy: Option<String>,
+    /// PEM certificates that are trusted in addition to the system's, like a private CA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ca_bundle: Option<PathBuf>,
+    /// A PEM certificate that is presented to servers that ask for one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_cert: Option<PathBuf>,
+    /// The PEM PKCS #8 key of `client_cert`, which is read from `client_cert` itself if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_key: Option<PathBuf>,
+    /// Secrets in cloud secret managers, whose values are available through `{{secret:NAME}}`
+    /// in this environment.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    secrets: Vec<CloudSecret>,
+}
+
+impl EnvironmentConfig {
+    pub fn client(&self) -> &ClientConfig {
+        &self.client
+    }
+
+    pub fn proxy(&self) -> Option<&str> {
+        self.proxy.as_deref()
+    }
+
+    pub fn ca_bundle(&self) -> Option<&Path> {
+        self.ca_bundle.as_deref()
+    }
+
+    pub fn client_cert(&self) -> Option<&Path> {
+        self.client_cert.as_deref()
+    }
+
+    pub fn client_key(&self) -> Option<&Path> {
+        self.client_key.as_deref()
+    }
+
+    pub fn secrets(&self) -> &[CloudSecret] {
+        &self.secrets
+    }
+
+    /// Adds the proxy, CA bundle and client certificate of the environment to `builder`, whose
+    /// connections are made with `backend`. The proxy is added first, so that it is used before
+    /// any proxy added after it.
+    pub fn configure(
+        &self,
+        mut builder: ClientBuilder,
+        backend: TlsBackend,
+    ) -> KuiperResult<ClientBuilder> {
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(path) = &self.ca_bundle {
+            let pem = read_pem(path)?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| KuiperError::InvalidCertificate(format!("{}: {e}", path.display())))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(cert) = &self.client_cert {
+            let key = self.client_key.as_ref().unwrap_or(cert);
+            let identity = match backend {
+                TlsBackend::NativeTls => {
+                    Identity::from_pkcs8_pem(&read_pem(cert)?, &read_pem(key)?)
+                }
+                #[cfg(feature = "rustls")]
+                TlsBackend::Rustls => {
+                    let mut pem = read_pem(cert)?;
+                    if key != cert {
+                        pem.push(b'\n');
+                        pem.extend(read_pem(key)?);
+                    }
+                    Identity::from_pem(&pem)
+                }
+                #[cfg(not(feature = "rustls"))]
+                TlsBackend::Rustls => {
+                    return Err(KuiperError::UnsupportedTls(
+                        "kuiper was built without the `rustls` feature".to_string(),
+                    ))
+                }
+            }
+            .map_err(|e| KuiperError::InvalidCertificate(format!("{}: {e}", cert.display())))?;
+            builder = builder.identity(identity);
+        }
+        Ok(builder)
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in [
+            &mut self.ca_bundle,
+            &mut self.client_cert,
+            &mut self.client_key,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = dir.join(&*path);
+        }
+    }
+}
+
+/// The contents of the PEM file at `path`.
+fn read_pem(path: &Path) -> KuiperResult<Vec<u8>> {
+    fs::read(path).map_err(|e| KuiperError::InvalidCertificate(format!("{}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn discover_test() {
+        let config = Config::discover("../requests/subdir").unwrap();
+        assert_eq!(
+            config.path(),
+            Some(Path::new("../requests").join(CONFIG_FILE_NAME).as_path())
+        );
+        assert_eq!(
+            config.client().connect_timeout(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(config.hosts().len(), 1);
+        assert!(config.hosts()[0].matches("localhost"));
+
+        // kuiper.json is read where there is no kuiper.toml
+        let dir = std::env::temp_dir().join(format!("kuiper-config-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(JSON_CONFIG_FILE_NAME),
+            r#"{ "client": { "tcp_nodelay": true } }"#,
+        )
+        .unwrap();
+        let config = Config::discover(&dir).unwrap();
+        assert_eq!(
+            config.path(),
+            Some(dir.join(JSON_CONFIG_FILE_NAME).as_path())
+        );
+        assert_eq!(config.client().tcp_nodelay(), Some(true));
+
+        fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            "[client]\ntcp_nodelay = false\n",
+        )
+        .unwrap();
+        let config = Config::discover(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.path(), Some(dir.join(CONFIG_FILE_NAME).as_path()));
+        assert_eq!(config.client().tcp_nodelay(), Some(false));
+    }
+
+    #[test]
+    fn with_fallback_test() {
+        let config: Config = serde_json::from_str(
+            r#"{ "client": { "tcp_nodelay": true }, "hosts": [{ "pattern": "a.*" }] }"#,
+        )
+        .unwrap();
+        let fallback: Config = serde_json::from_str(
+            r#"{ "client": { "tcp_nodelay": false, "connect_timeout_ms": 100 }, "hosts": [{ "pattern": "b.*" }], "normalize_uri": { "collapse_slashes": true, "trailing_slash": "remove" } }"#,
+        )
+        .unwrap();
+        let config = config.with_fallback(fallback);
+        assert_eq!(config.client().tcp_nodelay(), Some(true));
+        assert_eq!(
+            config.client().connect_timeout(),
+            Some(Duration::from_millis(100))
+        );
+        let patterns: Vec<_> = config.hosts().iter().map(|h| h.pattern()).collect();
+        assert_eq!(patterns, vec!["a.*", "b.*"]);
+        assert_eq!(
+            config.uri_normalization(),
+            UriNormalization::new()
+                .with_collapse_slashes(true)
+                .with_trailing_slash(Some(crate::uri::TrailingSlash::Remove))
+        );
+    }
+
+    #[test]
+    fn environment_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-config-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(JSON_CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            r#"{
+                "environments": {
+                    "staging": {
+                        "proxy": "http://mtls-proxy.staging:3128",
+                        "ca_bundle": "certs/staging-ca.pem",
+                        "client_cert": "certs/client.pem",
+                        "min_tls_version": "1.2"
+                    },
+                    "prod": {
+                        "connect_timeout_ms": 500,
+                        "secrets": [{ "provider": "aws", "id": "prod/api" }]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+
+        let staging = config.environment("staging").unwrap();
+        assert_eq!(staging.proxy(), Some("http://mtls-proxy.staging:3128"));
+        assert_eq!(
+            staging.ca_bundle(),
+            Some(dir.join("certs/staging-ca.pem").as_path())
+        );
+        assert_eq!(staging.client_key(), None);
+        assert_eq!(
+            staging.client().min_tls_version(),
+            Some(crate::client::TlsVersion::Tls1_2)
+        );
+        // env files are matched by their name without the extension
+        assert_eq!(
+            config.environment("environments/staging.env"),
+            Some(staging)
+        );
+        assert!(config.environment("dev").is_none());
+        assert!(staging.secrets().is_empty());
+        assert_eq!(
+            config.environment("prod").unwrap().secrets()[0].id(),
+            "prod/api"
+        );
+
+        let builder = reqwest::blocking::Client::builder();
+        assert!(matches!(
+            staging.configure(builder, TlsBackend::NativeTls),
+            Err(KuiperError::InvalidCertificate(reason)) if reason.contains("staging-ca.pem")
+        ));
+        let prod = config.environment("prod").unwrap();
+        assert!(prod
+            .configure(reqwest::blocking::Client::builder(), TlsBackend::NativeTls)
+            .unwrap()
+            .build()
+            .is_ok());
+
+        let fallback: Config =
+            serde_json::from_str(r#"{ "environments": { "prod": {}, "dev": {} } }"#).unwrap();
+        let config = config.with_fallback(fallback);
+        assert!(config.environment("dev").is_some());
+        assert_eq!(
+            config
+                .environment("prod")
+                .unwrap()
+                .client()
+                .connect_timeout(),
+            Some(Duration::from_millis(500))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
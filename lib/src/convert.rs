@@ -0,0 +1,159 @@
+//! Converting JSON bodies to other formats, so that responses can be used as config files or
+//! opened in spreadsheets.
+
+use crate::{KuiperError, KuiperResult};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Converts `value` to YAML.
+pub fn to_yaml(value: &Value) -> KuiperResult<String> {
+    serde_yaml::to_string(value).map_err(|e| KuiperError::Unconvertible(e.to_string()))
+}
+
+/// Converts `value` to TOML. The value must be an object, and keys with `null` values are left
+/// out, since TOML has no null.
+pub fn to_toml(value: &Value) -> KuiperResult<String> {
+    if !value.is_object() {
+        return Err(KuiperError::Unconvertible(
+            "only objects can be converted to TOML".to_string(),
+        ));
+    }
+    toml::to_string(&without_null_values(value))
+        .map_err(|e| KuiperError::Unconvertible(e.to_string()))
+}
+
+/// Leaves out the keys of objects in `value` whose values are `null`. A `null` in an array is
+/// kept, for the conversion to fail on.
+fn without_null_values(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_null_values(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(without_null_values).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Converts `value`, an array of objects whose values are not arrays or objects, to CSV with a
+/// header row of every key, in sorted order.
+pub fn to_csv(value: &Value) -> KuiperResult<String> {
+    let rows = value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(Value::as_object)
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| {
+            KuiperError::Unconvertible("only arrays of objects can be converted to CSV".to_string())
+        })?;
+    let columns: BTreeSet<&String> = rows.iter().flat_map(|row| row.keys()).collect();
+
+    let mut out = String::new();
+    let header: Vec<_> = columns.iter().map(|column| csv_field(column)).collect();
+    out.push_str(&header.join(","));
+    out.push('\n');
+    for row in rows {
+        let fields = columns
+            .iter()
+            .map(|column| match row.get(*column) {
+                None | Some(Value::Null) => Ok(String::new()),
+                Some(Value::String(s)) => Ok(csv_field(s)),
+                Some(Value::Array(_) | Value::Object(_)) => Err(KuiperError::Unconvertible(
+                    format!("'{column}' is not a flat value, which CSV can't hold"),
+                )),
+                Some(value) => Ok(value.to_string()),
+            })
+            .collect::<KuiperResult<Vec<_>>>()?;
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn yaml_test() {
+        let value = json!({
+            "id": 42,
+            "name": "Ada Lovelace",
+            "tags": ["admin", "true", "yes", { "since": "2020-01-01", "by": null }],
+            "address": { "city": "London", "lines": [] },
+            "notes": "a: b\n{{not a placeholder}}"
+        });
+        let yaml = to_yaml(&value).unwrap();
+        assert!(yaml.contains("name: Ada Lovelace\n"));
+        assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), value);
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&to_yaml(&json!("yes")).unwrap()).unwrap(),
+            json!("yes")
+        );
+    }
+
+    #[test]
+    fn toml_test() {
+        let value = json!({
+            "name": "kuiper",
+            "deleted": null,
+            "ports": [80, 443],
+            "owner": { "first name": "Ada", "pets": [{ "kind": "cat", "age": null }] },
+            "servers": [{ "host": "a" }, { "host": "b" }]
+        });
+        let toml = to_toml(&value).unwrap();
+        assert!(toml.contains("[[servers]]"));
+        assert_eq!(
+            toml::from_str::<Value>(&toml).unwrap(),
+            json!({
+                "name": "kuiper",
+                "ports": [80, 443],
+                "owner": { "first name": "Ada", "pets": [{ "kind": "cat" }] },
+                "servers": [{ "host": "a" }, { "host": "b" }]
+            })
+        );
+        assert!(matches!(
+            to_toml(&json!([1, 2])),
+            Err(KuiperError::Unconvertible(_))
+        ));
+        assert!(matches!(
+            to_toml(&json!({ "values": [1, null] })),
+            Err(KuiperError::Unconvertible(_))
+        ));
+    }
+
+    #[test]
+    fn csv_test() {
+        let value = json!([
+            { "id": 1, "name": "Ada, Countess", "admin": true },
+            { "id": 2, "name": "Grace \"Amazing\" Hopper", "email": null }
+        ]);
+        assert_eq!(
+            to_csv(&value).unwrap(),
+            "admin,email,id,name\ntrue,,1,\"Ada, Countess\"\n,,2,\"Grace \"\"Amazing\"\" Hopper\"\n"
+        );
+        assert!(matches!(
+            to_csv(&json!({ "id": 1 })),
+            Err(KuiperError::Unconvertible(_))
+        ));
+        assert!(matches!(
+            to_csv(&json!([{ "tags": [] }])),
+            Err(KuiperError::Unconvertible(_))
+        ));
+    }
+}
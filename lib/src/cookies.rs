@@ -0,0 +1,313 @@
+//! A cookie jar, which keeps the cookies that responses set and sends them with later requests
+//! to the same site, the way browsers do.
+
+use jiff::{fmt::rfc2822::DateTimeParser, Timestamp};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+static DATE_PARSER: DateTimeParser = DateTimeParser::new();
+
+/// A cookie set by a response.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    /// The lowercase domain the cookie is sent to, without a leading dot.
+    domain: String,
+    /// Whether the cookie is only sent to `domain` itself and not to its subdomains, which is
+    /// the case when the response set no `Domain`.
+    #[serde(default)]
+    host_only: bool,
+    path: String,
+    /// When the cookie expires, in seconds since the Unix epoch. Cookies without one are kept
+    /// until they are replaced, rather than until the end of the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<i64>,
+    /// Whether the cookie is only sent over `https`.
+    #[serde(default)]
+    secure: bool,
+}
+
+impl Cookie {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// When the cookie expires, in seconds since the Unix epoch, if it does.
+    pub fn expires(&self) -> Option<i64> {
+        self.expires
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// The key that the cookie is kept under, which a later cookie with the same domain, path
+    /// and name replaces.
+    pub(crate) fn key(&self) -> String {
+        format!("{};{};{}", self.domain, self.path, self.name)
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Parses a `Set-Cookie` header of a response from `url`, or returns `None` if it is
+    /// malformed or sets a cookie for a domain that `url` is not part of.
+    fn parse(url: &Url, header: &str, now: i64) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut attributes = header.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().trim_matches('"').to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    if let Some(expires) = parse_date(value) {
+                        cookie.expires = Some(expires);
+                    }
+                }
+                "secure" => cookie.secure = true,
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(if max_age <= 0 {
+                i64::MIN
+            } else {
+                now + max_age
+            });
+        }
+        Some(cookie)
+    }
+
+    /// Returns `true` if the cookie is sent with a request to `url`.
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        domain && path_matches(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+    }
+}
+
+/// The cookies kept from responses, by their domain, path and name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: BTreeMap<String, Cookie>,
+}
+
+impl CookieJar {
+    /// The cookies in the jar, including expired ones that haven't been replaced yet.
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Cookie> {
+        self.cookies.get(key)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.cookies.keys()
+    }
+
+    /// Adds `cookie`, replacing the one with the same domain, path and name.
+    pub(crate) fn insert(&mut self, cookie: Cookie) {
+        self.cookies.insert(cookie.key(), cookie);
+    }
+
+    /// Keeps the cookies that the `Set-Cookie` headers of a response from `url` set, and removes
+    /// the ones they expire.
+    pub fn store<'a>(&mut self, url: &Url, set_cookies: impl IntoIterator<Item = &'a str>) {
+        let now = Timestamp::now().as_second();
+        for header in set_cookies {
+            let Some(cookie) = Cookie::parse(url, header, now) else {
+                log::debug!("ignoring cookie '{header}' from '{url}'");
+                continue;
+            };
+            if cookie.is_expired(now) {
+                self.cookies.remove(&cookie.key());
+            } else {
+                self.insert(cookie);
+            }
+        }
+    }
+
+    /// The `Cookie` header to send with a request to `url`, with longer paths first, or `None`
+    /// if no cookie is sent to it.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let now = Timestamp::now().as_second();
+        let mut cookies: Vec<_> = self
+            .cookies
+            .values()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(url))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+}
+
+/// The path of `url` up to its last slash, which cookies without a `Path` are sent to.
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => url.path()[..end].to_string(),
+    }
+}
+
+/// Returns `true` if `host` is `domain` or one of its subdomains.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Returns `true` if a cookie with the path `cookie_path` is sent to `path`.
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || path
+            .strip_prefix(cookie_path)
+            .is_some_and(|rest| cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// Parses the date of an `Expires` attribute, like `Wed, 21 Oct 2015 07:28:00 GMT`, or the
+/// `Wed, 21-Oct-2015 07:28:00 GMT` that some servers send.
+fn parse_date(date: &str) -> Option<i64> {
+    DATE_PARSER
+        .parse_timestamp(date)
+        .or_else(|_| DATE_PARSER.parse_timestamp(date.replace('-', " ")))
+        .ok()
+        .map(|timestamp| timestamp.as_second())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn header(jar: &CookieJar, uri: &str) -> Option<String> {
+        jar.header(&Url::parse(uri).unwrap())
+    }
+
+    #[test]
+    fn store_test() {
+        let login = Url::parse("https://api.example.com/auth/login").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(
+            &login,
+            [
+                "session=abc; Path=/; HttpOnly; Secure",
+                "theme=\"dark\"; Domain=.example.com; Path=/; Expires=Wed, 21-Oct-2099 07:28:00 GMT",
+                "step=1",
+                "tracker=1; Domain=other.com",
+                "=nameless",
+            ],
+        );
+        assert_eq!(jar.len(), 3);
+        assert_eq!(
+            jar.get("example.com;/;theme").unwrap().expires(),
+            Some(4096250880)
+        );
+
+        assert_eq!(
+            header(&jar, "https://api.example.com/auth/next").as_deref(),
+            Some("step=1; session=abc; theme=dark")
+        );
+        // the session cookie is only sent over https, and to the host that set it
+        assert_eq!(
+            header(&jar, "http://api.example.com/orders").as_deref(),
+            Some("theme=dark")
+        );
+        assert_eq!(
+            header(&jar, "https://www.example.com/").as_deref(),
+            Some("theme=dark")
+        );
+        assert_eq!(header(&jar, "https://example.org/"), None);
+
+        // cookies are replaced, and removed once they expire
+        jar.store(
+            &login,
+            [
+                "session=def; Path=/",
+                "theme=light; Domain=example.com; Path=/; Max-Age=0",
+            ],
+        );
+        assert_eq!(
+            header(&jar, "https://api.example.com/").as_deref(),
+            Some("session=def")
+        );
+    }
+
+    #[test]
+    fn path_matches_test() {
+        assert!(path_matches("/auth", "/auth"));
+        assert!(path_matches("/auth/login", "/auth"));
+        assert!(path_matches("/auth/login", "/"));
+        assert!(!path_matches("/authors", "/auth"));
+        assert_eq!(
+            default_path(&Url::parse("http://a/auth/login").unwrap()),
+            "/auth"
+        );
+        assert_eq!(default_path(&Url::parse("http://a/login").unwrap()), "/");
+    }
+}
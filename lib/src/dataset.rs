@@ -0,0 +1,133 @@
+use crate::{KuiperError, KuiperResult};
+use log::trace;
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path};
+
+/// A single row of a dataset, mapping column names to values.
+pub type Row = HashMap<String, String>;
+
+/// Loads the rows of a `.csv` or `.json` dataset.
+///
+/// CSV files must start with a header row naming the columns. JSON files must contain an array
+/// of objects; non-string values are converted to their JSON representation.
+pub fn load(path: impl AsRef<Path>) -> KuiperResult<Vec<Row>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let rows = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => parse_csv(&contents)?,
+        Some("json") => parse_json(&contents)?,
+        _ => {
+            return Err(KuiperError::InvalidDataset(format!(
+                "unsupported file type '{}'",
+                path.display()
+            )))
+        }
+    };
+    trace!("loaded {} rows from dataset at '{path:?}'", rows.len());
+    Ok(rows)
+}
+
+fn parse_json(contents: &str) -> KuiperResult<Vec<Row>> {
+    let rows: Vec<serde_json::Map<String, Value>> = serde_json::from_str(contents)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(column, value)| match value {
+                    Value::String(s) => (column, s),
+                    other => (column, other.to_string()),
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn parse_csv(contents: &str) -> KuiperResult<Vec<Row>> {
+    let mut records = csv_records(contents)?.into_iter();
+    let Some(columns) = records.next() else {
+        return Ok(Vec::new());
+    };
+
+    records
+        .enumerate()
+        .map(|(idx, record)| {
+            if record.len() != columns.len() {
+                return Err(KuiperError::InvalidDataset(format!(
+                    "row {} has {} values, expected {}",
+                    idx + 1,
+                    record.len(),
+                    columns.len()
+                )));
+            }
+            Ok(columns.iter().cloned().zip(record).collect())
+        })
+        .collect()
+}
+
+/// Splits CSV text into records, honoring quoted fields with `""` escapes.
+fn csv_records(contents: &str) -> KuiperResult<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(KuiperError::InvalidDataset(
+            "unterminated quoted field".to_string(),
+        ));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records
+        .into_iter()
+        .filter(|record| !(record.len() == 1 && record[0].is_empty()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn csv_test() {
+        let rows =
+            parse_csv("name,note\r\nalice,\"hello, world\"\nbob,\"say \"\"hi\"\"\"\n\n").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["name"], "alice");
+        assert_eq!(rows[0]["note"], "hello, world");
+        assert_eq!(rows[1]["note"], "say \"hi\"");
+
+        assert!(matches!(
+            parse_csv("a,b\n1"),
+            Err(KuiperError::InvalidDataset(_))
+        ));
+    }
+
+    #[test]
+    fn dataset_file_test() {
+        let csv = load("../requests/data/users.csv").unwrap();
+        let json = load("../requests/data/users.json").unwrap();
+        assert_eq!(csv, json);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::normalize::Normalizer;
+use serde_json::Value;
+use std::fmt::Display;
+
+/// A line in the difference between two texts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl Display for Line<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Line::Same(line) => write!(f, "  {line}"),
+            Line::Removed(line) => write!(f, "- {line}"),
+            Line::Added(line) => write!(f, "+ {line}"),
+        }
+    }
+}
+
+/// Computes the lines to remove from `old` and add to it to get `new`, keeping as many lines
+/// in common as possible.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            diff.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| Line::Added(line)));
+    diff
+}
+
+/// A value that differs between two JSON documents, at a JSONPath.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {path}: {value}"),
+            Change::Removed { path, value } => write!(f, "- {path}: {value}"),
+            Change::Changed { path, old, new } => write!(f, "~ {path}: {old} -> {new}"),
+        }
+    }
+}
+
+/// Computes the values that were added to, removed from or changed in `old` to get `new`.
+/// Objects are compared key by key and arrays index by index, and values that differ in type are
+/// changed as a whole.
+pub fn json(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare("$", old, new, &mut changes);
+    changes
+}
+
+fn compare(path: &str, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = child(path, key);
+                match new.get(key) {
+                    Some(new_value) => compare(&path, old_value, new_value, changes),
+                    None => changes.push(Change::Removed {
+                        path,
+                        value: old_value.clone(),
+                    }),
+                }
+            }
+            for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                changes.push(Change::Added {
+                    path: child(path, key),
+                    value: new_value.clone(),
+                });
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for idx in 0..old.len().max(new.len()) {
+                let path = format!("{path}[{idx}]");
+                match (old.get(idx), new.get(idx)) {
+                    (Some(old_value), Some(new_value)) => {
+                        compare(&path, old_value, new_value, changes)
+                    }
+                    (Some(old_value), None) => changes.push(Change::Removed {
+                        path,
+                        value: old_value.clone(),
+                    }),
+                    (None, Some(new_value)) => changes.push(Change::Added {
+                        path,
+                        value: new_value.clone(),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old != new => changes.push(Change::Changed {
+            path: path.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// The JSONPath of `key` in the object at `path`, as `.key` if it can be written that way.
+fn child(path: &str, key: &str) -> String {
+    let plain = key.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        format!("{path}.{key}")
+    } else {
+        format!("{path}['{key}']")
+    }
+}
+
+/// Canonicalizes and pretty-prints `body` if it is JSON, so that JSON bodies are compared line by
+/// line no matter how they were formatted.
+pub fn normalize_json(body: &str) -> String {
+    Normalizer::new()
+        .normalize_body(body)
+        .unwrap_or_else(|_| body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn lines_test() {
+        let diff = lines("a\nb\nc\nd", "a\nc\nd\ne");
+        assert_eq!(
+            diff,
+            vec![
+                Line::Same("a"),
+                Line::Removed("b"),
+                Line::Same("c"),
+                Line::Same("d"),
+                Line::Added("e"),
+            ]
+        );
+        assert!(lines("same", "same")
+            .iter()
+            .all(|line| matches!(line, Line::Same(_))));
+    }
+
+    #[test]
+    fn json_test() {
+        let old = serde_json::json!({
+            "id": 1,
+            "items": [{ "price": 10 }, { "price": 5 }],
+            "first name": "Ada",
+            "legacy": true
+        });
+        let new = serde_json::json!({
+            "id": "1",
+            "items": [{ "price": 12 }, { "price": 5 }, { "price": 1 }],
+            "first name": "Ada",
+            "region": "eu"
+        });
+        assert_eq!(
+            json(&old, &new),
+            vec![
+                Change::Changed {
+                    path: "$.id".to_string(),
+                    old: 1.into(),
+                    new: "1".into()
+                },
+                Change::Changed {
+                    path: "$.items[0].price".to_string(),
+                    old: 10.into(),
+                    new: 12.into()
+                },
+                Change::Added {
+                    path: "$.items[2]".to_string(),
+                    value: serde_json::json!({ "price": 1 })
+                },
+                Change::Removed {
+                    path: "$.legacy".to_string(),
+                    value: true.into()
+                },
+                Change::Added {
+                    path: "$.region".to_string(),
+                    value: "eu".into()
+                },
+            ]
+        );
+        assert_eq!(
+            Change::Removed {
+                path: child("$", "first name"),
+                value: "Ada".into()
+            }
+            .to_string(),
+            r#"- $['first name']: "Ada""#
+        );
+        assert!(json(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn normalize_json_test() {
+        assert_eq!(
+            normalize_json(r#"{"a":1}"#),
+            normalize_json("{\n    \"a\": 1\n}")
+        );
+        assert_eq!(
+            normalize_json(r#"{"b":1.0,"a":2}"#),
+            normalize_json(r#"{"a":2,"b":1}"#)
+        );
+        assert_eq!(normalize_json("not json"), "not json");
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{KuiperError, KuiperResult};
+use serde::de::Error;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// Reads the `NAME=value` entries of the env file at `path`, without changing the environment of
+/// the process.
+pub fn read(path: impl AsRef<Path>) -> KuiperResult<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    for (idx, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .trim_start_matches("export ")
+            .split_once('=')
+            .ok_or_else(|| KuiperError::InvalidEnvFile(idx + 1))?;
+        values.insert(name.trim().to_string(), parse_value(value.trim()));
+    }
+    Ok(values)
+}
+
+/// Reads the values of the JSON object at `path`, such as `environments/dev.json`. Numbers and
+/// booleans are read as the strings they are written as.
+pub fn read_json(path: impl AsRef<Path>) -> KuiperResult<HashMap<String, String>> {
+    let object: HashMap<String, Value> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    object
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::String(value) => Ok((name, value)),
+            Value::Number(_) | Value::Bool(_) => Ok((name, value.to_string())),
+            _ => Err(serde_json::Error::custom(format!(
+                "'{name}' must be a string, number or boolean"
+            ))
+            .into()),
+        })
+        .collect()
+}
+
+/// The names of the values that were added, removed or changed between `old` and `new`, sorted.
+/// Only names are returned, since values may be secret.
+pub fn changes(old: &HashMap<String, String>, new: &HashMap<String, String>) -> Vec<String> {
+    let mut names: Vec<_> = old
+        .keys()
+        .chain(new.keys().filter(|name| !old.contains_key(*name)))
+        .filter(|name| old.get(*name) != new.get(*name))
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+fn parse_value(value: &str) -> String {
+    if let Some(quoted) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        let mut unescaped = String::with_capacity(quoted.len());
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push('\\'),
+            }
+        }
+        unescaped
+    } else if let Some(quoted) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        quoted.to_string()
+    } else {
+        // unquoted values end at a comment
+        match value.find(" #") {
+            Some(idx) => value[..idx].trim_end().to_string(),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// Writes `values` to the env file at `path`, so that they can be loaded by other tools or
+/// with `kuiper -e`.
+///
+/// Entries already in the file are kept, unless `values` contains a new value for them.
+pub fn write<'a>(
+    path: impl AsRef<Path>,
+    values: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> KuiperResult<()> {
+    let path = path.as_ref();
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut values: BTreeMap<&str, &str> = values.into_iter().collect();
+
+    let mut out = String::new();
+    for line in existing.lines() {
+        let name = line
+            .split_once('=')
+            .map(|(name, _)| name.trim().trim_start_matches("export ").trim());
+        match name.and_then(|name| values.remove_entry(name)) {
+            Some((name, value)) => out.push_str(&entry(name, value)),
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    for (name, value) in values {
+        out.push_str(&entry(name, value));
+        out.push('\n');
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, out)?;
+    Ok(())
+}
+
+/// Formats a single `NAME=value` line, quoting the value if needed.
+fn entry(name: &str, value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '#' | '$' | '='));
+    if needs_quotes {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        format!("{name}=\"{escaped}\"")
+    } else {
+        format!("{name}={value}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn write_test() {
+        let path = std::env::temp_dir().join(format!("kuiper-{}.env", uuid::Uuid::new_v4()));
+        fs::write(&path, "# comment\nuser_id=1\nOTHER=kept\n").unwrap();
+
+        write(
+            &path,
+            [("kuiper_envfile_token", "abc def"), ("user_id", "42")],
+        )
+        .unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "# comment\nuser_id=42\nOTHER=kept\nkuiper_envfile_token=\"abc def\"\n"
+        );
+
+        dotenv::from_path(&path).unwrap();
+        assert_eq!(std::env::var("kuiper_envfile_token").unwrap(), "abc def");
+
+        let read = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read["kuiper_envfile_token"], "abc def");
+        assert_eq!(read["user_id"], "42");
+        assert_eq!(read.len(), 3);
+    }
+
+    #[test]
+    fn read_test() {
+        let values = read("../requests/example.env").unwrap();
+        assert_eq!(values["route"], "route_value");
+        assert_eq!(
+            parse_value(r#""a \"quoted\" value\n""#),
+            "a \"quoted\" value\n"
+        );
+        assert_eq!(parse_value("'$literal'"), "$literal");
+        assert_eq!(parse_value("value # comment"), "value");
+    }
+
+    #[test]
+    fn changes_test() {
+        let old = HashMap::from([
+            ("HOST".to_string(), "localhost".to_string()),
+            ("PORT".to_string(), "8080".to_string()),
+            ("TOKEN".to_string(), "abc".to_string()),
+        ]);
+        let new = HashMap::from([
+            ("HOST".to_string(), "dev.example.com".to_string()),
+            ("PORT".to_string(), "8080".to_string()),
+            ("USER".to_string(), "ada".to_string()),
+        ]);
+        assert_eq!(changes(&old, &new), vec!["HOST", "TOKEN", "USER"]);
+        assert!(changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn read_json_test() {
+        let path = std::env::temp_dir().join(format!("kuiper-{}.json", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            r#"{ "HOST": "dev.example.com", "PORT": 8080, "TLS": true }"#,
+        )
+        .unwrap();
+        let values = read_json(&path).unwrap();
+        assert_eq!(values["HOST"], "dev.example.com");
+        assert_eq!(values["PORT"], "8080");
+        assert_eq!(values["TLS"], "true");
+
+        fs::write(&path, r#"{ "HOST": null }"#).unwrap();
+        let result = read_json(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(KuiperError::JsonError(_))));
+    }
+}
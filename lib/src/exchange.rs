@@ -0,0 +1,132 @@
+use crate::{KuiperResult, Request, Response};
+use jiff::Timestamp;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// A self-contained record of a sent request and the response it got, which can be attached to
+/// bug reports and sent again later.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Exchange {
+    request_name: String,
+    request: Request,
+    response: Response,
+    environment: Fingerprint,
+}
+
+/// Describes the environment an [`Exchange`] was recorded in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    kuiper_version: String,
+    os: String,
+    arch: String,
+    recorded_at: String,
+}
+
+impl Fingerprint {
+    fn current() -> Self {
+        Self {
+            kuiper_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            recorded_at: Timestamp::now().to_string(),
+        }
+    }
+
+    pub fn kuiper_version(&self) -> &str {
+        &self.kuiper_version
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    pub fn recorded_at(&self) -> &str {
+        &self.recorded_at
+    }
+}
+
+impl Exchange {
+    /// Records `request`, as it was sent after interpolation, along with `response`.
+    pub fn new(request: &Request, response: &Response) -> Self {
+        Self {
+            request_name: request.name().to_string(),
+            request: request.clone(),
+            response: response.clone(),
+            environment: Fingerprint::current(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> KuiperResult<Self> {
+        let path = path.as_ref();
+        let exchange = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        trace!("loaded exchange at '{path:?}'");
+        Ok(exchange)
+    }
+
+    /// Writes the exchange to a new file in `dir`, named after the request and the current time,
+    /// and returns the path of that file.
+    pub fn save_in(&self, dir: impl AsRef<Path>) -> KuiperResult<PathBuf> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let stem = Path::new(&self.request_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("request");
+        let path = dir.join(format!("{stem}-{}.json", Timestamp::now().as_millisecond()));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        trace!("saved exchange to '{path:?}'");
+        Ok(path)
+    }
+
+    /// The recorded request, ready to be sent again.
+    pub fn request(&self) -> Request {
+        let mut request = self.request.clone();
+        request.name = self.request_name.clone();
+        request
+    }
+
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    pub fn environment(&self) -> &Fingerprint {
+        &self.environment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn round_trip_test() {
+        let request = Request::find("../requests/dependencies/login.kuiper").unwrap();
+        let response = Response::new(
+            200,
+            vec![("content-type".to_string(), "text/plain".to_string())],
+            "hello".to_string(),
+            Duration::from_millis(12),
+        );
+        let exchange = Exchange::new(&request, &response);
+
+        let dir = std::env::temp_dir().join(format!("kuiper-exchange-{}", uuid::Uuid::new_v4()));
+        let path = exchange.save_in(&dir).unwrap();
+        let loaded = Exchange::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.environment(), exchange.environment());
+        assert_eq!(loaded.request(), request);
+        assert_eq!(loaded.response(), &response);
+    }
+}
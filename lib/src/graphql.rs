@@ -0,0 +1,231 @@
+//! GraphQL requests, whose query and variables are sent as a JSON body, and the `errors` of
+//! their responses.
+
+use crate::{KuiperError, KuiperResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// The query of a GraphQL request and the variables it is sent with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GraphQl {
+    /// The query, unless it is read from `query_file`. It is sent as it is written, without
+    /// interpolating placeholders, which belong in `variables`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    /// A `.graphql` file with the query, relative to the request file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query_file: Option<PathBuf>,
+    /// The values of the variables of the query, whose strings are interpolated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    variables: Option<Value>,
+    /// The operation to run, if the query defines more than one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    operation_name: Option<String>,
+}
+
+impl GraphQl {
+    /// The query, if it is written in the request file.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// The file the query is read from, if it is not written in the request file.
+    pub fn query_file(&self) -> Option<&Path> {
+        self.query_file.as_deref()
+    }
+
+    pub fn variables(&self) -> Option<&Value> {
+        self.variables.as_ref()
+    }
+
+    pub fn operation_name(&self) -> Option<&str> {
+        self.operation_name.as_deref()
+    }
+
+    pub(crate) fn variables_mut(&mut self) -> Option<&mut Value> {
+        self.variables.as_mut()
+    }
+
+    /// Checks that exactly one of `query` and `query_file` is set, and resolves the path of
+    /// `query_file` relative to `dir`.
+    pub(crate) fn resolve(&mut self, dir: Option<&Path>) -> KuiperResult<()> {
+        match (&self.query, &mut self.query_file) {
+            (Some(_), Some(_)) => Err(KuiperError::InvalidGraphQl(
+                "only one of query and query_file can be set".to_string(),
+            )),
+            (None, None) => Err(KuiperError::InvalidGraphQl(
+                "one of query and query_file has to be set".to_string(),
+            )),
+            (None, Some(file)) => {
+                if let Some(dir) = dir {
+                    *file = dir.join(&*file);
+                }
+                Ok(())
+            }
+            (Some(_), None) => Ok(()),
+        }
+    }
+
+    /// The JSON body that the request is sent with, reading the query from its file if it has
+    /// one.
+    pub(crate) fn body(&self) -> KuiperResult<Value> {
+        let query = match (&self.query, &self.query_file) {
+            (Some(query), _) => query.clone(),
+            (None, Some(file)) => std::fs::read_to_string(file)?,
+            (None, None) => {
+                return Err(KuiperError::InvalidGraphQl(
+                    "one of query and query_file has to be set".to_string(),
+                ))
+            }
+        };
+        let mut body = json!({ "query": query });
+        if let Some(variables) = &self.variables {
+            body["variables"] = variables.clone();
+        }
+        if let Some(operation_name) = &self.operation_name {
+            body["operationName"] = json!(operation_name);
+        }
+        Ok(body)
+    }
+}
+
+/// An error in the `errors` of a GraphQL response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    message: String,
+    /// The path of the field that failed, like `user.friends.0.name`.
+    path: Option<String>,
+    /// The lines and columns, from 1, of the parts of the query that caused the error.
+    locations: Vec<(u64, u64)>,
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn locations(&self) -> &[(u64, u64)] {
+        &self.locations
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(path) = &self.path {
+            write!(f, " at {path}")?;
+        }
+        for (line, column) in &self.locations {
+            write!(f, " (line {line}, column {column})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The errors of a GraphQL response body, which are none if the body is not JSON or has no
+/// `errors`.
+pub fn errors(body: &str) -> Vec<Error> {
+    let Ok(body) = serde_json::from_str::<Value>(body) else {
+        return Vec::new();
+    };
+    let Some(errors) = body.get("errors").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    errors
+        .iter()
+        .map(|error| Error {
+            message: match error.get("message") {
+                Some(Value::String(message)) => message.clone(),
+                _ => error.to_string(),
+            },
+            path: error.get("path").and_then(Value::as_array).map(|path| {
+                path.iter()
+                    .map(crate::value_text)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            }),
+            locations: error
+                .get("locations")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|location| {
+                    Some((
+                        location.get("line")?.as_u64()?,
+                        location.get("column")?.as_u64()?,
+                    ))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn body_test() {
+        let graphql: GraphQl = serde_json::from_value(json!({
+            "query": "query User($id: ID!) { user(id: $id) { name } }",
+            "variables": { "id": "42" },
+            "operation_name": "User"
+        }))
+        .unwrap();
+        assert_eq!(
+            graphql.body().unwrap(),
+            json!({
+                "query": "query User($id: ID!) { user(id: $id) { name } }",
+                "variables": { "id": "42" },
+                "operationName": "User"
+            })
+        );
+
+        let mut both: GraphQl =
+            serde_json::from_value(json!({ "query": "{ me { id } }", "query_file": "me.graphql" }))
+                .unwrap();
+        assert!(matches!(
+            both.resolve(None),
+            Err(KuiperError::InvalidGraphQl(_))
+        ));
+        let mut neither = GraphQl::default();
+        assert!(matches!(
+            neither.resolve(None),
+            Err(KuiperError::InvalidGraphQl(_))
+        ));
+    }
+
+    #[test]
+    fn errors_test() {
+        let body = r#"{
+            "data": { "user": null },
+            "errors": [
+                {
+                    "message": "user 42 not found",
+                    "path": ["user", 0, "name"],
+                    "locations": [{ "line": 2, "column": 3 }]
+                },
+                { "extensions": { "code": "INTERNAL" } }
+            ]
+        }"#;
+        let messages: Vec<_> = errors(body).iter().map(Error::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "user 42 not found at user.0.name (line 2, column 3)",
+                r#"{"extensions":{"code":"INTERNAL"}}"#
+            ]
+        );
+        assert!(errors(r#"{ "data": {} }"#).is_empty());
+        assert!(errors("not json").is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{KuiperError, KuiperResult, Request};
+use regex::Regex;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Which part of a request file to search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Every line of the file.
+    All,
+    /// Only the `uri` field.
+    Uri,
+    /// Only the value of the header with this name, compared case-insensitively.
+    Header(String),
+}
+
+/// A line in a request file that matched a pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrepMatch {
+    path: PathBuf,
+    line: usize,
+    text: String,
+}
+
+impl GrepMatch {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The 1-based line number of the match.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The matching line, without surrounding whitespace.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Searches the contents of every request file under `root` for `pattern`, a regex.
+///
+/// Files are searched as written, before interpolation.
+pub fn grep(
+    root: impl Into<PathBuf>,
+    pattern: &str,
+    scope: &Scope,
+) -> KuiperResult<Vec<GrepMatch>> {
+    let regex = Regex::new(pattern).map_err(|_| KuiperError::InvalidRegex(pattern.to_string()))?;
+    let mut paths = Request::search_paths(root, "")?;
+    paths.sort();
+
+    let mut matches = Vec::new();
+    for path in paths {
+        let contents = fs::read_to_string(&path)?;
+        let lines: Vec<usize> = match scope {
+            Scope::All => contents
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| regex.is_match(line))
+                .map(|(idx, _)| idx + 1)
+                .collect(),
+            Scope::Uri | Scope::Header(_) => {
+                let value: Value = crate::parse_request(&path, &contents)?;
+                scoped_values(&value, scope)
+                    .into_iter()
+                    .filter(|v| regex.is_match(v))
+                    .filter_map(|v| line_of(&contents, v))
+                    .collect()
+            }
+        };
+
+        let all_lines: Vec<&str> = contents.lines().collect();
+        matches.extend(lines.into_iter().map(|line| GrepMatch {
+            path: path.clone(),
+            line,
+            text: all_lines[line - 1].trim().to_string(),
+        }));
+    }
+
+    Ok(matches)
+}
+
+fn scoped_values<'a>(request: &'a Value, scope: &Scope) -> Vec<&'a str> {
+    match scope {
+        Scope::All => Vec::new(),
+        Scope::Uri => request["uri"].as_str().into_iter().collect(),
+        Scope::Header(name) => request["headers"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .filter_map(|(_, value)| value.as_str())
+            .collect(),
+    }
+}
+
+/// Finds the line a JSON string with the value `value` is written on.
+fn line_of(contents: &str, value: &str) -> Option<usize> {
+    let encoded = serde_json::to_string(value).ok()?;
+    let offset = contents.find(&encoded)?;
+    Some(contents[..offset].lines().count().max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn locations(matches: &[GrepMatch]) -> Vec<(String, usize)> {
+        matches
+            .iter()
+            .map(|m| {
+                (
+                    m.path().file_name().unwrap().to_string_lossy().into_owned(),
+                    m.line(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grep_test() {
+        let matches = grep("../requests", "api/user", &Scope::All).unwrap();
+        assert_eq!(
+            locations(&matches),
+            vec![
+                ("create_user.kuiper".to_string(), 2),
+                ("profile.kuiper".to_string(), 2),
+                ("settings.kuiper".to_string(), 2),
+                ("request_in_subdir.kuiper".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            matches[3].text(),
+            r#""uri": "http://localhost/api/user/1","#
+        );
+    }
+
+    #[test]
+    fn grep_scope_test() {
+        let matches = grep("../requests", "password", &Scope::Uri).unwrap();
+        assert!(matches.is_empty());
+
+        let matches = grep(
+            "../requests/subdir",
+            "value_1",
+            &Scope::Header("REQUEST_SPECIFIC_HEADER_1".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            locations(&matches),
+            vec![("request_in_subdir.kuiper".to_string(), 6)]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! gRPC calls, made with [grpcurl](https://github.com/fullstorydev/grpcurl) when built with the
+//! `grpc` feature.
+//!
+//! The message is written as JSON, encoded with the types of a descriptor set or a `.proto`
+//! file, and the response is decoded back to JSON. The `uri` of the request is the address of
+//! the server, over TLS for `https` and in plaintext for `http`.
+
+use crate::{KuiperError, KuiperResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A gRPC call of a request.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Grpc {
+    /// The fully qualified name of the service, like `shop.v1.Orders`.
+    service: String,
+    /// The name of the method of the service to call.
+    method: String,
+    /// A descriptor set made with `protoc --descriptor_set_out --include_imports`, relative to
+    /// the request file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    descriptor_set: Option<PathBuf>,
+    /// A `.proto` file, relative to the request file, that is used instead of a descriptor set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proto: Option<PathBuf>,
+    /// Directories that the imports of `proto` are found in, relative to the request file. The
+    /// directory of `proto` is used if none are given.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    import_paths: Vec<PathBuf>,
+    /// The request message as JSON, whose strings are interpolated.
+    #[serde(default = "empty_message")]
+    message: Value,
+}
+
+fn empty_message() -> Value {
+    Value::Object(Default::default())
+}
+
+impl Grpc {
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// The descriptor set of the service, with its path resolved, if it has one.
+    pub fn descriptor_set(&self) -> Option<&Path> {
+        self.descriptor_set.as_deref()
+    }
+
+    /// The `.proto` file of the service, with its path resolved, if it has one.
+    pub fn proto(&self) -> Option<&Path> {
+        self.proto.as_deref()
+    }
+
+    pub fn import_paths(&self) -> &[PathBuf] {
+        &self.import_paths
+    }
+
+    pub fn message(&self) -> &Value {
+        &self.message
+    }
+
+    pub(crate) fn message_mut(&mut self) -> &mut Value {
+        &mut self.message
+    }
+
+    /// Checks that exactly one of `descriptor_set` and `proto` is set, and resolves the paths
+    /// relative to `dir`.
+    pub(crate) fn resolve(&mut self, dir: Option<&Path>) -> KuiperResult<()> {
+        match (&self.descriptor_set, &self.proto) {
+            (Some(_), Some(_)) => {
+                return Err(KuiperError::InvalidGrpc(
+                    "only one of descriptor_set and proto can be set".to_string(),
+                ))
+            }
+            (None, None) => {
+                return Err(KuiperError::InvalidGrpc(
+                    "one of descriptor_set and proto has to be set".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        if let Some(dir) = dir {
+            for path in self
+                .descriptor_set
+                .iter_mut()
+                .chain(self.proto.iter_mut())
+                .chain(self.import_paths.iter_mut())
+            {
+                *path = dir.join(&*path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The HTTP status that a gRPC status code corresponds to, the way gRPC gateways map them, so
+/// that expectations, retries and reports treat gRPC responses like any other.
+pub fn http_status(code: u8) -> u16 {
+    match code {
+        0 => 200,
+        1 => 499,
+        3 | 9 | 11 => 400,
+        4 => 504,
+        5 => 404,
+        6 | 10 => 409,
+        7 => 403,
+        8 => 429,
+        12 => 501,
+        14 => 503,
+        16 => 401,
+        _ => 500,
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub(crate) use grpcurl::call;
+
+#[cfg(feature = "grpc")]
+mod grpcurl {
+    use super::{http_status, Grpc};
+    use crate::{KuiperError, KuiperResult, Request, Response};
+    use std::{
+        collections::BTreeMap,
+        io::Write,
+        process::{Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    /// grpcurl exits with this plus the status code when a call fails with a status other than
+    /// `OK`.
+    const STATUS_EXIT_OFFSET: i32 = 64;
+
+    /// Calls the method of `grpc`, the call of `request`, with grpcurl.
+    pub(crate) fn call(request: &Request, grpc: &Grpc) -> KuiperResult<Response> {
+        let mut command = command(request, grpc)?;
+        let start = Instant::now();
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| KuiperError::GrpcFailed(format!("failed to run grpcurl: {e}")))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(grpc.message.to_string().as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        response(
+            output.status.code(),
+            output.stdout,
+            &String::from_utf8_lossy(&output.stderr),
+            start.elapsed(),
+        )
+    }
+
+    /// The grpcurl command that calls the method of `grpc`, with the message on its stdin.
+    fn command(request: &Request, grpc: &Grpc) -> KuiperResult<Command> {
+        let url = reqwest::Url::parse(request.uri())
+            .map_err(|e| KuiperError::InvalidGrpc(format!("invalid uri: {e}")))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| KuiperError::InvalidGrpc("the uri has no host".to_string()))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| KuiperError::InvalidGrpc("the uri has no port".to_string()))?;
+
+        let mut command = Command::new("grpcurl");
+        command.args(["-format", "json", "-format-error", "-emit-defaults"]);
+        if url.scheme() == "http" {
+            command.arg("-plaintext");
+        }
+        if let Some(descriptor_set) = &grpc.descriptor_set {
+            command.arg("-protoset").arg(descriptor_set);
+        }
+        if let Some(proto) = &grpc.proto {
+            let import_paths = match proto.parent() {
+                Some(dir) if grpc.import_paths.is_empty() => vec![dir.to_path_buf()],
+                _ => grpc.import_paths.clone(),
+            };
+            for dir in &import_paths {
+                command.arg("-import-path").arg(dir);
+            }
+            // grpcurl looks for the file in the import paths
+            let name = import_paths
+                .iter()
+                .find_map(|dir| proto.strip_prefix(dir).ok())
+                .unwrap_or(proto);
+            command.arg("-proto").arg(name);
+        }
+        let headers: BTreeMap<_, _> = request
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name, value.as_ref()?)))
+            .collect();
+        for (name, value) in headers {
+            command.arg("-H").arg(format!("{name}: {value}"));
+        }
+        if let Some(timeout) = request.timeout() {
+            command
+                .arg("-max-time")
+                .arg(format!("{:.3}", timeout.as_secs_f64()));
+        }
+        command
+            .args(["-d", "@"])
+            .arg(format!("{host}:{port}"))
+            .arg(format!("{}/{}", grpc.service, grpc.method));
+        Ok(command)
+    }
+
+    /// The response of a call that grpcurl exited from with `code`, after printing `stdout` and
+    /// `stderr`. The status code is in the `grpc-status` header, and the body is the response
+    /// messages, or the error, as JSON.
+    fn response(
+        code: Option<i32>,
+        stdout: Vec<u8>,
+        stderr: &str,
+        duration: Duration,
+    ) -> KuiperResult<Response> {
+        let status = match code {
+            Some(0) => 0,
+            Some(code) if (STATUS_EXIT_OFFSET..=STATUS_EXIT_OFFSET + 16).contains(&code) => {
+                (code - STATUS_EXIT_OFFSET) as u8
+            }
+            _ => return Err(KuiperError::GrpcFailed(stderr.trim().to_string())),
+        };
+        let headers = vec![("grpc-status".to_string(), status.to_string())];
+        Ok(Response::from_bytes(
+            http_status(status),
+            headers,
+            stdout,
+            duration,
+        ))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use test_log::test;
+
+        #[test]
+        fn command_test() {
+            let request = Request::find("../requests/grpc/get_order.kuiper").unwrap();
+            let grpc = request.grpc().unwrap();
+            let command = command(&request, grpc).unwrap();
+            let args: Vec<_> = command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            let dir = std::path::Path::new("../requests/grpc")
+                .canonicalize()
+                .unwrap();
+            assert_eq!(
+                args,
+                vec![
+                    "-format",
+                    "json",
+                    "-format-error",
+                    "-emit-defaults",
+                    "-plaintext",
+                    "-import-path",
+                    &dir.to_string_lossy(),
+                    "-proto",
+                    "orders.proto",
+                    "-H",
+                    "root_header_1: root_value_1",
+                    "-H",
+                    "root_header_2: root_value_2",
+                    "-H",
+                    "root_header_3: root_value_3",
+                    "-H",
+                    "x-tenant: acme",
+                    "-max-time",
+                    "2.500",
+                    "-d",
+                    "@",
+                    "localhost:50051",
+                    "shop.v1.Orders/GetOrder",
+                ]
+            );
+        }
+
+        #[test]
+        fn response_test() {
+            let ok = response(Some(0), br#"{"id": "7"}"#.to_vec(), "", Duration::ZERO).unwrap();
+            assert_eq!(ok.status(), 200);
+            assert_eq!(ok.header("grpc-status"), Some("0"));
+            assert_eq!(ok.body(), r#"{"id": "7"}"#);
+
+            let not_found = response(
+                Some(69),
+                br#"{"code": 5, "message": "no order 7"}"#.to_vec(),
+                "",
+                Duration::ZERO,
+            )
+            .unwrap();
+            assert_eq!(not_found.status(), 404);
+            assert_eq!(not_found.header("grpc-status"), Some("5"));
+
+            assert!(matches!(
+                response(Some(1), Vec::new(), "Failed to dial target host\n", Duration::ZERO),
+                Err(KuiperError::GrpcFailed(reason)) if reason == "Failed to dial target host"
+            ));
+        }
+    }
+}
+
+/// Fails, since calls are only made when built with the `grpc` feature.
+#[cfg(not(feature = "grpc"))]
+pub(crate) fn call(request: &crate::Request, _grpc: &Grpc) -> KuiperResult<crate::Response> {
+    Err(KuiperError::GrpcFailed(format!(
+        "'{}' can't be called, since kuiper was built without the `grpc` feature",
+        request.name()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn resolve_test() {
+        let mut grpc: Grpc = serde_json::from_value(serde_json::json!({
+            "service": "shop.v1.Orders",
+            "method": "GetOrder",
+            "proto": "orders.proto",
+            "import_paths": ["protos"]
+        }))
+        .unwrap();
+        assert_eq!(grpc.message(), &empty_message());
+        grpc.resolve(Some(Path::new("dir"))).unwrap();
+        assert_eq!(grpc.proto(), Some(Path::new("dir/orders.proto")));
+        assert_eq!(grpc.import_paths(), [PathBuf::from("dir/protos")]);
+
+        let mut both = grpc.clone();
+        both.descriptor_set = Some(PathBuf::from("orders.pb"));
+        assert!(matches!(
+            both.resolve(None),
+            Err(KuiperError::InvalidGrpc(_))
+        ));
+        assert_eq!(http_status(0), 200);
+        assert_eq!(http_status(14), 503);
+    }
+}
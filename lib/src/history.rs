@@ -0,0 +1,279 @@
+//! A history of the requests that were sent, with the values they were interpolated with, so
+//! that a request can be sent again exactly as it was, even after tokens and environment
+//! variables have changed.
+
+use crate::{
+    lint::NAMESPACES,
+    lock::{self, FileLock},
+    KuiperError, KuiperResult, Request,
+};
+use jiff::Timestamp;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// How many entries are kept, dropping the oldest ones first.
+const MAX_ENTRIES: usize = 200;
+
+/// Namespaces whose values are never recorded, since they are secret or may be.
+const REDACTED_NAMESPACES: &[&str] = &["secret", "prompt_secret", "cmd"];
+
+/// Values whose names contain one of these are never recorded, like `{{env:API_TOKEN}}`.
+const SENSITIVE_NAMES: &[&str] = &[
+    "token",
+    "password",
+    "secret",
+    "key",
+    "auth",
+    "credential",
+    "jwt",
+];
+
+/// The values that the placeholders of a request were replaced by when it was interpolated,
+/// keyed by the placeholder without its braces, like `env:HOST`. Sensitive values are redacted,
+/// and are `None`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Snapshot {
+    values: BTreeMap<String, Option<String>>,
+}
+
+impl Snapshot {
+    /// Records the `resolved` values, redacting the sensitive ones.
+    pub(crate) fn new(resolved: BTreeMap<String, String>) -> Self {
+        let values = resolved
+            .into_iter()
+            .map(|(placeholder, value)| {
+                let redacted = is_sensitive(&placeholder);
+                (placeholder, (!redacted).then_some(value))
+            })
+            .collect();
+        Self { values }
+    }
+
+    pub fn values(&self) -> &BTreeMap<String, Option<String>> {
+        &self.values
+    }
+
+    /// The values that were recorded, without the redacted ones.
+    pub(crate) fn recorded(&self) -> HashMap<String, String> {
+        self.values
+            .iter()
+            .filter_map(|(placeholder, value)| Some((placeholder.clone(), value.clone()?)))
+            .collect()
+    }
+
+    /// The placeholders whose values were redacted, and have to be provided again.
+    pub fn redacted(&self) -> impl Iterator<Item = &str> {
+        self.values
+            .iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(placeholder, _)| placeholder.as_str())
+    }
+}
+
+fn is_sensitive(placeholder: &str) -> bool {
+    let (namespace, name) = placeholder.split_once(':').unwrap_or(("", placeholder));
+    let name = name.to_lowercase();
+    // a placeholder is as sensitive as the ones nested in it, like `expr:base64({{cmd:whoami}})`
+    let mut nested = placeholder
+        .split("{{")
+        .skip(1)
+        .filter_map(|nested| nested.split_once("}}").map(|(nested, _)| nested));
+    REDACTED_NAMESPACES.contains(&namespace)
+        // the namespaces of resolvers, like `vault`, may hold anything
+        || !NAMESPACES.contains(&namespace)
+        || SENSITIVE_NAMES
+            .iter()
+            .any(|sensitive| name.contains(sensitive))
+        || nested.any(is_sensitive)
+}
+
+/// A request that was sent, and the values it was interpolated with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    id: String,
+    request_name: String,
+    sent_at: String,
+    /// `None` if no response was received.
+    status: Option<u16>,
+    snapshot: Snapshot,
+}
+
+impl HistoryEntry {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The path of the request file.
+    pub fn request_name(&self) -> &str {
+        &self.request_name
+    }
+
+    pub fn sent_at(&self) -> &str {
+        &self.sent_at
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+/// A directory with a file per [`HistoryEntry`].
+#[derive(Clone, Debug)]
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Records that `request` was sent and got a response with `status`, and drops the oldest
+    /// entries if there are too many.
+    pub fn record(&self, request: &Request, status: Option<u16>) -> KuiperResult<HistoryEntry> {
+        fs::create_dir_all(&self.dir)?;
+        let now = Timestamp::now();
+        // ids sort in the order entries were recorded in
+        let id = format!(
+            "{}-{}",
+            now.as_millisecond(),
+            &Uuid::new_v4().simple().to_string()[..8]
+        );
+        let entry = HistoryEntry {
+            id,
+            request_name: request.name().to_string(),
+            sent_at: now.to_string(),
+            status,
+            snapshot: request.snapshot().clone(),
+        };
+        lock::write_atomic(&self.path(&entry.id), serde_json::to_string_pretty(&entry)?)?;
+        trace!(
+            "recorded '{}' in the history as '{}'",
+            request.name(),
+            entry.id
+        );
+
+        // other runs may be pruning at the same time
+        let _lock = FileLock::acquire(&self.dir.join("entries"))?;
+        let ids = self.ids()?;
+        for id in &ids[..ids.len().saturating_sub(MAX_ENTRIES)] {
+            match fs::remove_file(self.path(id)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+
+    pub fn load(&self, id: &str) -> KuiperResult<HistoryEntry> {
+        let path = self.path(id);
+        if !path.is_file() {
+            return Err(KuiperError::UnknownHistoryEntry(id.to_string()));
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Every entry, oldest first.
+    pub fn entries(&self) -> KuiperResult<Vec<HistoryEntry>> {
+        self.ids()?.iter().map(|id| self.load(id)).collect()
+    }
+
+    fn ids(&self) -> KuiperResult<Vec<String>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    ids.push(stem.to_string());
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use test_log::test;
+
+    #[test]
+    fn snapshot_test() {
+        let snapshot = Snapshot::new(BTreeMap::from([
+            ("env:HOST".to_string(), "example.com".to_string()),
+            ("env:API_TOKEN".to_string(), "t0k3n".to_string()),
+            ("secret:db".to_string(), "hunter2".to_string()),
+            ("expr:uuid".to_string(), "0000".to_string()),
+            (
+                "expr:base64({{cmd:whoami}})".to_string(),
+                "YWRh".to_string(),
+            ),
+        ]));
+        assert_eq!(
+            snapshot.redacted().collect::<Vec<_>>(),
+            vec!["env:API_TOKEN", "expr:base64({{cmd:whoami}})", "secret:db"]
+        );
+        assert_eq!(
+            snapshot.values()["env:HOST"].as_deref(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn history_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-history-{}", Uuid::new_v4()));
+        let history = History::open(&dir);
+        let context = Context::new().with_data(HashMap::from([
+            ("id".to_string(), "42".to_string()),
+            ("name".to_string(), "Ada".to_string()),
+        ]));
+        let request =
+            Request::find_with_context("../requests/data/create_user.kuiper", &context).unwrap();
+        assert_eq!(
+            request.snapshot().values()["data:id"].as_deref(),
+            Some("42")
+        );
+
+        let entry = history.record(&request, Some(200)).unwrap();
+        assert_eq!(history.load(entry.id()).unwrap(), entry);
+        assert_eq!(history.entries().unwrap(), vec![entry.clone()]);
+        assert!(matches!(
+            history.load("missing"),
+            Err(KuiperError::UnknownHistoryEntry(_))
+        ));
+
+        // the recorded values are used again, instead of the values of the new context
+        let replayed = Request::find_with_context(
+            request.name(),
+            &Context::new().with_snapshot(entry.snapshot()),
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(replayed, request);
+    }
+}
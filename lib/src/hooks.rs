@@ -0,0 +1,164 @@
+//! Commands run after a response has been received, from the `hooks` block of a request.
+
+use crate::{AssertionOutcome, Response};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Environment variable with the status code of the response, set for every hook.
+pub const STATUS_VAR: &str = "KUIPER_STATUS";
+/// Environment variable with the path of the request file, set for every hook.
+pub const REQUEST_VAR: &str = "KUIPER_REQUEST";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hooks {
+    /// Commands run, in order, after the response has been received and checked.
+    #[serde(default)]
+    after: Vec<Hook>,
+}
+
+/// A shell command, run in the directory of the request file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Hook {
+    cmd: String,
+    #[serde(default)]
+    stdin: HookInput,
+}
+
+/// What part of the response is written to the standard input of a hook.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookInput {
+    #[default]
+    Body,
+    /// The headers, as a JSON array of name and value pairs.
+    Headers,
+    /// The status, headers, body and duration, as a JSON object.
+    Response,
+    None,
+}
+
+impl Hooks {
+    pub fn after(&self) -> &[Hook] {
+        &self.after
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.after.is_empty()
+    }
+}
+
+impl Hook {
+    pub fn cmd(&self) -> &str {
+        &self.cmd
+    }
+
+    pub fn stdin(&self) -> HookInput {
+        self.stdin
+    }
+
+    /// Runs the hook in `dir` with the response to the request at `request_path`. The hook fails
+    /// if it can't be started or exits with a non-zero code.
+    pub fn run(&self, dir: &Path, request_path: &str, response: &Response) -> AssertionOutcome {
+        let description = format!("hook '{}' succeeds", self.cmd);
+        let failure = match self.execute(dir, request_path, response) {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(match status.code() {
+                Some(code) => format!("exited with code {code}"),
+                None => "was terminated by a signal".to_string(),
+            }),
+            Err(e) => Some(format!("failed to run: {e}")),
+        };
+        AssertionOutcome::new(description, failure)
+    }
+
+    fn execute(
+        &self,
+        dir: &Path,
+        request_path: &str,
+        response: &Response,
+    ) -> io::Result<std::process::ExitStatus> {
+        let input = match self.stdin {
+            HookInput::Body => Some(response.body().to_string()),
+            HookInput::Headers => Some(serde_json::to_string(response.headers())?),
+            HookInput::Response => Some(serde_json::to_string(response)?),
+            HookInput::None => None,
+        };
+
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(&self.cmd);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.cmd);
+            command
+        };
+        let mut child = command
+            .current_dir(dir)
+            .env(STATUS_VAR, response.status().to_string())
+            .env(REQUEST_VAR, request_path)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .spawn()?;
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            // a hook that exits without reading its input is not an error
+            match stdin.write_all(input.as_bytes()) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+                _ => {}
+            }
+        }
+        child.wait()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn run_test() {
+        let hooks: Hooks = serde_json::from_str(
+            r#"{ "after": [
+                { "cmd": "grep -q alice" },
+                { "cmd": "grep -q bob" },
+                { "cmd": "grep -q x-id", "stdin": "headers" },
+                { "cmd": "test \"$KUIPER_STATUS\" = 201", "stdin": "none" },
+                { "cmd": "exit 3", "stdin": "none" }
+            ] }"#,
+        )
+        .unwrap();
+        let response = Response::new(
+            201,
+            vec![("x-id".to_string(), "1".to_string())],
+            r#"{"name":"alice"}"#.to_string(),
+            Duration::ZERO,
+        );
+
+        let failures: Vec<_> = hooks
+            .after()
+            .iter()
+            .map(|hook| hook.run(Path::new("."), "request.kuiper", &response))
+            .map(|outcome| outcome.failure().map(str::to_string))
+            .collect();
+        assert_eq!(
+            failures,
+            vec![
+                None,
+                Some("exited with code 1".to_string()),
+                None,
+                None,
+                Some("exited with code 3".to_string())
+            ]
+        );
+    }
+}
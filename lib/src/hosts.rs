@@ -0,0 +1,250 @@
+use crate::{Headers, KuiperError, KuiperResult};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Defaults for requests to hosts matching a pattern, from the `hosts` section of `kuiper.toml`.
+///
+/// Patterns are host names in which `*` matches any number of characters, like
+/// `*.internal.example.com`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HostDefaults {
+    pattern: String,
+    /// Headers added to matching requests that don't already set them.
+    #[serde(default)]
+    headers: Headers,
+    /// Proxy for matching requests, where `null`, or `false` in TOML, means connecting directly.
+    #[serde(
+        default,
+        deserialize_with = "present_proxy",
+        skip_serializing_if = "Option::is_none"
+    )]
+    proxy: Option<Option<String>>,
+    /// How many requests may be sent to each matching host per minute, across runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    requests_per_minute: Option<u32>,
+    /// The SHA-256 fingerprint of the certificate that matching hosts have to present, unless
+    /// a request pins one of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pin_sha256: Option<String>,
+}
+
+impl HostDefaults {
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The proxy for matching requests, `Some(None)` if they should not use a proxy, or `None`
+    /// if these defaults don't specify one.
+    pub fn proxy(&self) -> Option<Option<&str>> {
+        self.proxy.as_ref().map(Option::as_deref)
+    }
+
+    pub fn requests_per_minute(&self) -> Option<u32> {
+        self.requests_per_minute
+    }
+
+    pub fn pin_sha256(&self) -> Option<&str> {
+        self.pin_sha256.as_deref()
+    }
+
+    /// Returns `true` if `host` matches the pattern, compared case-insensitively.
+    pub fn matches(&self, host: &str) -> bool {
+        glob_matches(
+            &self.pattern.to_ascii_lowercase(),
+            &host.to_ascii_lowercase(),
+        )
+    }
+}
+
+/// Which hosts requests may be sent to, from the `allowed_hosts` and `denied_hosts` sections of
+/// `kuiper.toml`, which contain patterns like those of [`HostDefaults`].
+///
+/// If there is an allowlist, only hosts on it are allowed. Hosts on the denylist are never
+/// allowed, unless they are added as exceptions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostPolicy {
+    #[serde(default)]
+    allowed_hosts: Vec<String>,
+    #[serde(default)]
+    denied_hosts: Vec<String>,
+    /// Hosts that are allowed regardless of the lists, e.g. from `--allow-host`.
+    #[serde(skip)]
+    exceptions: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn allowed_hosts(&self) -> &[String] {
+        &self.allowed_hosts
+    }
+
+    pub fn denied_hosts(&self) -> &[String] {
+        &self.denied_hosts
+    }
+
+    /// Allows `hosts`, which are host names or patterns, regardless of the lists.
+    pub fn with_exceptions(mut self, hosts: Vec<String>) -> Self {
+        self.exceptions = hosts;
+        self
+    }
+
+    /// Combines the lists and exceptions of this policy with those of `other`.
+    pub fn merge(mut self, other: HostPolicy) -> Self {
+        self.allowed_hosts.extend(other.allowed_hosts);
+        self.denied_hosts.extend(other.denied_hosts);
+        self.exceptions.extend(other.exceptions);
+        self
+    }
+
+    /// Returns an error if a request to `uri` may not be sent. A uri without a host, like one
+    /// whose base url was interpolated from an empty variable, is only allowed if there are no
+    /// lists at all.
+    pub fn check(&self, uri: &str) -> KuiperResult<()> {
+        if self.allowed_hosts.is_empty() && self.denied_hosts.is_empty() {
+            return Ok(());
+        }
+        let host = reqwest::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| KuiperError::HostNotAllowed(uri.to_string()))?;
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| glob_matches(&pattern.to_ascii_lowercase(), &host))
+        };
+        let allowed = matches(&self.exceptions)
+            || (!matches(&self.denied_hosts)
+                && (self.allowed_hosts.is_empty() || matches(&self.allowed_hosts)));
+        if allowed {
+            Ok(())
+        } else {
+            Err(KuiperError::HostNotAllowed(host))
+        }
+    }
+}
+
+/// Builds a proxy that sends each request through the proxy of the first defaults that match
+/// its host and specify one, or `None` if no defaults specify a proxy.
+pub fn proxy(hosts: &[HostDefaults]) -> Option<reqwest::Proxy> {
+    if hosts.iter().all(|defaults| defaults.proxy().is_none()) {
+        return None;
+    }
+    let hosts = hosts.to_vec();
+    Some(reqwest::Proxy::custom(move |url| {
+        let host = url.host_str()?;
+        let proxy = hosts
+            .iter()
+            .filter(|defaults| defaults.matches(host))
+            .find_map(|defaults| defaults.proxy())??;
+        reqwest::Url::parse(proxy).ok()
+    }))
+}
+
+/// Deserializes a proxy that is present, even if it is `null` or `false`, as `Some`, so that it
+/// can be told apart from a missing one.
+fn present_proxy<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Proxy {
+        Url(Option<String>),
+        // TOML has no `null`
+        Direct(bool),
+    }
+
+    match Proxy::deserialize(deserializer)? {
+        Proxy::Url(url) => Ok(Some(url)),
+        Proxy::Direct(false) => Ok(Some(None)),
+        Proxy::Direct(true) => Err(serde::de::Error::custom(
+            "proxy must be a url, or null or false to connect directly",
+        )),
+    }
+}
+
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let parts: Vec<_> = pattern.split('*').collect();
+    let [first, middle @ .., last] = parts.as_slice() else {
+        return pattern == text;
+    };
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn matches_test() {
+        let defaults: HostDefaults =
+            serde_json::from_str(r#"{ "pattern": "*.internal.example.com" }"#).unwrap();
+        assert!(defaults.matches("api.internal.example.com"));
+        assert!(defaults.matches("API.Internal.Example.com"));
+        assert!(!defaults.matches("internal.example.com"));
+        assert!(!defaults.matches("api.example.com"));
+
+        assert!(glob_matches("*", "anything"));
+        assert!(glob_matches("api-*.example.*", "api-eu.example.org"));
+        assert!(!glob_matches("api-*.example.*", "web-eu.example.org"));
+        assert!(glob_matches("example.com", "example.com"));
+    }
+
+    #[test]
+    fn proxy_field_test() {
+        let defaults: Vec<HostDefaults> = serde_json::from_str(
+            r#"[
+                { "pattern": "*.internal.example.com", "proxy": null },
+                { "pattern": "*", "proxy": "http://proxy:3128" },
+                { "pattern": "example.com" },
+                { "pattern": "localhost", "proxy": false }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(defaults[0].proxy(), Some(None));
+        assert_eq!(defaults[1].proxy(), Some(Some("http://proxy:3128")));
+        assert_eq!(defaults[2].proxy(), None);
+        assert_eq!(defaults[3].proxy(), Some(None));
+        assert!(proxy(&defaults).is_some());
+        assert!(proxy(&defaults[2..3]).is_none());
+        assert!(
+            serde_json::from_str::<HostDefaults>(r#"{ "pattern": "*", "proxy": true }"#).is_err()
+        );
+    }
+
+    #[test]
+    fn policy_test() {
+        let policy: HostPolicy = serde_json::from_str(
+            r#"{
+                "allowed_hosts": ["*.example.com", "localhost"],
+                "denied_hosts": ["prod.example.com"]
+            }"#,
+        )
+        .unwrap();
+        assert!(policy.check("https://api.example.com/users").is_ok());
+        assert!(policy.check("http://localhost:8080").is_ok());
+        assert!(matches!(
+            policy.check("https://prod.example.com"),
+            Err(KuiperError::HostNotAllowed(host)) if host == "prod.example.com"
+        ));
+        assert!(policy.check("https://example.org").is_err());
+        assert!(policy.check("/users").is_err());
+
+        let policy = policy.with_exceptions(vec!["prod.example.com".to_string()]);
+        assert!(policy.check("https://prod.example.com").is_ok());
+
+        assert!(HostPolicy::default().check("/users").is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{KuiperError, KuiperResult};
+use serde_json::Value;
+
+/// A single step in a parsed JSONPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Selects the value at `path` in `value`.
+///
+/// Only a small subset of JSONPath is supported: the root `$`, followed by any number of
+/// `.key`, `['key']` or `[index]` segments, e.g. `$.users[0].name`.
+pub fn select<'a>(value: &'a Value, path: &str) -> KuiperResult<Option<&'a Value>> {
+    let mut current = value;
+    for segment in parse(path)? {
+        let next = match (&segment, current) {
+            (Segment::Key(key), Value::Object(map)) => map.get(key),
+            (Segment::Index(idx), Value::Array(values)) => values.get(*idx),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(current))
+}
+
+/// Selects the value at `path` in `value`, so that it can be modified.
+///
+/// Supports the same subset of JSONPath as [`select`].
+pub fn select_mut<'a>(value: &'a mut Value, path: &str) -> KuiperResult<Option<&'a mut Value>> {
+    Ok(descend(value, &parse(path)?))
+}
+
+/// Removes the value at `path` from `value`, and returns it.
+///
+/// Supports the same subset of JSONPath as [`select`]. Removing the root is not supported, and
+/// returns `None`.
+pub fn remove(value: &mut Value, path: &str) -> KuiperResult<Option<Value>> {
+    let mut segments = parse(path)?;
+    let Some(last) = segments.pop() else {
+        return Ok(None);
+    };
+    let Some(current) = descend(value, &segments) else {
+        return Ok(None);
+    };
+
+    Ok(match (last, current) {
+        (Segment::Key(key), Value::Object(map)) => map.remove(&key),
+        (Segment::Index(idx), Value::Array(values)) if idx < values.len() => {
+            Some(values.remove(idx))
+        }
+        _ => None,
+    })
+}
+
+fn descend<'a>(value: &'a mut Value, segments: &[Segment]) -> Option<&'a mut Value> {
+    let mut current = value;
+    for segment in segments {
+        current = match (segment, current) {
+            (Segment::Key(key), Value::Object(map)) => map.get_mut(key)?,
+            (Segment::Index(idx), Value::Array(values)) => values.get_mut(*idx)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn parse(path: &str) -> KuiperResult<Vec<Segment>> {
+    let invalid = || KuiperError::InvalidJsonPath(path.to_string());
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(Segment::Key(after_dot[..end].to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let end = after_bracket.find(']').ok_or_else(invalid)?;
+            let inner = &after_bracket[..end];
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|s| s.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+            let segment = match quoted {
+                Some(key) => Segment::Key(key.to_string()),
+                None => Segment::Index(inner.parse().map_err(|_| invalid())?),
+            };
+            segments.push(segment);
+            rest = &after_bracket[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn select_test() {
+        let value = json!({
+            "users": [{ "name": "a" }, { "name": "b", "tags": ["x"] }],
+            "odd key": 1
+        });
+
+        assert_eq!(select(&value, "$").unwrap(), Some(&value));
+        assert_eq!(
+            select(&value, "$.users[1].name").unwrap(),
+            Some(&json!("b"))
+        );
+        assert_eq!(
+            select(&value, "$['users'][1].tags[0]").unwrap(),
+            Some(&json!("x"))
+        );
+        assert_eq!(select(&value, "$['odd key']").unwrap(), Some(&json!(1)));
+        assert_eq!(select(&value, "$.users[2]").unwrap(), None);
+        assert_eq!(select(&value, "$.missing.name").unwrap(), None);
+
+        assert!(matches!(
+            select(&value, "users"),
+            Err(KuiperError::InvalidJsonPath(_))
+        ));
+        assert!(matches!(
+            select(&value, "$.users[x]"),
+            Err(KuiperError::InvalidJsonPath(_))
+        ));
+    }
+
+    #[test]
+    fn remove_test() {
+        let mut value = json!({
+            "users": [{ "name": "a", "updated_at": 1 }, { "name": "b" }],
+            "updated_at": 2
+        });
+
+        assert_eq!(remove(&mut value, "$.updated_at").unwrap(), Some(json!(2)));
+        assert_eq!(
+            remove(&mut value, "$.users[0].updated_at").unwrap(),
+            Some(json!(1))
+        );
+        assert_eq!(
+            remove(&mut value, "$.users[1]").unwrap(),
+            Some(json!({ "name": "b" }))
+        );
+        assert_eq!(remove(&mut value, "$.missing.key").unwrap(), None);
+        assert_eq!(remove(&mut value, "$").unwrap(), None);
+        assert_eq!(value, json!({ "users": [{ "name": "a" }] }));
+    }
+}
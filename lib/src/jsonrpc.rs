@@ -0,0 +1,130 @@
+//! JSON-RPC 2.0 calls, whose method and params are wrapped in the envelope the spec requires.
+
+use crate::{KuiperError, KuiperResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// The version of JSON-RPC that calls are sent with.
+pub const VERSION: &str = "2.0";
+
+/// The id that calls without one are sent with, a new uuid for every call.
+const DEFAULT_ID: &str = "{{expr:uuid}}";
+
+/// A JSON-RPC call of a request.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JsonRpc {
+    /// The name of the method to call.
+    method: String,
+    /// The params of the method, by position in an array or by name in an object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params: Option<Value>,
+    /// The id of the call, which is `{{expr:uuid}}` unless it is set or the call is a
+    /// notification.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    /// Sends the call without an id, as a notification that the server doesn't respond to.
+    #[serde(default)]
+    notification: bool,
+}
+
+impl JsonRpc {
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn params(&self) -> Option<&Value> {
+        self.params.as_ref()
+    }
+
+    /// The id of the call, which is only set by default once the request has been interpolated.
+    pub fn id(&self) -> Option<&Value> {
+        self.id.as_ref()
+    }
+
+    pub fn is_notification(&self) -> bool {
+        self.notification
+    }
+
+    /// Checks that the params are an array or an object, and that notifications have no id.
+    pub(crate) fn validate(&self) -> KuiperResult<()> {
+        if self
+            .params
+            .as_ref()
+            .is_some_and(|params| !params.is_array() && !params.is_object())
+        {
+            return Err(KuiperError::InvalidJsonRpc(
+                "params has to be an array or an object".to_string(),
+            ));
+        }
+        if self.notification && self.id.is_some() {
+            return Err(KuiperError::InvalidJsonRpc(
+                "a notification can't have an id".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The params and the id, whose strings are interpolated. The id is set to `{{expr:uuid}}`
+    /// first if the call needs one and has none, so that it is generated like any other uuid.
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut Value> {
+        if !self.notification && self.id.is_none() {
+            self.id = Some(Value::String(DEFAULT_ID.to_string()));
+        }
+        self.params.iter_mut().chain(self.id.iter_mut())
+    }
+
+    /// The JSON body that the request is sent with.
+    pub(crate) fn body(&self) -> Value {
+        let mut body = json!({ "jsonrpc": VERSION, "method": self.method });
+        if let Some(params) = &self.params {
+            body["params"] = params.clone();
+        }
+        if let Some(id) = &self.id {
+            body["id"] = id.clone();
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn body_test() {
+        let mut call: JsonRpc = serde_json::from_value(json!({
+            "method": "subtract",
+            "params": [42, 23]
+        }))
+        .unwrap();
+        assert!(call.validate().is_ok());
+        assert_eq!(call.values_mut().count(), 2);
+        assert_eq!(
+            call.body(),
+            json!({ "jsonrpc": "2.0", "method": "subtract", "params": [42, 23], "id": DEFAULT_ID })
+        );
+
+        let mut notification: JsonRpc =
+            serde_json::from_value(json!({ "method": "update", "notification": true })).unwrap();
+        assert_eq!(notification.values_mut().count(), 0);
+        assert_eq!(
+            notification.body(),
+            json!({ "jsonrpc": "2.0", "method": "update" })
+        );
+
+        let invalid: JsonRpc =
+            serde_json::from_value(json!({ "method": "subtract", "params": 42 })).unwrap();
+        assert!(matches!(
+            invalid.validate(),
+            Err(KuiperError::InvalidJsonRpc(_))
+        ));
+        let invalid: JsonRpc =
+            serde_json::from_value(json!({ "method": "update", "id": 1, "notification": true }))
+                .unwrap();
+        assert!(matches!(
+            invalid.validate(),
+            Err(KuiperError::InvalidJsonRpc(_))
+        ));
+    }
+}
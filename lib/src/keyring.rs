@@ -0,0 +1,193 @@
+//! Storage of secrets in the OS keyring.
+//!
+//! The keyring is accessed through `secret-tool` (libsecret) on Linux and `security` (Keychain)
+//! on macOS. On other platforms, or when the tool is missing, the keyring is unavailable and
+//! callers are expected to fall back to storing the secret in a file.
+
+use crate::{KuiperError, KuiperResult};
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Service name that secrets stored by kuiper are grouped under.
+const SERVICE: &str = "kuiper";
+
+/// Returns `true` if the keyring can be used.
+pub fn is_available() -> bool {
+    get("kuiper-availability-check").is_ok()
+}
+
+/// Returns the secret stored for `account`, or `None` if there is none.
+pub fn get(account: &str) -> KuiperResult<Option<String>> {
+    let output = if cfg!(target_os = "macos") {
+        run(Command::new("security").args([
+            "find-generic-password",
+            "-s",
+            SERVICE,
+            "-a",
+            account,
+            "-w",
+        ]))?
+    } else {
+        run(Command::new("secret-tool").args(["lookup", "service", SERVICE, "account", account]))?
+    };
+
+    if output.status.success() {
+        let secret = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(secret.trim_end_matches('\n').to_string()))
+    } else if is_not_found(&output) {
+        Ok(None)
+    } else {
+        Err(failure(&output))
+    }
+}
+
+/// Stores `secret` for `account`, replacing any secret already stored for it.
+pub fn set(account: &str, secret: &str) -> KuiperResult<()> {
+    // the secret is written to stdin rather than passed as an argument, where other users could
+    // see it in the process list
+    let output = if cfg!(target_os = "macos") {
+        // a trailing -w makes security prompt for the password, and then for it again
+        run_with_stdin(
+            Command::new("security").args([
+                "add-generic-password",
+                "-U",
+                "-s",
+                SERVICE,
+                "-a",
+                account,
+                "-w",
+            ]),
+            &format!("{secret}\n{secret}\n"),
+        )?
+    } else {
+        run_with_stdin(
+            Command::new("secret-tool")
+                .args(["store", "--label", &format!("{SERVICE}: {account}")])
+                .args(["service", SERVICE, "account", account]),
+            secret,
+        )?
+    };
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(failure(&output))
+    }
+}
+
+/// Removes the secret stored for `account`, returning `false` if there was none.
+pub fn delete(account: &str) -> KuiperResult<bool> {
+    if get(account)?.is_none() {
+        return Ok(false);
+    }
+    let output = if cfg!(target_os = "macos") {
+        run(Command::new("security").args([
+            "delete-generic-password",
+            "-s",
+            SERVICE,
+            "-a",
+            account,
+        ]))?
+    } else {
+        run(Command::new("secret-tool").args(["clear", "service", SERVICE, "account", account]))?
+    };
+
+    if output.status.success() {
+        Ok(true)
+    } else {
+        Err(failure(&output))
+    }
+}
+
+fn run(command: &mut Command) -> KuiperResult<Output> {
+    if !cfg!(any(target_os = "linux", target_os = "macos")) {
+        return Err(KuiperError::KeyringUnavailable(
+            "not supported on this platform".to_string(),
+        ));
+    }
+    command.output().map_err(unavailable)
+}
+
+fn run_with_stdin(command: &mut Command, input: &str) -> KuiperResult<Output> {
+    if !cfg!(any(target_os = "linux", target_os = "macos")) {
+        return Err(KuiperError::KeyringUnavailable(
+            "not supported on this platform".to_string(),
+        ));
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(unavailable)?;
+    child
+        .stdin
+        .take()
+        .expect("stdin should be piped")
+        .write_all(input.as_bytes())?;
+    Ok(child.wait_with_output()?)
+}
+
+/// Both tools exit with a failure code when nothing is stored, `secret-tool` without printing
+/// anything and `security` with exit code 44.
+fn is_not_found(output: &Output) -> bool {
+    if cfg!(target_os = "macos") {
+        output.status.code() == Some(44)
+    } else {
+        output.status.code() == Some(1) && output.stderr.is_empty()
+    }
+}
+
+fn unavailable(error: std::io::Error) -> KuiperError {
+    KuiperError::KeyringUnavailable(error.to_string())
+}
+
+fn failure(output: &Output) -> KuiperError {
+    KuiperError::KeyringUnavailable(String::from_utf8_lossy(&output.stderr).trim().to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use test_log::test;
+
+    fn output(code: i32, stderr: &str) -> Output {
+        Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn is_not_found_test() {
+        if cfg!(target_os = "macos") {
+            assert!(is_not_found(&output(44, "security: SecKeychainSearchCopyNext: The specified item could not be found in the keychain.")));
+            assert!(!is_not_found(&output(1, "")));
+        } else {
+            assert!(is_not_found(&output(1, "")));
+            assert!(!is_not_found(&output(
+                1,
+                "secret-tool: Cannot autolaunch D-Bus without X11 $DISPLAY\n"
+            )));
+        }
+        assert!(!is_not_found(&output(0, "")));
+    }
+
+    #[test]
+    fn failure_test() {
+        let KuiperError::KeyringUnavailable(reason) = failure(&output(
+            1,
+            "secret-tool: Cannot autolaunch D-Bus without X11 $DISPLAY\n",
+        )) else {
+            panic!("expected KeyringUnavailable");
+        };
+        assert_eq!(
+            reason,
+            "secret-tool: Cannot autolaunch D-Bus without X11 $DISPLAY"
+        );
+    }
+}
@@ -1,19 +1,96 @@
-use jiff::Timestamp;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use jiff::{tz::TimeZone, SignedDuration, Timestamp};
 use log::{error, trace};
+use ring::{digest, hmac};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
-    collections::{hash_map::Entry, HashMap, VecDeque},
+    collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
     error::Error,
     ffi::OsStr,
     fmt::Display,
     fs::{self, File},
-    io::BufReader,
+    io::{BufReader, Read},
     path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
+use tokenizer::Token;
 use uuid::Uuid;
 
+pub use assertions::{Assertion, AssertionOutcome, Expect};
+#[cfg(feature = "async")]
+pub use async_client::AsyncClient;
+pub use client::{BodyLimit, Client, ClientConfig, TlsVersion};
+pub use config::{Config, EnvironmentConfig, CONFIG_FILE_NAME, JSON_CONFIG_FILE_NAME};
+pub use exchange::Exchange;
+pub use resolver::Resolver;
+pub use response::{Attempt, HeaderMap, Response, Timings};
+pub use session::{Session, SessionStore};
+
+pub mod affected;
+pub mod assertions;
+#[cfg(feature = "async")]
+mod async_client;
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod bundle;
+mod client;
+mod config;
+pub mod convert;
+pub mod cookies;
+pub mod dataset;
+pub mod diff;
+pub mod envfile;
+pub mod exchange;
+pub mod graphql;
+pub mod grep;
+pub mod grpc;
+pub mod history;
+pub mod hooks;
+pub mod hosts;
+pub mod jsonpath;
+pub mod jsonrpc;
+pub mod keyring;
+pub mod lint;
+mod lock;
+pub mod multipart;
+pub mod normalize;
+pub mod observer;
+pub mod plugins;
+pub mod poll;
+pub mod ratelimit;
+pub mod remote;
+mod resolver;
+mod response;
+pub mod secrets;
+pub mod seed;
+mod session;
+pub mod sse;
+pub mod stats;
+pub mod suite;
+pub mod templates;
+pub mod tls;
+mod tokenizer;
+pub mod uri;
+pub mod watch;
+
 pub type Headers = HashMap<String, Option<String>>;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// A header or param value that removes the header or param, like `null` does for headers.
+pub const UNSET: &str = "$unset";
+/// How long tokens minted with `{{expr:jwt(CLAIMS, SECRET_ENV)}}` are valid for, in seconds,
+/// unless the claims set `exp`.
+pub const JWT_LIFETIME_SECONDS: i64 = 300;
+/// How deep `{{env:...}}` and `{{var:...}}` values can refer to other values that refer to
+/// other values.
+pub const MAX_INTERPOLATION_DEPTH: usize = 8;
 pub type KuiperResult<T> = Result<T, KuiperError>;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -21,14 +98,486 @@ pub struct Request {
     #[serde(skip)]
     name: String,
     uri: String,
+    #[serde(default)]
     headers: Headers,
+    #[serde(default)]
     params: HashMap<String, String>,
     method: String,
     body: Option<Value>,
+    /// A newline-delimited JSON file, relative to the request file, that is streamed as the body
+    /// instead of being read into memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_ndjson_file: Option<PathBuf>,
+    /// A body that is sent as it is written, like XML or plain text, instead of as JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_raw: Option<String>,
+    /// A file, relative to the request file, whose bytes are streamed as the body as they are.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_file_binary: Option<PathBuf>,
+    /// The content type of `body_raw` or `body_file_binary`, unless `headers` has a
+    /// `Content-Type`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// Fields that are sent as an `application/x-www-form-urlencoded` body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    form: Option<BTreeMap<String, String>>,
+    /// Parts of a `multipart/form-data` body, which are strings or files to upload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    multipart: Option<BTreeMap<String, multipart::Part>>,
+    /// A GraphQL query and its variables, which are sent as a JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    graphql: Option<graphql::GraphQl>,
+    /// A JSON-RPC call, which is sent as a JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jsonrpc: Option<jsonrpc::JsonRpc>,
+    /// A gRPC call, which is made instead of an HTTP request with the `grpc` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grpc: Option<grpc::Grpc>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    captures: HashMap<String, String>,
+    #[serde(default, alias = "assertions")]
+    expect: Option<Expect>,
+    /// Names of `{{env:NAME}}` values that must be set for the request to be sent.
+    #[serde(default)]
+    required: Vec<String>,
+    #[serde(default)]
+    hooks: hooks::Hooks,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    poll: Option<poll::Poll>,
+    /// The most time the request may take, from sending it until its response body is read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    /// Overrides the auth of the `auth.json` files above the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<auth::Auth>,
+    /// Why the request should no longer be used, and what to use instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deprecated: Option<String>,
+    /// What the request is for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Labels that group related requests, like `"payments"`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// The SHA-256 fingerprint of the certificate the server has to present, in hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pin_sha256: Option<String>,
+    #[serde(skip)]
+    resolution: Resolution,
+}
+
+/// How a request was put together from its file, its `headers.json` files and the defaults for
+/// its host. This is not part of the request itself, and is ignored when comparing requests.
+#[derive(Clone, Debug, Default)]
+struct Resolution {
+    warnings: Vec<Warning>,
+    origins: HashMap<String, HeaderOrigin>,
+    /// The values of the `variables.json` files above the request.
+    variables: HashMap<String, String>,
+    /// The variables that are JSON values other than strings.
+    json_variables: HashMap<String, Value>,
+    /// The request file or `auth.json` that the auth of the request was set in.
+    auth_origin: Option<PathBuf>,
+    /// The values the placeholders of the request were replaced by.
+    snapshot: history::Snapshot,
+    /// Whether the placeholders have been replaced, after which braces that are left are literal.
+    resolved: bool,
+}
+
+impl PartialEq for Resolution {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Resolution {}
+
+/// Where the value of a header of a resolved request was set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderOrigin {
+    /// The request file or a `headers.json`.
+    File(PathBuf),
+    /// The defaults for hosts matching a pattern in `kuiper.toml`.
+    Host(String),
+    /// An override applied with [`Request::override_header`], e.g. from the command line.
+    Override,
+    /// A header kept in the session from an earlier response from the same host.
+    Session,
+}
+
+impl Display for HeaderOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderOrigin::File(path) => write!(f, "{path:?}"),
+            HeaderOrigin::Host(pattern) => write!(f, "defaults for hosts matching '{pattern}'"),
+            HeaderOrigin::Override => write!(f, "an override"),
+            HeaderOrigin::Session => write!(f, "the session"),
+        }
+    }
+}
+
+/// Something suspicious about a request that does not prevent it from being sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// A header set in a `headers.json` or in the request itself overrides a different value
+    /// set in an ancestor directory.
+    ShadowedHeader {
+        name: String,
+        value: Option<String>,
+        origin: PathBuf,
+        shadowed_value: Option<String>,
+        shadowed_origin: PathBuf,
+    },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::ShadowedHeader {
+                name,
+                value,
+                origin,
+                shadowed_value,
+                shadowed_origin,
+            } => write!(
+                f,
+                "header '{name}' is set to {} in {origin:?}, shadowing {} from {shadowed_origin:?}",
+                header_value(value),
+                header_value(shadowed_value)
+            ),
+        }
+    }
+}
+
+/// A value that is asked for when a request is sent, instead of being kept in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prompt {
+    name: String,
+    secret: bool,
+}
+
+impl Prompt {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` for `{{prompt_secret:NAME}}`, whose answer should not be shown as it is
+    /// typed.
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+}
+
+fn header_value(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("'{value}'"),
+        None => "null".to_string(),
+    }
+}
+
+/// Values available to interpolation in addition to the process environment.
+#[derive(Clone, Debug, Default)]
+pub struct Context {
+    captures: HashMap<String, String>,
+    data: dataset::Row,
+    secrets: HashMap<String, String>,
+    env: HashMap<String, String>,
+    variables: HashMap<String, String>,
+    /// Answers to `{{prompt:NAME}}` and `{{prompt_secret:NAME}}`.
+    prompts: HashMap<String, String>,
+    /// Whether `{{cmd:COMMAND}}` may run commands.
+    commands_allowed: bool,
+    /// The output of every command run for `{{cmd:COMMAND}}`, shared by clones of the context so
+    /// that each command only runs once.
+    command_outputs: Arc<Mutex<HashMap<String, String>>>,
+    /// Values recorded in a [`history::Snapshot`], used instead of resolving placeholders again.
+    pinned: HashMap<String, String>,
+    /// The values that placeholders have been replaced by while interpolating a request.
+    resolved: Arc<Mutex<BTreeMap<String, String>>>,
+    /// The directory that `{{file:PATH}}` is relative to, or the working directory if `None`.
+    dir: Option<PathBuf>,
+    hosts: Vec<hosts::HostDefaults>,
+    generator: Option<Arc<seed::Generator>>,
+    resolvers: resolver::Resolvers,
+    /// The placeholders whose values are being interpolated, outermost first.
+    resolving: Vec<String>,
+    uri_normalization: uri::UriNormalization,
+    /// Captures and variables that are JSON values other than strings, keyed by placeholder like
+    /// `capture:items`, which keep their type when they are all of a string in a body.
+    json: HashMap<String, Value>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `captures` available through `{{capture:NAME}}`.
+    pub fn with_captures(mut self, captures: HashMap<String, String>) -> Self {
+        self.captures = captures;
+        self
+    }
+
+    /// Like [`Context::with_captures`], where values other than strings keep their type when
+    /// they are all of a string in a body, like `"items": "{{capture:items}}"`.
+    pub fn with_captured_values(mut self, captures: HashMap<String, Value>) -> Self {
+        self.captures = captures
+            .iter()
+            .map(|(name, value)| (name.clone(), value_text(value)))
+            .collect();
+        self.json
+            .retain(|placeholder, _| !placeholder.starts_with("capture:"));
+        self.json.extend(
+            captures
+                .into_iter()
+                .filter(|(_, value)| !value.is_string())
+                .map(|(name, value)| (format!("capture:{name}"), value)),
+        );
+        self
+    }
+
+    /// Makes the columns of a dataset row available through `{{data:COLUMN}}`.
+    pub fn with_data(mut self, row: dataset::Row) -> Self {
+        self.data = row;
+        self
+    }
+
+    /// Makes `env` available through `{{env:NAME}}`, taking precedence over the environment
+    /// variables of the process.
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Makes `variables` available through `{{var:NAME}}`, taking precedence over the values of
+    /// the `variables.json` files above a request.
+    pub fn with_variables(mut self, variables: HashMap<String, String>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    /// Makes `prompts`, the answers given when asked for them, available through
+    /// `{{prompt:NAME}}` and `{{prompt_secret:NAME}}`.
+    pub fn with_prompts(mut self, prompts: HashMap<String, String>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Replaces placeholders by the values recorded in `snapshot` when a request was sent before,
+    /// so that it is sent again the same way. Values that were redacted are resolved as usual.
+    pub fn with_snapshot(mut self, snapshot: &history::Snapshot) -> Self {
+        self.pinned = snapshot.recorded();
+        self
+    }
+
+    /// Lets `{{cmd:COMMAND}}` run `COMMAND` and use what it prints. Without this, requests that
+    /// use it fail to interpolate.
+    pub fn with_commands_allowed(mut self, allowed: bool) -> Self {
+        self.commands_allowed = allowed;
+        self
+    }
+
+    /// Adds the headers of the `hosts` defaults that match the host of a request, unless the
+    /// request already sets them. Earlier defaults take precedence over later ones.
+    pub fn with_hosts(mut self, hosts: Vec<hosts::HostDefaults>) -> Self {
+        self.hosts = hosts;
+        self
+    }
+
+    /// Normalizes the uris of requests like this once they are interpolated.
+    pub fn with_uri_normalization(mut self, normalization: uri::UriNormalization) -> Self {
+        self.uri_normalization = normalization;
+        self
+    }
+
+    /// Resolves the placeholders of the namespace of `resolver` with it, replacing the resolver
+    /// previously registered for that namespace if there is one.
+    pub fn with_resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolvers.add(Arc::new(resolver));
+        self
+    }
+
+    /// Returns `true` if a [`Resolver`] is registered for `namespace`.
+    pub fn has_resolver(&self, namespace: &str) -> bool {
+        self.resolvers.get(namespace).is_some()
+    }
+
+    /// Generates `{{expr:...}}` values from `seed`, with the clock frozen at [`Seed::now`],
+    /// instead of from the system's random number generator and clock.
+    ///
+    /// [`Seed::now`]: seed::Seed::now
+    pub fn with_seed(mut self, seed: seed::Seed) -> Self {
+        self.generator = Some(Arc::new(seed::Generator::new(seed)));
+        self
+    }
+
+    /// Makes decrypted secrets available through `{{secret:NAME}}`.
+    pub fn with_secrets(mut self, secrets: HashMap<String, String>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl Context {
+    /// Returns `true` if `{{env:NAME}}` has a value that is not empty.
+    pub fn has_env(&self, name: &str) -> bool {
+        self.env_var(name).is_some_and(|value| !value.is_empty())
+    }
+
+    /// Returns `true` if `{{var:NAME}}` is set by the context itself, regardless of the
+    /// `variables.json` files above a request.
+    pub fn has_variable(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+    }
+
+    /// The JSON value of `placeholder`, like `capture:items`, if it is a capture or variable
+    /// that isn't a string.
+    fn json_value(&self, placeholder: &str) -> Option<Value> {
+        let value = self.json.get(placeholder)?;
+        let (namespace, name) = placeholder.split_once(':')?;
+        let text = match self.pinned.get(placeholder) {
+            Some(pinned) => Some(pinned),
+            None if namespace == "capture" => self.captures.get(name),
+            None => self.variables.get(name),
+        }?;
+        // the value may have been replaced by a string since
+        (value_text(value) == *text).then(|| value.clone())
+    }
+
+    /// Returns `true` if `{{secret:NAME}}` has a value.
+    pub fn has_secret(&self, name: &str) -> bool {
+        self.secrets.contains_key(name)
+    }
+
+    /// The value of `name` in the `env` namespace.
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.pinned
+            .get(&format!("env:{name}"))
+            .or_else(|| self.env.get(name))
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    fn has_prompt(&self, prompt: &Prompt) -> bool {
+        let namespace = if prompt.secret {
+            "prompt_secret"
+        } else {
+            "prompt"
+        };
+        self.prompts.contains_key(&prompt.name)
+            || self
+                .pinned
+                .contains_key(&format!("{namespace}:{}", prompt.name))
+    }
+}
+
+impl From<&Session> for Context {
+    fn from(session: &Session) -> Self {
+        let mut context = Self::new().with_captures(session.captures().clone());
+        context.json = session
+            .values()
+            .iter()
+            .map(|(name, value)| (format!("capture:{name}"), value.clone()))
+            .collect();
+        context
+    }
 }
 
 impl Request {
     pub fn find(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        Self::find_with_context(path, &Context::default())
+    }
+
+    /// Like [`Request::find_with_context`], resolving the placeholders of the namespace of
+    /// `resolver` with it.
+    pub fn find_with_resolver(
+        path: impl Into<PathBuf>,
+        context: &Context,
+        resolver: impl Resolver + 'static,
+    ) -> KuiperResult<Self> {
+        Self::find_with_context(path, &context.clone().with_resolver(resolver))
+    }
+
+    pub fn find_with_context(path: impl Into<PathBuf>, context: &Context) -> KuiperResult<Self> {
+        Self::find_uninterpolated(path)?.resolve(context)
+    }
+
+    /// Interpolates the placeholders of a request read with [`Request::find_uninterpolated`]
+    /// with `context`, so that tools can look at the [`Request::unresolved_placeholders`] first.
+    /// A request that has already been resolved is returned as it is.
+    pub fn resolve(&self, context: &Context) -> KuiperResult<Self> {
+        let mut request = self.clone();
+        if request.resolution.resolved {
+            return Ok(request);
+        }
+        let missing: Vec<_> = request
+            .required
+            .iter()
+            .filter(|name| !context.has_env(name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(KuiperError::MissingRequired(missing));
+        }
+        let unanswered: Vec<_> = request
+            .prompts()
+            .into_iter()
+            .filter(|prompt| !context.has_prompt(prompt))
+            .collect();
+        if !unanswered.is_empty() {
+            return Err(KuiperError::MissingPrompts(unanswered));
+        }
+
+        let mut variables = request.resolution.variables.clone();
+        variables.extend(context.variables.clone());
+        let mut json = context.json.clone();
+        json.extend(
+            request
+                .resolution
+                .json_variables
+                .iter()
+                .map(|(name, value)| (format!("var:{name}"), value.clone())),
+        );
+        let context = &Context {
+            variables,
+            json,
+            dir: Path::new(&request.name).parent().map(Path::to_path_buf),
+            resolved: Arc::default(),
+            ..context.clone()
+        };
+
+        // every missing value is reported at once, so that they can be fixed in one go
+        let mut errors = Vec::new();
+        // the host is only known once the uri has been interpolated
+        request.interpolate_uri(context, &mut errors)?;
+        request.add_host_defaults(context);
+        request.interpolate_params(context, &mut errors)?;
+        request.interpolate_headers(context, &mut errors)?;
+        request.interpolate_body(context, &mut errors)?;
+        request.interpolate_auth(context, &mut errors)?;
+        if errors.len() > 1 {
+            return Err(KuiperError::InterpolationErrors(errors));
+        }
+        if let Some(error) = errors.pop() {
+            return Err(error.into());
+        }
+        if let Some((name, value)) = request.auth.as_ref().and_then(auth::Auth::header) {
+            request.set_auth_header(&name, value);
+        }
+        let resolved = std::mem::take(&mut *context.resolved.lock().unwrap());
+        request.resolution.snapshot = history::Snapshot::new(resolved);
+        request.resolution.resolved = true;
+        trace!("successfully interpolated request");
+
+        Ok(request)
+    }
+
+    /// Reads the request at `path` and adds the headers of the `headers.json` files above it, the
+    /// values of the `variables.json` files above it and the auth of the closest `auth.json`
+    /// above it, without interpolating any values.
+    pub fn find_uninterpolated(path: impl Into<PathBuf>) -> KuiperResult<Self> {
         let mut path: PathBuf = path.into();
         trace!("finding request at '{path:?}");
         if path.is_relative() {
@@ -38,21 +587,158 @@ impl Request {
 
         let mut request = Self::from_file(&path)?;
         let ancestors: Vec<_> = path.ancestors().collect();
-        let mut headers = Headers::new();
+        let mut headers = HeaderOrigins::new();
+        let mut inherited_auth = None;
         for subdir in ancestors.into_iter().skip(1).rev().skip(1) {
-            overwrite_headers(&subdir.join("headers.json"), &mut headers)?;
+            overwrite_headers(
+                &subdir.join("headers.json"),
+                &mut headers,
+                &mut request.resolution.warnings,
+            )?;
+            overwrite_variables(
+                &subdir.join("variables.json"),
+                &mut request.resolution.variables,
+                &mut request.resolution.json_variables,
+            )?;
+            let auth_path = subdir.join("auth.json");
+            if let Some(auth) = read_auth(&auth_path)? {
+                inherited_auth = Some((auth, auth_path));
+            }
         }
-
-        for (name, value) in headers {
-            request.add_header_if_not_exists(name, value);
+        if request.auth.is_some() {
+            request.resolution.auth_origin = Some(path.clone());
+        } else if let Some((auth, origin)) = inherited_auth {
+            request.auth = Some(auth);
+            request.resolution.auth_origin = Some(origin);
         }
 
-        request.interpolate()?;
+        for name in request.headers.keys() {
+            request
+                .resolution
+                .origins
+                .insert(name.clone(), HeaderOrigin::File(path.clone()));
+        }
+        for (name, (value, origin)) in headers {
+            match request.headers.get(&name) {
+                Some(own_value) if *own_value != value => {
+                    request.resolution.warnings.push(Warning::ShadowedHeader {
+                        name,
+                        value: own_value.clone(),
+                        origin: path.clone(),
+                        shadowed_value: value,
+                        shadowed_origin: origin,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    request
+                        .resolution
+                        .origins
+                        .insert(name.clone(), HeaderOrigin::File(origin));
+                    request.add_header_if_not_exists(name, value);
+                }
+            }
+        }
 
         Ok(request)
     }
 
+    /// Reads the `variables.json` files above the request at `path`, where the values of files in
+    /// nested directories take precedence over those of their ancestors.
+    pub fn find_variables(path: impl AsRef<Path>) -> KuiperResult<HashMap<String, String>> {
+        let path = path.as_ref();
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut variables = HashMap::new();
+        let ancestors: Vec<_> = path.ancestors().collect();
+        for subdir in ancestors.into_iter().skip(1).rev().skip(1) {
+            overwrite_variables(
+                &subdir.join("variables.json"),
+                &mut variables,
+                &mut HashMap::new(),
+            )?;
+        }
+        Ok(variables)
+    }
+
+    /// Returns the requests that `path` depends on, transitively, in the order they need to be
+    /// sent, followed by `path` itself.
+    ///
+    /// Dependencies are read without being interpolated, since they may reference values that
+    /// are only captured once an earlier dependency has been sent.
+    pub fn dependency_order(path: impl Into<PathBuf>) -> KuiperResult<Vec<PathBuf>> {
+        let path = path.into().canonicalize()?;
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+        Self::visit_dependencies(path, &mut visiting, &mut order)?;
+        Ok(order)
+    }
+
+    fn visit_dependencies(
+        path: PathBuf,
+        visiting: &mut Vec<PathBuf>,
+        order: &mut Vec<PathBuf>,
+    ) -> KuiperResult<()> {
+        if order.contains(&path) {
+            return Ok(());
+        }
+        if let Some(idx) = visiting.iter().position(|p| p == &path) {
+            let cycle = visiting[idx..]
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(KuiperError::DependencyCycle(cycle));
+        }
+
+        let request = Self::from_file(&path)?;
+        visiting.push(path);
+        for dependency in request.dependencies()? {
+            Self::visit_dependencies(dependency, visiting, order)?;
+        }
+        let path = visiting.pop().expect("path was pushed above");
+        order.push(path);
+        Ok(())
+    }
+
+    /// Resolves the paths in `depends_on`, which are relative to the directory of this request.
+    fn dependencies(&self) -> KuiperResult<Vec<PathBuf>> {
+        let dir = Path::new(&self.name)
+            .parent()
+            .ok_or(KuiperError::PathError)?;
+        self.depends_on
+            .iter()
+            .map(|dependency| {
+                dir.join(dependency)
+                    .canonicalize()
+                    .map_err(|e| match e.kind() {
+                        std::io::ErrorKind::NotFound => {
+                            KuiperError::DependencyNotFound(dependency.to_string())
+                        }
+                        _ => e.into(),
+                    })
+            })
+            .collect()
+    }
+
     pub fn search(root: impl Into<PathBuf>, term: &str) -> KuiperResult<Vec<Self>> {
+        Self::search_paths(root, term)?
+            .into_iter()
+            .map(Self::find)
+            .collect::<Result<_, _>>()
+    }
+
+    /// Returns `true` if `path` is named like a request file, `.kuiper` or, for requests written
+    /// in YAML or TOML, `.kuiper.yaml`, `.kuiper.yml` or `.kuiper.toml`.
+    pub fn is_request_file(path: impl AsRef<Path>) -> bool {
+        path.as_ref()
+            .file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(|name| REQUEST_EXTENSIONS.iter().any(|ext| name.ends_with(ext)))
+    }
+
+    /// Returns the paths of all request files under `root` whose path contains `term`, in
+    /// sorted order.
+    pub fn search_paths(root: impl Into<PathBuf>, term: &str) -> KuiperResult<Vec<PathBuf>> {
         let root: PathBuf = root.into();
         let mut matches = Vec::with_capacity(10);
         let mut dirs = VecDeque::new();
@@ -63,8 +749,7 @@ impl Request {
                 let entry = entry?.path();
                 if entry.is_dir() {
                     dirs.push_back(entry);
-                } else if entry.is_file() && entry.extension().unwrap_or(OsStr::new("")) == "kuiper"
-                {
+                } else if entry.is_file() && Self::is_request_file(&entry) {
                     let name = entry.to_str().unwrap();
                     if name.contains(term) {
                         matches.push(entry.clone());
@@ -73,10 +758,8 @@ impl Request {
             }
         }
 
-        matches
-            .into_iter()
-            .map(Self::find)
-            .collect::<Result<_, _>>()
+        matches.sort();
+        Ok(matches)
     }
 
     pub fn name(&self) -> &str {
@@ -87,6 +770,35 @@ impl Request {
         &self.method
     }
 
+    /// Returns `true` if sending this request several times has the same effect as sending it
+    /// once, according to its method.
+    pub fn is_idempotent(&self) -> bool {
+        ["GET", "HEAD", "PUT", "DELETE", "OPTIONS", "TRACE"]
+            .iter()
+            .any(|method| self.method.eq_ignore_ascii_case(method))
+    }
+
+    /// Makes sure the request carries an `Idempotency-Key` header, so that it can be retried
+    /// safely, generating a new key if it doesn't have one.
+    ///
+    /// Returns `false` if the header has been explicitly removed by setting it to `null`.
+    pub fn ensure_idempotency_key(&mut self) -> bool {
+        let existing = self
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(IDEMPOTENCY_KEY_HEADER));
+        match existing {
+            Some((_, value)) => value.is_some(),
+            None => {
+                let key = Uuid::new_v4().to_string();
+                trace!("generated idempotency key '{key}'");
+                self.headers
+                    .insert(IDEMPOTENCY_KEY_HEADER.to_string(), Some(key));
+                true
+            }
+        }
+    }
+
     pub fn uri(&self) -> &str {
         &self.uri
     }
@@ -95,340 +807,3020 @@ impl Request {
         &self.headers
     }
 
-    pub fn body(&self) -> Option<&Value> {
-        self.body.as_ref()
+    /// Warnings collected while resolving the request, such as headers that shadow headers set
+    /// in an ancestor directory.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.resolution.warnings
     }
 
-    pub fn params(&self) -> &HashMap<String, String> {
-        &self.params
+    /// The values of the `variables.json` files above the request, available through
+    /// `{{var:NAME}}`.
+    pub fn variables(&self) -> &HashMap<String, String> {
+        &self.resolution.variables
     }
 
-    fn interpolate(&mut self) -> KuiperResult<()> {
-        self.interpolate_uri()?;
-        self.interpolate_params()?;
-        self.interpolate_headers()?;
-        self.interpolate_body()?;
-        trace!("successfully interpolated request");
-        Ok(())
+    /// How the request is authenticated, set in the request itself or in the closest `auth.json`
+    /// above it.
+    pub fn auth(&self) -> Option<&auth::Auth> {
+        self.auth.as_ref()
     }
 
-    fn interpolate_uri(&mut self) -> KuiperResult<()> {
-        let new_url = Self::interpolate_str(&self.uri)?;
-        self.uri = new_url;
+    /// The request file or `auth.json` that the auth of the request was set in.
+    pub fn auth_origin(&self) -> Option<&Path> {
+        self.resolution.auth_origin.as_deref()
+    }
 
-        Ok(())
+    /// The values the placeholders of the request were replaced by when it was interpolated,
+    /// with sensitive values redacted.
+    pub fn snapshot(&self) -> &history::Snapshot {
+        &self.resolution.snapshot
     }
 
-    fn interpolate_headers(&mut self) -> KuiperResult<()> {
-        for (_, value) in self.headers.iter_mut() {
-            if let Some(v) = value {
-                let new_value = Self::interpolate_str(&v.clone())?;
-                *v = new_value;
+    /// Sets the header `name` added by the auth of the request, unless it is already set by the
+    /// request or a `headers.json`. Headers from the defaults for the host are replaced.
+    pub(crate) fn set_auth_header(&mut self, name: &str, value: String) {
+        let existing: Vec<_> = self
+            .headers
+            .keys()
+            .filter(|existing| existing.eq_ignore_ascii_case(name))
+            .cloned()
+            .collect();
+        for existing in existing {
+            match self.resolution.origins.get(&existing) {
+                Some(HeaderOrigin::Host(_)) => {
+                    self.headers.remove(&existing);
+                    self.resolution.origins.remove(&existing);
+                }
+                _ => return,
             }
         }
-
-        Ok(())
+        self.headers.insert(name.to_string(), Some(value));
+        if let Some(origin) = &self.resolution.auth_origin {
+            self.resolution
+                .origins
+                .insert(name.to_string(), HeaderOrigin::File(origin.clone()));
+        }
     }
 
-    fn interpolate_body(&mut self) -> KuiperResult<()> {
-        if let Some(body) = &self.body {
-            let s = body.to_string();
-            let new_body_s = Self::interpolate_str(&s)?;
-            self.body = serde_json::from_str(&new_body_s)?;
+    /// Sets the header `name` kept in the session, unless the request sets or removes it in any
+    /// case.
+    pub(crate) fn set_session_header(&mut self, name: &str, value: String) {
+        if self
+            .headers
+            .keys()
+            .any(|existing| existing.eq_ignore_ascii_case(name))
+        {
+            return;
         }
+        self.headers.insert(name.to_string(), Some(value));
+        self.resolution
+            .origins
+            .insert(name.to_string(), HeaderOrigin::Session);
+    }
 
-        Ok(())
+    /// Where the header `name` was set, if the request was found with [`Request::find`] or
+    /// [`Request::find_with_context`].
+    pub fn header_origin(&self, name: &str) -> Option<&HeaderOrigin> {
+        self.resolution.origins.get(name)
     }
 
-    fn interpolate_params(&mut self) -> KuiperResult<()> {
-        for (_name, value) in self.params.iter_mut() {
-            *value = Self::interpolate_str(value)?;
+    /// Sets the header `name`, replacing any value it has regardless of case, or removes it if
+    /// `value` is `None`.
+    pub fn override_header(&mut self, name: &str, value: Option<String>) {
+        let existing: Vec<_> = self
+            .headers
+            .keys()
+            .filter(|existing| existing.eq_ignore_ascii_case(name))
+            .cloned()
+            .collect();
+        for existing in existing {
+            self.headers.remove(&existing);
+            self.resolution.origins.remove(&existing);
         }
-        Ok(())
+        self.headers.insert(name.to_string(), value);
+        self.resolution
+            .origins
+            .insert(name.to_string(), HeaderOrigin::Override);
     }
 
-    fn interpolate_str(input: &str) -> KuiperResult<String> {
-        let mut result = input.to_owned();
-        for (start_idx, _) in input.match_indices("{{") {
-            let (end_idx, _) = input[start_idx..]
-                .match_indices("}}")
-                .next()
-                .ok_or(InterpolationError::InvalidFormat)?;
-            let interpolated_name = &input[start_idx + 2..start_idx + end_idx];
-
-            let (interpolation_type, name) = interpolated_name
-                .split_once(':')
-                .ok_or(InterpolationError::InvalidFormat)?;
-
-            let value = match interpolation_type {
-                "env" => std::env::var(name)
-                    .map_err(|_| InterpolationError::MissingEnvVar(name.to_string()))?,
-                "expr" => Self::interpolation_expr(name)?,
-                s => {
-                    error!(
-                        "parsing Request from file failed, tried to interpolate the following '{}'",
-                        s
-                    );
-                    return Err(InterpolationError::InvalidFormat.into());
-                }
-            };
+    /// Sets the query param `name`, or removes it if `value` is `None`.
+    pub fn override_param(&mut self, name: &str, value: Option<String>) {
+        match value {
+            Some(value) => self.params.insert(name.to_string(), value),
+            None => self.params.remove(name),
+        };
+    }
 
-            result = result.replace(&input[start_idx..start_idx + end_idx + 2], &value);
-        }
+    pub fn body(&self) -> Option<&Value> {
+        self.body.as_ref()
+    }
 
-        Ok(result)
+    /// The newline-delimited JSON file that is streamed as the body, if the request has one.
+    pub fn body_ndjson_file(&self) -> Option<&Path> {
+        self.body_ndjson_file.as_deref()
     }
 
-    fn interpolation_expr(expr: &str) -> KuiperResult<String> {
-        match expr {
-            "uuid" => Ok(Uuid::new_v4().to_string()),
-            "now" => Ok(Timestamp::now().to_string()),
-            invalid => Err(KuiperError::InvalidExpr(invalid.to_string())),
-        }
+    /// The body that is sent as it is written, if the request has one.
+    pub fn body_raw(&self) -> Option<&str> {
+        self.body_raw.as_deref()
     }
 
-    fn add_header_if_not_exists(&mut self, header_name: String, header_value: Option<String>) {
-        if let Entry::Vacant(vacant_entry) = self.headers.entry(header_name) {
-            vacant_entry.insert(header_value);
-        }
+    /// The file whose bytes are streamed as the body, if the request has one.
+    pub fn body_file_binary(&self) -> Option<&Path> {
+        self.body_file_binary.as_deref()
     }
 
-    fn from_file(path: &Path) -> KuiperResult<Self> {
-        let file = File::open(path).map_err(|e| match e.kind() {
-            std::io::ErrorKind::NotFound => KuiperError::RequestNotFound,
-            _ => e.into(),
-        })?;
-        let reader = BufReader::new(file);
-        let mut request: Request = serde_json::from_reader(reader)?;
-        trace!("successfully parsed request at '{path:?}'");
-        request.name = path.to_str().ok_or(KuiperError::PathError)?.to_string();
-        Ok(request)
+    /// The content type of the raw or binary body, if the request sets one with `content_type`.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
     }
-}
 
-fn overwrite_headers(path: &Path, headers: &mut Headers) -> KuiperResult<()> {
-    match File::open(path) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-            let file_headers: Headers = serde_json::from_reader(reader)?;
-            for (name, value) in file_headers {
-                // TODO: handle interpolation
-                headers.insert(name.to_owned(), value.to_owned());
-            }
-        }
-        Err(e) => match e.kind() {
-            std::io::ErrorKind::NotFound => return Ok(()),
-            _ => return Err(KuiperError::IoError(e)),
-        },
+    /// The fields of the form-urlencoded body, if the request has one.
+    pub fn form(&self) -> Option<&BTreeMap<String, String>> {
+        self.form.as_ref()
     }
-    trace!("successfully parsed headers at '{path:?}");
-    Ok(())
-}
 
-#[derive(Debug)]
-pub enum KuiperError {
-    IoError(std::io::Error),
-    JsonError(serde_json::Error),
-    RequestNotFound,
-    FileFormatError,
-    PathError,
-    InvalidExpr(String),
-    InterpolationError(InterpolationError),
-}
+    /// The parts of the multipart body, with the paths of files resolved, if the request has one.
+    pub fn multipart(&self) -> Option<&BTreeMap<String, multipart::Part>> {
+        self.multipart.as_ref()
+    }
 
-impl KuiperError {
-    /// Returns `true` if the kuiper error is [`FileFormatError`].
-    ///
-    /// [`FileFormatError`]: KuiperError::FileFormatError
-    #[must_use]
-    pub fn is_file_format_error(&self) -> bool {
-        matches!(self, Self::FileFormatError)
+    /// The GraphQL query of the request, with the path of its file resolved, if it has one.
+    pub fn graphql(&self) -> Option<&graphql::GraphQl> {
+        self.graphql.as_ref()
     }
-}
 
-impl Error for KuiperError {}
+    /// The JSON-RPC call of the request, if it has one.
+    pub fn jsonrpc(&self) -> Option<&jsonrpc::JsonRpc> {
+        self.jsonrpc.as_ref()
+    }
 
-impl Display for KuiperError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                KuiperError::IoError(error) => format!("I/O error: {error}"),
-                KuiperError::JsonError(error) => format!("JSON error: {error}"),
-                KuiperError::RequestNotFound => "request not found".to_string(),
-                KuiperError::InterpolationError(error) =>
-                    format!("interpolation error '{}'", error),
-                KuiperError::FileFormatError => "file format error".to_string(),
-                KuiperError::PathError => "path error".to_string(),
-                KuiperError::InvalidExpr(expr) => format!("invalid expr: '{}'", expr),
-            }
-        )
+    /// The gRPC call of the request, with its paths resolved, if it has one.
+    pub fn grpc(&self) -> Option<&grpc::Grpc> {
+        self.grpc.as_ref()
     }
-}
 
-impl From<std::io::Error> for KuiperError {
-    fn from(value: std::io::Error) -> Self {
-        Self::IoError(value)
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
     }
-}
 
-impl From<serde_json::Error> for KuiperError {
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
+
+    /// The values this request captures from its response, by name, along with where they are
+    /// found: a JSONPath into the response body, `header:NAME`, `status` or `duration`.
+    pub fn captures(&self) -> &HashMap<String, String> {
+        &self.captures
+    }
+
+    /// How the request is sent repeatedly until its response satisfies a condition, if it is.
+    pub fn poll(&self) -> Option<&poll::Poll> {
+        self.poll.as_ref()
+    }
+
+    /// Polls until `until` holds, keeping the interval and timeout of the `poll` block if the
+    /// request has one.
+    pub fn override_poll_until(&mut self, until: poll::Condition) {
+        self.poll = Some(match self.poll.take() {
+            Some(poll) => poll.with_until(until),
+            None => poll::Poll::new(until),
+        });
+    }
+
+    /// The most time the request may take, from sending it until its response body is read.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+
+    /// Gives up on the request if it takes longer than `timeout`, instead of its own timeout.
+    pub fn override_timeout(&mut self, timeout: Duration) {
+        self.timeout_ms = Some(timeout.as_millis() as u64);
+    }
+
+    pub fn required(&self) -> &[String] {
+        &self.required
+    }
+
+    /// The message of the `deprecated` field, if the request is deprecated.
+    pub fn deprecated(&self) -> Option<&str> {
+        self.deprecated.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// The fingerprint the certificate of the server has to have, from the request or the
+    /// defaults of its host.
+    pub fn pin_sha256(&self) -> Option<&str> {
+        self.pin_sha256.as_deref()
+    }
+
+    /// The `{{NAMESPACE:NAME}}` values used by the uri, params, headers and body, as pairs of
+    /// namespace and name, in the order they are used and without duplicates. Values with a
+    /// default, like `{{env:NAME|default}}`, are left out, since they are never missing.
+    pub fn references(&self) -> Vec<(String, String)> {
+        let mut references = Vec::new();
+        for placeholder in self.unresolved_placeholders() {
+            if let Some((namespace, name)) = placeholder.split_once(':') {
+                if name.contains('|') && takes_default(namespace) {
+                    continue;
+                }
+                let reference = (namespace.to_string(), name.to_string());
+                if !references.contains(&reference) {
+                    references.push(reference);
+                }
+            }
+        }
+        references
+    }
+
+    /// The placeholders of the uri, params, headers, body and auth, without their braces, like
+    /// `env:HOST` or `env:PORT|8080`, in the order they are used and without duplicates. The
+    /// placeholders nested in a placeholder follow it. Empty once the request has been resolved.
+    pub fn unresolved_placeholders(&self) -> Vec<String> {
+        if self.resolution.resolved {
+            return Vec::new();
+        }
+        let mut params: Vec<_> = self.params.iter().collect();
+        params.sort();
+        let mut headers: Vec<_> = self.headers.iter().collect();
+        headers.sort();
+        let mut body = Vec::new();
+        if let Some(value) = &self.body {
+            json_strings(value, &mut body);
+        }
+        if let Some(variables) = self.graphql.as_ref().and_then(graphql::GraphQl::variables) {
+            json_strings(variables, &mut body);
+        }
+        if let Some(call) = &self.jsonrpc {
+            for value in call.params().into_iter().chain(call.id()) {
+                json_strings(value, &mut body);
+            }
+        }
+        if let Some(call) = &self.grpc {
+            json_strings(call.message(), &mut body);
+        }
+        body.extend(self.body_raw.as_deref());
+        body.extend(self.form.iter().flatten().map(|(_, value)| value.as_str()));
+        body.extend(
+            self.multipart
+                .iter()
+                .flatten()
+                .filter_map(|(_, part)| match part {
+                    multipart::Part::Text(value) => Some(value.as_str()),
+                    multipart::Part::File { .. } => None,
+                }),
+        );
+
+        let inputs = std::iter::once(self.uri.as_str())
+            .chain(params.into_iter().map(|(_, value)| value.as_str()))
+            .chain(
+                headers
+                    .into_iter()
+                    .filter_map(|(_, value)| value.as_deref()),
+            )
+            .chain(body)
+            .chain(self.auth.iter().flat_map(auth::Auth::values));
+        let mut placeholders: Vec<String> = Vec::new();
+        for input in inputs {
+            for (_, placeholder) in tokenizer::placeholders(input) {
+                if !placeholders.iter().any(|p| p == placeholder) {
+                    placeholders.push(placeholder.to_string());
+                }
+            }
+        }
+        placeholders
+    }
+
+    /// The `{{prompt:NAME}}` and `{{prompt_secret:NAME}}` values without a default that have to
+    /// be asked for before the request is sent, in the order they appear in.
+    pub fn prompts(&self) -> Vec<Prompt> {
+        let mut prompts: Vec<Prompt> = Vec::new();
+        for (namespace, name) in self.references() {
+            let secret = match namespace.as_str() {
+                "prompt" => false,
+                "prompt_secret" => true,
+                _ => continue,
+            };
+            match prompts.iter_mut().find(|prompt| prompt.name == name) {
+                // a value that is used as a secret anywhere is asked for as one
+                Some(prompt) => prompt.secret |= secret,
+                None => prompts.push(Prompt { name, secret }),
+            }
+        }
+        prompts
+    }
+
+    pub fn hooks(&self) -> &hooks::Hooks {
+        &self.hooks
+    }
+
+    /// Runs the `after` hooks of the request with `response`, in the directory of the request
+    /// file, and returns whether each one succeeded.
+    pub fn run_hooks(&self, response: &Response) -> Vec<AssertionOutcome> {
+        let dir = Path::new(&self.name).parent().unwrap_or(Path::new("."));
+        self.hooks
+            .after()
+            .iter()
+            .map(|hook| hook.run(dir, &self.name, response))
+            .collect()
+    }
+
+    pub fn expect(&self) -> Option<&Expect> {
+        self.expect.as_ref()
+    }
+
+    /// Extracts the values declared in `captures` from `response`.
+    ///
+    /// Values are found with a JSONPath into the JSON body, or are the value of a header with
+    /// `header:NAME`, the status code with `status`, or the time the response took to arrive, in
+    /// milliseconds, with `duration`.
+    pub fn capture(&self, response: &Response) -> KuiperResult<HashMap<String, String>> {
+        Ok(self
+            .capture_values(response)?
+            .into_iter()
+            .map(|(name, value)| (name, value_text(&value)))
+            .collect())
+    }
+
+    /// Like [`Request::capture`], keeping the type of the values captured from the JSON body,
+    /// so that arrays, objects, numbers and booleans can fill later bodies as they are.
+    pub fn capture_values(&self, response: &Response) -> KuiperResult<HashMap<String, Value>> {
+        let mut body = None;
+        let mut captured = HashMap::with_capacity(self.captures.len());
+        for (name, source) in &self.captures {
+            let value = match source.as_str() {
+                "status" => Some(Value::String(response.status().to_string())),
+                "duration" => Some(Value::String(response.duration().as_millis().to_string())),
+                source => match source.strip_prefix("header:") {
+                    Some(header) => response
+                        .header(header)
+                        .map(|value| Value::String(value.to_string())),
+                    None => {
+                        // the body is only parsed if something is captured from it
+                        let body = match &body {
+                            Some(body) => body,
+                            None => body.insert(serde_json::from_str::<Value>(response.body())?),
+                        };
+                        jsonpath::select(body, source)?.cloned()
+                    }
+                },
+            };
+            let value = value.ok_or_else(|| KuiperError::CaptureNotFound(name.to_string()))?;
+            trace!("captured '{name}' from '{source}'");
+            captured.insert(name.to_string(), value);
+        }
+
+        Ok(captured)
+    }
+
+    fn interpolate_uri(
+        &mut self,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        let new_url = Self::interpolate_collecting(&self.uri, context, errors)?;
+        self.uri = context.uri_normalization.normalize(&new_url);
+
+        Ok(())
+    }
+
+    fn interpolate_headers(
+        &mut self,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        for (name, value) in self.headers.iter_mut() {
+            if let Some(v) = value {
+                // `{{file:PATH}}` in a `headers.json` is relative to the directory of that file
+                let dir = match self.resolution.origins.get(name) {
+                    Some(HeaderOrigin::File(origin)) => origin.parent(),
+                    _ => None,
+                };
+                let new_value = match dir {
+                    Some(dir) if context.dir.as_deref() != Some(dir) => {
+                        let context = Context {
+                            dir: Some(dir.to_path_buf()),
+                            ..context.clone()
+                        };
+                        Self::interpolate_collecting(v, &context, errors)?
+                    }
+                    _ => Self::interpolate_collecting(v, context, errors)?,
+                };
+                *v = new_value;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn interpolate_body(
+        &mut self,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        if let Some(body) = &mut self.body {
+            inline_json_values(body, context)?;
+            // the strings and keys are interpolated one by one, so that braces of the JSON itself
+            // are never taken for placeholders
+            Self::interpolate_json(body, context, &mut HashMap::new(), errors)?;
+        }
+        if let Some(variables) = self
+            .graphql
+            .as_mut()
+            .and_then(graphql::GraphQl::variables_mut)
+        {
+            inline_json_values(variables, context)?;
+            Self::interpolate_json(variables, context, &mut HashMap::new(), errors)?;
+        }
+        for value in self
+            .jsonrpc
+            .iter_mut()
+            .flat_map(jsonrpc::JsonRpc::values_mut)
+        {
+            inline_json_values(value, context)?;
+            Self::interpolate_json(value, context, &mut HashMap::new(), errors)?;
+        }
+        if let Some(message) = self.grpc.as_mut().map(grpc::Grpc::message_mut) {
+            inline_json_values(message, context)?;
+            Self::interpolate_json(message, context, &mut HashMap::new(), errors)?;
+        }
+        if let Some(raw) = &mut self.body_raw {
+            *raw = Self::interpolate_collecting(raw, context, errors)?;
+        }
+        for value in self.form.iter_mut().flat_map(|form| form.values_mut()) {
+            *value = Self::interpolate_collecting(value, context, errors)?;
+        }
+        for part in self
+            .multipart
+            .iter_mut()
+            .flat_map(|parts| parts.values_mut())
+        {
+            if let multipart::Part::Text(value) = part {
+                *value = Self::interpolate_collecting(value, context, errors)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn interpolate_auth(
+        &mut self,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        if let Some(auth) = &mut self.auth {
+            auth.interpolate(|value| Self::interpolate_collecting(value, context, errors))?;
+        }
+        Ok(())
+    }
+
+    fn interpolate_params(
+        &mut self,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        for (_name, value) in self.params.iter_mut() {
+            *value = Self::interpolate_collecting(value, context, errors)?;
+        }
+        Ok(())
+    }
+
+    /// Interpolates the strings and keys of `value`, sharing the values of `placeholders`.
+    fn interpolate_json(
+        value: &mut Value,
+        context: &Context,
+        placeholders: &mut HashMap<String, String>,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<()> {
+        match value {
+            Value::String(s) => {
+                *s = Self::interpolate_shared(s, context, placeholders, Some(errors))?;
+            }
+            Value::Array(items) => {
+                for item in items {
+                    Self::interpolate_json(item, context, placeholders, errors)?;
+                }
+            }
+            Value::Object(map) => {
+                for (key, mut value) in std::mem::take(map) {
+                    let key = Self::interpolate_shared(&key, context, placeholders, Some(errors))?;
+                    Self::interpolate_json(&mut value, context, placeholders, errors)?;
+                    map.insert(key, value);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn interpolate_str(input: &str, context: &Context) -> KuiperResult<String> {
+        Self::interpolate_shared(input, context, &mut HashMap::new(), None)
+    }
+
+    /// Like [`Request::interpolate_str`], adding the [`InterpolationError`]s to `errors` instead
+    /// of failing on the first one.
+    fn interpolate_collecting(
+        input: &str,
+        context: &Context,
+        errors: &mut Vec<InterpolationError>,
+    ) -> KuiperResult<String> {
+        Self::interpolate_shared(input, context, &mut HashMap::new(), Some(errors))
+    }
+
+    /// Interpolates `input`, reusing the values of `placeholders` and adding the values of the
+    /// placeholders it resolves, so that identical placeholders are replaced by the same value,
+    /// even if it is generated.
+    ///
+    /// If `errors` is given, the [`InterpolationError`]s are added to it, and the placeholders
+    /// that caused them are left as they are.
+    fn interpolate_shared(
+        input: &str,
+        context: &Context,
+        placeholders: &mut HashMap<String, String>,
+        mut errors: Option<&mut Vec<InterpolationError>>,
+    ) -> KuiperResult<String> {
+        let tokens = match (tokenizer::tokenize(input), errors.as_deref_mut()) {
+            (Ok(tokens), _) => tokens,
+            (Err(e), Some(errors)) => {
+                add_error(errors, e);
+                return Ok(input.to_string());
+            }
+            (Err(e), None) => return Err(e.into()),
+        };
+        let mut result = String::with_capacity(input.len());
+        for (start_idx, token) in tokens {
+            match token {
+                Token::Text(text) => result.push_str(text),
+                Token::Escaped(literal) => result.push_str(literal),
+                Token::Placeholder(interpolated_name) => {
+                    if let Some(value) = placeholders.get(interpolated_name) {
+                        result.push_str(value);
+                        continue;
+                    }
+                    let value =
+                        Self::interpolate_placeholder(interpolated_name, context).map_err(|e| {
+                            match e {
+                                // errors in the placeholder are moved to where it is in `input`
+                                KuiperError::InterpolationError(
+                                    InterpolationError::InvalidFormat { offset, .. },
+                                ) => {
+                                    tokenizer::invalid_format(input, start_idx + 2 + offset).into()
+                                }
+                                e => e,
+                            }
+                        });
+                    let value = match (value, errors.as_deref_mut()) {
+                        (Ok(value), _) => value,
+                        (Err(KuiperError::InterpolationError(e)), Some(errors)) => {
+                            add_error(errors, e);
+                            let end_idx = start_idx + interpolated_name.len() + 4;
+                            result.push_str(&input[start_idx..end_idx]);
+                            continue;
+                        }
+                        (Err(e), _) => return Err(e),
+                    };
+                    result.push_str(&value);
+                    placeholders.insert(interpolated_name.to_string(), value);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Resolves the value of a single placeholder, given without its braces, like `env:HOST`.
+    /// Placeholders nested in it, like `{{env:USER}}` in `expr:base64({{env:USER}})`, are
+    /// resolved first.
+    fn interpolate_placeholder(interpolated_name: &str, context: &Context) -> KuiperResult<String> {
+        if let Some(value) = context.pinned.get(interpolated_name) {
+            return Ok(value.clone());
+        }
+        let nested = interpolated_name
+            .contains("{{")
+            .then(|| Self::interpolate_str(interpolated_name, context))
+            .transpose()?;
+        let resolved_name = nested.as_deref().unwrap_or(interpolated_name);
+
+        let (interpolation_type, full_name) = resolved_name
+            .split_once(':')
+            .ok_or_else(|| tokenizer::invalid_format(interpolated_name, 0))?;
+        // `{{env:NAME|default}}` is replaced by `default` if NAME has no value
+        let (name, default) = match full_name.split_once('|') {
+            Some((name, default)) if takes_default(interpolation_type) => {
+                (name, Some(default.to_string()))
+            }
+            _ => (full_name, None),
+        };
+
+        let value = match interpolation_type {
+            // defaults are used as they are, but values can refer to other values
+            "env" => match context.env_var(name) {
+                Some(value) => Self::interpolate_value(format!("env:{name}"), value, context)?,
+                None => {
+                    default.ok_or_else(|| InterpolationError::MissingEnvVar(name.to_string()))?
+                }
+            },
+            // expressions always have a value, so they don't take a default
+            "expr" => Self::interpolation_expr(full_name, context).map_err(|e| match e {
+                // the nested values may be secret, so they are not shown
+                KuiperError::InvalidExpr(_) if nested.is_some() => KuiperError::InvalidExpr(
+                    interpolated_name
+                        .split_once(':')
+                        .map_or(interpolated_name, |(_, expr)| expr)
+                        .to_string(),
+                ),
+                e => e,
+            })?,
+            "cmd" => run_command(full_name, context)?,
+            "capture" => context
+                .captures
+                .get(name)
+                .cloned()
+                .or(default)
+                .ok_or_else(|| InterpolationError::MissingCapture(name.to_string()))?,
+            "data" => context
+                .data
+                .get(name)
+                .cloned()
+                .or(default)
+                .ok_or_else(|| InterpolationError::MissingDataColumn(name.to_string()))?,
+            "secret" => context
+                .secrets
+                .get(name)
+                .cloned()
+                .or(default)
+                .ok_or_else(|| InterpolationError::MissingSecret(name.to_string()))?,
+            "var" => match context.variables.get(name) {
+                Some(value) => {
+                    Self::interpolate_value(format!("var:{name}"), value.clone(), context)?
+                }
+                None => {
+                    default.ok_or_else(|| InterpolationError::MissingVariable(name.to_string()))?
+                }
+            },
+            "prompt" | "prompt_secret" => context
+                .prompts
+                .get(name)
+                .cloned()
+                .or(default)
+                .ok_or_else(|| InterpolationError::MissingPrompt(name.to_string()))?,
+            "file" => match read_file(name, context)? {
+                Some(contents) => contents,
+                None => default.ok_or_else(|| InterpolationError::MissingFile(name.to_string()))?,
+            },
+            namespace => match context.resolvers.get(namespace) {
+                Some(resolver) => resolver.resolve(name)?.or(default).ok_or_else(|| {
+                    InterpolationError::MissingValue(format!("{namespace}:{name}"))
+                })?,
+                None => {
+                    error!(
+                        "parsing Request from file failed, tried to interpolate the following '{}'",
+                        namespace
+                    );
+                    return Err(tokenizer::invalid_format(interpolated_name, 0).into());
+                }
+            },
+        };
+
+        context
+            .resolved
+            .lock()
+            .unwrap()
+            .insert(interpolated_name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Interpolates `value`, the value of `placeholder`, so that values can be composed of other
+    /// values, like `BASE_URL=http://{{env:HOST}}:{{env:PORT}}`.
+    fn interpolate_value(
+        placeholder: String,
+        value: String,
+        context: &Context,
+    ) -> KuiperResult<String> {
+        // recorded values were interpolated when they were recorded
+        let literal = tokenizer::tokenize(&value)
+            .is_ok_and(|tokens| tokens.iter().all(|(_, t)| matches!(t, Token::Text(_))));
+        if literal || context.pinned.contains_key(&placeholder) {
+            return Ok(value);
+        }
+        if let Some(idx) = context.resolving.iter().position(|p| *p == placeholder) {
+            let mut cycle = context.resolving[idx..].to_vec();
+            cycle.push(placeholder);
+            return Err(InterpolationError::Cycle(cycle).into());
+        }
+        if context.resolving.len() >= MAX_INTERPOLATION_DEPTH {
+            return Err(InterpolationError::TooDeep(placeholder).into());
+        }
+        let mut context = context.clone();
+        context.resolving.push(placeholder.clone());
+        Self::interpolate_str(&value, &context).map_err(|e| match e {
+            // values may be secret, so the error points at the placeholder instead
+            KuiperError::InterpolationError(InterpolationError::InvalidFormat { .. }) => {
+                tokenizer::invalid_format(&placeholder, 0).into()
+            }
+            e => e,
+        })
+    }
+
+    fn interpolation_expr(expr: &str, context: &Context) -> KuiperResult<String> {
+        let generator = context.generator.as_deref();
+        let invalid = || KuiperError::InvalidExpr(expr.to_string());
+        // random values come from the seed of the run, if it has one
+        let next = || generator.map_or_else(|| Uuid::new_v4().as_u64_pair().0, |g| g.next_u64());
+        // scales a random u64 to below `span`, which unlike `%` is not biased
+        let below = |span: u128| (u128::from(next()) * span) >> 64;
+        let pick = |chars: &[u8], len: &str| -> KuiperResult<String> {
+            let len: usize = len.parse().map_err(|_| invalid())?;
+            Ok((0..len)
+                .map(|_| char::from(chars[below(chars.len() as u128) as usize]))
+                .collect())
+        };
+        let now = || generator.map_or_else(Timestamp::now, |g| g.now());
+
+        // formats may contain commas, so these are parsed before the arguments are split
+        for (base, formattable) in [("now", true), ("epoch_millis", false)] {
+            let Some(rest) = expr
+                .strip_prefix(base)
+                .filter(|rest| rest.is_empty() || rest.starts_with(['+', '-', '(']))
+            else {
+                continue;
+            };
+            let (offset, format) = match rest.strip_suffix(')').and_then(|r| r.split_once('(')) {
+                Some((offset, format)) if formattable => (offset, Some(format)),
+                Some(_) => return Err(invalid()),
+                None => (rest, None),
+            };
+            let timestamp = now()
+                .checked_add(parse_offset(offset).ok_or_else(invalid)?)
+                .map_err(|_| invalid())?;
+            return match format {
+                Some(format) => {
+                    jiff::fmt::strtime::format(format, &timestamp.to_zoned(TimeZone::UTC))
+                        .map_err(|_| invalid())
+                }
+                None if formattable => Ok(timestamp.to_string()),
+                None => Ok(timestamp.as_millisecond().to_string()),
+            };
+        }
+
+        if let Some((function, args)) = expr.strip_suffix(')').and_then(|e| e.split_once('(')) {
+            // these take a single value, which may contain commas
+            match function {
+                "base64" => return Ok(STANDARD.encode(args)),
+                "base64decode" => {
+                    let decoded = STANDARD
+                        .decode(args)
+                        .or_else(|_| URL_SAFE_NO_PAD.decode(args.trim_end_matches('=')))
+                        .map_err(|_| invalid())?;
+                    return String::from_utf8(decoded).map_err(|_| invalid());
+                }
+                "urlencode" => return Ok(url_encode(args)),
+                "sha256" => return Ok(hex(digest::digest(&digest::SHA256, args.as_bytes()))),
+                "hmac_sha256" => {
+                    // the message may contain commas, the key is everything before the first one
+                    let (key, message) = args.split_once(',').ok_or_else(invalid)?;
+                    let key = hmac::Key::new(hmac::HMAC_SHA256, key.trim().as_bytes());
+                    return Ok(hex(hmac::sign(&key, message.trim_start().as_bytes())));
+                }
+                "jwt" => {
+                    // the claims may contain commas, the secret is named after the last one
+                    let (claims, secret) = args.rsplit_once(',').ok_or_else(invalid)?;
+                    let secret = secret.trim();
+                    let secret = context
+                        .env_var(secret)
+                        .ok_or_else(|| InterpolationError::MissingEnvVar(secret.to_string()))?;
+                    let mut claims: serde_json::Map<String, Value> =
+                        serde_json::from_str(claims).map_err(|_| invalid())?;
+                    let issued_at = now().as_second();
+                    claims.entry("iat").or_insert(issued_at.into());
+                    claims
+                        .entry("exp")
+                        .or_insert((issued_at + JWT_LIFETIME_SECONDS).into());
+                    return Ok(sign_jwt(&claims, &secret));
+                }
+                _ => {}
+            }
+            let args: Vec<_> = args.split(',').map(str::trim).collect();
+            return match (function, args.as_slice()) {
+                ("rand_int", [min, max]) => {
+                    let min: i64 = min.parse().map_err(|_| invalid())?;
+                    let max: i64 = max.parse().map_err(|_| invalid())?;
+                    if min > max {
+                        return Err(invalid());
+                    }
+                    let span = (i128::from(max) - i128::from(min) + 1) as u128;
+                    Ok((i128::from(min) + below(span) as i128).to_string())
+                }
+                ("rand_string", [len]) => pick(
+                    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                    len,
+                ),
+                ("rand_hex", [len]) => pick(b"0123456789abcdef", len),
+                ("rand_choice", choices) if choices.iter().all(|choice| !choice.is_empty()) => {
+                    Ok(choices[below(choices.len() as u128) as usize].to_string())
+                }
+                _ => Err(invalid()),
+            };
+        }
+
+        match expr {
+            "uuid" => Ok(generator
+                .map_or_else(Uuid::new_v4, |g| g.uuid())
+                .to_string()),
+            // sortable identifiers, which start with the time in milliseconds
+            "uuidv7" => {
+                let mut random = [0; 10];
+                random[..8].copy_from_slice(&next().to_le_bytes());
+                random[8..].copy_from_slice(&next().to_le_bytes()[..2]);
+                let millis = now().as_millisecond().max(0) as u64;
+                Ok(uuid::Builder::from_unix_timestamp_millis(millis, &random)
+                    .into_uuid()
+                    .to_string())
+            }
+            "ulid" => {
+                let random =
+                    (u128::from(next()) << 16 | u128::from(next() >> 48)) & ((1 << 80) - 1);
+                let millis = now().as_millisecond().max(0) as u128 & ((1 << 48) - 1);
+                Ok(ulid(millis << 80 | random))
+            }
+            invalid => Err(KuiperError::InvalidExpr(invalid.to_string())),
+        }
+    }
+
+    fn add_host_defaults(&mut self, context: &Context) {
+        let Some(host) = reqwest::Url::parse(&self.uri)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        else {
+            return;
+        };
+        for defaults in context.hosts.iter().filter(|d| d.matches(&host)) {
+            for (name, value) in defaults.headers() {
+                if !self.headers.contains_key(name) {
+                    self.resolution.origins.insert(
+                        name.clone(),
+                        HeaderOrigin::Host(defaults.pattern().to_string()),
+                    );
+                    self.add_header_if_not_exists(name.clone(), value.clone());
+                }
+            }
+            if self.pin_sha256.is_none() {
+                self.pin_sha256 = defaults.pin_sha256().map(str::to_string);
+            }
+        }
+    }
+
+    fn add_header_if_not_exists(&mut self, header_name: String, header_value: Option<String>) {
+        if let Entry::Vacant(vacant_entry) = self.headers.entry(header_name) {
+            vacant_entry.insert(header_value);
+        }
+    }
+
+    fn from_file(path: &Path) -> KuiperResult<Self> {
+        let file = File::open(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => KuiperError::RequestNotFound,
+            _ => e.into(),
+        })?;
+        let mut contents = String::new();
+        BufReader::new(file).read_to_string(&mut contents)?;
+        let mut request: Request = parse_request(path, &contents)?;
+        trace!("successfully parsed request at '{path:?}'");
+        unset_headers(&mut request.headers);
+        request.params.retain(|_, value| value != UNSET);
+        let bodies: Vec<_> = [
+            ("body", request.body.is_some()),
+            ("body_ndjson_file", request.body_ndjson_file.is_some()),
+            ("body_raw", request.body_raw.is_some()),
+            ("form", request.form.is_some()),
+            ("multipart", request.multipart.is_some()),
+            ("body_file_binary", request.body_file_binary.is_some()),
+            ("graphql", request.graphql.is_some()),
+            ("jsonrpc", request.jsonrpc.is_some()),
+            ("grpc", request.grpc.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name.to_string())
+        .collect();
+        if bodies.len() > 1 {
+            return Err(KuiperError::ConflictingBodies(bodies));
+        }
+        if let Some(graphql) = &mut request.graphql {
+            graphql.resolve(path.parent())?;
+        }
+        if let Some(call) = &request.jsonrpc {
+            call.validate()?;
+        }
+        if let Some(call) = &mut request.grpc {
+            call.resolve(path.parent())?;
+        }
+        if let Some(dir) = path.parent() {
+            if let Some(expect) = &mut request.expect {
+                expect.resolve_paths(dir);
+            }
+            if let Some(file) = &mut request.body_ndjson_file {
+                *file = dir.join(&*file);
+            }
+            if let Some(file) = &mut request.body_file_binary {
+                *file = dir.join(&*file);
+            }
+            for part in request
+                .multipart
+                .iter_mut()
+                .flat_map(|parts| parts.values_mut())
+            {
+                part.resolve_path(dir);
+            }
+        }
+        request.name = path.to_str().ok_or(KuiperError::PathError)?.to_string();
+        Ok(request)
+    }
+}
+
+/// The endings of the names of request files.
+const REQUEST_EXTENSIONS: [&str; 4] = [".kuiper", ".kuiper.yaml", ".kuiper.yml", ".kuiper.toml"];
+
+/// Returns `true` if `path` is a request file written in YAML.
+fn is_yaml(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(".kuiper.yaml") || path.ends_with(".kuiper.yml"))
+}
+
+/// Returns `true` if `path` is a request file written in TOML.
+fn is_toml(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(".kuiper.toml"))
+}
+
+/// Parses `contents`, the contents of the request file at `path`, as YAML, TOML or JSON
+/// depending on its name.
+pub(crate) fn parse_request<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    contents: &str,
+) -> KuiperResult<T> {
+    if is_yaml(path) {
+        let request = serde_yaml::from_str(contents)?;
+        Ok(serde_json::from_value(stringify_fields(request))?)
+    } else if is_toml(path) {
+        let request = toml::from_str(contents).map_err(|e| toml_error(contents, e))?;
+        let request = toml_to_json(toml::Value::Table(request))?;
+        Ok(serde_json::from_value(stringify_fields(request))?)
+    } else {
+        Ok(serde_json::from_str(contents)?)
+    }
+}
+
+/// Converts `value`, read from a TOML file, to JSON. Dates and times are read as strings, and
+/// infinite and NaN floats, which JSON can't represent, are errors.
+fn toml_to_json(value: toml::Value) -> KuiperResult<Value> {
+    Ok(match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => match serde_json::Number::from_f64(f) {
+            Some(number) => Value::Number(number),
+            None => {
+                return Err(KuiperError::InvalidToml(
+                    0,
+                    format!("'{f}' can't be read as a JSON number"),
+                ))
+            }
+        },
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(toml_to_json)
+                .collect::<KuiperResult<_>>()?,
+        ),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| Ok((key, toml_to_json(value)?)))
+                .collect::<KuiperResult<_>>()?,
+        ),
+    })
+}
+
+/// The error for `error`, from reading `contents` as TOML, with the line it is on.
+pub(crate) fn toml_error(contents: &str, error: toml::de::Error) -> KuiperError {
+    let line = error
+        .span()
+        .map_or(0, |span| contents[..span.start].matches('\n').count() + 1);
+    KuiperError::InvalidToml(line, error.to_string())
+}
+
+/// The fields of a request whose values are strings.
+const STRING_FIELDS: [&str; 3] = ["params", "headers", "form"];
+
+/// Turns the numbers and booleans in the string fields of `request` into strings, since YAML and
+/// TOML read unquoted values like `page: 2` as numbers instead of the strings that JSON request
+/// files would have to use.
+fn stringify_fields(mut request: Value) -> Value {
+    for field in STRING_FIELDS {
+        if let Some(Value::Object(values)) = request.get_mut(field) {
+            for value in values.values_mut() {
+                if let Value::Number(_) | Value::Bool(_) = value {
+                    *value = Value::String(value.to_string());
+                }
+            }
+        }
+    }
+    request
+}
+
+/// Replaces `$unset` header values with `None`, which removes inherited values.
+fn unset_headers(headers: &mut Headers) {
+    for value in headers.values_mut() {
+        if value.as_deref() == Some(UNSET) {
+            *value = None;
+        }
+    }
+}
+
+/// Header values keyed by name, along with the file each value was set in.
+type HeaderOrigins = HashMap<String, (Option<String>, PathBuf)>;
+
+fn overwrite_headers(
+    path: &Path,
+    headers: &mut HeaderOrigins,
+    warnings: &mut Vec<Warning>,
+) -> KuiperResult<()> {
+    match File::open(path) {
+        Ok(file) => {
+            let reader = BufReader::new(file);
+            let mut file_headers: Headers = serde_json::from_reader(reader)?;
+            unset_headers(&mut file_headers);
+            // values are interpolated along with the headers of the request
+            for (name, value) in file_headers {
+                if let Some((shadowed_value, shadowed_origin)) =
+                    headers.insert(name.clone(), (value.clone(), path.to_path_buf()))
+                {
+                    if shadowed_value != value {
+                        warnings.push(Warning::ShadowedHeader {
+                            name,
+                            value,
+                            origin: path.to_path_buf(),
+                            shadowed_value,
+                            shadowed_origin,
+                        });
+                    }
+                }
+            }
+        }
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => return Ok(()),
+            _ => return Err(KuiperError::IoError(e)),
+        },
+    }
+    trace!("successfully parsed headers at '{path:?}");
+    Ok(())
+}
+
+/// Encodes `claims` as a JWT signed with HS256 and `secret`.
+fn sign_jwt(claims: &serde_json::Map<String, Value>, secret: &str) -> String {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(Value::Object(claims.clone()).to_string());
+    let signed = format!("{header}.{payload}");
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&key, signed.as_bytes()));
+    format!("{signed}.{signature}")
+}
+
+/// Encodes the 128 bits of a ULID as 26 characters of Crockford's base32.
+fn ulid(value: u128) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    (0..26)
+        .map(|i| char::from(ALPHABET[((value >> (125 - 5 * i)) & 31) as usize]))
+        .collect()
+}
+
+/// Formats a digest or signature as lowercase hex.
+fn hex(bytes: impl AsRef<[u8]>) -> String {
+    bytes
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, so that `value` can be
+/// used anywhere in a URL.
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns `false` for the namespaces whose values may contain `|`, and so can't be followed by
+/// a default: expressions, which always have a value, and commands, which may be pipelines.
+pub(crate) fn takes_default(namespace: &str) -> bool {
+    !matches!(namespace, "expr" | "cmd")
+}
+
+/// Runs `command` in the directory of the request for `{{cmd:COMMAND}}`, if commands are
+/// allowed, and returns what it printed without surrounding whitespace.
+fn run_command(command: &str, context: &Context) -> KuiperResult<String> {
+    if !context.commands_allowed {
+        return Err(InterpolationError::CommandNotAllowed(command.to_string()).into());
+    }
+    let mut outputs = context.command_outputs.lock().unwrap();
+    if let Some(output) = outputs.get(command) {
+        return Ok(output.clone());
+    }
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    };
+    if let Some(dir) = context
+        .dir
+        .as_deref()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        shell.current_dir(dir);
+    }
+    let output = shell
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| KuiperError::CommandFailed(format!("failed to run '{command}': {e}")))?;
+    if !output.status.success() {
+        return Err(KuiperError::CommandFailed(format!(
+            "'{command}' failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let output = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    trace!("ran '{command}' for interpolation");
+    outputs.insert(command.to_string(), output.clone());
+    Ok(output)
+}
+
+/// Parses an offset like `+2h` or `-30m`, in `ms`, `s`, `m`, `h`, `d` or `w`. An empty offset is
+/// zero.
+fn parse_offset(offset: &str) -> Option<SignedDuration> {
+    if offset.is_empty() {
+        return Some(SignedDuration::ZERO);
+    }
+    let (sign, offset) = match offset.split_at(1) {
+        ("+", offset) => (1, offset),
+        ("-", offset) => (-1, offset),
+        _ => return None,
+    };
+    let unit_start = offset.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = offset[..unit_start].parse().ok()?;
+    let millis_per_unit = match &offset[unit_start..] {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 604_800_000,
+        _ => return None,
+    };
+    Some(SignedDuration::from_millis(
+        amount.checked_mul(millis_per_unit)?.checked_mul(sign)?,
+    ))
+}
+
+/// Reads the file at `path`, relative to the directory of the request, for `{{file:PATH}}`, without
+/// the newline at its end. Returns `None` if there is no such file.
+fn read_file(path: &str, context: &Context) -> KuiperResult<Option<String>> {
+    let path = match &context.dir {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+    match fs::read_to_string(&path) {
+        Ok(contents) => {
+            let contents = contents.strip_suffix('\n').unwrap_or(&contents);
+            let contents = contents.strip_suffix('\r').unwrap_or(contents);
+            Ok(Some(contents.to_string()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(KuiperError::IoError(e)),
+    }
+}
+
+/// Adds `error` to `errors`, unless the same value is missing in several places.
+fn add_error(errors: &mut Vec<InterpolationError>, error: InterpolationError) {
+    if !errors.contains(&error) {
+        errors.push(error);
+    }
+}
+
+/// Collects the strings and keys of `value`.
+fn json_strings<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => strings.push(s),
+        Value::Array(items) => {
+            for item in items {
+                json_strings(item, strings);
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                strings.push(key);
+                json_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replaces the strings in `body` that are only a `{{file:PATH}}` placeholder for a `.json` file
+/// with the JSON in that file, so that large bodies can be kept in files of their own, and the
+/// ones that are only a capture or variable holding JSON other than a string with that JSON.
+fn inline_json_values(body: &mut Value, context: &Context) -> KuiperResult<()> {
+    match body {
+        Value::String(s) => {
+            let placeholder = s
+                .strip_prefix("{{")
+                .and_then(|s| s.strip_suffix("}}"))
+                .filter(|placeholder| !placeholder.contains("{{") && !placeholder.contains("}}"));
+            let Some(placeholder) = placeholder else {
+                return Ok(());
+            };
+            if let Some(path) = placeholder
+                .strip_prefix("file:")
+                .filter(|path| path.ends_with(".json"))
+            {
+                // a missing file is reported along with the other missing values
+                if let Some(contents) = read_file(path, context)? {
+                    *body = serde_json::from_str(&contents)?;
+                }
+            } else if let Some(value) = context.json_value(placeholder) {
+                context
+                    .resolved
+                    .lock()
+                    .unwrap()
+                    .insert(placeholder.to_string(), value_text(&value));
+                *body = value;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                inline_json_values(item, context)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                inline_json_values(value, context)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `value` as it is interpolated into text: strings as they are, and other values as JSON.
+pub(crate) fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Reads the auth in the `auth.json` at `path`, if there is one.
+fn read_auth(path: &Path) -> KuiperResult<Option<auth::Auth>> {
+    match File::open(path) {
+        Ok(file) => {
+            let auth = serde_json::from_reader(BufReader::new(file))?;
+            trace!("successfully parsed auth at '{path:?}");
+            Ok(Some(auth))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(KuiperError::IoError(e)),
+    }
+}
+
+/// Adds the variables of the `variables.json` at `path` to `variables`, and the ones that are
+/// JSON values other than strings to `json` as well.
+fn overwrite_variables(
+    path: &Path,
+    variables: &mut HashMap<String, String>,
+    json: &mut HashMap<String, Value>,
+) -> KuiperResult<()> {
+    match File::open(path) {
+        Ok(file) => {
+            let file_variables: HashMap<String, Value> =
+                serde_json::from_reader(BufReader::new(file))?;
+            for (name, value) in file_variables {
+                variables.insert(name.clone(), value_text(&value));
+                match value {
+                    Value::String(_) => json.remove(&name),
+                    value => json.insert(name, value),
+                };
+            }
+        }
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => return Ok(()),
+            _ => return Err(KuiperError::IoError(e)),
+        },
+    }
+    trace!("successfully parsed variables at '{path:?}");
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum KuiperError {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    RequestNotFound,
+    FileFormatError,
+    PathError,
+    InvalidExpr(String),
+    InterpolationError(InterpolationError),
+    /// A request has several placeholders without a value, in the order they were found in.
+    /// A single one is reported as a [`KuiperError::InterpolationError`].
+    InterpolationErrors(Vec<InterpolationError>),
+    InvalidJsonPath(String),
+    CaptureNotFound(String),
+    DependencyNotFound(String),
+    DependencyCycle(Vec<String>),
+    InvalidDataset(String),
+    InvalidRegex(String),
+    InvalidBundle(String),
+    InvalidRemote(String),
+    HttpError(reqwest::Error),
+    /// Requests can't be sent over HTTP/3, for this reason.
+    UnsupportedHttp3(String),
+    InvalidTlsVersion(String),
+    /// The TLS backend can't be used with these settings, for this reason.
+    UnsupportedTls(String),
+    /// A TLS session could not be negotiated with a server, for this reason.
+    TlsHandshakeFailed(String),
+    DecryptionFailed(String),
+    /// A secret could not be fetched from a cloud secret manager, for this reason.
+    SecretFetchFailed(String),
+    KeyringUnavailable(String),
+    InvalidEnvFile(usize),
+    /// A YAML file is invalid on this line, from 1 or 0 if it isn't known, for this reason, which
+    /// says where.
+    InvalidYaml(usize, String),
+    /// A TOML file is invalid on this line, from 1 or 0 if it isn't known, for this reason, which
+    /// says where.
+    InvalidToml(usize, String),
+    HostNotAllowed(String),
+    MissingRequired(Vec<String>),
+    InvalidTemplate(String),
+    InvalidMethod(String),
+    /// The response body was larger than the limit, in bytes.
+    ResponseTooLarge(u64),
+    InvalidCondition(String),
+    InvalidDuration(String),
+    /// Polling gave up before this condition held.
+    PollTimedOut(String),
+    /// A flow of requests did not finish within this deadline.
+    DeadlineExceeded(Duration),
+    /// A body could not be converted to another format, for this reason.
+    Unconvertible(String),
+    /// The entry at this position, from 1, of an audit log does not follow the entry before it.
+    AuditLogTampered(usize),
+    /// A token could not be fetched for a request, for this reason.
+    AuthFailed(String),
+    /// These prompts have to be answered before the request can be interpolated.
+    MissingPrompts(Vec<Prompt>),
+    /// A `{{cmd:COMMAND}}` command could not be run or failed, for this reason.
+    CommandFailed(String),
+    /// There is no entry with this id in the history.
+    UnknownHistoryEntry(String),
+    /// A request sets more than one of these kinds of body.
+    ConflictingBodies(Vec<String>),
+    /// The certificate of this host doesn't match the `pin_sha256` of the request, or the host
+    /// was not reached over TLS.
+    CertificatePinMismatch(String),
+    /// A CA bundle or client certificate could not be read, for this reason.
+    InvalidCertificate(String),
+    /// The requests of a suite could not be found, for this reason.
+    InvalidSuite(String),
+    /// A GraphQL request is invalid, for this reason.
+    InvalidGraphQl(String),
+    /// A JSON-RPC call is invalid, for this reason.
+    InvalidJsonRpc(String),
+    /// A gRPC call is invalid, for this reason.
+    InvalidGrpc(String),
+    /// A gRPC call could not be made, for this reason.
+    GrpcFailed(String),
+    /// The cancel flag of the client was set before the response was read.
+    Cancelled,
+    /// A plugin could not be run, failed, or there is none for what was asked, for this reason.
+    PluginFailed(String),
+}
+
+impl KuiperError {
+    /// Returns `true` if the kuiper error is [`FileFormatError`].
+    ///
+    /// [`FileFormatError`]: KuiperError::FileFormatError
+    #[must_use]
+    pub fn is_file_format_error(&self) -> bool {
+        matches!(self, Self::FileFormatError)
+    }
+
+    /// The class of the failure, for tools that react to failures without parsing messages.
+    pub fn failure(&self) -> Failure {
+        match self {
+            KuiperError::InvalidExpr(_)
+            | KuiperError::InterpolationError(_)
+            | KuiperError::InterpolationErrors(_)
+            | KuiperError::MissingRequired(_)
+            | KuiperError::MissingPrompts(_)
+            | KuiperError::CommandFailed(_) => Failure::Interpolation,
+            KuiperError::HttpError(e) if e.is_builder() => Failure::Resolve,
+            KuiperError::HttpError(e) if e.is_status() || e.is_decode() => Failure::Http,
+            KuiperError::HttpError(_)
+            | KuiperError::DeadlineExceeded(_)
+            | KuiperError::CertificatePinMismatch(_)
+            | KuiperError::TlsHandshakeFailed(_)
+            | KuiperError::GrpcFailed(_) => Failure::Network,
+            KuiperError::ResponseTooLarge(_)
+            | KuiperError::AuthFailed(_)
+            | KuiperError::Unconvertible(_) => Failure::Http,
+            KuiperError::CaptureNotFound(_) | KuiperError::PollTimedOut(_) => Failure::Assertion,
+            KuiperError::Cancelled => Failure::Cancelled,
+            // everything else is about reading requests and what they need
+            _ => Failure::Resolve,
+        }
+    }
+}
+
+/// The class of a failure, from the earliest stage of sending a request to the latest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Failure {
+    /// A request, its dependencies, or the config, environment or files it needs could not be
+    /// found or read.
+    Resolve,
+    /// A placeholder could not be interpolated.
+    Interpolation,
+    /// A request could not be sent, or no response was received in time.
+    Network,
+    /// A response has an error status, or could not be read.
+    Http,
+    /// A response did not meet the expectations of its request.
+    Assertion,
+    /// The run was interrupted.
+    Cancelled,
+}
+
+impl Error for KuiperError {}
+
+impl Display for KuiperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                KuiperError::IoError(error) => format!("I/O error: {error}"),
+                KuiperError::JsonError(error) => format!("JSON error: {error}"),
+                KuiperError::RequestNotFound => "request not found".to_string(),
+                KuiperError::InterpolationError(error) =>
+                    format!("interpolation error '{}'", error),
+                KuiperError::InterpolationErrors(errors) => format!(
+                    "{} interpolation errors: {}",
+                    errors.len(),
+                    errors
+                        .iter()
+                        .map(|e| format!("'{e}'"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                KuiperError::FileFormatError => "file format error".to_string(),
+                KuiperError::PathError => "path error".to_string(),
+                KuiperError::InvalidExpr(expr) => format!("invalid expr: '{}'", expr),
+                KuiperError::InvalidJsonPath(path) => format!("invalid JSONPath: '{path}'"),
+                KuiperError::CaptureNotFound(name) =>
+                    format!("value for capture '{name}' not found in response"),
+                KuiperError::DependencyNotFound(dependency) =>
+                    format!("dependency not found: '{dependency}'"),
+                KuiperError::DependencyCycle(cycle) =>
+                    format!("dependency cycle: {}", cycle.join(" -> ")),
+                KuiperError::InvalidDataset(reason) => format!("invalid dataset: {reason}"),
+                KuiperError::InvalidRegex(regex) => format!("invalid regex: '{regex}'"),
+                KuiperError::InvalidBundle(reason) => format!("invalid bundle: {reason}"),
+                KuiperError::InvalidRemote(name) => format!("invalid remote name: '{name}'"),
+                KuiperError::HttpError(error) => format!("HTTP error: {error}"),
+                KuiperError::UnsupportedHttp3(reason) =>
+                    format!("HTTP/3 is not supported: {reason}"),
+                KuiperError::InvalidTlsVersion(version) =>
+                    format!("invalid TLS version: '{version}', expected one of 1.0, 1.1, 1.2, 1.3"),
+                KuiperError::UnsupportedTls(reason) =>
+                    format!("unsupported TLS settings: {reason}"),
+                KuiperError::TlsHandshakeFailed(reason) =>
+                    format!("TLS handshake failed: {reason}"),
+                KuiperError::DecryptionFailed(reason) =>
+                    format!("failed to decrypt secrets: {reason}"),
+                KuiperError::SecretFetchFailed(reason) =>
+                    format!("failed to fetch secrets: {reason}"),
+                KuiperError::KeyringUnavailable(reason) =>
+                    format!("failed to access the OS keyring: {reason}"),
+                KuiperError::InvalidEnvFile(line) =>
+                    format!("invalid env file: expected NAME=value on line {line}"),
+                KuiperError::InvalidYaml(_, reason) => format!("invalid YAML: {reason}"),
+                KuiperError::InvalidToml(_, reason) => format!("invalid TOML: {reason}"),
+                KuiperError::HostNotAllowed(host) =>
+                    format!("sending requests to '{host}' is not allowed by the host allowlist"),
+                KuiperError::MissingRequired(names) =>
+                    format!("missing required values: {}", names.join(", ")),
+                KuiperError::InvalidTemplate(reason) => format!("invalid template: {reason}"),
+                KuiperError::InvalidMethod(method) => format!("invalid method: '{method}'"),
+                KuiperError::ResponseTooLarge(max) =>
+                    format!("response body is larger than {max} bytes"),
+                KuiperError::InvalidCondition(condition) => format!(
+                    "invalid condition: '{condition}', expected PATH, PATH == VALUE or PATH != VALUE"
+                ),
+                KuiperError::InvalidDuration(duration) => format!(
+                    "invalid duration: '{duration}', expected a number followed by ms, s, m or h"
+                ),
+                KuiperError::PollTimedOut(condition) =>
+                    format!("timed out waiting for '{condition}'"),
+                KuiperError::DeadlineExceeded(deadline) =>
+                    format!("the deadline of {}ms was exceeded", deadline.as_millis()),
+                KuiperError::Unconvertible(reason) =>
+                    format!("failed to convert body: {reason}"),
+                KuiperError::AuthFailed(reason) => format!("failed to authenticate: {reason}"),
+                KuiperError::CommandFailed(reason) => format!("command failed: {reason}"),
+                KuiperError::UnknownHistoryEntry(id) => format!("no history entry with id '{id}'"),
+                KuiperError::CertificatePinMismatch(host) =>
+                    format!("the certificate of '{host}' does not match the pinned fingerprint"),
+                KuiperError::InvalidCertificate(reason) =>
+                    format!("invalid certificate: {reason}"),
+                KuiperError::InvalidSuite(reason) => format!("invalid suite: {reason}"),
+                KuiperError::InvalidGraphQl(reason) => format!("invalid GraphQL request: {reason}"),
+                KuiperError::InvalidJsonRpc(reason) => format!("invalid JSON-RPC call: {reason}"),
+                KuiperError::InvalidGrpc(reason) => format!("invalid gRPC call: {reason}"),
+                KuiperError::GrpcFailed(reason) => format!("gRPC call failed: {reason}"),
+                KuiperError::ConflictingBodies(fields) =>
+                    format!("only one of {} can be set", fields.join(", ")),
+                KuiperError::MissingPrompts(prompts) => format!(
+                    "missing answers to prompts: {}",
+                    prompts
+                        .iter()
+                        .map(Prompt::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                KuiperError::AuditLogTampered(position) => format!(
+                    "entry {position} of the audit log has been changed, removed or reordered"
+                ),
+                KuiperError::Cancelled => "cancelled before the response was read".to_string(),
+                KuiperError::PluginFailed(reason) => format!("plugin failed: {reason}"),
+            }
+        )
+    }
+}
+
+impl From<std::io::Error> for KuiperError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<serde_json::Error> for KuiperError {
     fn from(value: serde_json::Error) -> Self {
         Self::JsonError(value)
     }
-}
+}
+
+impl From<serde_yaml::Error> for KuiperError {
+    fn from(value: serde_yaml::Error) -> Self {
+        let line = value.location().map_or(0, |location| location.line());
+        Self::InvalidYaml(line, value.to_string())
+    }
+}
+
+impl From<reqwest::Error> for KuiperError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::HttpError(value)
+    }
+}
+
+impl From<InterpolationError> for KuiperError {
+    fn from(value: InterpolationError) -> Self {
+        Self::InterpolationError(value)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterpolationError {
+    MissingEnvVar(String),
+    MissingCapture(String),
+    MissingDataColumn(String),
+    MissingSecret(String),
+    MissingVariable(String),
+    MissingFile(String),
+    MissingPrompt(String),
+    /// `{{cmd:COMMAND}}` was used without allowing commands to run.
+    CommandNotAllowed(String),
+    /// The [`Resolver`] of a custom namespace has no value for this `NAMESPACE:NAME`.
+    MissingValue(String),
+    /// Values refer to each other, starting and ending with the same placeholder.
+    Cycle(Vec<String>),
+    /// The value of this placeholder is nested more than [`MAX_INTERPOLATION_DEPTH`] values deep.
+    TooDeep(String),
+    /// A placeholder is never closed, or isn't a `NAMESPACE:NAME` of a known namespace, at this
+    /// byte offset of the text that is interpolated, which is shown around it.
+    InvalidFormat {
+        offset: usize,
+        snippet: String,
+    },
+}
+
+impl Error for InterpolationError {}
+
+impl Display for InterpolationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                InterpolationError::MissingEnvVar(var) => format!("missing env var: '{var}'"),
+                InterpolationError::MissingCapture(name) => format!("missing capture: '{name}'"),
+                InterpolationError::MissingDataColumn(column) =>
+                    format!("missing data column: '{column}'"),
+                InterpolationError::MissingSecret(name) => format!("missing secret: '{name}'"),
+                InterpolationError::MissingVariable(name) => format!("missing variable: '{name}'"),
+                InterpolationError::MissingFile(path) => format!("missing file: '{path}'"),
+                InterpolationError::MissingPrompt(name) => format!("missing prompt: '{name}'"),
+                InterpolationError::CommandNotAllowed(command) =>
+                    format!("running commands is not allowed, but '{command}' is used"),
+                InterpolationError::MissingValue(placeholder) =>
+                    format!("missing value: '{placeholder}'"),
+                InterpolationError::Cycle(cycle) =>
+                    format!("values refer to each other: {}", cycle.join(" -> ")),
+                InterpolationError::TooDeep(placeholder) => format!(
+                    "the value of '{placeholder}' refers to values more than {MAX_INTERPOLATION_DEPTH} levels deep"
+                ),
+                InterpolationError::InvalidFormat { offset, snippet } =>
+                    format!("invalid interpolation format at offset {offset}, near '{snippet}'"),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fmt::Debug, hash::Hash, path::Path, time::Duration};
+    use test_log::test;
+
+    fn assert_hash_map_eq<K, V>(left: &HashMap<K, V>, right: &HashMap<K, V>)
+    where
+        K: Hash + Eq + Debug,
+        V: Debug + PartialEq,
+    {
+        assert_eq!(left.len(), right.len());
+        for (left_key, left_value) in left {
+            let (right_key, right_value) = right
+                .get_key_value(left_key)
+                .unwrap_or_else(|| panic!("right HashMap does not contain key '{:?}'", left_key));
+            assert_eq!(left_key, right_key);
+            assert_eq!(
+                left_value, right_value,
+                "headers differ at key '{:?}', left: '{:?}', right: '{:?}'",
+                left_key, left_value, right_value
+            );
+        }
+    }
+
+    #[test]
+    fn ancestors_rev_test() {
+        let path = PathBuf::from("x/y/z/f.kuiper");
+        let v: Vec<_> = path.ancestors().collect();
+        let reversed: Vec<_> = v.into_iter().skip(1).rev().skip(1).collect();
+
+        assert_eq!(
+            reversed,
+            vec![Path::new("x"), Path::new("x/y"), Path::new("x/y/z"),]
+        );
+    }
+
+    #[test]
+    fn root_request_test() {
+        let request = Request::find("../requests/request_in_root.kuiper").unwrap();
+        assert_eq!(request.uri(), "http://www.example.com");
+        let expected_headers: Headers = [
+            ("root_header_1", Some("root_value_1")),
+            ("root_header_2", Some("root_value_2")),
+            ("root_header_3", None),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.map(|s| s.to_string())))
+        .collect();
+
+        assert_hash_map_eq(request.headers(), &expected_headers);
+    }
+
+    #[test]
+    fn subdir_request_test() {
+        let request = Request::find("../requests/subdir/request_in_subdir.kuiper").unwrap();
+        assert_eq!(request.uri(), "http://localhost/api/user/1");
+        let expected_headers: Headers = [
+            ("root_header_1", Some("root_value_1")),
+            ("root_header_2", Some("subdir_value_2")),
+            ("root_header_3", Some("root_value_3")),
+            ("subdir_header_1", Some("subdir_value_1")),
+            (
+                "request_specific_header_1",
+                Some("request_specific_header_value_1"),
+            ),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.map(|s| s.to_string())))
+        .collect();
+
+        assert_hash_map_eq(request.headers(), &expected_headers);
+    }
+
+    #[test]
+    fn header_shadowing_test() {
+        let request = Request::find("../requests/subdir/request_in_subdir.kuiper").unwrap();
+        let [Warning::ShadowedHeader {
+            name,
+            value,
+            origin,
+            shadowed_value,
+            shadowed_origin,
+        }] = request.warnings()
+        else {
+            panic!("expected one warning, got {:?}", request.warnings());
+        };
+        assert_eq!(name, "root_header_2");
+        assert_eq!(value.as_deref(), Some("subdir_value_2"));
+        assert!(origin.ends_with("requests/subdir/headers.json"));
+        assert_eq!(shadowed_value.as_deref(), Some("root_value_2"));
+        assert!(shadowed_origin.ends_with("requests/headers.json"));
+
+        let request = Request::find("../requests/request_in_root.kuiper").unwrap();
+        assert!(matches!(
+            request.warnings(),
+            [Warning::ShadowedHeader { name, value: None, .. }] if name == "root_header_3"
+        ));
+
+        let request = Request::find("../requests/dependencies/login.kuiper").unwrap();
+        assert!(request.warnings().is_empty());
+    }
+
+    #[test]
+    fn shared_headers_test() {
+        let path = "../requests/shared/users/get_user.kuiper";
+        let context =
+            Context::new().with_env(HashMap::from([("TOKEN".to_string(), "abc123".to_string())]));
+        let request = Request::find_with_context(path, &context).unwrap();
+        assert_eq!(
+            request.headers()["Authorization"].as_deref(),
+            Some("Bearer abc123")
+        );
+        // files are found next to the headers.json, not the request
+        assert_eq!(
+            request.headers()["X-Client"].as_deref(),
+            Some("kuiper-tests")
+        );
+
+        assert!(matches!(
+            Request::find_with_context(path, &Context::new()),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::MissingEnvVar(name)
+            )) if name == "TOKEN"
+        ));
+    }
+
+    #[test]
+    fn unset_test() {
+        let mut request = Request::find("../requests/subdir/unset/unset.kuiper").unwrap();
+        assert_eq!(request.headers()["root_header_1"], None);
+        assert_eq!(request.headers()["subdir_header_1"], None);
+        assert_eq!(
+            request.headers()["root_header_2"].as_deref(),
+            Some("subdir_value_2")
+        );
+        assert!(matches!(
+            request.header_origin("root_header_1"),
+            Some(HeaderOrigin::File(path)) if path.ends_with("unset/headers.json")
+        ));
+        assert_eq!(
+            request.params(),
+            &HashMap::from([("kept".to_string(), "1".to_string())])
+        );
+
+        request.override_header("ROOT_HEADER_2", None);
+        request.override_header("X-Extra", Some("1".to_string()));
+        request.override_param("kept", None);
+        request.override_param("added", Some("2".to_string()));
+        assert!(!request.headers().contains_key("root_header_2"));
+        assert_eq!(request.headers()["ROOT_HEADER_2"], None);
+        assert_eq!(
+            request.header_origin("ROOT_HEADER_2"),
+            Some(&HeaderOrigin::Override)
+        );
+        assert_eq!(request.headers()["X-Extra"].as_deref(), Some("1"));
+        assert_eq!(
+            request.params(),
+            &HashMap::from([("added".to_string(), "2".to_string())])
+        );
+    }
+
+    #[test]
+    fn host_defaults_test() {
+        let config = Config::discover("../requests/subdir").unwrap();
+        let context = Context::new()
+            .with_env(HashMap::from([(
+                "KUIPER_ORG_ID".to_string(),
+                "org-1".to_string(),
+            )]))
+            .with_hosts(config.hosts().to_vec());
+
+        let request =
+            Request::find_with_context("../requests/subdir/request_in_subdir.kuiper", &context)
+                .unwrap();
+        assert_eq!(
+            request.headers().get("X-Org-Id"),
+            Some(&Some("org-1".to_string()))
+        );
+        assert_eq!(
+            request.header_origin("X-Org-Id"),
+            Some(&HeaderOrigin::Host("localhost".to_string()))
+        );
+        assert!(matches!(
+            request.header_origin("request_specific_header_1"),
+            Some(HeaderOrigin::File(path)) if path.ends_with("request_in_subdir.kuiper")
+        ));
+        assert!(matches!(
+            request.header_origin("root_header_1"),
+            Some(HeaderOrigin::File(path)) if path.ends_with("requests/headers.json")
+        ));
+
+        let request =
+            Request::find_with_context("../requests/request_in_root.kuiper", &context).unwrap();
+        assert!(!request.headers().contains_key("X-Org-Id"));
+    }
+
+    #[test]
+    fn interpolation_test() {
+        dotenv::from_path("../requests/example.env").unwrap();
+        let interpolated_request = Request::find("../requests/interpolation.kuiper").unwrap();
+
+        assert_eq!(interpolated_request.params.len(), 3);
+        assert_eq!(interpolated_request.params["env_1"], "123");
+        // a new Uuid is generated every time the test is ran,
+        // so just assert that it is a Uuids
+        assert!(interpolated_request.params["expr_uuid"]
+            .parse::<Uuid>()
+            .is_ok());
+        assert!(interpolated_request.params["expr_now"]
+            .parse::<Timestamp>()
+            .is_ok());
+
+        assert_eq!(interpolated_request.uri, "http://localhost/route_value");
+
+        let expected_headers: HashMap<String, Option<String>> = [
+            ("root_header_1", Some("root_value_1")),
+            ("root_header_2", Some("root_value_2")),
+            ("root_header_3", Some("root_value_3")),
+            ("interpolated_header", Some("1234")),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.map(|v| v.to_string())))
+        .collect();
+        assert_hash_map_eq(&interpolated_request.headers, &expected_headers);
+    }
+
+    #[test]
+    fn assertions_alias_test() {
+        let request: Request = serde_json::from_str(
+            r#"{ "uri": "http://localhost", "method": "GET", "assertions": { "status": 204 } }"#,
+        )
+        .unwrap();
+        let response = Response::new(204, Vec::new(), String::new(), Duration::ZERO);
+        let outcomes = request.expect().unwrap().evaluate(&response).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes[0].passed());
+    }
+
+    #[test]
+    fn required_test() {
+        let missing = Request::find("../requests/required.kuiper");
+        assert!(matches!(
+            missing,
+            Err(KuiperError::MissingRequired(names))
+                if names == vec!["KUIPER_TEST_USER_ID", "KUIPER_TEST_TENANT"]
+        ));
+
+        let env = HashMap::from([
+            ("KUIPER_TEST_USER_ID".to_string(), "42".to_string()),
+            ("KUIPER_TEST_TENANT".to_string(), String::new()),
+        ]);
+        let missing = Request::find_with_context(
+            "../requests/required.kuiper",
+            &Context::new().with_env(env),
+        );
+        assert!(matches!(
+            missing,
+            Err(KuiperError::MissingRequired(names)) if names == vec!["KUIPER_TEST_TENANT"]
+        ));
+
+        let env = HashMap::from([
+            ("KUIPER_TEST_USER_ID".to_string(), "42".to_string()),
+            ("KUIPER_TEST_TENANT".to_string(), "acme".to_string()),
+        ]);
+        let request = Request::find_with_context(
+            "../requests/required.kuiper",
+            &Context::new().with_env(env),
+        )
+        .unwrap();
+        assert_eq!(request.uri(), "http://localhost/users/42");
+        assert_eq!(request.required().len(), 2);
+    }
+
+    #[test]
+    fn variables_test() {
+        let path = "../requests/variables/nested/versioned.kuiper";
+        let request = Request::find(path).unwrap();
+        assert_eq!(request.uri(), "http://localhost/v2/items");
+        assert_eq!(
+            request.headers().get("x-api-version"),
+            Some(&Some("v2".to_string()))
+        );
+        assert_eq!(request.variables(), &Request::find_variables(path).unwrap());
+
+        let context = Context::new().with_variables(HashMap::from([(
+            "host".to_string(),
+            "example.com".to_string(),
+        )]));
+        let request = Request::find_with_context(path, &context).unwrap();
+        assert_eq!(request.uri(), "http://example.com/v2/items");
+
+        assert!(matches!(
+            Request::interpolate_str("{{var:host}}", &Context::new()),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::MissingVariable(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn auth_test() {
+        let request = Request::find("../requests/auth/internal.kuiper").unwrap();
+        assert_eq!(
+            request.headers()["Authorization"].as_deref(),
+            Some("Bearer internal-token")
+        );
+        assert!(matches!(
+            request.header_origin("Authorization"),
+            Some(HeaderOrigin::File(path)) if path.ends_with("requests/auth/auth.json")
+        ));
+
+        let request = Request::find("../requests/auth/partner/partner.kuiper").unwrap();
+        assert!(!request.headers().contains_key("Authorization"));
+        assert_eq!(
+            request.headers()["X-API-Key"].as_deref(),
+            Some("partner-key")
+        );
+        assert!(request
+            .auth_origin()
+            .unwrap()
+            .ends_with("requests/auth/partner/auth.json"));
+
+        let request = Request::find("../requests/auth/partner/public.kuiper").unwrap();
+        assert_eq!(request.auth(), Some(&auth::Auth::None));
+        assert!(request
+            .auth_origin()
+            .unwrap()
+            .ends_with("requests/auth/partner/public.kuiper"));
+        assert!(!request.headers().contains_key("X-API-Key"));
+    }
+
+    #[test]
+    fn references_test() {
+        let request = Request::find_uninterpolated("../requests/interpolation.kuiper").unwrap();
+        let references: Vec<_> = request
+            .references()
+            .into_iter()
+            .map(|(namespace, name)| format!("{namespace}:{name}"))
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                "env:route",
+                "env:query_param_1",
+                "expr:now",
+                "expr:uuid",
+                "env:header_value"
+            ]
+        );
+        assert_eq!(request.uri(), "http://localhost/{{env:route}}");
+
+        let request = Request::find_uninterpolated("../requests/required.kuiper").unwrap();
+        assert_eq!(
+            request.references(),
+            vec![
+                ("env".to_string(), "KUIPER_TEST_USER_ID".to_string()),
+                ("env".to_string(), "KUIPER_TEST_TENANT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_test() {
+        let request = Request::find_uninterpolated("../requests/interpolation.kuiper").unwrap();
+        assert_eq!(
+            request.unresolved_placeholders(),
+            vec![
+                "env:route",
+                "env:query_param_1",
+                "expr:now",
+                "expr:uuid",
+                "env:header_value"
+            ]
+        );
+        let context = Context::new().with_env(
+            [
+                ("route", "users"),
+                ("query_param_1", "1"),
+                ("header_value", "a"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        );
+        let resolved = request.resolve(&context).unwrap();
+        assert_eq!(resolved.uri(), "http://localhost/users");
+        assert!(resolved.unresolved_placeholders().is_empty());
+        // the request itself is left as it is, so it can be resolved in other contexts as well
+        assert_eq!(request.uri(), "http://localhost/{{env:route}}");
+
+        let request =
+            Request::find_uninterpolated("../requests/escape/render_template.kuiper").unwrap();
+        assert_eq!(request.unresolved_placeholders(), vec!["var:greeting"]);
+        let resolved = request.resolve(&Context::new()).unwrap();
+        // braces that are left after resolving are literal, and are not resolved again
+        assert_eq!(
+            resolved.resolve(&Context::new()).unwrap().uri(),
+            "http://localhost/templates/{{id}}/render"
+        );
+        assert!(resolved.references().is_empty());
+    }
+
+    #[test]
+    fn context_env_test() {
+        let env = [
+            ("route", "staging_route"),
+            ("query_param_1", "456"),
+            ("header_value", "5678"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let request = Request::find_with_context(
+            "../requests/interpolation.kuiper",
+            &Context::new().with_env(env),
+        )
+        .unwrap();
+
+        assert_eq!(request.uri(), "http://localhost/staging_route");
+        assert_eq!(request.params()["env_1"], "456");
+        assert_eq!(
+            request.headers()["interpolated_header"].as_deref(),
+            Some("5678")
+        );
+    }
+
+    #[test]
+    fn seeded_expr_test() {
+        let seed = seed::Seed::new(7).derive("interpolation.kuiper");
+        let interpolate = || {
+            let context = Context::new().with_seed(seed);
+            [
+                Request::interpolate_str("{{expr:uuid}}", &context).unwrap(),
+                Request::interpolate_str("{{expr:uuid}}", &context).unwrap(),
+                Request::interpolate_str("{{expr:now}}", &context).unwrap(),
+            ]
+        };
+        let values = interpolate();
+        assert_eq!(values, interpolate());
+
+        assert_ne!(values[0], values[1]);
+        assert!(values[0].parse::<Uuid>().is_ok());
+        assert_eq!(values[2], "2000-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn interpolation_error_test() {
+        let result = Request::interpolate_str("asd{{env:{{env:abc}}}}", &Context::default());
+        assert!(
+            matches!(&result, Err(KuiperError::InterpolationError(InterpolationError::MissingEnvVar(var))) if var == "abc"),
+            "{:?}",
+            result
+        );
+
+        // the nested placeholder is closed, but the outer one is not
+        let result = Request::interpolate_str("asd{{env:{{env:abc}}", &Context::default());
+        assert!(
+            matches!(
+                &result,
+                Err(KuiperError::InterpolationError(
+                    InterpolationError::InvalidFormat { offset: 3, snippet }
+                )) if snippet == "asd{{env:{{env:abc}}"
+            ),
+            "{:?}",
+            result
+        );
+
+        // the error points at the unknown namespace of the nested placeholder
+        let result = Request::interpolate_str("{{e{{nv:hello}}}}", &Context::default());
+        assert!(
+            matches!(
+                &result,
+                Err(KuiperError::InterpolationError(
+                    InterpolationError::InvalidFormat { offset: 5, .. }
+                ))
+            ),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "interpolation error 'invalid interpolation format at offset 5, near '{{e{{nv:hello}}}}''"
+        );
+    }
+
+    #[test]
+    fn interpolation_escape_test() {
+        let request = Request::find_with_context(
+            "../requests/escape/render_template.kuiper",
+            &Context::new(),
+        )
+        .unwrap();
+        assert_eq!(request.uri(), "http://localhost/templates/{{id}}/render");
+        // a `}}` outside of placeholders closes nothing, and needs no escape
+        assert_eq!(request.params()["preview"], "{{preview}}");
+        assert_eq!(
+            request.headers()["X-Template"].as_deref(),
+            Some("{{name}} welcome")
+        );
+        assert_eq!(
+            request.body().unwrap()["template"],
+            "Hello {{user.name}}, welcome!"
+        );
+        assert!(request
+            .references()
+            .iter()
+            .all(|(namespace, _)| namespace == "var"));
+
+        let context = Context::new().with_env(HashMap::from([(
+            "KUIPER_ESCAPE_TEST".to_string(),
+            "value".to_string(),
+        )]));
+        assert_eq!(
+            Request::interpolate_str("{{{{env:KUIPER_ESCAPE_TEST}}}}", &context).unwrap(),
+            "{{env:KUIPER_ESCAPE_TEST}}"
+        );
+        // closing braces without a placeholder are left alone
+        assert_eq!(
+            Request::interpolate_str("{{env:KUIPER_ESCAPE_TEST}} }}", &context).unwrap(),
+            "value }}"
+        );
+        assert_eq!(
+            lint::placeholders(r#"{ "uri": "{{{{env:A}}}}/{{env:B}}" }"#)
+                .iter()
+                .map(|p| p.name())
+                .collect::<Vec<_>>(),
+            vec!["B"]
+        );
+    }
+
+    #[test]
+    fn interpolation_recursive_test() {
+        let env = |values: &[(&str, &str)]| {
+            Context::new().with_env(
+                values
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            )
+        };
+        let context = env(&[
+            ("BASE_URL", "http://{{env:HOST}}:{{env:PORT|8080}}"),
+            ("HOST", "{{var:host}}"),
+        ])
+        .with_variables(HashMap::from([(
+            "host".to_string(),
+            "localhost".to_string(),
+        )]));
+        assert_eq!(
+            Request::interpolate_str("{{env:BASE_URL}}/users", &context).unwrap(),
+            "http://localhost:8080/users"
+        );
+
+        let context = env(&[("A", "{{env:B}}"), ("B", "x{{env:C}}"), ("C", "{{env:A}}")]);
+        let result = Request::interpolate_str("{{env:A}}", &context);
+        assert!(
+            matches!(&result, Err(KuiperError::InterpolationError(InterpolationError::Cycle(cycle))) if cycle == &["env:A", "env:B", "env:C", "env:A"]),
+            "{result:?}"
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "interpolation error 'values refer to each other: env:A -> env:B -> env:C -> env:A'"
+        );
+
+        let names: Vec<_> = (0..=MAX_INTERPOLATION_DEPTH + 1)
+            .map(|idx| format!("KUIPER_DEPTH_{idx}"))
+            .collect();
+        let values: Vec<_> = names
+            .windows(2)
+            .map(|pair| (pair[0].clone(), format!("{{{{env:{}}}}}", pair[1])))
+            .collect();
+        let context = Context::new().with_env(values.into_iter().collect());
+        assert!(matches!(
+            Request::interpolate_str("{{env:KUIPER_DEPTH_0}}", &context),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::TooDeep(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn interpolation_file_test() {
+        let request =
+            Request::find_with_context("../requests/files/upload.kuiper", &Context::new()).unwrap();
+        assert_eq!(
+            request.headers()["x-signing-key"].as_deref(),
+            Some("-----BEGIN KEY-----")
+        );
+        assert_eq!(
+            request.body(),
+            Some(&serde_json::json!({
+                "document": { "title": "Quarterly report", "pages": 12 },
+                "script": "echo \"hello\"\nexit 0",
+                "notes": "none"
+            }))
+        );
+
+        assert!(matches!(
+            Request::interpolate_str("{{file:missing.txt}}", &Context::new()),
+            Err(KuiperError::InterpolationError(InterpolationError::MissingFile(path)))
+                if path == "missing.txt"
+        ));
+    }
+
+    #[test]
+    fn interpolation_prompt_test() {
+        let request: Request = serde_json::from_str(
+            r#"{ "uri": "http://localhost/login", "method": "POST", "headers": { "x-otp": "{{prompt:otp}}" }, "body": { "username": "{{prompt:username|admin}}", "password": "{{prompt_secret:password}}" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.prompts(),
+            vec![
+                Prompt {
+                    name: "otp".to_string(),
+                    secret: false
+                },
+                Prompt {
+                    name: "password".to_string(),
+                    secret: true
+                }
+            ]
+        );
+
+        let context = Context::new().with_prompts(HashMap::from([
+            ("otp".to_string(), "123456".to_string()),
+            ("password".to_string(), "hunter\"2".to_string()),
+        ]));
+        assert_eq!(
+            Request::interpolate_str("{{prompt:otp}}/{{prompt:username|admin}}", &context).unwrap(),
+            "123456/admin"
+        );
+        assert!(matches!(
+            Request::interpolate_str("{{prompt_secret:pin}}", &context),
+            Err(KuiperError::InterpolationError(InterpolationError::MissingPrompt(name)))
+                if name == "pin"
+        ));
+    }
 
-impl From<InterpolationError> for KuiperError {
-    fn from(value: InterpolationError) -> Self {
-        Self::InterpolationError(value)
+    #[cfg(unix)]
+    #[test]
+    fn interpolation_cmd_test() {
+        let placeholder = "{{cmd:printf ' token ' | tr a-z A-Z}}";
+        assert!(matches!(
+            Request::interpolate_str(placeholder, &Context::new()),
+            Err(KuiperError::InterpolationError(InterpolationError::CommandNotAllowed(command)))
+                if command == "printf ' token ' | tr a-z A-Z"
+        ));
+
+        let context = Context::new().with_commands_allowed(true);
+        assert_eq!(
+            Request::interpolate_str(placeholder, &context).unwrap(),
+            "TOKEN"
+        );
+        assert!(matches!(
+            Request::interpolate_str("{{cmd:echo oops >&2; exit 1}}", &context),
+            Err(KuiperError::CommandFailed(reason)) if reason.ends_with("oops")
+        ));
     }
-}
 
-#[derive(Debug)]
-pub enum InterpolationError {
-    MissingEnvVar(String),
-    InvalidFormat,
-}
+    #[test]
+    fn interpolation_random_test() {
+        let context = Context::new();
+        for _ in 0..20 {
+            let int: i64 = Request::interpolate_str("{{expr:rand_int(-2, 2)}}", &context)
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!((-2..=2).contains(&int));
+        }
+        let string = Request::interpolate_str("{{expr:rand_string(12)}}", &context).unwrap();
+        assert_eq!(string.len(), 12);
+        assert!(string.chars().all(|c| c.is_ascii_alphanumeric()));
+        let hex = Request::interpolate_str("{{expr:rand_hex(8)}}", &context).unwrap();
+        assert_eq!(hex.len(), 8);
+        assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+        let choice =
+            Request::interpolate_str("{{expr:rand_choice(red, green)}}", &context).unwrap();
+        assert!(choice == "red" || choice == "green");
+        let picked: std::collections::BTreeSet<_> = (0..200)
+            .map(|_| Request::interpolate_str("{{expr:rand_choice(a, b, c)}}", &context).unwrap())
+            .collect();
+        assert_eq!(picked.len(), 3);
+        assert_eq!(
+            Request::interpolate_str("{{expr:rand_int(7,7)}}", &context).unwrap(),
+            "7"
+        );
 
-impl Error for InterpolationError {}
+        let input = "{{expr:rand_int(0,1000000)}}-{{expr:rand_string(8)}}";
+        let seeded = |seed| Context::new().with_seed(seed::Seed::new(seed));
+        assert_eq!(
+            Request::interpolate_str(input, &seeded(1)).unwrap(),
+            Request::interpolate_str(input, &seeded(1)).unwrap()
+        );
 
-impl Display for InterpolationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                InterpolationError::MissingEnvVar(var) => format!("missing env var: '{var}'"),
-                InterpolationError::InvalidFormat => "invalid interpolation format".to_string(),
-            }
-        )
+        for invalid in [
+            "rand_int(2,1)",
+            "rand_int(1)",
+            "rand_string(x)",
+            "rand_choice(a,,b)",
+            "rand_uuid()",
+        ] {
+            assert!(matches!(
+                Request::interpolate_str(&format!("{{{{expr:{invalid}}}}}"), &context),
+                Err(KuiperError::InvalidExpr(expr)) if expr == invalid
+            ));
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::{fmt::Debug, hash::Hash, path::Path};
-    use test_log::test;
+    #[test]
+    fn interpolation_time_test() {
+        // the clock of a seeded run is frozen at 2000-01-01T00:00:00Z
+        let context = Context::new().with_seed(seed::Seed::new(1));
+        let interpolate =
+            |expr: &str| Request::interpolate_str(&format!("{{{{expr:{expr}}}}}"), &context);
+        assert_eq!(interpolate("now").unwrap(), "2000-01-01T00:00:00Z");
+        assert_eq!(interpolate("now+2h").unwrap(), "2000-01-01T02:00:00Z");
+        assert_eq!(interpolate("now-1d").unwrap(), "1999-12-31T00:00:00Z");
+        assert_eq!(interpolate("now(%Y-%m-%d)").unwrap(), "2000-01-01");
+        assert_eq!(
+            interpolate("now+1w(%a, %d %b %Y %H:%M:%S GMT)").unwrap(),
+            "Sat, 08 Jan 2000 00:00:00 GMT"
+        );
+        assert_eq!(interpolate("epoch_millis").unwrap(), "946684800000");
+        assert_eq!(interpolate("epoch_millis+500ms").unwrap(), "946684800500");
 
-    fn assert_hash_map_eq<K, V>(left: &HashMap<K, V>, right: &HashMap<K, V>)
-    where
-        K: Hash + Eq + Debug,
-        V: Debug + PartialEq,
-    {
-        assert_eq!(left.len(), right.len());
-        for (left_key, left_value) in left {
-            let (right_key, right_value) = right
-                .get_key_value(left_key)
-                .unwrap_or_else(|| panic!("right HashMap does not contain key '{:?}'", left_key));
-            assert_eq!(left_key, right_key);
-            assert_eq!(
-                left_value, right_value,
-                "headers differ at key '{:?}', left: '{:?}', right: '{:?}'",
-                left_key, left_value, right_value
-            );
+        for invalid in ["now+2", "now+h", "now*2h", "nowish", "epoch_millis(%Y)"] {
+            assert!(matches!(
+                interpolate(invalid),
+                Err(KuiperError::InvalidExpr(expr)) if expr == invalid
+            ));
         }
     }
 
     #[test]
-    fn ancestors_rev_test() {
-        let path = PathBuf::from("x/y/z/f.kuiper");
-        let v: Vec<_> = path.ancestors().collect();
-        let reversed: Vec<_> = v.into_iter().skip(1).rev().skip(1).collect();
+    fn interpolation_encoding_test() {
+        let context = Context::new()
+            .with_env(HashMap::from([(
+                "KUIPER_ENCODING_TEST_USER".to_string(),
+                "ada".to_string(),
+            )]))
+            .with_secrets(HashMap::from([(
+                "password".to_string(),
+                "p@ss, word".to_string(),
+            )]));
+        let interpolate = |input: &str| Request::interpolate_str(input, &context);
+        assert_eq!(
+            interpolate(
+                "Basic {{expr:base64({{env:KUIPER_ENCODING_TEST_USER}}:{{secret:password}})}}"
+            )
+            .unwrap(),
+            "Basic YWRhOnBAc3MsIHdvcmQ="
+        );
+        assert_eq!(
+            interpolate("{{expr:base64decode(YWRhOnBAc3MsIHdvcmQ=)}}").unwrap(),
+            "ada:p@ss, word"
+        );
+        assert_eq!(
+            interpolate("q={{expr:urlencode({{secret:password}}/ä~)}}").unwrap(),
+            "q=p%40ss%2C%20word%2F%C3%A4~"
+        );
+        assert_eq!(
+            interpolate("{{expr:base64decode({{expr:base64(a(b))}})}}").unwrap(),
+            "a(b)"
+        );
+
+        // the nested values are not shown in errors
+        assert!(matches!(
+            interpolate("{{expr:base64decode({{secret:password}})}}"),
+            Err(KuiperError::InvalidExpr(expr)) if expr == "base64decode({{secret:password}})"
+        ));
+        assert!(matches!(
+            interpolate("{{expr:base64({{env:KUIPER_ENCODING_TEST_MISSING}})}}"),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::MissingEnvVar(_)
+            ))
+        ));
+        assert!(matches!(
+            interpolate("{{expr:base64({{env:KUIPER_ENCODING_TEST_USER}}"),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::InvalidFormat { .. }
+            ))
+        ));
+    }
 
+    #[test]
+    fn interpolation_signing_test() {
+        let context = Context::new().with_secrets(HashMap::from([(
+            "signing_key".to_string(),
+            "key".to_string(),
+        )]));
+        let interpolate = |input: &str| Request::interpolate_str(input, &context);
         assert_eq!(
-            reversed,
-            vec![Path::new("x"), Path::new("x/y"), Path::new("x/y/z"),]
+            interpolate("{{expr:sha256(abc)}}").unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // the example from Wikipedia's HMAC article
+        assert_eq!(
+            interpolate(
+                "{{expr:hmac_sha256({{secret:signing_key}}, The quick brown fox jumps over the lazy dog)}}"
+            )
+            .unwrap(),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert_eq!(
+            interpolate("{{expr:hmac_sha256(key, a, b)}}").unwrap(),
+            interpolate("{{expr:hmac_sha256(key,a, b)}}").unwrap()
         );
+        assert!(matches!(
+            interpolate("{{expr:hmac_sha256(key)}}"),
+            Err(KuiperError::InvalidExpr(_))
+        ));
     }
 
     #[test]
-    fn root_request_test() {
-        let request = Request::find("../requests/request_in_root.kuiper").unwrap();
-        assert_eq!(request.uri(), "http://www.example.com");
-        let expected_headers: Headers = [
-            ("root_header_1", Some("root_value_1")),
-            ("root_header_2", Some("root_value_2")),
-            ("root_header_3", None),
-        ]
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v.map(|s| s.to_string())))
-        .collect();
+    fn interpolation_jwt_test() {
+        let context = Context::new()
+            .with_seed(seed::Seed::new(1))
+            .with_env(HashMap::from([(
+                "KUIPER_JWT_TEST_SECRET".to_string(),
+                "your-256-bit-secret".to_string(),
+            )]));
+        let interpolate = |input: &str| Request::interpolate_str(input, &context);
+        // the example from jwt.io, whose claims set `iat` but not `exp`
+        let token = interpolate(
+            r#"Bearer {{expr:jwt({"sub": "1234567890", "name": "John Doe", "iat": 1516239022}, KUIPER_JWT_TEST_SECRET)}}"#,
+        )
+        .unwrap();
+        let parts: Vec<_> = token.trim_start_matches("Bearer ").split('.').collect();
+        assert_eq!(parts[0], "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9");
+        let claims: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(claims["iat"], 1516239022);
+        // the clock of a seeded run is frozen at 2000-01-01T00:00:00Z
+        assert_eq!(
+            claims["exp"],
+            seed::DEFAULT_NOW_SECOND + JWT_LIFETIME_SECONDS
+        );
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"your-256-bit-secret");
+        let signature = URL_SAFE_NO_PAD.decode(parts[2]).unwrap();
+        let signed = format!("{}.{}", parts[0], parts[1]);
+        assert!(hmac::verify(&key, signed.as_bytes(), &signature).is_ok());
 
-        assert_hash_map_eq(request.headers(), &expected_headers);
+        assert!(matches!(
+            interpolate(r#"{{expr:jwt({"sub": "1"}, KUIPER_JWT_TEST_MISSING)}}"#),
+            Err(KuiperError::InterpolationError(
+                InterpolationError::MissingEnvVar(_)
+            ))
+        ));
+        assert!(matches!(
+            interpolate("{{expr:jwt(not json, KUIPER_JWT_TEST_SECRET)}}"),
+            Err(KuiperError::InvalidExpr(_))
+        ));
     }
 
     #[test]
-    fn subdir_request_test() {
-        let request = Request::find("../requests/subdir/request_in_subdir.kuiper").unwrap();
-        assert_eq!(request.uri(), "http://localhost/api/user/1");
-        let expected_headers: Headers = [
-            ("root_header_1", Some("root_value_1")),
-            ("root_header_2", Some("subdir_value_2")),
-            ("root_header_3", Some("root_value_3")),
-            ("subdir_header_1", Some("subdir_value_1")),
-            (
-                "request_specific_header_1",
-                Some("request_specific_header_value_1"),
-            ),
-        ]
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v.map(|s| s.to_string())))
-        .collect();
+    fn interpolation_sortable_id_test() {
+        let context = Context::new();
+        let interpolate = |expr: &str| {
+            Request::interpolate_str(&format!("{{{{expr:{expr}}}}}"), &context).unwrap()
+        };
+        let uuid: Uuid = interpolate("uuidv7").parse().unwrap();
+        assert_eq!(uuid.get_version_num(), 7);
+        let ulid = interpolate("ulid");
+        assert_eq!(ulid.len(), 26);
+        assert!(ulid
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() && !"ILOU".contains(c)));
 
-        assert_hash_map_eq(request.headers(), &expected_headers);
+        // ids generated later sort after earlier ones
+        let earlier = interpolate("ulid");
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(interpolate("ulid") > earlier);
+        let earlier = interpolate("uuidv7");
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(interpolate("uuidv7") > earlier);
+
+        // the clock of a seeded run is frozen at 2000-01-01T00:00:00Z
+        let seeded = Context::new().with_seed(seed::Seed::new(1));
+        let ulid = Request::interpolate_str("{{expr:ulid}}", &seeded).unwrap();
+        assert_eq!(&ulid[..10], "00VHNCZB00");
+        assert_eq!(
+            ulid,
+            Request::interpolate_str(
+                "{{expr:ulid}}",
+                &Context::new().with_seed(seed::Seed::new(1))
+            )
+            .unwrap()
+        );
+    }
+
+    struct Vault;
+
+    impl Resolver for Vault {
+        fn namespace(&self) -> &str {
+            "vault"
+        }
+
+        fn resolve(&self, name: &str) -> KuiperResult<Option<String>> {
+            match name {
+                "secret/api_key" => Ok(Some("s3cr3t".to_string())),
+                "secret/broken" => Err(KuiperError::InvalidExpr(name.to_string())),
+                _ => Ok(None),
+            }
+        }
     }
 
     #[test]
-    fn interpolation_test() {
-        dotenv::from_path("../requests/example.env").unwrap();
-        let interpolated_request = Request::find("../requests/interpolation.kuiper").unwrap();
+    fn resolver_test() {
+        let request = Request::find_with_resolver(
+            "../requests/resolver/get_secret.kuiper",
+            &Context::new(),
+            Vault,
+        )
+        .unwrap();
+        assert_eq!(request.headers()["X-Api-Key"].as_deref(), Some("s3cr3t"));
+        assert_eq!(request.headers()["X-Region"].as_deref(), Some("eu-north-1"));
+        // values of custom namespaces may be secret
+        assert_eq!(
+            request.snapshot().redacted().collect::<Vec<_>>(),
+            vec!["vault:secret/api_key", "vault:secret/region|eu-north-1"]
+        );
 
-        assert_eq!(interpolated_request.params.len(), 3);
-        assert_eq!(interpolated_request.params["env_1"], "123");
-        // a new Uuid is generated every time the test is ran,
-        // so just assert that it is a Uuids
-        assert!(interpolated_request.params["expr_uuid"]
-            .parse::<Uuid>()
-            .is_ok());
-        assert!(interpolated_request.params["expr_now"]
-            .parse::<Timestamp>()
-            .is_ok());
+        let context = Context::new().with_resolver(Vault);
+        assert!(context.has_resolver("vault"));
+        assert!(matches!(
+            Request::interpolate_str("{{vault:secret/missing}}", &context),
+            Err(KuiperError::InterpolationError(InterpolationError::MissingValue(p))) if p == "vault:secret/missing"
+        ));
+        assert!(matches!(
+            Request::interpolate_str("{{vault:secret/broken}}", &context),
+            Err(KuiperError::InvalidExpr(_))
+        ));
+        // built-in namespaces can't be taken over
+        struct Env;
+        impl Resolver for Env {
+            fn namespace(&self) -> &str {
+                "env"
+            }
+            fn resolve(&self, _: &str) -> KuiperResult<Option<String>> {
+                Ok(Some("resolved".to_string()))
+            }
+        }
+        let context = Context::new()
+            .with_env(HashMap::from([(
+                "HOST".to_string(),
+                "example.com".to_string(),
+            )]))
+            .with_resolver(Env);
+        assert_eq!(
+            Request::interpolate_str("{{env:HOST}}", &context).unwrap(),
+            "example.com"
+        );
+        assert!(matches!(
+            Request::find_with_context("../requests/resolver/get_secret.kuiper", &Context::new()),
+            Err(KuiperError::InterpolationErrors(errors))
+                if errors.iter().all(|e| matches!(e, InterpolationError::InvalidFormat { .. }))
+        ));
+    }
 
-        assert_eq!(interpolated_request.uri, "http://localhost/route_value");
+    #[test]
+    fn interpolation_errors_test() {
+        let path = "../requests/errors/create_invoice.kuiper";
+        let Err(KuiperError::InterpolationErrors(errors)) = Request::find(path) else {
+            panic!("expected several interpolation errors");
+        };
+        // values missing in several places are reported once
+        assert_eq!(
+            errors,
+            vec![
+                InterpolationError::MissingEnvVar("KUIPER_ERRORS_HOST".to_string()),
+                InterpolationError::MissingEnvVar("KUIPER_ERRORS_TOKEN".to_string()),
+                InterpolationError::MissingVariable("customer".to_string()),
+                InterpolationError::MissingFile("lines.json".to_string()),
+            ]
+        );
+        assert_eq!(
+            KuiperError::InterpolationErrors(errors[..2].to_vec()).to_string(),
+            "2 interpolation errors: 'missing env var: 'KUIPER_ERRORS_HOST'', 'missing env var: 'KUIPER_ERRORS_TOKEN''"
+        );
 
-        let expected_headers: HashMap<String, Option<String>> = [
-            ("root_header_1", Some("root_value_1")),
-            ("root_header_2", Some("root_value_2")),
-            ("root_header_3", Some("root_value_3")),
-            ("interpolated_header", Some("1234")),
-        ]
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v.map(|v| v.to_string())))
-        .collect();
-        assert_hash_map_eq(&interpolated_request.headers, &expected_headers);
+        let context = Context::new()
+            .with_env(HashMap::from([
+                ("KUIPER_ERRORS_HOST".to_string(), "localhost".to_string()),
+                ("KUIPER_ERRORS_TOKEN".to_string(), "abc".to_string()),
+            ]))
+            .with_variables(HashMap::from([("customer".to_string(), "c-1".to_string())]));
+        assert!(matches!(
+            Request::find_with_context(path, &context),
+            Err(KuiperError::InterpolationError(InterpolationError::MissingFile(file)))
+                if file == "lines.json"
+        ));
     }
 
     #[test]
-    fn interpolation_error_test() {
-        let result = Request::interpolate_str("asd{{env:{{env:abc}}");
+    fn interpolation_default_test() {
+        let context = Context::new().with_env(HashMap::from([(
+            "KUIPER_DEFAULT_TEST_HOST".to_string(),
+            "example.com".to_string(),
+        )]));
+        assert_eq!(
+            Request::interpolate_str(
+                "http://{{env:KUIPER_DEFAULT_TEST_HOST|localhost}}:{{env:KUIPER_DEFAULT_TEST_PORT|8080}}",
+                &context
+            )
+            .unwrap(),
+            "http://example.com:8080"
+        );
+        assert_eq!(
+            Request::interpolate_str("{{capture:token|}}-{{var:region|eu-north-1}}", &context)
+                .unwrap(),
+            "-eu-north-1"
+        );
+        assert!(matches!(
+            Request::interpolate_str("{{expr:uuid|x}}", &context),
+            Err(KuiperError::InvalidExpr(expr)) if expr == "uuid|x"
+        ));
+
+        let request: Request = serde_json::from_str(
+            r#"{ "uri": "http://{{env:HOST|localhost}}/{{env:ROUTE}}", "method": "GET" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.references(),
+            vec![("env".to_string(), "ROUTE".to_string())]
+        );
+    }
+
+    #[test]
+    fn dependency_order_test() {
+        let order = Request::dependency_order("../requests/dependencies/settings.kuiper").unwrap();
+        let names: Vec<_> = order
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["login.kuiper", "profile.kuiper", "settings.kuiper"]
+        );
+
+        let result = Request::dependency_order("../requests/dependencies/cycle/a.kuiper");
         assert!(
-            matches!(&result, Err(KuiperError::InterpolationError(InterpolationError::MissingEnvVar(var))) if var == "{{env:abc"),
+            matches!(&result, Err(KuiperError::DependencyCycle(cycle)) if cycle.len() == 3),
             "{:?}",
             result
         );
+    }
+
+    #[test]
+    fn capture_test() {
+        let login = Request::find("../requests/dependencies/login.kuiper").unwrap();
+        let response =
+            |body: &str| Response::new(200, Vec::new(), body.to_string(), Duration::ZERO);
+        let captured = login
+            .capture(&response(r#"{"access_token": "abc", "user": {"id": 42}}"#))
+            .unwrap();
+        assert_eq!(captured["token"], "abc");
+        assert_eq!(captured["user_id"], "42");
 
-        let result = Request::interpolate_str("{{e{{nv:hello}}}}");
+        let result = login.capture(&response(r#"{"access_token": "abc"}"#));
         assert!(
-            matches!(
-                &result,
-                Err(KuiperError::InterpolationError(
-                    InterpolationError::InvalidFormat
-                ))
-            ),
+            matches!(&result, Err(KuiperError::CaptureNotFound(name)) if name == "user_id"),
             "{:?}",
             result
         );
+
+        let context = Context::new().with_captures(captured);
+        let profile =
+            Request::find_with_context("../requests/dependencies/profile.kuiper", &context)
+                .unwrap();
+        assert_eq!(profile.uri(), "http://localhost/api/user/42");
+        assert_eq!(
+            profile.headers()["Authorization"].as_deref(),
+            Some("Bearer abc")
+        );
+    }
+
+    #[test]
+    fn interpolation_body_test() {
+        let request = Request::find("../requests/json/update_settings.kuiper");
+        // the braces of the template aren't closed, and the error points at them
+        assert!(matches!(
+            request,
+            Err(KuiperError::InterpolationError(
+                InterpolationError::InvalidFormat { offset: 23, .. }
+            ))
+        ));
+
+        let mut request = serde_json::from_str::<Request>(
+            &fs::read_to_string("../requests/json/update_settings.kuiper").unwrap(),
+        )
+        .unwrap();
+        request.body.as_mut().unwrap()["template"] = "Dear }} {{var:region}}".into();
+        let context = Context::new()
+            .with_variables(HashMap::from([("region".to_string(), "eu".to_string())]));
+        request.interpolate_body(&context, &mut Vec::new()).unwrap();
+        assert_eq!(
+            request.body().unwrap(),
+            &serde_json::json!({
+                "regions": { "eu": { "limits": { "daily": { "max": "eu" } } } },
+                "template": "Dear }} eu",
+                "quote": "say \"eu\""
+            })
+        );
+    }
+
+    #[test]
+    fn yaml_request_test() {
+        let path = Path::new("../requests/yaml/create_note.kuiper.yaml");
+        assert!(Request::is_request_file(path));
+        assert!(Request::search_paths("../requests/yaml", "")
+            .unwrap()
+            .contains(&path.to_path_buf()));
+
+        let request = Request::find(path).unwrap();
+        assert_eq!(request.uri(), "http://localhost/v1/notes?draft=preview");
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.tags(), &["notes", "write"]);
+        assert_eq!(
+            request.body().unwrap(),
+            &serde_json::json!({
+                "title": "Release notes",
+                "text": "Fixed the login page.\nAdded preview mode.\n",
+                "labels": ["release", "preview"],
+                "pinned": false
+            })
+        );
+
+        // unquoted numbers and booleans are strings in params, headers and forms
+        let request: Request = parse_request(
+            Path::new("a.kuiper.yaml"),
+            "uri: x\nmethod: GET\nparams:\n  page: 2\n  ratio: 0.5\nheaders:\n  X-Debug: true\n",
+        )
+        .unwrap();
+        assert_eq!(request.params()["page"], "2");
+        assert_eq!(request.params()["ratio"], "0.5");
+        assert_eq!(request.headers()["X-Debug"].as_deref(), Some("true"));
+
+        assert!(matches!(
+            parse_request::<Request>(Path::new("a.kuiper.yml"), "uri: x\nmethod: [GET"),
+            Err(KuiperError::InvalidYaml(2, _))
+        ));
+    }
+
+    #[test]
+    fn toml_request_test() {
+        let path = Path::new("../requests/toml/create_invoice.kuiper.toml");
+        assert!(Request::is_request_file(path));
+        assert!(Request::search_paths("../requests/toml", "")
+            .unwrap()
+            .contains(&path.to_path_buf()));
+
+        let request = Request::find(path).unwrap();
+        assert_eq!(request.uri(), "http://localhost/v1/invoices?currency=EUR");
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.tags(), &["invoices", "write"]);
+        assert_eq!(
+            request.body().unwrap(),
+            &serde_json::json!({
+                "customer": "ACME",
+                "due": "2026-11-01",
+                "note": "Thank you for your business.\nPayment is due in EUR.\n",
+                "lines": [
+                    { "item": "Widget", "quantity": 3, "price": 9.5 },
+                    { "item": "Gadget", "quantity": 1, "price": 24 }
+                ]
+            })
+        );
+
+        let request: Request = parse_request(
+            Path::new("a.kuiper.toml"),
+            "uri = 'x'\nmethod = 'GET'\n[params]\npage = 2\n[form]\nremember = false\n",
+        )
+        .unwrap();
+        assert_eq!(request.params()["page"], "2");
+        assert_eq!(request.form().unwrap()["remember"], "false");
+
+        assert!(matches!(
+            parse_request::<Request>(Path::new("a.kuiper.toml"), "uri = 'x'\nmethod = GET"),
+            Err(KuiperError::InvalidToml(2, _))
+        ));
+    }
+
+    #[test]
+    fn capture_json_test() {
+        let mut search = Request::find("../requests/dependencies/login.kuiper").unwrap();
+        search.captures = [
+            ("results", "$.results"),
+            ("count", "$.count"),
+            ("label", "$.label"),
+        ]
+        .into_iter()
+        .map(|(name, path)| (name.to_string(), path.to_string()))
+        .collect();
+        let response = Response::new(
+            200,
+            Vec::new(),
+            r#"{"results": [{"id": 1}, {"id": 2}], "count": 2, "label": "[draft]"}"#.to_string(),
+            Duration::ZERO,
+        );
+        let captured = search.capture_values(&response).unwrap();
+        assert_eq!(
+            search.capture(&response).unwrap()["results"],
+            r#"[{"id":1},{"id":2}]"#
+        );
+
+        let path = "../requests/json/create_order.kuiper";
+        let context = Context::new().with_captured_values(captured.clone());
+        let request = Request::find_with_context(path, &context).unwrap();
+        assert_eq!(
+            request.body().unwrap(),
+            &serde_json::json!({
+                "items": [{ "id": 1 }, { "id": 2 }],
+                "count": 2,
+                // strings stay strings, even if they look like JSON
+                "label": "[draft]",
+                "tags": ["priority", "gift"],
+                "note": "2 items for eu"
+            })
+        );
+        assert_eq!(
+            request.snapshot().values()["capture:results"].as_deref(),
+            Some(r#"[{"id":1},{"id":2}]"#)
+        );
+
+        // values that were replaced by strings are strings
+        let context = context.with_captures(HashMap::from([
+            ("results".to_string(), "none".to_string()),
+            ("count".to_string(), "2".to_string()),
+            ("label".to_string(), "x".to_string()),
+        ]));
+        let request = Request::find_with_context(path, &context).unwrap();
+        assert_eq!(request.body().unwrap()["items"], "none");
+        assert_eq!(request.body().unwrap()["count"], 2);
+
+        // and the session keeps their type
+        let dir = std::env::temp_dir().join(format!("kuiper-json-{}", Uuid::new_v4()));
+        let mut session = Session::load(dir.join("session.json")).unwrap();
+        session.extend_values(captured);
+        session.save().unwrap();
+        let session = Session::load(dir.join("session.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(session.get("count"), Some("2"));
+        assert_eq!(
+            session.values()["results"],
+            serde_json::json!([{ "id": 1 }, { "id": 2 }])
+        );
+        assert!(!session.values().contains_key("label"));
+        let request = Request::find_with_context(path, &Context::from(&session)).unwrap();
+        assert_eq!(request.body().unwrap()["count"], 2);
+    }
+
+    #[test]
+    fn capture_response_test() {
+        let mut request = Request::find("../requests/request_in_root.kuiper").unwrap();
+        request.captures = [
+            ("location", "header:location"),
+            ("status", "status"),
+            ("duration", "duration"),
+        ]
+        .into_iter()
+        .map(|(name, source)| (name.to_string(), source.to_string()))
+        .collect();
+        let response = Response::new(
+            201,
+            vec![("Location".to_string(), "/api/orders/7".to_string())],
+            String::new(),
+            Duration::from_millis(12),
+        );
+        let captured = request.capture(&response).unwrap();
+        assert_eq!(captured["location"], "/api/orders/7");
+        assert_eq!(captured["status"], "201");
+        assert_eq!(captured["duration"], "12");
+
+        request
+            .captures
+            .insert("missing".to_string(), "header:X-Missing".to_string());
+        assert!(matches!(
+            request.capture(&response),
+            Err(KuiperError::CaptureNotFound(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn idempotency_key_test() {
+        let mut get = Request::find("../requests/request_in_root.kuiper").unwrap();
+        assert!(get.is_idempotent());
+
+        let mut login = Request::find("../requests/dependencies/login.kuiper").unwrap();
+        assert!(!login.is_idempotent());
+        assert!(login.ensure_idempotency_key());
+        let key = login.headers()[IDEMPOTENCY_KEY_HEADER].clone();
+        assert!(key.is_some());
+        assert!(login.ensure_idempotency_key());
+        assert_eq!(login.headers()[IDEMPOTENCY_KEY_HEADER], key);
+
+        get.headers.insert("idempotency-key".to_string(), None);
+        assert!(!get.ensure_idempotency_key());
+    }
+
+    #[test]
+    fn jsonrpc_test() {
+        let request = Request::find("../requests/jsonrpc/get_balance.kuiper").unwrap();
+        let call = request.jsonrpc().unwrap();
+        assert_eq!(call.method(), "wallet.getBalance");
+        assert_eq!(
+            call.params(),
+            Some(&serde_json::json!({ "account": "acc-7", "confirmations": 6 }))
+        );
+        let id = call.id().and_then(Value::as_str).unwrap();
+        assert!(Uuid::parse_str(id).is_ok(), "{id}");
+
+        let uninterpolated =
+            Request::find_uninterpolated("../requests/jsonrpc/get_balance.kuiper").unwrap();
+        assert!(uninterpolated.jsonrpc().unwrap().id().is_none());
+        assert_eq!(
+            uninterpolated.unresolved_placeholders(),
+            vec!["var:account", "var:confirmations"]
+        );
+    }
+
+    #[test]
+    fn failure_test() {
+        let builder = reqwest::blocking::Client::new()
+            .get("not a url")
+            .build()
+            .unwrap_err();
+        assert_eq!(KuiperError::HttpError(builder).failure(), Failure::Resolve);
+        assert_eq!(
+            KuiperError::InvalidSuite("broken".to_string()).failure(),
+            Failure::Resolve
+        );
+        assert_eq!(
+            KuiperError::MissingRequired(vec!["TOKEN".to_string()]).failure(),
+            Failure::Interpolation
+        );
+        assert_eq!(
+            KuiperError::DeadlineExceeded(Duration::from_secs(1)).failure(),
+            Failure::Network
+        );
+        assert_eq!(KuiperError::ResponseTooLarge(1).failure(), Failure::Http);
+        assert_eq!(
+            KuiperError::CaptureNotFound("id".to_string()).failure(),
+            Failure::Assertion
+        );
+        // the earliest class of failure is the most important
+        assert!(Failure::Resolve < Failure::Assertion);
     }
 }
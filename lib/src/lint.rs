@@ -0,0 +1,544 @@
+//! Checks of request files as they are being edited, which may not have been saved yet.
+
+use crate::{auth, Context, Headers, KuiperError, Request};
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+/// The namespaces that can be used in `{{NAMESPACE:NAME}}`.
+pub const NAMESPACES: &[&str] = &[
+    "env",
+    "expr",
+    "capture",
+    "data",
+    "secret",
+    "var",
+    "file",
+    "prompt",
+    "prompt_secret",
+    "cmd",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a request file, on a single line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    line: usize,
+    start: usize,
+    end: usize,
+    severity: Severity,
+    message: String,
+}
+
+impl Diagnostic {
+    fn new(line: usize, start: usize, end: usize, severity: Severity, message: String) -> Self {
+        Self {
+            line,
+            start,
+            end,
+            severity,
+            message,
+        }
+    }
+
+    /// The line of the problem, starting at 0.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The byte offset of the start of the problem in its line.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset of the end of the problem in its line.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {severity}: {}",
+            self.line + 1,
+            self.start + 1,
+            self.message
+        )
+    }
+}
+
+/// A `{{NAMESPACE:NAME}}` or `{{NAMESPACE:NAME|default}}` placeholder in a request file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placeholder {
+    line: usize,
+    start: usize,
+    end: usize,
+    namespace: String,
+    name: String,
+    default: Option<String>,
+}
+
+impl Placeholder {
+    /// The line of the placeholder, starting at 0.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The byte offset of the opening `{{` in its line.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset just past the closing `}}` in its line.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value used if the placeholder has no value of its own.
+    pub fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Describes the value the placeholder is replaced by in `context`. Secrets are not
+    /// revealed, only whether they are set.
+    pub fn describe(&self, context: &Context) -> String {
+        let placeholder = format!("{{{{{}:{}}}}}", self.namespace, self.name);
+        let value = match self.namespace.as_str() {
+            "env" => context
+                .env_var(&self.name)
+                .map(|value| format!("`{value}`")),
+            "capture" => context
+                .captures
+                .get(&self.name)
+                .map(|value| format!("`{value}`, captured in the session")),
+            "secret" => context
+                .has_secret(&self.name)
+                .then(|| "a secret, which is set".to_string()),
+            "data" => Some(format!("the '{}' column of the `--data` row", self.name)),
+            "var" => context
+                .variables
+                .get(&self.name)
+                .map(|value| format!("`{value}`")),
+            "file" => Some(format!("the contents of `{}`", self.name)),
+            "prompt" => Some("asked for when the request is sent".to_string()),
+            "cmd" => Some(format!(
+                "the output of `{}`, run when the request is sent if commands are allowed",
+                self.name
+            )),
+            "prompt_secret" => {
+                Some("asked for without showing the answer when the request is sent".to_string())
+            }
+            "expr" => match Request::interpolate_str(&placeholder, context) {
+                Ok(example) => Some(format!("generated when sent, e.g. `{example}`")),
+                Err(e) => Some(e.to_string()),
+            },
+            namespace if context.has_resolver(namespace) => {
+                Some(format!("resolved by the '{namespace}' resolver when sent"))
+            }
+            namespace => Some(format!("unknown namespace '{namespace}'")),
+        };
+        let value = match (value, &self.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => format!("not set, defaults to `{default}`"),
+            (None, None) => "not set".to_string(),
+        };
+        format!("{placeholder}: {value}")
+    }
+}
+
+/// Finds every `{{NAMESPACE:NAME}}` placeholder in `contents`.
+pub fn placeholders(contents: &str) -> Vec<Placeholder> {
+    let mut placeholders = Vec::new();
+    for (line_idx, line) in contents.lines().enumerate() {
+        // placeholders nested in other placeholders are found as well
+        for (start, inner) in crate::tokenizer::placeholders(line) {
+            let end = start + inner.len() + 4;
+            if let Some((namespace, name)) = inner.split_once(':') {
+                let (name, default) = match name.split_once('|') {
+                    Some((name, default)) if crate::takes_default(namespace) => {
+                        (name, Some(default))
+                    }
+                    _ => (name, None),
+                };
+                placeholders.push(Placeholder {
+                    line: line_idx,
+                    start,
+                    end,
+                    namespace: namespace.to_string(),
+                    name: name.to_string(),
+                    default: default.map(str::to_string),
+                });
+            }
+        }
+    }
+    placeholders
+}
+
+/// Returns the innermost placeholder at the byte offset `offset` of `line`, if there is one.
+pub fn placeholder_at(contents: &str, line: usize, offset: usize) -> Option<Placeholder> {
+    placeholders(contents)
+        .into_iter()
+        .rev()
+        .find(|p| p.line == line && (p.start..p.end).contains(&offset))
+}
+
+/// Checks `contents`, the possibly unsaved contents of the request file, `headers.json`,
+/// `variables.json` or `auth.json` at `path`, for invalid JSON, unknown placeholders, `env` values
+/// that are not set in `context`, `var` values that are not set in any `variables.json` above
+/// `path`, and `file` values, `depends_on`, `body_ndjson_file`, `body_file_binary` and `multipart`
+/// files that don't exist.
+pub fn lint(path: &Path, contents: &str, context: &Context) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let parsed = match path.file_name().and_then(|name| name.to_str()) {
+        Some("headers.json") => serde_json::from_str::<Headers>(contents)
+            .map(|_| None)
+            .map_err(KuiperError::from),
+        Some("auth.json") => serde_json::from_str::<auth::Auth>(contents)
+            .map(|_| None)
+            .map_err(KuiperError::from),
+        Some("variables.json") => serde_json::from_str::<HashMap<String, String>>(contents)
+            .map(|_| None)
+            .map_err(KuiperError::from),
+        _ => crate::parse_request::<Request>(path, contents).map(Some),
+    };
+    let request = match parsed {
+        Ok(request) => request,
+        Err(e) => {
+            // errors in YAML and TOML files only know their line, and the fields of those files
+            // neither
+            let (line, start, message) = match &e {
+                KuiperError::JsonError(e) => (e.line(), e.column(), e.to_string()),
+                KuiperError::InvalidYaml(line, _) | KuiperError::InvalidToml(line, _) => {
+                    (*line, 0, e.to_string())
+                }
+                _ => (0, 0, e.to_string()),
+            };
+            let (line, start) = (line.saturating_sub(1), start.saturating_sub(1));
+            diagnostics.push(Diagnostic::new(
+                line,
+                start,
+                start + 1,
+                Severity::Error,
+                message,
+            ));
+            None
+        }
+    };
+
+    let variables = Request::find_variables(path).unwrap_or_default();
+    for placeholder in placeholders(contents) {
+        let Placeholder {
+            line,
+            start,
+            end,
+            namespace,
+            name,
+            default,
+        } = &placeholder;
+        let problem = match namespace.as_str() {
+            _ if default.is_some() && NAMESPACES.contains(&namespace.as_str()) => None,
+            "env" if !context.has_env(name) => Some((
+                Severity::Warning,
+                format!("{{{{env:{name}}}}} is not set in the selected environment"),
+            )),
+            "var" if !context.has_variable(name) && !variables.contains_key(name) => Some((
+                Severity::Error,
+                format!("{{{{var:{name}}}}} is not set in any variables.json above this file"),
+            )),
+            "file" if !path.parent().unwrap_or(Path::new("")).join(name).is_file() => Some((
+                Severity::Error,
+                format!("{{{{file:{name}}}}} does not exist next to this file"),
+            )),
+            // the placeholders nested in it are checked on their own
+            "expr" if name.contains("{{") => None,
+            "expr" => Request::interpolate_str(&format!("{{{{expr:{name}}}}}"), context)
+                .err()
+                .map(|e| (Severity::Error, e.to_string())),
+            namespace if !NAMESPACES.contains(&namespace) && !context.has_resolver(namespace) => {
+                Some((
+                    Severity::Error,
+                    format!(
+                        "unknown namespace '{namespace}', expected one of {}",
+                        NAMESPACES.join(", ")
+                    ),
+                ))
+            }
+            _ => None,
+        };
+        if let Some((severity, message)) = problem {
+            diagnostics.push(Diagnostic::new(*line, *start, *end, severity, message));
+        }
+    }
+
+    if let (Some(request), Some(dir)) = (request, path.parent()) {
+        if request.graphql().is_some() && !request.method().eq_ignore_ascii_case("POST") {
+            let quoted = format!("\"{}\"", request.method());
+            let (line, start) = position_of(contents, &quoted).unwrap_or((0, 0));
+            diagnostics.push(Diagnostic::new(
+                line,
+                start,
+                start + quoted.len(),
+                Severity::Warning,
+                format!(
+                    "GraphQL queries are sent as a POST body, but the method is {}",
+                    request.method()
+                ),
+            ));
+        }
+        for dependency in &request.depends_on {
+            if !dir.join(dependency).is_file() {
+                let quoted = format!("\"{dependency}\"");
+                let (line, start) = position_of(contents, &quoted).unwrap_or((0, 0));
+                diagnostics.push(Diagnostic::new(
+                    line,
+                    start,
+                    start + quoted.len(),
+                    Severity::Error,
+                    format!("depends on '{dependency}', which does not exist"),
+                ));
+            } else if let Some(message) = Request::find_uninterpolated(dir.join(dependency))
+                .ok()
+                .and_then(|request| request.deprecated().map(str::to_string))
+            {
+                let quoted = format!("\"{dependency}\"");
+                let (line, start) = position_of(contents, &quoted).unwrap_or((0, 0));
+                diagnostics.push(Diagnostic::new(
+                    line,
+                    start,
+                    start + quoted.len(),
+                    Severity::Warning,
+                    format!("depends on '{dependency}', which is deprecated: {message}"),
+                ));
+            }
+        }
+        let body_files = [
+            ("body_ndjson_file", request.body_ndjson_file()),
+            ("body_file_binary", request.body_file_binary()),
+            (
+                "query_file",
+                request.graphql().and_then(|graphql| graphql.query_file()),
+            ),
+            (
+                "descriptor_set",
+                request.grpc().and_then(|grpc| grpc.descriptor_set()),
+            ),
+            ("proto", request.grpc().and_then(|grpc| grpc.proto())),
+        ];
+        for (field, file) in body_files {
+            let Some(file) = file.filter(|file| !dir.join(file).is_file()) else {
+                continue;
+            };
+            let quoted = format!("\"{}\"", file.display());
+            let (line, start) = position_of(contents, &quoted).unwrap_or((0, 0));
+            diagnostics.push(Diagnostic::new(
+                line,
+                start,
+                start + quoted.len(),
+                Severity::Error,
+                format!("{field} '{}' does not exist", file.display()),
+            ));
+        }
+        for (name, file) in request
+            .multipart()
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, part)| Some((name, part.file()?)))
+        {
+            if !dir.join(file).is_file() {
+                let quoted = format!("\"{}\"", file.display());
+                let (line, start) = position_of(contents, &quoted).unwrap_or((0, 0));
+                diagnostics.push(Diagnostic::new(
+                    line,
+                    start,
+                    start + quoted.len(),
+                    Severity::Error,
+                    format!(
+                        "the file of part '{name}', '{}', does not exist",
+                        file.display()
+                    ),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// The line and byte offset in that line of the first occurrence of `needle` in `contents`.
+fn position_of(contents: &str, needle: &str) -> Option<(usize, usize)> {
+    contents
+        .lines()
+        .enumerate()
+        .find_map(|(line, text)| text.find(needle).map(|start| (line, start)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use test_log::test;
+
+    #[test]
+    fn lint_test() {
+        let path = Path::new("../requests/dependencies/profile.kuiper");
+        let contents = r#"{
+    "uri": "http://localhost/{{env:KUIPER_LINT_HOST}}/{{expr:nope}}",
+    "method": "GET",
+    "headers": { "X-Id": "{{capture:id}}", "X-Other": "{{other:x}}" },
+    "depends_on": ["login.kuiper", "missing.kuiper"]
+}"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line(), d.start(), d.severity()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, 29, Severity::Warning),
+                (1, 54, Severity::Error),
+                (3, 55, Severity::Error),
+                (4, 35, Severity::Error),
+            ],
+            "{diagnostics:?}"
+        );
+
+        let context = Context::new().with_env(HashMap::from([(
+            "KUIPER_LINT_HOST".to_string(),
+            "api".to_string(),
+        )]));
+        assert_eq!(lint(path, contents, &context).len(), 3);
+
+        // namespaces of resolvers are known too
+        struct Other;
+        impl crate::Resolver for Other {
+            fn namespace(&self) -> &str {
+                "other"
+            }
+            fn resolve(&self, _: &str) -> crate::KuiperResult<Option<String>> {
+                Ok(None)
+            }
+        }
+        let resolved = context.clone().with_resolver(Other);
+        assert_eq!(lint(path, contents, &resolved).len(), 2);
+        assert_eq!(
+            placeholder_at(contents, 3, 55).unwrap().describe(&resolved),
+            "{{other:x}}: resolved by the 'other' resolver when sent"
+        );
+
+        let diagnostics = lint(path, "{\n  \"uri\": \n}", &context);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 2);
+        assert!(lint(Path::new("headers.json"), r#"{"A": "b"}"#, &context).is_empty());
+
+        let path = Path::new("../requests/variables/nested/versioned.kuiper");
+        let contents = r#"{ "uri": "http://{{var:host}}/{{var:missing}}", "method": "GET" }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].start(), 30);
+        assert_eq!(lint(Path::new("variables.json"), "[]", &context).len(), 1);
+
+        // only the nested placeholder is not set
+        let contents = r#"{ "uri": "http://localhost/{{expr:base64({{env:KUIPER_LINT_USER}})}}", "method": "GET" }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].start(), 41);
+        assert_eq!(placeholder_at(contents, 0, 45).unwrap().namespace(), "env");
+
+        let path = Path::new("../requests/deprecated/create_user_v2.kuiper");
+        let contents = r#"{ "uri": "http://localhost", "method": "POST", "depends_on": ["create_user_v1.kuiper"] }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity(), Severity::Warning);
+        assert!(diagnostics[0]
+            .message()
+            .ends_with("deprecated: use data/create_user.kuiper instead"));
+
+        let contents = r#"{ "uri": "http://localhost", "method": "POST", "body_ndjson_file": "events.ndjson" }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].start(), 67);
+
+        let path = Path::new("../requests/multipart/upload_report.kuiper");
+        let contents = r#"{ "uri": "http://localhost", "method": "POST", "multipart": { "a": { "file": "report.csv" }, "b": { "file": "missing.csv" } } }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message(),
+            "the file of part 'b', 'missing.csv', does not exist"
+        );
+
+        let path = Path::new("../requests/graphql/get_user.kuiper");
+        let contents = r#"{ "uri": "http://localhost", "method": "GET", "graphql": { "query_file": "missing.graphql" } }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        let messages: Vec<_> = diagnostics.iter().map(Diagnostic::message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "GraphQL queries are sent as a POST body, but the method is GET",
+                "query_file 'missing.graphql' does not exist"
+            ]
+        );
+
+        let path = Path::new("../requests/grpc/get_order.kuiper");
+        let contents = r#"{ "uri": "http://localhost:50051", "method": "POST", "grpc": { "service": "shop.v1.Orders", "method": "GetOrder", "proto": "shop.proto" } }"#;
+        let diagnostics = lint(path, contents, &Context::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message(),
+            "proto 'shop.proto' does not exist"
+        );
+    }
+
+    #[test]
+    fn placeholder_at_test() {
+        let contents = "{\n  \"uri\": \"http://{{env:HOST}}/{{capture:id}}\"\n}";
+        let placeholder = placeholder_at(contents, 1, 20).unwrap();
+        assert_eq!(placeholder.namespace(), "env");
+        assert_eq!(placeholder.name(), "HOST");
+        assert_eq!(placeholder_at(contents, 1, 5), None);
+
+        let context =
+            Context::new().with_captures(HashMap::from([("id".to_string(), "42".to_string())]));
+        let placeholder = placeholder_at(contents, 1, 34).unwrap();
+        assert_eq!(
+            placeholder.describe(&context),
+            "{{capture:id}}: `42`, captured in the session"
+        );
+
+        let contents = r#"{ "uri": "http://{{env:KUIPER_LINT_UNSET|localhost}}" }"#;
+        let placeholder = placeholder_at(contents, 0, 20).unwrap();
+        assert_eq!(placeholder.name(), "KUIPER_LINT_UNSET");
+        assert_eq!(placeholder.default(), Some("localhost"));
+        assert_eq!(
+            placeholder.describe(&context),
+            "{{env:KUIPER_LINT_UNSET}}: not set, defaults to `localhost`"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Locks and atomic writes for the files that several runs of kuiper may use at once, like
+//! sessions, the history, the rate limit ledger and the audit log, so that parallel CI jobs and
+//! concurrent terminals don't corrupt them.
+
+use crate::KuiperResult;
+use log::trace;
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// An exclusive lock on the state at a path, shared with other processes, which is released when
+/// it is dropped.
+///
+/// The lock is taken on a `.lock` file next to the state rather than on the state itself, since
+/// the state is replaced rather than written in place.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    // unlocked when closed
+    _file: File,
+}
+
+impl FileLock {
+    /// Waits until no other thread or process holds the lock for `path`, and takes it.
+    pub(crate) fn acquire(path: &Path) -> KuiperResult<Self> {
+        let lock_path = sibling(path, ".lock");
+        if let Some(parent) = lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        file.lock()?;
+        trace!("locked '{lock_path:?}'");
+        Ok(Self { _file: file })
+    }
+}
+
+/// Writes `contents` to `path` by renaming a file with the complete contents over it, so that
+/// readers never see a partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> KuiperResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = sibling(path, &format!(".{}.tmp", Uuid::new_v4().simple()));
+    fs::write(&temporary, contents)?;
+    if let Err(e) = fs::rename(&temporary, path) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use test_log::test;
+
+    #[test]
+    fn lock_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-lock-{}", Uuid::new_v4()));
+        let path = dir.join("counter");
+        write_atomic(&path, "0").unwrap();
+
+        // without the lock, increments would be lost between reading and writing the counter
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10 {
+                        let _lock = FileLock::acquire(&path).unwrap();
+                        let count: u32 = fs::read_to_string(&path).unwrap().parse().unwrap();
+                        write_atomic(&path, (count + 1).to_string()).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(fs::read_to_string(&path).unwrap(), "80");
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["counter", "counter.lock"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
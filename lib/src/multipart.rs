@@ -0,0 +1,175 @@
+//! `multipart/form-data` bodies, whose files are streamed as the body is sent instead of being
+//! read into memory.
+
+use crate::{client::OCTET_STREAM, KuiperResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// A part of a `multipart` body, either a string or a file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Part {
+    Text(String),
+    File {
+        /// The path of the file, relative to the request file.
+        file: PathBuf,
+        /// The file name that is sent, which is the name of `file` by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        /// The content type of the part, `application/octet-stream` by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<String>,
+    },
+}
+
+impl Part {
+    /// The file of the part, if it is a file.
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Part::Text(_) => None,
+            Part::File { file, .. } => Some(file),
+        }
+    }
+
+    /// Resolves the path of a file part relative to `dir`.
+    pub(crate) fn resolve_path(&mut self, dir: &Path) {
+        if let Part::File { file, .. } = self {
+            *file = dir.join(&*file);
+        }
+    }
+}
+
+/// An encoded `multipart/form-data` body.
+pub(crate) struct Body {
+    /// The `Content-Type` of the body, with its boundary.
+    pub(crate) content_type: String,
+    /// The length of the body in bytes.
+    pub(crate) length: u64,
+    /// Reads the body, opening files as they are reached.
+    pub(crate) reader: Box<dyn Read + Send>,
+}
+
+/// Encodes `parts` as a `multipart/form-data` body. The strings come first and the files last,
+/// each in the order of their names, since some upload endpoints expect the file at the end.
+///
+/// Only the sizes of the files are read here, their contents are read by the returned reader.
+pub(crate) fn encode(parts: &BTreeMap<String, Part>) -> KuiperResult<Body> {
+    let boundary = format!("kuiper-{}", Uuid::new_v4().simple());
+    let mut pending = String::new();
+    let mut length = 0;
+    let mut reader: Box<dyn Read + Send> = Box::new(std::io::empty());
+
+    for (name, value) in parts {
+        if let Part::Text(value) = value {
+            pending.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{value}\r\n",
+                escape(name)
+            ));
+        }
+    }
+    for (name, part) in parts {
+        let Part::File {
+            file,
+            filename,
+            content_type,
+        } = part
+        else {
+            continue;
+        };
+        let filename = match filename {
+            Some(filename) => filename.clone(),
+            None => file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        pending.push_str(&format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: {}\r\n\r\n",
+            escape(name),
+            escape(&filename),
+            content_type.as_deref().unwrap_or(OCTET_STREAM)
+        ));
+        let opened = File::open(file)?;
+        length += pending.len() as u64 + opened.metadata()?.len();
+        reader = Box::new(reader.chain(Cursor::new(std::mem::take(&mut pending))));
+        reader = Box::new(reader.chain(opened));
+        pending.push_str("\r\n");
+    }
+    pending.push_str(&format!("--{boundary}--\r\n"));
+    length += pending.len() as u64;
+    reader = Box::new(reader.chain(Cursor::new(pending)));
+
+    Ok(Body {
+        content_type: format!("multipart/form-data; boundary={boundary}"),
+        length,
+        reader,
+    })
+}
+
+/// Escapes the quotes and line breaks of a name in a `Content-Disposition` header, the way
+/// browsers do.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn encode_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-multipart-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("report.csv"), "a,b\n1,2\n").unwrap();
+        let parts = BTreeMap::from([
+            (
+                "attachment".to_string(),
+                Part::File {
+                    file: dir.join("report.csv"),
+                    filename: None,
+                    content_type: Some("text/csv".to_string()),
+                },
+            ),
+            ("title".to_string(), Part::Text("Q\"3\"".to_string())),
+        ]);
+        let mut body = encode(&parts).unwrap();
+        let mut encoded = String::new();
+        body.reader.read_to_string(&mut encoded).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let boundary = body
+            .content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        assert_eq!(
+            encoded,
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nQ\"3\"\r\n\
+                 --{boundary}\r\nContent-Disposition: form-data; name=\"attachment\"; \
+                 filename=\"report.csv\"\r\nContent-Type: text/csv\r\n\r\na,b\n1,2\n\r\n\
+                 --{boundary}--\r\n"
+            )
+        );
+        assert_eq!(body.length, encoded.len() as u64);
+
+        let missing = BTreeMap::from([(
+            "attachment".to_string(),
+            Part::File {
+                file: dir.join("report.csv"),
+                filename: None,
+                content_type: None,
+            },
+        )]);
+        assert!(encode(&missing).is_err());
+    }
+}
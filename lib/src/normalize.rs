@@ -0,0 +1,150 @@
+//! Normalization of JSON bodies, so that bodies that only differ in formatting, key order, how
+//! numbers are written or in volatile values compare as equal.
+
+use crate::{jsonpath, KuiperResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+/// The value that masked values are replaced by.
+pub const MASK: &str = "<masked>";
+
+/// Values to mask or leave out of JSON bodies, on top of the canonicalization of every body.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Normalizer {
+    /// JSONPaths of values that are replaced by [`MASK`], such as ids and timestamps.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mask: Vec<String>,
+    /// JSONPaths of values that are left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ignore: Vec<String>,
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the value at the JSONPath `path` by [`MASK`].
+    pub fn with_mask(mut self, path: impl Into<String>) -> Self {
+        self.mask.push(path.into());
+        self
+    }
+
+    /// Leaves out the value at the JSONPath `path`.
+    pub fn with_ignore(mut self, path: impl Into<String>) -> Self {
+        self.ignore.push(path.into());
+        self
+    }
+
+    pub fn mask(&self) -> &[String] {
+        &self.mask
+    }
+
+    pub fn ignore(&self) -> &[String] {
+        &self.ignore
+    }
+
+    /// Masks and removes values in `value`, and then canonicalizes it.
+    ///
+    /// Returns an error if one of the JSONPaths is invalid. Paths that don't exist in `value` are
+    /// skipped.
+    pub fn normalize(&self, value: &mut Value) -> KuiperResult<()> {
+        for path in &self.mask {
+            if let Some(masked) = jsonpath::select_mut(value, path)? {
+                *masked = Value::String(MASK.to_string());
+            }
+        }
+        for path in &self.ignore {
+            jsonpath::remove(value, path)?;
+        }
+        canonicalize(value);
+        Ok(())
+    }
+
+    /// Normalizes `json` and pretty-prints it, returning an error if it is not valid JSON.
+    pub fn normalize_json(&self, json: &str) -> KuiperResult<String> {
+        self.pretty(serde_json::from_str(json)?)
+    }
+
+    /// Normalizes `body` and pretty-prints it, if it is JSON. Other bodies are returned as is.
+    pub fn normalize_body(&self, body: &str) -> KuiperResult<String> {
+        match serde_json::from_str(body) {
+            Ok(value) => self.pretty(value),
+            Err(_) => Ok(body.to_string()),
+        }
+    }
+
+    fn pretty(&self, mut value: Value) -> KuiperResult<String> {
+        self.normalize(&mut value)?;
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+}
+
+/// Sorts the keys of every object in `value`, and writes every number that is a whole number as
+/// an integer, so that `1.0`, `1e0` and `1` are all written as `1`.
+pub fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, value) in &mut entries {
+                canonicalize(value);
+            }
+            map.extend(entries);
+        }
+        Value::Array(values) => values.iter_mut().for_each(canonicalize),
+        Value::Number(number) => {
+            if let Some(float) = number.as_f64().filter(|_| number.is_f64()) {
+                if float.fract() == 0.0 && float.abs() < 2f64.powi(53) {
+                    *number = Number::from(float as i64);
+                }
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn normalize_test() {
+        let mut value: Value = serde_json::from_str(
+            r#"{"z": 1.0, "a": {"id": 42, "at": "2024-01-01", "n": [1e2, -0.0, 1.5]}}"#,
+        )
+        .unwrap();
+        Normalizer::new()
+            .with_mask("$.a.id")
+            .with_mask("$.missing")
+            .with_ignore("$.a.at")
+            .normalize(&mut value)
+            .unwrap();
+        assert_eq!(
+            value,
+            json!({ "a": { "id": MASK, "n": [100, 0, 1.5] }, "z": 1 })
+        );
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":{"id":"<masked>","n":[100,0,1.5]},"z":1}"#
+        );
+
+        assert!(Normalizer::new()
+            .with_mask("id")
+            .normalize(&mut value)
+            .is_err());
+        assert_eq!(
+            Normalizer::new().normalize_body("not json").unwrap(),
+            "not json"
+        );
+        assert!(Normalizer::new().normalize_json("not json").is_err());
+        assert_eq!(
+            Normalizer::new()
+                .normalize_body(r#"{"b":2,"a":1}"#)
+                .unwrap(),
+            "{\n  \"a\": 1,\n  \"b\": 2\n}"
+        );
+    }
+}
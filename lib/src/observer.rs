@@ -0,0 +1,91 @@
+//! Events reported while requests are sent, so that frontends and reporters can show live
+//! progress without polling.
+
+use std::{
+    fmt::{self, Debug},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// Something that happened while a request was being sent. Requests are identified by their
+/// name, the path of the request file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The addresses of `host` are being looked up.
+    ResolveStarted { host: String },
+    /// `request` is being sent to `uri`, which is another uri than its own when a redirect is
+    /// followed.
+    SendStarted {
+        request: String,
+        method: String,
+        uri: String,
+    },
+    /// `received` bytes of the response body to `request` have been read so far, out of `total`
+    /// if the response says how long its body is.
+    BytesReceived {
+        request: String,
+        received: u64,
+        total: Option<u64>,
+    },
+    /// `request` failed, and is sent again after `delay` as retry number `attempt`.
+    RetryScheduled {
+        request: String,
+        attempt: u32,
+        delay: Duration,
+        reason: String,
+    },
+    /// `request` got a response with `status` after `duration`, including redirects, or failed
+    /// if `status` is `None`.
+    Completed {
+        request: String,
+        status: Option<u16>,
+        duration: Duration,
+    },
+}
+
+/// Receives the [`Event`]s of a [`Client`](crate::Client), possibly from several threads at
+/// once.
+pub trait Observer: Send + Sync {
+    fn observe(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> Observer for F {
+    fn observe(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// The observers of a client, shared by its clones and its resolver.
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Arc<RwLock<Vec<Arc<dyn Observer>>>>);
+
+impl Observers {
+    pub(crate) fn add(&self, observer: Arc<dyn Observer>) {
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(observer);
+    }
+
+    pub(crate) fn notify(&self, event: &Event) {
+        for observer in self.0.read().unwrap_or_else(|e| e.into_inner()).iter() {
+            observer.observe(event);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field(
+                "count",
+                &self.0.read().unwrap_or_else(|e| e.into_inner()).len(),
+            )
+            .finish()
+    }
+}
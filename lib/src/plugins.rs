@@ -0,0 +1,430 @@
+//! Executable plugins, which add interpolation namespaces, auth schemes, response filters and
+//! reporters without changing kuiper.
+//!
+//! Plugins are the executables in the `plugins` directory of a collection, in any language.
+//! Every call runs the plugin with a JSON object on its stdin, and reads the JSON object it
+//! prints as the answer:
+//!
+//! - `{ "call": "describe" }` asks what the plugin adds, answered with something like
+//!   `{ "namespaces": ["vault"], "auth": ["hmac"], "filters": ["redact"], "reporters": ["teamcity"] }`.
+//! - `{ "call": "resolve", "namespace": "vault", "name": "db/password" }` asks for the value of
+//!   `{{vault:db/password}}`, answered with `{ "value": "..." }`, or a `null` value if it has none.
+//! - `{ "call": "authenticate", "scheme": "hmac", "params": { ... }, "request": { ... } }` asks for
+//!   the headers that authenticate a request, answered with `{ "headers": [["Name", "value"]] }`.
+//! - `{ "call": "filter", "filter": "redact", "response": { ... } }` asks for the body that a
+//!   response is printed and checked with, answered with `{ "body": "..." }`.
+//! - `{ "call": "report", "reporter": "teamcity", "results": { ... } }` asks for a report of the
+//!   results of a run, answered with `{ "output": "..." }`.
+//!
+//! A plugin fails a call by answering with `{ "error": "..." }`, or by exiting with a non-zero
+//! code.
+
+use crate::{KuiperError, KuiperResult, Request, Resolver, Response};
+use log::{debug, trace};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+/// The directory of a collection that plugins are discovered in.
+pub const PLUGINS_DIR: &str = "plugins";
+
+/// What a plugin adds, as it answered `describe`.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Interpolation namespaces, like `vault` for `{{vault:NAME}}`.
+    #[serde(default)]
+    namespaces: Vec<String>,
+    /// Auth schemes, used with `{ "type": "plugin", "scheme": "NAME" }`.
+    #[serde(default)]
+    auth: Vec<String>,
+    /// Response filters, used with `--filter NAME`.
+    #[serde(default)]
+    filters: Vec<String>,
+    /// Reporters, used with `--plugin-reporter NAME`.
+    #[serde(default)]
+    reporters: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn namespaces(&self) -> &[String] {
+        &self.namespaces
+    }
+
+    pub fn auth(&self) -> &[String] {
+        &self.auth
+    }
+
+    pub fn filters(&self) -> &[String] {
+        &self.filters
+    }
+
+    pub fn reporters(&self) -> &[String] {
+        &self.reporters
+    }
+}
+
+/// An executable plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plugin {
+    path: PathBuf,
+    capabilities: Capabilities,
+}
+
+/// The answer of a plugin, which either has the fields of `T` or an `error`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Answer<T> {
+    Failed { error: String },
+    Answered(T),
+}
+
+#[derive(Deserialize)]
+struct Resolved {
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authenticated {
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct Filtered {
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct Reported {
+    output: String,
+}
+
+impl Plugin {
+    /// Loads the plugin at `path`, asking it what it adds.
+    pub fn load(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        let mut plugin = Self {
+            path: path.into(),
+            capabilities: Capabilities::default(),
+        };
+        plugin.capabilities = plugin.call(json!({ "call": "describe" }))?;
+        trace!("loaded plugin at '{:?}'", plugin.path);
+        Ok(plugin)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the plugin, its file name without an extension.
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// The value of `{{namespace:name}}`, or `None` if it has none.
+    pub fn resolve(&self, namespace: &str, name: &str) -> KuiperResult<Option<String>> {
+        let resolved: Resolved = self.call(json!({
+            "call": "resolve",
+            "namespace": namespace,
+            "name": name,
+        }))?;
+        Ok(resolved.value)
+    }
+
+    /// The headers that authenticate `request` with the auth scheme `scheme`, given `params`.
+    pub fn authenticate(
+        &self,
+        scheme: &str,
+        params: &BTreeMap<String, String>,
+        request: &Request,
+    ) -> KuiperResult<Vec<(String, String)>> {
+        let authenticated: Authenticated = self.call(json!({
+            "call": "authenticate",
+            "scheme": scheme,
+            "params": params,
+            "request": request,
+        }))?;
+        Ok(authenticated.headers)
+    }
+
+    /// The body of `response` after the filter `filter`.
+    pub fn filter(&self, filter: &str, response: &Response) -> KuiperResult<String> {
+        let filtered: Filtered = self.call(json!({
+            "call": "filter",
+            "filter": filter,
+            "response": response,
+        }))?;
+        Ok(filtered.body)
+    }
+
+    /// What the reporter `reporter` makes of `results`, to be printed.
+    pub fn report(&self, reporter: &str, results: &Value) -> KuiperResult<String> {
+        let reported: Reported = self.call(json!({
+            "call": "report",
+            "reporter": reporter,
+            "results": results,
+        }))?;
+        Ok(reported.output)
+    }
+
+    /// Runs the plugin with `call` on its stdin, and parses what it prints.
+    fn call<T: DeserializeOwned>(&self, call: Value) -> KuiperResult<T> {
+        let failed = |reason: String| {
+            KuiperError::PluginFailed(format!("{}: {reason}", self.path.display()))
+        };
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| failed(format!("failed to run: {e}")))?;
+        let input = serde_json::to_vec(&call)?;
+        // a plugin that answers without reading its input is not an error
+        match child
+            .stdin
+            .take()
+            .expect("stdin should be piped")
+            .write_all(&input)
+        {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(failed(format!(
+                "{} failed: {}",
+                call["call"],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        match serde_json::from_slice(&output.stdout) {
+            Ok(Answer::Answered(answer)) => Ok(answer),
+            Ok(Answer::Failed { error }) => Err(failed(error)),
+            Err(e) => Err(failed(format!("invalid answer to {}: {e}", call["call"]))),
+        }
+    }
+}
+
+/// The plugins of a collection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plugins(Vec<Arc<Plugin>>);
+
+impl Plugins {
+    /// Loads the executables in the `plugins` directory in `root`, in the order of their names.
+    /// A collection without one has no plugins.
+    pub fn discover(root: impl AsRef<Path>) -> KuiperResult<Self> {
+        let dir = root.as_ref().join(PLUGINS_DIR);
+        if !dir.is_dir() {
+            return Ok(Self::default());
+        }
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if is_executable(&path) {
+                paths.push(path);
+            } else {
+                debug!("skipping {path:?} in {dir:?}, it is not executable");
+            }
+        }
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| Ok(Arc::new(Plugin::load(path)?)))
+            .collect::<KuiperResult<_>>()
+            .map(Self)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plugin> {
+        self.0.iter().map(|plugin| &**plugin)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The resolvers of the namespaces that the plugins add, to register with
+    /// [`Context::with_resolver`](crate::Context::with_resolver).
+    pub fn resolvers(&self) -> Vec<PluginResolver> {
+        self.0
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .capabilities
+                    .namespaces
+                    .iter()
+                    .map(|namespace| PluginResolver {
+                        plugin: plugin.clone(),
+                        namespace: namespace.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// The first plugin that adds the auth scheme `scheme`.
+    pub fn auth(&self, scheme: &str) -> KuiperResult<&Plugin> {
+        self.find("auth scheme", scheme, |capabilities| &capabilities.auth)
+    }
+
+    /// The first plugin that adds the response filter `filter`.
+    pub fn filter(&self, filter: &str) -> KuiperResult<&Plugin> {
+        self.find("filter", filter, |capabilities| &capabilities.filters)
+    }
+
+    /// The first plugin that adds the reporter `reporter`.
+    pub fn reporter(&self, reporter: &str) -> KuiperResult<&Plugin> {
+        self.find("reporter", reporter, |capabilities| &capabilities.reporters)
+    }
+
+    fn find(
+        &self,
+        kind: &str,
+        name: &str,
+        added: impl Fn(&Capabilities) -> &Vec<String>,
+    ) -> KuiperResult<&Plugin> {
+        self.iter()
+            .find(|plugin| added(&plugin.capabilities).iter().any(|n| n == name))
+            .ok_or_else(|| KuiperError::PluginFailed(format!("no plugin adds the {kind} '{name}'")))
+    }
+}
+
+/// Resolves the placeholders of a namespace that a plugin adds.
+#[derive(Clone, Debug)]
+pub struct PluginResolver {
+    plugin: Arc<Plugin>,
+    namespace: String,
+}
+
+impl Resolver for PluginResolver {
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn resolve(&self, name: &str) -> KuiperResult<Option<String>> {
+        self.plugin.resolve(&self.namespace, name)
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exe"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use std::{os::unix::fs::PermissionsExt, time::Duration};
+    use test_log::test;
+
+    /// A plugin that answers every call the same way, by matching on its input.
+    const PLUGIN: &str = r#"#!/bin/sh
+input=$(cat)
+case "$input" in
+    *'"call":"describe"'*)
+        echo '{ "namespaces": ["vault"], "auth": ["hmac"], "filters": ["redact"], "reporters": ["count"] }' ;;
+    *'"name":"missing"'*) echo '{ "value": null }' ;;
+    *'"name":"broken"'*) echo '{ "error": "sealed" }' ;;
+    *'"call":"resolve"'*) echo '{ "value": "s3cr3t" }' ;;
+    *'"key":"k1"'*) echo '{ "headers": [["X-Signature", "signed"]] }' ;;
+    *'"call":"authenticate"'*) echo '{ "error": "unknown key" }' ;;
+    *'"call":"filter"'*) echo '{ "body": "[redacted]" }' ;;
+    *'"call":"report"'*) echo '{ "output": "1 result" }' ;;
+    *) exit 1 ;;
+esac
+"#;
+
+    fn collection() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("kuiper-plugins-{}", uuid::Uuid::new_v4()));
+        let dir = root.join(PLUGINS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("acme");
+        fs::write(&path, PLUGIN).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("README.md"), "not a plugin").unwrap();
+        root
+    }
+
+    #[test]
+    fn discover_test() {
+        let root = collection();
+        let plugins = Plugins::discover(&root).unwrap();
+        assert_eq!(plugins.iter().count(), 1);
+        let plugin = plugins.iter().next().unwrap();
+        assert_eq!(plugin.name(), "acme");
+        assert_eq!(plugin.capabilities().namespaces(), ["vault"]);
+        assert_eq!(plugins.filter("redact").unwrap(), plugin);
+        assert!(matches!(
+            plugins.reporter("junit"),
+            Err(KuiperError::PluginFailed(_))
+        ));
+        assert!(Plugins::discover(root.join("elsewhere"))
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn calls_test() {
+        let root = collection();
+        let plugins = Plugins::discover(&root).unwrap();
+        let mut context = Context::new();
+        for resolver in plugins.resolvers() {
+            context = context.with_resolver(resolver);
+        }
+        assert!(context.has_resolver("vault"));
+        let resolver = &plugins.resolvers()[0];
+        assert_eq!(resolver.resolve("db").unwrap().as_deref(), Some("s3cr3t"));
+        assert_eq!(resolver.resolve("missing").unwrap(), None);
+        assert!(matches!(
+            resolver.resolve("broken"),
+            Err(KuiperError::PluginFailed(reason)) if reason.ends_with("sealed")
+        ));
+
+        let plugin = plugins.auth("hmac").unwrap();
+        let request: Request =
+            serde_json::from_str(r#"{ "uri": "http://localhost/", "method": "GET" }"#).unwrap();
+        let params = BTreeMap::from([("key".to_string(), "k1".to_string())]);
+        assert_eq!(
+            plugin.authenticate("hmac", &params, &request).unwrap(),
+            vec![("X-Signature".to_string(), "signed".to_string())]
+        );
+        let params = BTreeMap::from([("key".to_string(), "k2".to_string())]);
+        assert!(matches!(
+            plugin.authenticate("hmac", &params, &request),
+            Err(KuiperError::PluginFailed(reason)) if reason.ends_with("unknown key")
+        ));
+
+        let plugin = plugins.filter("redact").unwrap();
+        let response = Response::new(200, Vec::new(), "token=abc".to_string(), Duration::ZERO);
+        assert_eq!(plugin.filter("redact", &response).unwrap(), "[redacted]");
+        assert_eq!(
+            plugin.report("count", &json!({ "results": [] })).unwrap(),
+            "1 result"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
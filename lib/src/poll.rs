@@ -0,0 +1,312 @@
+//! Sending a request repeatedly until its response satisfies a condition, for APIs that start
+//! long-running jobs.
+
+use crate::{jsonpath, Attempt, KuiperError, KuiperResult, Response};
+use log::trace;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The `poll` block of a request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Poll {
+    until: Condition,
+    /// Time between the end of an attempt and the start of the next one.
+    #[serde(
+        default = "default_interval",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    interval: Duration,
+    /// Time after which polling gives up, measured from the start of the first attempt.
+    #[serde(
+        default = "default_timeout",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    timeout: Duration,
+}
+
+fn default_interval() -> Duration {
+    DEFAULT_INTERVAL
+}
+
+fn default_timeout() -> Duration {
+    DEFAULT_TIMEOUT
+}
+
+impl Poll {
+    /// Polls until `until` holds, every 2 seconds for at most a minute.
+    pub fn new(until: Condition) -> Self {
+        Self {
+            until,
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_until(mut self, until: Condition) -> Self {
+        self.until = until;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn until(&self) -> &Condition {
+        &self.until
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Calls `send` until the condition holds for the body of the response it returns, and
+    /// returns that response.
+    ///
+    /// `attempted` is called with the number of every attempt whose response doesn't satisfy the
+    /// condition, and stops polling early, returning the last response, if it returns `false`.
+    /// Returns [`KuiperError::PollTimedOut`] if the condition doesn't hold before the timeout.
+    pub fn run(
+        &self,
+        mut send: impl FnMut() -> KuiperResult<Response>,
+        mut attempted: impl FnMut(usize, &Response) -> bool,
+    ) -> KuiperResult<Response> {
+        let start = Instant::now();
+        let mut attempts = Vec::new();
+        for attempt in 1.. {
+            let response = send()?.with_earlier_attempts(attempts);
+            if self.until.holds(response.body()) {
+                trace!("'{}' holds after {attempt} attempts", self.until);
+                return Ok(response);
+            }
+            if !attempted(attempt, &response) {
+                return Ok(response);
+            }
+            attempts = response.attempts().to_vec();
+            attempts.push(Attempt::Poll {
+                status: response.status(),
+            });
+            if start.elapsed() + self.interval > self.timeout {
+                return Err(KuiperError::PollTimedOut(self.until.to_string()));
+            }
+            thread::sleep(self.interval);
+        }
+        unreachable!("attempts are unbounded")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Equals,
+    NotEquals,
+}
+
+/// A condition on a JSON body: `PATH == VALUE`, `PATH != VALUE`, or just `PATH` for a path that
+/// exists. Values are JSON, or strings in single quotes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    path: String,
+    check: Option<(Operator, Value)>,
+}
+
+impl Condition {
+    /// Returns `true` if the condition holds for `body`. Bodies that are not JSON never satisfy
+    /// a condition.
+    pub fn holds(&self, body: &str) -> bool {
+        let Ok(body) = serde_json::from_str::<Value>(body) else {
+            return false;
+        };
+        // the path was validated when the condition was parsed
+        let value = jsonpath::select(&body, &self.path).ok().flatten();
+        match (&self.check, value) {
+            (None, value) => value.is_some(),
+            (Some((Operator::Equals, expected)), value) => value == Some(expected),
+            (Some((Operator::NotEquals, expected)), value) => value != Some(expected),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = KuiperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || KuiperError::InvalidCondition(s.to_string());
+        let operator = [("==", Operator::Equals), ("!=", Operator::NotEquals)]
+            .into_iter()
+            .filter_map(|(token, operator)| s.find(token).map(|idx| (idx, operator)))
+            .min_by_key(|(idx, _)| *idx);
+        let (path, check) = match operator {
+            Some((idx, operator)) => {
+                let literal = s[idx + 2..].trim();
+                let value = match literal
+                    .strip_prefix('\'')
+                    .and_then(|literal| literal.strip_suffix('\''))
+                {
+                    Some(string) => Value::String(string.to_string()),
+                    None => serde_json::from_str(literal).map_err(|_| invalid())?,
+                };
+                (s[..idx].trim(), Some((operator, value)))
+            }
+            None => (s.trim(), None),
+        };
+        jsonpath::select(&Value::Null, path)?;
+        Ok(Self {
+            source: s.to_string(),
+            path: path.to_string(),
+            check,
+        })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parses a duration like `500ms`, `2s`, `5m` or `1h`.
+pub fn parse_duration(s: &str) -> KuiperResult<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| KuiperError::InvalidDuration(s.to_string()))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 60 * 60)),
+        _ => Err(KuiperError::InvalidDuration(s.to_string())),
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    parse_duration(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn serialize_duration<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{}ms", duration.as_millis()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn condition_test() {
+        let done: Condition = "$.status == 'done'".parse().unwrap();
+        assert!(done.holds(r#"{"status": "done"}"#));
+        assert!(!done.holds(r#"{"status": "running"}"#));
+        assert!(!done.holds("not json"));
+
+        let progress: Condition = "$.job.progress != 0".parse().unwrap();
+        assert!(progress.holds(r#"{"job": {"progress": 10}}"#));
+        assert!(!progress.holds(r#"{"job": {"progress": 0}}"#));
+
+        let exists: Condition = "$.result".parse().unwrap();
+        assert!(exists.holds(r#"{"result": null}"#));
+        assert!(!exists.holds("{}"));
+
+        assert!(matches!(
+            "status == 'done'".parse::<Condition>(),
+            Err(KuiperError::InvalidJsonPath(_))
+        ));
+        assert!(matches!(
+            "$.status == done".parse::<Condition>(),
+            Err(KuiperError::InvalidCondition(_))
+        ));
+    }
+
+    #[test]
+    fn poll_test() {
+        let poll: Poll = serde_json::from_str(
+            r#"{ "until": "$.status == 'done'", "interval": "1ms", "timeout": "1s" }"#,
+        )
+        .unwrap();
+        assert_eq!(poll.interval(), Duration::from_millis(1));
+        assert_eq!(poll.timeout(), Duration::from_secs(1));
+
+        let bodies = [r#"{"status": "running"}"#, r#"{"status": "done"}"#];
+        let mut sent = 0;
+        let mut attempts = Vec::new();
+        let response = poll
+            .run(
+                || {
+                    sent += 1;
+                    Ok(Response::new(
+                        200,
+                        Vec::new(),
+                        bodies[sent - 1].to_string(),
+                        Duration::ZERO,
+                    ))
+                },
+                |attempt, _| {
+                    attempts.push(attempt);
+                    true
+                },
+            )
+            .unwrap();
+        assert_eq!(response.body(), bodies[1]);
+        assert_eq!(attempts, vec![1]);
+        assert_eq!(response.attempts(), &[Attempt::Poll { status: 200 }]);
+
+        let poll = poll.with_timeout(Duration::from_millis(5));
+        let result = poll.run(
+            || {
+                Ok(Response::new(
+                    200,
+                    Vec::new(),
+                    bodies[0].to_string(),
+                    Duration::ZERO,
+                ))
+            },
+            |_, _| true,
+        );
+        assert!(matches!(result, Err(KuiperError::PollTimedOut(_))));
+
+        let defaults: Poll = serde_json::from_str(r#"{ "until": "$.id" }"#).unwrap();
+        assert_eq!(defaults, Poll::new("$.id".parse().unwrap()));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert!(parse_duration("2 days").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    hosts::HostDefaults,
+    lock::{self, FileLock},
+    KuiperResult,
+};
+use jiff::Timestamp;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+const WINDOW_MS: i64 = 60_000;
+
+/// When requests were recently sent to each host, kept in a file so that rate limits hold across
+/// runs.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Send times in milliseconds since the epoch, oldest first, by host.
+    hosts: BTreeMap<String, Vec<i64>>,
+}
+
+impl Ledger {
+    /// Loads the ledger at `path`, or an empty ledger if the file does not exist.
+    pub fn load(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        let path = path.into();
+        let mut ledger: Self = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+        ledger.path = path;
+        Ok(ledger)
+    }
+
+    pub fn save(&self) -> KuiperResult<()> {
+        lock::write_atomic(&self.path, serde_json::to_string(self)?)?;
+        trace!("saved rate limit ledger to '{:?}'", self.path);
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How long to wait at `now` before another request can be sent to `host` without sending
+    /// more than `per_minute` requests within a minute.
+    pub fn delay(&self, host: &str, per_minute: u32, now: Timestamp) -> Duration {
+        let now = now.as_millisecond();
+        let recent: Vec<_> = self
+            .hosts
+            .get(host)
+            .into_iter()
+            .flatten()
+            .filter(|sent| now - **sent < WINDOW_MS)
+            .collect();
+        let per_minute = per_minute.max(1) as usize;
+        if recent.len() < per_minute {
+            return Duration::ZERO;
+        }
+        // the send that has to leave the window before there is room for another one
+        let blocking = recent[recent.len() - per_minute];
+        Duration::from_millis((blocking + WINDOW_MS - now).max(0) as u64)
+    }
+
+    /// Records that a request was sent to `host` at `now`, and forgets sends that are too old to
+    /// matter.
+    pub fn record(&mut self, host: &str, now: Timestamp) {
+        let now = now.as_millisecond();
+        let sends = self.hosts.entry(host.to_string()).or_default();
+        sends.retain(|sent| now - *sent < WINDOW_MS);
+        sends.push(now);
+    }
+}
+
+/// Delays requests to hosts whose defaults set `requests_per_minute`, using a [`Ledger`] file
+/// shared by every run.
+#[derive(Debug)]
+pub struct RateLimiter {
+    ledger: PathBuf,
+    hosts: Vec<HostDefaults>,
+    /// Held while waiting, so that requests sent concurrently by one run wait for each other.
+    lock: Mutex<()>,
+}
+
+impl RateLimiter {
+    pub fn new(ledger: impl Into<PathBuf>, hosts: Vec<HostDefaults>) -> Self {
+        Self {
+            ledger: ledger.into(),
+            hosts,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Waits until a request can be sent to `uri` within its host's budget, records the send,
+    /// and returns how long it waited.
+    pub fn acquire(&self, uri: &str) -> KuiperResult<Duration> {
+        let Some(host) = reqwest::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        else {
+            return Ok(Duration::ZERO);
+        };
+        let Some(per_minute) = self
+            .hosts
+            .iter()
+            .filter(|defaults| defaults.matches(&host))
+            .find_map(HostDefaults::requests_per_minute)
+        else {
+            return Ok(Duration::ZERO);
+        };
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        // other runs wait as well, so that they don't send requests while this one is waiting
+        let _lock = FileLock::acquire(&self.ledger)?;
+        let mut ledger = Ledger::load(&self.ledger)?;
+        let delay = ledger.delay(&host, per_minute, Timestamp::now());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        ledger.record(&host, Timestamp::now());
+        ledger.save()?;
+        Ok(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn at(millis: i64) -> Timestamp {
+        Timestamp::from_millisecond(millis).unwrap()
+    }
+
+    #[test]
+    fn delay_test() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.delay("api", 2, at(0)), Duration::ZERO);
+        ledger.record("api", at(0));
+        assert_eq!(ledger.delay("api", 2, at(1_000)), Duration::ZERO);
+        ledger.record("api", at(1_000));
+        assert_eq!(ledger.delay("api", 2, at(2_000)), Duration::from_secs(58));
+        assert_eq!(ledger.delay("other", 2, at(2_000)), Duration::ZERO);
+        assert_eq!(ledger.delay("api", 2, at(60_000)), Duration::ZERO);
+
+        ledger.record("api", at(60_500));
+        assert_eq!(ledger.hosts["api"], vec![1_000, 60_500]);
+        assert_eq!(
+            ledger.delay("api", 2, at(60_500)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn acquire_test() {
+        let path = std::env::temp_dir()
+            .join(format!("kuiper-ratelimit-{}", uuid::Uuid::new_v4()))
+            .join("ledger.json");
+        let hosts: Vec<HostDefaults> = serde_json::from_str(
+            r#"[{ "pattern": "limited.example.com", "requests_per_minute": 60 }]"#,
+        )
+        .unwrap();
+        let limiter = RateLimiter::new(&path, hosts);
+
+        assert_eq!(
+            limiter.acquire("http://limited.example.com/a").unwrap(),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.acquire("http://other.example.com/a").unwrap(),
+            Duration::ZERO
+        );
+        let ledger = Ledger::load(&path).unwrap();
+        assert_eq!(ledger.hosts.len(), 1);
+        assert_eq!(ledger.hosts["limited.example.com"].len(), 1);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{KuiperError, KuiperResult};
+use log::trace;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Named remote repositories that requests can be run from, persisted to a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Remotes {
+    path: PathBuf,
+    /// Repository URLs keyed by remote name.
+    remotes: BTreeMap<String, String>,
+}
+
+impl Remotes {
+    /// Loads the remotes stored at `path`, or starts with none if the file does not exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        let path = path.into();
+        let remotes = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        trace!("loaded remotes at '{path:?}'");
+        Ok(Self { path, remotes })
+    }
+
+    /// Writes the remotes back to the file they were loaded from.
+    pub fn save(&self) -> KuiperResult<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.remotes)?)?;
+        trace!("saved remotes to '{:?}'", self.path);
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a remote, replacing the URL of an existing remote with the same name.
+    ///
+    /// Names may only contain ASCII letters, digits, `-` and `_`, so that they can be used as
+    /// directory names and told apart from paths in `name:path/to/request`.
+    pub fn add(&mut self, name: &str, url: &str) -> KuiperResult<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(KuiperError::InvalidRemote(name.to_string()));
+        }
+        self.remotes.insert(name.to_string(), url.to_string());
+        Ok(())
+    }
+
+    /// Removes a remote, returning its URL if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.remotes.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.remotes.get(name).map(String::as_str)
+    }
+
+    /// Iterates over the names and URLs of all remotes.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.remotes
+            .iter()
+            .map(|(name, url)| (name.as_str(), url.as_str()))
+    }
+
+    /// Splits `remote:path/to/request` into the name of a known remote and the path within it.
+    pub fn split<'a>(&self, path: &'a str) -> Option<(&'a str, &'a str)> {
+        path.split_once(':')
+            .filter(|(name, _)| self.remotes.contains_key(*name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn remotes_test() {
+        let path =
+            std::env::temp_dir().join(format!("kuiper-remotes-{}.json", uuid::Uuid::new_v4()));
+        let mut remotes = Remotes::load(&path).unwrap();
+        assert_eq!(remotes.iter().count(), 0);
+
+        remotes
+            .add("team", "git@github.com:org/api-requests.git")
+            .unwrap();
+        assert!(matches!(
+            remotes.add("not/valid", "url"),
+            Err(KuiperError::InvalidRemote(_))
+        ));
+        remotes.save().unwrap();
+
+        let mut loaded = Remotes::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, remotes);
+        assert_eq!(
+            loaded.split("team:users/create"),
+            Some(("team", "users/create"))
+        );
+        assert_eq!(loaded.split("other:users/create"), None);
+        assert_eq!(loaded.split("users/create.kuiper"), None);
+
+        assert_eq!(
+            loaded.remove("team").as_deref(),
+            Some("git@github.com:org/api-requests.git")
+        );
+        assert_eq!(loaded.get("team"), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! Interpolation namespaces registered by embedders of the library, like `{{vault:secret/path}}`.
+
+use crate::KuiperResult;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+/// Resolves the placeholders of a namespace of its own. Built-in namespaces, like `env`, can't be
+/// taken over by a resolver.
+///
+/// Values of custom namespaces may be secret, so they are never recorded in the history.
+pub trait Resolver: Send + Sync {
+    /// The namespace whose placeholders are resolved, like `vault` for `{{vault:NAME}}`.
+    fn namespace(&self) -> &str;
+
+    /// The value of `{{NAMESPACE:name}}`, or `None` if it has none, in which case the default of
+    /// the placeholder is used if it has one.
+    fn resolve(&self, name: &str) -> KuiperResult<Option<String>>;
+}
+
+/// The resolvers of a context, by namespace.
+#[derive(Clone, Default)]
+pub(crate) struct Resolvers(BTreeMap<String, Arc<dyn Resolver>>);
+
+impl Resolvers {
+    pub(crate) fn add(&mut self, resolver: Arc<dyn Resolver>) {
+        self.0.insert(resolver.namespace().to_string(), resolver);
+    }
+
+    pub(crate) fn get(&self, namespace: &str) -> Option<&dyn Resolver> {
+        self.0.get(namespace).map(|resolver| &**resolver)
+    }
+}
+
+impl Debug for Resolvers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
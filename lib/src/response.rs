@@ -0,0 +1,361 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, time::Duration};
+
+/// A response received after sending a [`Request`](crate::Request).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    headers: HeaderMap,
+    body: String,
+    /// The body as it was received, if it is not valid UTF-8 and `body` had to be decoded lossily.
+    #[serde(skip)]
+    raw_body: Option<Vec<u8>>,
+    duration: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timings: Option<Timings>,
+    /// What happened before this response was received, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attempts: Vec<Attempt>,
+}
+
+/// Something that happened on the way to a response, like a redirect that was followed or a
+/// failed attempt that was retried.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Attempt {
+    /// A response with `status` redirected the request from `from` to `to`.
+    Redirect {
+        status: u16,
+        from: String,
+        to: String,
+    },
+    /// An attempt failed because of `reason`, and was retried after `delay_ms` milliseconds.
+    Retry { reason: String, delay_ms: u64 },
+    /// A token was fetched from `token_url` before the request was sent.
+    AuthRefresh { token_url: String },
+    /// A response with `status` did not satisfy the `poll` condition of the request, which was
+    /// sent again.
+    Poll { status: u16 },
+}
+
+impl Display for Attempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attempt::Redirect { status, from, to } => {
+                write!(f, "redirected from '{from}' to '{to}' ({status})")
+            }
+            Attempt::Retry { reason, delay_ms } => {
+                write!(f, "retried after {delay_ms}ms: {reason}")
+            }
+            Attempt::AuthRefresh { token_url } => write!(f, "fetched a token from '{token_url}'"),
+            Attempt::Poll { status } => write!(f, "polled again after {status}"),
+        }
+    }
+}
+
+/// How long each phase of getting a response took. The phases follow each other, so they add
+/// up to the duration of the response.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timings {
+    /// Time spent resolving the host, or `None` if an open connection was reused.
+    dns: Option<Duration>,
+    /// Time spent connecting over TCP and, for `https`, on the TLS handshake, or `None` if an
+    /// open connection was reused. The HTTP client connects and shakes hands in one step, so
+    /// the two are measured together.
+    #[serde(default)]
+    connect: Option<Duration>,
+    /// Time from sending the request, over an open connection, until the response headers
+    /// arrived.
+    time_to_first_byte: Duration,
+    /// Time spent reading the response body.
+    download: Duration,
+}
+
+/// Response headers in the order they were received.
+///
+/// Names are compared case-insensitively, and a header that was sent several times keeps all of
+/// its values.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl Response {
+    pub fn new(
+        status: u16,
+        headers: impl Into<HeaderMap>,
+        body: String,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            status,
+            headers: headers.into(),
+            body,
+            raw_body: None,
+            duration,
+            timings: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Creates a response with a body that was received as `body`, which may not be UTF-8, like
+    /// an image or an archive.
+    pub fn from_bytes(
+        status: u16,
+        headers: impl Into<HeaderMap>,
+        body: Vec<u8>,
+        duration: Duration,
+    ) -> Self {
+        let (body, raw_body) = match String::from_utf8(body) {
+            Ok(body) => (body, None),
+            Err(e) => (
+                String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                Some(e.into_bytes()),
+            ),
+        };
+        Self {
+            raw_body,
+            ..Self::new(status, headers, body, duration)
+        }
+    }
+
+    pub fn with_timings(mut self, timings: Timings) -> Self {
+        self.timings = Some(timings);
+        self
+    }
+
+    /// Puts `attempts`, which happened before the attempts already recorded, first.
+    pub fn with_earlier_attempts(mut self, mut attempts: Vec<Attempt>) -> Self {
+        attempts.append(&mut self.attempts);
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the first value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// The value of the `Content-Type` header.
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+
+    /// The value of the `Content-Length` header, if it is a valid length.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.trim().parse().ok()
+    }
+
+    /// The value of the `Location` header.
+    pub fn location(&self) -> Option<&str> {
+        self.header("Location")
+    }
+
+    /// The body, decoded as UTF-8 with invalid sequences replaced. See [`Response::bytes`] for
+    /// the body of a binary response.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// The body as it was received.
+    pub fn bytes(&self) -> &[u8] {
+        self.raw_body.as_deref().unwrap_or(self.body.as_bytes())
+    }
+
+    /// Replaces the body, like a filter does.
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = body;
+        self.raw_body = None;
+        self
+    }
+
+    /// The time between sending the request and receiving the full response body.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The time each phase took, if the response was received by a [`Client`](crate::Client).
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// The redirects, retries, token fetches and poll attempts before this response, in order.
+    /// Empty if the first attempt got this response.
+    pub fn attempts(&self) -> &[Attempt] {
+        &self.attempts
+    }
+}
+
+impl Timings {
+    pub fn new(dns: Option<Duration>, time_to_first_byte: Duration, download: Duration) -> Self {
+        Self {
+            dns,
+            connect: None,
+            time_to_first_byte,
+            download,
+        }
+    }
+
+    pub fn with_connect(mut self, connect: Option<Duration>) -> Self {
+        self.connect = connect;
+        self
+    }
+
+    pub fn with_download(mut self, download: Duration) -> Self {
+        self.download = download;
+        self
+    }
+
+    pub fn dns(&self) -> Option<Duration> {
+        self.dns
+    }
+
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect
+    }
+
+    pub fn time_to_first_byte(&self) -> Duration {
+        self.time_to_first_byte
+    }
+
+    pub fn download(&self) -> Duration {
+        self.download
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(dns) = self.dns {
+            write!(f, "dns {:.2}ms, ", dns.as_secs_f64() * 1000.0)?;
+        }
+        if let Some(connect) = self.connect {
+            write!(
+                f,
+                "connect and TLS {:.2}ms, ",
+                connect.as_secs_f64() * 1000.0
+            )?;
+        }
+        write!(
+            f,
+            "first byte {:.2}ms, download {:.2}ms",
+            self.time_to_first_byte.as_secs_f64() * 1000.0,
+            self.download.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+impl HeaderMap {
+    /// Returns the first value of the header `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of the header `name`, in the order they were received.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Iterates over every name and value, with names as they were received.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl From<Vec<(String, String)>> for HeaderMap {
+    fn from(entries: Vec<(String, String)>) -> Self {
+        Self { entries }
+    }
+}
+
+impl FromIterator<(String, String)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn headers_test() {
+        let response = Response::new(
+            201,
+            vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("Content-Length".to_string(), "42".to_string()),
+                ("location".to_string(), "/users/1".to_string()),
+                ("Set-Cookie".to_string(), "a=1".to_string()),
+                ("set-cookie".to_string(), "b=2".to_string()),
+            ],
+            String::new(),
+            Duration::ZERO,
+        );
+
+        assert_eq!(response.content_type(), Some("application/json"));
+        assert_eq!(response.content_length(), Some(42));
+        assert_eq!(response.location(), Some("/users/1"));
+        assert_eq!(response.header("SET-COOKIE"), Some("a=1"));
+        assert_eq!(
+            response.headers().get_all("set-cookie").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert!(!response.headers().contains("etag"));
+        assert_eq!(response.headers().len(), 5);
+    }
+
+    #[test]
+    fn timings_test() {
+        let timings = Timings::new(None, Duration::from_millis(40), Duration::ZERO)
+            .with_download(Duration::from_micros(1500));
+        assert_eq!(timings.to_string(), "first byte 40.00ms, download 1.50ms");
+
+        let timings = Timings::new(
+            Some(Duration::from_millis(3)),
+            Duration::from_millis(40),
+            Duration::ZERO,
+        )
+        .with_connect(Some(Duration::from_millis(12)));
+        assert_eq!(
+            timings.to_string(),
+            "dns 3.00ms, connect and TLS 12.00ms, first byte 40.00ms, download 0.00ms"
+        );
+
+        let response = Response::new(200, HeaderMap::default(), String::new(), Duration::ZERO)
+            .with_timings(timings);
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+    }
+}
@@ -0,0 +1,305 @@
+//! Encrypted secrets files, whose values are available through `{{secret:NAME}}`.
+//!
+//! Files encrypted with [SOPS](https://github.com/getsops/sops) are decrypted with `sops`, which
+//! finds its keys the way it normally does, for example through `SOPS_AGE_KEY_FILE`. Other files
+//! are decrypted with [age](https://age-encryption.org), using the identity file in
+//! `KUIPER_AGE_IDENTITY`, or the identity stored in the OS keyring when it is unset. Either way,
+//! the decrypted file must be a JSON object.
+//!
+//! Secrets can also be fetched from AWS Secrets Manager with `aws`, and from GCP Secret Manager
+//! with `gcloud`, when built with the `aws-secrets` and `gcp-secrets` features. The CLIs find
+//! their credentials the way they normally do, so secrets never have to be written to disk.
+
+use crate::{keyring, KuiperError, KuiperResult};
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Environment variable with the path of the age identity used to decrypt secrets files.
+pub const AGE_IDENTITY_VAR: &str = "KUIPER_AGE_IDENTITY";
+
+/// Keyring account that the age identity is read from when `KUIPER_AGE_IDENTITY` is unset.
+pub const AGE_IDENTITY_ACCOUNT: &str = "age-identity";
+
+/// Decrypts the secrets file at `path`.
+pub fn load(path: impl AsRef<Path>) -> KuiperResult<HashMap<String, String>> {
+    let path = path.as_ref();
+    let contents = fs::read(path)?;
+    let plaintext = if is_sops(&contents) {
+        decrypt(
+            Command::new("sops")
+                .args(["--decrypt", "--output-type", "json"])
+                .arg(path),
+            None,
+        )?
+    } else if let Some(identity) = std::env::var_os(AGE_IDENTITY_VAR) {
+        decrypt(
+            Command::new("age")
+                .arg("--decrypt")
+                .arg("--identity")
+                .arg(identity)
+                .arg(path),
+            None,
+        )?
+    } else {
+        // age reads an identity of "-" from stdin, which keeps it off disk
+        let identity = keyring_identity()?;
+        decrypt(
+            Command::new("age")
+                .args(["--decrypt", "--identity", "-"])
+                .arg(path),
+            Some(&identity),
+        )?
+    };
+    trace!("decrypted secrets at '{path:?}'");
+    parse(&plaintext)
+}
+
+/// SOPS keeps encrypted files in their original format, with its metadata under a `sops` key.
+fn is_sops(contents: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(contents).is_ok_and(|value| value.get("sops").is_some())
+}
+
+/// The age identity stored in the keyring under [`AGE_IDENTITY_ACCOUNT`].
+fn keyring_identity() -> KuiperResult<String> {
+    let stored = keyring::get(AGE_IDENTITY_ACCOUNT).unwrap_or_else(|e| {
+        debug!("can't read the age identity from the keyring: {e}");
+        None
+    });
+    stored.ok_or_else(|| {
+        KuiperError::DecryptionFailed(format!(
+            "set {AGE_IDENTITY_VAR} to the path of an age identity file, or store the identity \
+             in the keyring under the account '{AGE_IDENTITY_ACCOUNT}'"
+        ))
+    })
+}
+
+fn decrypt(command: &mut Command, input: Option<&str>) -> KuiperResult<Vec<u8>> {
+    run(command, input, KuiperError::DecryptionFailed)
+}
+
+/// Runs `command` with `input` written to its stdin, and returns what it printed, or an error
+/// made by `error` if it failed.
+fn run(
+    command: &mut Command,
+    input: Option<&str>,
+    error: fn(String) -> KuiperError,
+) -> KuiperResult<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let failed = |e: std::io::Error| error(format!("failed to run {program}: {e}"));
+    let output = match input {
+        Some(input) => {
+            let mut child = command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(failed)?;
+            child
+                .stdin
+                .take()
+                .expect("stdin should be piped")
+                .write_all(input.as_bytes())
+                .map_err(failed)?;
+            child.wait_with_output().map_err(failed)?
+        }
+        None => command.output().map_err(failed)?,
+    };
+    if !output.status.success() {
+        return Err(error(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// A secret in a cloud secret manager, read from the `secrets` of an environment in
+/// `kuiper.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CloudSecret {
+    provider: Provider,
+    /// The name or ARN of the secret.
+    id: String,
+    /// The name that the value of the secret is available as, `id` by default. Secrets whose
+    /// value is a JSON object make each of its values available by its key instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The AWS region of the secret, if not the default region of the `aws` CLI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    /// The GCP project of the secret, if not the default project of the `gcloud` CLI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+}
+
+/// A cloud secret manager.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// AWS Secrets Manager, through the `aws` CLI.
+    Aws,
+    /// GCP Secret Manager, through the `gcloud` CLI.
+    Gcp,
+}
+
+impl CloudSecret {
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Fetches the value of the secret, and returns the values it makes available by name.
+    pub fn fetch(&self) -> KuiperResult<HashMap<String, String>> {
+        let value = match self.provider {
+            Provider::Aws => self.fetch_aws()?,
+            Provider::Gcp => self.fetch_gcp()?,
+        };
+        trace!("fetched secret '{}'", self.id);
+        Ok(self.values(value))
+    }
+
+    #[cfg(feature = "aws-secrets")]
+    fn fetch_aws(&self) -> KuiperResult<String> {
+        let mut command = Command::new("aws");
+        command.args([
+            "secretsmanager",
+            "get-secret-value",
+            "--secret-id",
+            &self.id,
+        ]);
+        if let Some(region) = &self.region {
+            command.args(["--region", region]);
+        }
+        command.args(["--query", "SecretString", "--output", "text"]);
+        let output = run(&mut command, None, KuiperError::SecretFetchFailed)?;
+        // text output ends with a newline that isn't part of the secret
+        let value = String::from_utf8_lossy(&output);
+        Ok(value.strip_suffix('\n').unwrap_or(&value).to_string())
+    }
+
+    #[cfg(not(feature = "aws-secrets"))]
+    fn fetch_aws(&self) -> KuiperResult<String> {
+        Err(unsupported("aws-secrets", &self.id))
+    }
+
+    #[cfg(feature = "gcp-secrets")]
+    fn fetch_gcp(&self) -> KuiperResult<String> {
+        let mut command = Command::new("gcloud");
+        command.args(["secrets", "versions", "access", "latest"]);
+        command.arg(format!("--secret={}", self.id));
+        if let Some(project) = &self.project {
+            command.arg(format!("--project={project}"));
+        }
+        let output = run(&mut command, None, KuiperError::SecretFetchFailed)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[cfg(not(feature = "gcp-secrets"))]
+    fn fetch_gcp(&self) -> KuiperResult<String> {
+        Err(unsupported("gcp-secrets", &self.id))
+    }
+
+    /// The values that `value`, the value of the secret, makes available by name.
+    fn values(&self, value: String) -> HashMap<String, String> {
+        match serde_json::from_str::<Value>(&value) {
+            Ok(Value::Object(_)) => parse(value.as_bytes()).unwrap_or_default(),
+            _ => HashMap::from([(self.name.clone().unwrap_or_else(|| self.id.clone()), value)]),
+        }
+    }
+}
+
+#[cfg(any(not(feature = "aws-secrets"), not(feature = "gcp-secrets")))]
+fn unsupported(feature: &str, id: &str) -> KuiperError {
+    KuiperError::SecretFetchFailed(format!(
+        "'{id}' can't be fetched, since kuiper was built without the `{feature}` feature"
+    ))
+}
+
+/// Fetches every secret in `secrets`, where values of later secrets take precedence.
+pub fn fetch(secrets: &[CloudSecret]) -> KuiperResult<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    for secret in secrets {
+        values.extend(secret.fetch()?);
+    }
+    Ok(values)
+}
+
+/// Parses decrypted secrets, a JSON object whose values are strings, or other values that are
+/// used as they are written.
+fn parse(plaintext: &[u8]) -> KuiperResult<HashMap<String, String>> {
+    let values: HashMap<String, Value> = serde_json::from_slice(plaintext)?;
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::String(s) => (name, s),
+            other => (name, other.to_string()),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn parse_test() {
+        let secrets = parse(br#"{ "api_key": "abc", "port": 8080 }"#).unwrap();
+        assert_eq!(secrets["api_key"], "abc");
+        assert_eq!(secrets["port"], "8080");
+        assert!(parse(b"not json").is_err());
+    }
+
+    #[test]
+    fn cloud_secret_test() {
+        let secrets: Vec<CloudSecret> = serde_json::from_str(
+            r#"[
+                { "provider": "aws", "id": "prod/api", "region": "eu-north-1" },
+                { "provider": "gcp", "id": "api-token", "project": "acme", "name": "TOKEN" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(secrets[0].provider(), Provider::Aws);
+        assert_eq!(secrets[1].id(), "api-token");
+
+        // JSON objects make each of their values available, other secrets a single value
+        assert_eq!(
+            secrets[0].values(r#"{ "db_user": "app", "db_port": 5432 }"#.to_string()),
+            HashMap::from([
+                ("db_user".to_string(), "app".to_string()),
+                ("db_port".to_string(), "5432".to_string())
+            ])
+        );
+        assert_eq!(
+            secrets[0].values("s3cr3t".to_string()),
+            HashMap::from([("prod/api".to_string(), "s3cr3t".to_string())])
+        );
+        assert_eq!(
+            secrets[1].values("[1, 2]".to_string()),
+            HashMap::from([("TOKEN".to_string(), "[1, 2]".to_string())])
+        );
+        assert!(
+            serde_json::from_str::<CloudSecret>(r#"{ "provider": "azure", "id": "x" }"#).is_err()
+        );
+    }
+
+    #[test]
+    fn is_sops_test() {
+        assert!(is_sops(
+            br#"{ "api_key": "ENC[AES256_GCM,data:...]", "sops": { "version": "3.8.1" } }"#
+        ));
+        assert!(!is_sops(br#"{ "api_key": "abc" }"#));
+        assert!(!is_sops(b"age-encryption.org/v1\n..."));
+    }
+}
@@ -0,0 +1,123 @@
+//! Seeded generation of `{{expr:...}}` values, so that the requests of a run can be reproduced.
+
+use jiff::Timestamp;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// The time that `{{expr:now}}` is frozen at, unless another time is given with
+/// [`Seed::with_now`]: `2000-01-01T00:00:00Z`.
+pub const DEFAULT_NOW_SECOND: i64 = 946_684_800;
+
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A seed for random values and a frozen clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seed {
+    seed: u64,
+    now: Timestamp,
+}
+
+impl Seed {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            now: Timestamp::from_second(DEFAULT_NOW_SECOND)
+                .expect("default frozen time should be a valid timestamp"),
+        }
+    }
+
+    /// Freezes the clock at `now` instead of at `2000-01-01T00:00:00Z`.
+    pub fn with_now(mut self, now: Timestamp) -> Self {
+        self.now = now;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    /// Derives a seed of its own for `key`, such as the path of a request relative to the
+    /// collection. Values generated for different keys don't depend on the order in which they
+    /// are generated, so requests interpolated concurrently still get the same values every run.
+    pub fn derive(&self, key: &str) -> Self {
+        // FNV-1a, which unlike the std hashers is stable across platforms and versions
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Self {
+            seed: mix(self.seed ^ hash),
+            now: self.now,
+        }
+    }
+}
+
+/// Generates the values of a single [`Seed`], shared by every clone of a
+/// [`Context`](crate::Context).
+#[derive(Debug)]
+pub(crate) struct Generator {
+    seed: Seed,
+    draws: AtomicU64,
+}
+
+impl Generator {
+    pub(crate) fn new(seed: Seed) -> Self {
+        Self {
+            seed,
+            draws: AtomicU64::new(0),
+        }
+    }
+
+    /// The next value of a SplitMix64 sequence.
+    pub(crate) fn next_u64(&self) -> u64 {
+        let draw = self.draws.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        mix(self.seed.seed.wrapping_add(draw.wrapping_mul(GAMMA)))
+    }
+
+    pub(crate) fn uuid(&self) -> Uuid {
+        let bytes = (u128::from(self.next_u64()) << 64 | u128::from(self.next_u64())).to_be_bytes();
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+
+    pub(crate) fn now(&self) -> Timestamp {
+        self.seed.now
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn generator_test() {
+        let seed = Seed::new(42);
+        let first = Generator::new(seed);
+        let second = Generator::new(seed);
+        let uuids: Vec<_> = (0..3).map(|_| first.uuid()).collect();
+        assert_eq!(uuids, (0..3).map(|_| second.uuid()).collect::<Vec<_>>());
+        assert_ne!(uuids[0], uuids[1]);
+        assert_eq!(uuids[0].get_version_num(), 4);
+
+        let other = Generator::new(seed.derive("users/create.kuiper"));
+        assert_ne!(other.uuid(), Generator::new(seed).uuid());
+        assert_eq!(
+            Generator::new(seed.derive("users/create.kuiper")).uuid(),
+            Generator::new(seed.derive("users/create.kuiper")).uuid()
+        );
+        assert_ne!(Generator::new(Seed::new(43)).uuid(), uuids[0]);
+
+        assert_eq!(first.now().to_string(), "2000-01-01T00:00:00Z");
+        let now: Timestamp = "2024-06-01T12:00:00Z".parse().unwrap();
+        assert_eq!(Generator::new(seed.with_now(now)).now(), now);
+    }
+}
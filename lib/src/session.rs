@@ -0,0 +1,429 @@
+use crate::{
+    cookies::{Cookie, CookieJar},
+    keyring,
+    lock::{self, FileLock},
+    value_text, KuiperResult, Request, Response,
+};
+use log::{trace, warn};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Values captured from responses, persisted between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Session {
+    path: PathBuf,
+    store: SessionStore,
+    captures: HashMap<String, String>,
+    /// The captures that are JSON values other than strings.
+    values: HashMap<String, Value>,
+    /// Headers kept from responses, by the host they were received from and their lowercase
+    /// names.
+    headers: BTreeMap<String, BTreeMap<String, String>>,
+    /// Cookies kept from responses.
+    cookies: CookieJar,
+    /// The captures set since the session was loaded, which are the only ones written when it is
+    /// saved, so that values captured by other runs in the meantime are kept.
+    changed: HashSet<String>,
+}
+
+/// The prefix of the names that headers are stored under, which is followed by the host and the
+/// name of the header, like `header:api.example.com:x-csrf-token`.
+const HEADER_PREFIX: &str = "header:";
+
+/// The prefix of the names that cookies are stored under, which is followed by their domain,
+/// path and name, like `cookie:api.example.com;/;session`.
+const COOKIE_PREFIX: &str = "cookie:";
+
+/// Where a [`Session`] is persisted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionStore {
+    /// A plaintext JSON file.
+    #[default]
+    File,
+    /// The OS keyring, in an entry named after the session file.
+    Keyring,
+}
+
+impl Session {
+    /// Loads the session stored at `path`, or starts an empty one if the file does not exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        let path = path.into();
+        let mut session = Self {
+            path,
+            store: SessionStore::File,
+            captures: HashMap::new(),
+            values: HashMap::new(),
+            headers: BTreeMap::new(),
+            cookies: CookieJar::default(),
+            changed: HashSet::new(),
+        };
+        session.set(read_file(&session.path)?);
+        trace!("loaded session at '{:?}'", session.path);
+        Ok(session)
+    }
+
+    /// Loads the session for `path` from the OS keyring, so that captured tokens are not stored
+    /// in plaintext.
+    ///
+    /// Falls back to the file at `path` if the keyring is unavailable. A session that is still
+    /// stored in that file is moved to the keyring the next time it is saved.
+    pub fn load_from_keyring(path: impl Into<PathBuf>) -> KuiperResult<Self> {
+        let path = path.into();
+        if !keyring::is_available() {
+            warn!("the OS keyring is unavailable, storing the session in '{path:?}' instead");
+            return Self::load(path);
+        }
+
+        let mut session = Self::load(&path)?;
+        if let Some(stored) = keyring::get(&session.keyring_account())? {
+            session.captures.clear();
+            session.values.clear();
+            session.headers.clear();
+            session.cookies = CookieJar::default();
+            session.set(serde_json::from_str(&stored)?);
+            trace!("loaded session for '{path:?}' from the keyring");
+        }
+        session.store = SessionStore::Keyring;
+        Ok(session)
+    }
+
+    /// Writes the captures set since the session was loaded back to where it was loaded from,
+    /// keeping the values that other runs have saved in the meantime.
+    pub fn save(&self) -> KuiperResult<()> {
+        let _lock = FileLock::acquire(&self.path)?;
+        match self.store {
+            SessionStore::File => {
+                let captures = self.merge(read_file(&self.path)?);
+                lock::write_atomic(&self.path, serde_json::to_string_pretty(&captures)?)?;
+                trace!("saved session to '{:?}'", self.path);
+            }
+            SessionStore::Keyring => {
+                let stored = match keyring::get(&self.keyring_account())? {
+                    Some(stored) => serde_json::from_str(&stored)?,
+                    None => read_file(&self.path)?,
+                };
+                keyring::set(
+                    &self.keyring_account(),
+                    &serde_json::to_string(&self.merge(stored))?,
+                )?;
+                self.remove_file()?;
+                trace!("saved session for '{:?}' to the keyring", self.path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every captured value, both from the session and from where it is persisted.
+    pub fn clear(&mut self) -> KuiperResult<()> {
+        let _lock = FileLock::acquire(&self.path)?;
+        self.captures.clear();
+        self.values.clear();
+        self.headers.clear();
+        self.cookies = CookieJar::default();
+        self.changed.clear();
+        if self.store == SessionStore::Keyring {
+            keyring::delete(&self.keyring_account())?;
+        }
+        self.remove_file()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn store(&self) -> SessionStore {
+        self.store
+    }
+
+    pub fn captures(&self) -> &HashMap<String, String> {
+        &self.captures
+    }
+
+    /// The captures that are JSON numbers, booleans, arrays, objects or null, which keep their
+    /// type when they fill a body.
+    pub fn values(&self) -> &HashMap<String, Value> {
+        &self.values
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.captures.get(name).map(String::as_str)
+    }
+
+    /// The headers kept from responses, by the host they were received from and their lowercase
+    /// names.
+    pub fn headers(&self) -> &BTreeMap<String, BTreeMap<String, String>> {
+        &self.headers
+    }
+
+    /// Keeps the headers in `names` that `response` to `request` has, so that they are sent with
+    /// later requests to the same host. Returns `true` if any header was kept.
+    pub fn keep_headers(
+        &mut self,
+        request: &Request,
+        names: &[String],
+        response: &Response,
+    ) -> bool {
+        let Some(host) = host(request) else {
+            return false;
+        };
+        let mut kept = false;
+        for name in names {
+            if let Some(value) = response.header(name) {
+                let name = name.to_ascii_lowercase();
+                self.changed.insert(format!("{HEADER_PREFIX}{host}:{name}"));
+                self.headers
+                    .entry(host.clone())
+                    .or_default()
+                    .insert(name, value.to_string());
+                kept = true;
+            }
+        }
+        kept
+    }
+
+    /// The cookies kept from responses.
+    pub fn cookies(&self) -> &CookieJar {
+        &self.cookies
+    }
+
+    /// Replaces the cookies kept from responses with `jar`, like that of a client that started
+    /// out with them. Returns `true` if any cookie was set or removed.
+    pub fn set_cookies(&mut self, jar: CookieJar) -> bool {
+        let changed: Vec<_> = jar
+            .keys()
+            .chain(self.cookies.keys())
+            .filter(|key| jar.get(key) != self.cookies.get(key))
+            .map(|key| format!("{COOKIE_PREFIX}{key}"))
+            .collect();
+        self.cookies = jar;
+        let set = !changed.is_empty();
+        self.changed.extend(changed);
+        set
+    }
+
+    /// Adds the headers kept from responses from the host of `request` to it, unless the request
+    /// sets or removes them itself.
+    pub fn attach_headers(&self, request: &mut Request) {
+        let Some(headers) = host(request).and_then(|host| self.headers.get(&host)) else {
+            return;
+        };
+        for (name, value) in headers {
+            request.set_session_header(name, value.clone());
+        }
+    }
+
+    pub fn extend(&mut self, captures: HashMap<String, String>) {
+        self.extend_values(
+            captures
+                .into_iter()
+                .map(|(name, value)| (name, Value::String(value)))
+                .collect(),
+        );
+    }
+
+    /// Like [`Session::extend`], keeping the type of values other than strings.
+    pub fn extend_values(&mut self, captures: HashMap<String, Value>) {
+        self.changed.extend(captures.keys().cloned());
+        self.set(captures);
+    }
+
+    fn set(&mut self, captures: HashMap<String, Value>) {
+        for (name, value) in captures {
+            // header names can't contain colons, but IPv6 hosts do
+            if let Some((host, header)) = name
+                .strip_prefix(HEADER_PREFIX)
+                .and_then(|rest| rest.rsplit_once(':'))
+            {
+                self.headers
+                    .entry(host.to_string())
+                    .or_default()
+                    .insert(header.to_string(), value_text(&value));
+                continue;
+            }
+            if name.starts_with(COOKIE_PREFIX) {
+                match serde_json::from_value::<Cookie>(value) {
+                    Ok(cookie) => self.cookies.insert(cookie),
+                    Err(e) => warn!("ignoring the stored cookie '{name}': '{e}'"),
+                }
+                continue;
+            }
+            self.captures.insert(name.clone(), value_text(&value));
+            match value {
+                Value::String(_) => self.values.remove(&name),
+                value => self.values.insert(name, value),
+            };
+        }
+    }
+
+    /// The capture `name` as it is stored, or `None` if it has been removed, like an expired
+    /// cookie.
+    fn stored(&self, name: &str) -> Option<Value> {
+        if let Some((host, header)) = name
+            .strip_prefix(HEADER_PREFIX)
+            .and_then(|rest| rest.rsplit_once(':'))
+        {
+            return Some(Value::String(self.headers[host][header].clone()));
+        }
+        if let Some(key) = name.strip_prefix(COOKIE_PREFIX) {
+            return self
+                .cookies
+                .get(key)
+                .and_then(|cookie| serde_json::to_value(cookie).ok());
+        }
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => Some(Value::String(self.captures[name].clone())),
+        }
+    }
+
+    /// Returns `true` if every value `request` captures is already present in the session.
+    ///
+    /// A request that does not capture anything is never satisfied, since there is no way to
+    /// tell whether it has been sent before.
+    pub fn satisfies(&self, request: &Request) -> bool {
+        !request.captures().is_empty()
+            && request
+                .captures()
+                .keys()
+                .all(|name| self.captures.contains_key(name))
+    }
+
+    /// `stored` with the captures that were set since the session was loaded.
+    fn merge(&self, mut stored: HashMap<String, Value>) -> HashMap<String, Value> {
+        for name in &self.changed {
+            match self.stored(name) {
+                Some(value) => stored.insert(name.clone(), value),
+                None => stored.remove(name),
+            };
+        }
+        stored
+    }
+
+    fn keyring_account(&self) -> String {
+        format!("session:{}", self.path.display())
+    }
+
+    fn remove_file(&self) -> KuiperResult<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The host that `request` is sent to, which headers are kept for.
+fn host(request: &Request) -> Option<String> {
+    reqwest::Url::parse(request.uri())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
+/// Reads the captures stored in the file at `path`, or none if it does not exist.
+fn read_file(path: &Path) -> KuiperResult<HashMap<String, Value>> {
+    match File::open(path) {
+        Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn concurrent_save_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-session-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("session.json");
+        let mut first = Session::load(&path).unwrap();
+        let mut second = Session::load(&path).unwrap();
+        first.extend(HashMap::from([("token".to_string(), "a".to_string())]));
+        second.extend(HashMap::from([("user_id".to_string(), "1".to_string())]));
+        first.save().unwrap();
+        second.save().unwrap();
+
+        // neither run overwrote what the other one captured
+        let session = Session::load(&path).unwrap();
+        assert_eq!(session.get("token"), Some("a"));
+        assert_eq!(session.get("user_id"), Some("1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headers_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-session-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("session.json");
+        let mut session = Session::load(&path).unwrap();
+        let response = Response::new(
+            200,
+            crate::HeaderMap::from_iter([
+                ("X-CSRF-Token".to_string(), "abc".to_string()),
+                ("X-Request-Id".to_string(), "1".to_string()),
+            ]),
+            String::new(),
+            std::time::Duration::ZERO,
+        );
+        let request = Request::find_uninterpolated("../requests/request_in_root.kuiper").unwrap();
+        let host = host(&request).unwrap();
+        let names = ["x-csrf-token".to_string(), "X-Session".to_string()];
+        assert!(session.keep_headers(&request, &names, &response));
+        assert!(!session.keep_headers(&request, &names[1..], &response));
+        session.save().unwrap();
+
+        // the headers are kept by host, apart from the captures
+        let session = Session::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(session.captures().is_empty());
+        assert_eq!(
+            session.headers()[&host],
+            BTreeMap::from([("x-csrf-token".to_string(), "abc".to_string())])
+        );
+
+        let mut attached = request.clone();
+        session.attach_headers(&mut attached);
+        assert_eq!(
+            attached.headers().get("x-csrf-token"),
+            Some(&Some("abc".to_string()))
+        );
+        let mut overridden = request.clone();
+        overridden.override_header("X-CSRF-Token", Some("mine".to_string()));
+        session.attach_headers(&mut overridden);
+        assert_eq!(
+            overridden.headers().get("X-CSRF-Token"),
+            Some(&Some("mine".to_string()))
+        );
+        assert!(!overridden.headers().contains_key("x-csrf-token"));
+    }
+
+    #[test]
+    fn cookies_test() {
+        let dir = std::env::temp_dir().join(format!("kuiper-session-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("session.json");
+        let url = reqwest::Url::parse("https://api.example.com/login").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, ["session=abc; Path=/", "theme=dark"]);
+        let mut session = Session::load(&path).unwrap();
+        assert!(session.set_cookies(jar.clone()));
+        assert!(!session.set_cookies(jar.clone()));
+        session.save().unwrap();
+
+        // cookies without an expiry are kept between runs too, apart from the captures
+        let mut session = Session::load(&path).unwrap();
+        assert!(session.captures().is_empty());
+        assert_eq!(session.cookies(), &jar);
+
+        jar.store(&url, ["session=; Path=/; Max-Age=0"]);
+        assert!(session.set_cookies(jar));
+        session.save().unwrap();
+        let session = Session::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = session.cookies().iter().map(Cookie::name).collect();
+        assert_eq!(names, vec!["theme"]);
+    }
+}
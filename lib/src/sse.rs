@@ -0,0 +1,162 @@
+//! Server-Sent Events, read from `text/event-stream` responses as they arrive.
+
+use std::fmt::Display;
+
+/// The content type of a stream of Server-Sent Events.
+pub const EVENT_STREAM: &str = "text/event-stream";
+
+/// Returns `true` if `content_type` is that of a stream of Server-Sent Events.
+pub fn is_event_stream(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case(EVENT_STREAM))
+}
+
+/// An event of a stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    /// The type of the event, which is `message` if the stream doesn't name one.
+    event: Option<String>,
+    /// The `data` lines of the event, joined by newlines.
+    data: String,
+    /// The id of the last event that set one, which reconnecting clients send back.
+    id: Option<String>,
+    /// How long the client should wait before reconnecting, in milliseconds.
+    retry: Option<u64>,
+}
+
+impl Event {
+    /// The type of the event, if the stream names one.
+    pub fn event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn retry(&self) -> Option<u64> {
+        self.retry
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.event {
+            Some(event) => write!(f, "[{event}] {}", self.data),
+            None => write!(f, "{}", self.data),
+        }
+    }
+}
+
+/// Puts events together from the lines of a stream, as they are read.
+#[derive(Debug, Default)]
+pub(crate) struct Parser {
+    event: Option<String>,
+    data: Option<String>,
+    /// The last id, which carries over to the following events.
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl Parser {
+    /// Reads a line of the stream, without its line ending, and returns the event it ends if it
+    /// is blank.
+    pub(crate) fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            let event = self.event.take();
+            let retry = self.retry.take();
+            // events without data are not dispatched
+            let data = self.data.take()?;
+            return Some(Event {
+                event,
+                data,
+                id: self.id.clone(),
+                retry,
+            });
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            "retry" => self.retry = value.parse().ok(),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// The events of a whole stream. An event that isn't followed by a blank line is left out,
+/// since the stream ended before it did.
+pub fn parse(body: &str) -> Vec<Event> {
+    let mut parser = Parser::default();
+    body.lines().filter_map(|line| parser.line(line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn parse_test() {
+        let body = ": keep-alive\n\
+                    data: first\n\
+                    \n\
+                    event: update\n\
+                    id: 7\n\
+                    retry: 3000\n\
+                    data: {\"a\": 1}\n\
+                    data:{\"b\": 2}\n\
+                    \n\
+                    event: ignored\n\
+                    \n\
+                    data\n\
+                    \n\
+                    data: unfinished\n";
+        assert_eq!(
+            parse(body),
+            vec![
+                Event {
+                    data: "first".to_string(),
+                    ..Event::default()
+                },
+                Event {
+                    event: Some("update".to_string()),
+                    data: "{\"a\": 1}\n{\"b\": 2}".to_string(),
+                    id: Some("7".to_string()),
+                    retry: Some(3000),
+                },
+                // the id carries over to the events after it
+                Event {
+                    id: Some("7".to_string()),
+                    ..Event::default()
+                },
+            ]
+        );
+        assert_eq!(
+            parse(body)[1].to_string(),
+            "[update] {\"a\": 1}\n{\"b\": 2}"
+        );
+        assert!(is_event_stream("text/event-stream; charset=utf-8"));
+        assert!(!is_event_stream("application/json"));
+    }
+}
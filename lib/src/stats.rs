@@ -0,0 +1,305 @@
+//! Statistics about a collection of requests, to find what is unused, undocumented or untested in
+//! large collections.
+
+use crate::{lint, HeaderOrigin, KuiperResult, Request};
+use log::warn;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A variable or header and the `variables.json` or `headers.json` it is set in.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Definition {
+    path: PathBuf,
+    name: String,
+}
+
+impl Definition {
+    /// The file the value is set in, relative to the root of the collection.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// What [`stats`] found in a collection. Paths are relative to the root of the collection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    requests: usize,
+    methods: BTreeMap<String, usize>,
+    hosts: BTreeMap<String, usize>,
+    tags: BTreeMap<String, usize>,
+    invalid: Vec<PathBuf>,
+    unused_variables: Vec<Definition>,
+    unoverridden_headers: Vec<Definition>,
+    bodies: Vec<(PathBuf, usize)>,
+    missing_descriptions: Vec<PathBuf>,
+    missing_expectations: Vec<PathBuf>,
+}
+
+impl Stats {
+    /// The number of requests that could be read.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// The number of requests by method, in upper case.
+    pub fn methods(&self) -> &BTreeMap<String, usize> {
+        &self.methods
+    }
+
+    /// The number of requests by the host of their uri, as written, so a uri that starts with a
+    /// placeholder is counted under that placeholder, like `{{var:base_url}}`.
+    pub fn hosts(&self) -> &BTreeMap<String, usize> {
+        &self.hosts
+    }
+
+    /// The number of requests by tag.
+    pub fn tags(&self) -> &BTreeMap<String, usize> {
+        &self.tags
+    }
+
+    /// The requests, `headers.json` and `variables.json` files that could not be read, and are
+    /// left out of the other statistics.
+    pub fn invalid(&self) -> &[PathBuf] {
+        &self.invalid
+    }
+
+    /// The variables that no request, `headers.json`, `auth.json` or other variable below their
+    /// `variables.json` refers to.
+    pub fn unused_variables(&self) -> &[Definition] {
+        &self.unused_variables
+    }
+
+    /// The headers of `headers.json` files that every request below them uses as they are, since
+    /// neither the request nor a closer `headers.json` sets them.
+    pub fn unoverridden_headers(&self) -> &[Definition] {
+        &self.unoverridden_headers
+    }
+
+    /// The requests with a body and the size of their body in bytes, as written, largest first.
+    pub fn bodies(&self) -> &[(PathBuf, usize)] {
+        &self.bodies
+    }
+
+    /// The requests without a `description`.
+    pub fn missing_descriptions(&self) -> &[PathBuf] {
+        &self.missing_descriptions
+    }
+
+    /// The requests without an `expect` block.
+    pub fn missing_expectations(&self) -> &[PathBuf] {
+        &self.missing_expectations
+    }
+}
+
+/// Collects statistics about the requests under `root`, as written, without interpolating them.
+pub fn stats(root: impl AsRef<Path>) -> KuiperResult<Stats> {
+    let root = root.as_ref().canonicalize()?;
+    let relative = |path: &Path| path.strip_prefix(&root).unwrap_or(path).to_path_buf();
+    let mut stats = Stats::default();
+
+    let mut requests = Vec::new();
+    for path in Request::search_paths(&root, "")? {
+        match Request::find_uninterpolated(&path) {
+            Ok(request) => requests.push((path, request)),
+            Err(e) => {
+                warn!("leaving {path:?} out of the statistics: '{e}'");
+                stats.invalid.push(relative(&path));
+            }
+        }
+    }
+
+    // the `var` placeholders of every file that can refer to variables
+    let mut references = Vec::new();
+    for path in requests.iter().map(|(path, _)| path.clone()).chain(files(
+        &root,
+        &["headers.json", "auth.json", "variables.json"],
+    )?) {
+        let contents = fs::read_to_string(&path)?;
+        let names: BTreeSet<_> = lint::placeholders(&contents)
+            .into_iter()
+            .filter(|placeholder| placeholder.namespace() == "var")
+            .map(|placeholder| placeholder.name().to_string())
+            .collect();
+        references.push((path, names));
+    }
+
+    for path in files(&root, &["variables.json"])? {
+        let Some(variables) = read_object(&path) else {
+            stats.invalid.push(relative(&path));
+            continue;
+        };
+        let dir = path.parent().unwrap_or(&root);
+        for name in variables.keys() {
+            let used = references
+                .iter()
+                .any(|(file, names)| file.starts_with(dir) && names.contains(name));
+            if !used {
+                stats.unused_variables.push(Definition {
+                    path: relative(&path),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    for path in files(&root, &["headers.json"])? {
+        let Some(headers) = read_object(&path) else {
+            stats.invalid.push(relative(&path));
+            continue;
+        };
+        let dir = path.parent().unwrap_or(&root);
+        let origin = HeaderOrigin::File(path.clone());
+        for name in headers.keys() {
+            let overridden = requests
+                .iter()
+                .filter(|(request_path, _)| request_path.starts_with(dir))
+                .any(|(_, request)| request.header_origin(name) != Some(&origin));
+            if !overridden {
+                stats.unoverridden_headers.push(Definition {
+                    path: relative(&path),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    for (path, request) in &requests {
+        stats.requests += 1;
+        *stats
+            .methods
+            .entry(request.method().to_uppercase())
+            .or_default() += 1;
+        *stats
+            .hosts
+            .entry(host(request.uri()).to_string())
+            .or_default() += 1;
+        for tag in request.tags() {
+            *stats.tags.entry(tag.clone()).or_default() += 1;
+        }
+        if let Some(body) = request.body() {
+            stats.bodies.push((relative(path), body.to_string().len()));
+        } else if let Some(body) = request.body_raw() {
+            stats.bodies.push((relative(path), body.len()));
+        }
+        if request.description().is_none_or(|d| d.trim().is_empty()) {
+            stats.missing_descriptions.push(relative(path));
+        }
+        if request.expect().is_none() {
+            stats.missing_expectations.push(relative(path));
+        }
+    }
+    stats
+        .bodies
+        .sort_by(|(a, a_len), (b, b_len)| b_len.cmp(a_len).then(a.cmp(b)));
+    stats.invalid.sort();
+
+    Ok(stats)
+}
+
+/// The files under `root` named like one of `names`, sorted.
+fn files(root: &Path, names: &[&str]) -> KuiperResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = VecDeque::from([root.to_path_buf()]);
+    while let Some(dir) = dirs.pop_front() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push_back(path);
+            } else if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| names.contains(&name))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The JSON object in `path`, or `None` if it isn't one.
+fn read_object(path: &Path) -> Option<Map<String, Value>> {
+    let contents = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => {
+            warn!("leaving {path:?} out of the statistics, it is not a JSON object");
+            None
+        }
+    }
+}
+
+/// The host of `uri`, with its port, or whatever comes before the path if it has no scheme.
+fn host(uri: &str) -> &str {
+    let rest = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    &rest[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn stats_test() {
+        let stats = stats("../requests/stats").unwrap();
+        assert_eq!(stats.requests(), 3);
+        assert_eq!(
+            stats.methods(),
+            &BTreeMap::from([("GET".to_string(), 2), ("POST".to_string(), 1)])
+        );
+        assert_eq!(
+            stats.hosts(),
+            &BTreeMap::from([
+                ("payments.local:8080".to_string(), 2),
+                ("{{var:ledger}}".to_string(), 1)
+            ])
+        );
+        assert_eq!(
+            stats.tags(),
+            &BTreeMap::from([("payments".to_string(), 2), ("read".to_string(), 1)])
+        );
+        assert!(stats.invalid().is_empty());
+
+        let names = |definitions: &[Definition]| {
+            definitions
+                .iter()
+                .map(|d| format!("{}:{}", d.path().display(), d.name()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(stats.unused_variables()),
+            vec!["variables.json:legacy_id"]
+        );
+        assert_eq!(
+            names(stats.unoverridden_headers()),
+            vec!["headers.json:Accept"]
+        );
+
+        assert_eq!(
+            stats.bodies(),
+            &[(PathBuf::from("refunds/create_refund.kuiper"), 36)]
+        );
+        assert_eq!(
+            stats.missing_descriptions(),
+            &[
+                PathBuf::from("ledger.kuiper"),
+                PathBuf::from("refunds/create_refund.kuiper")
+            ]
+        );
+        assert_eq!(
+            stats.missing_expectations(),
+            &[PathBuf::from("refunds/create_refund.kuiper")]
+        );
+    }
+}
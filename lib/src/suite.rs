@@ -0,0 +1,202 @@
+//! Named groups of requests, read from the `suites` section of `kuiper.toml`, which run together
+//! regardless of the directories the requests are in.
+
+use crate::{hosts, KuiperError, KuiperResult, Request};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// A named group of requests and the variables they run with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Suite {
+    /// The directory that patterns are relative to, which is the directory of the config file.
+    #[serde(skip)]
+    root: PathBuf,
+    /// Paths of requests, with or without their extension, in the order they run in. A `*`
+    /// matches any part of a file or directory name, and `**` any number of directories.
+    #[serde(default)]
+    requests: Vec<String>,
+    /// Requests with any of these tags run after the ones in `requests`, in path order.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Values of `{{var:NAME}}`, which take precedence over the `variables.json` files above the
+    /// requests.
+    #[serde(default)]
+    variables: HashMap<String, String>,
+}
+
+impl Suite {
+    /// The directory that the patterns of the suite are relative to.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.requests
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn variables(&self) -> &HashMap<String, String> {
+        &self.variables
+    }
+
+    pub(crate) fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// The requests of the suite, in the order they run in, each once.
+    ///
+    /// Returns an error if a pattern matches no request, since it is most likely misspelled or
+    /// refers to a request that was moved.
+    pub fn requests(&self) -> KuiperResult<Vec<PathBuf>> {
+        let all = Request::search_paths(&self.root, "")?;
+        let mut requests: Vec<PathBuf> = Vec::new();
+        for pattern in &self.requests {
+            let pattern: Vec<_> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+            let matched: Vec<_> = all
+                .iter()
+                .filter(|path| self.matches(&pattern, path))
+                .collect();
+            if matched.is_empty() {
+                return Err(KuiperError::InvalidSuite(format!(
+                    "'{}' matches no request",
+                    pattern.join("/")
+                )));
+            }
+            for path in matched {
+                if !requests.contains(path) {
+                    requests.push(path.clone());
+                }
+            }
+        }
+
+        if !self.tags.is_empty() {
+            for path in &all {
+                let tagged = match Request::find_uninterpolated(path) {
+                    Ok(request) => request.tags().iter().any(|tag| self.tags.contains(tag)),
+                    Err(e) => {
+                        warn!("leaving {path:?} out of the suite, its tags can't be read: '{e}'");
+                        false
+                    }
+                };
+                if tagged && !requests.contains(path) {
+                    requests.push(path.clone());
+                }
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Returns `true` if the request at `path` matches `pattern`, split at its slashes, with or
+    /// without its extension.
+    fn matches(&self, pattern: &[&str], path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut parts: Vec<String> = relative
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        if path_matches(pattern, &parts) {
+            return true;
+        }
+        match (parts.last_mut(), relative.file_stem()) {
+            (Some(last), Some(stem)) => {
+                *last = stem.to_string_lossy().into_owned();
+                path_matches(pattern, &parts)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns `true` if the components of a path match those of a pattern, where `**` matches any
+/// number of components.
+fn path_matches(pattern: &[&str], parts: &[String]) -> bool {
+    match (pattern.split_first(), parts.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            path_matches(rest, parts)
+                || parts
+                    .split_first()
+                    .is_some_and(|(_, parts)| path_matches(pattern, parts))
+        }
+        (Some((first, rest)), Some((part, parts))) => {
+            hosts::glob_matches(first, part) && path_matches(rest, parts)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use test_log::test;
+
+    #[test]
+    fn requests_test() {
+        let config = Config::load("../requests/suites/kuiper.toml").unwrap();
+        let requests = |name: &str| {
+            let suite = config.suite(name).unwrap();
+            suite.requests().map(|requests| {
+                requests
+                    .iter()
+                    .map(|path| {
+                        path.strip_prefix(suite.root())
+                            .unwrap()
+                            .display()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let smoke = config.suite("smoke").unwrap();
+        assert_eq!(
+            smoke.variables(),
+            &HashMap::from([("region".to_string(), "eu".to_string())])
+        );
+        // the listed requests run first and in order, then the tagged ones that aren't listed
+        assert_eq!(
+            requests("smoke").unwrap(),
+            vec![
+                "auth/login.kuiper",
+                "orders/get_order.kuiper",
+                "orders/list_orders.kuiper",
+                "health.kuiper"
+            ]
+        );
+        assert_eq!(
+            requests("orders").unwrap(),
+            vec![
+                "orders/archive/list_archived.kuiper",
+                "orders/get_order.kuiper",
+                "orders/list_orders.kuiper"
+            ]
+        );
+        assert!(matches!(
+            requests("broken"),
+            Err(KuiperError::InvalidSuite(reason)) if reason.contains("auth/logout.kuiper")
+        ));
+        assert!(config.suite("nightly").is_none());
+    }
+
+    #[test]
+    fn path_matches_test() {
+        let parts = |path: &str| path.split('/').map(str::to_string).collect::<Vec<_>>();
+        assert!(path_matches(&["**"], &parts("a/b/c.kuiper")));
+        assert!(path_matches(&["a", "**", "c.kuiper"], &parts("a/c.kuiper")));
+        assert!(path_matches(
+            &["a", "**", "*.kuiper"],
+            &parts("a/b/c.kuiper")
+        ));
+        assert!(!path_matches(&["*.kuiper"], &parts("a/c.kuiper")));
+        assert!(!path_matches(&["a", "b"], &parts("a")));
+    }
+}
@@ -0,0 +1,179 @@
+//! Templates that generate a set of related request files, with `{{resource}}` in their paths
+//! and contents replaced by the name of a resource.
+
+use crate::{KuiperError, KuiperResult};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The placeholder replaced by the name of the resource.
+pub const RESOURCE_PLACEHOLDER: &str = "{{resource}}";
+
+const CRUD: &[(&str, &str)] = &[
+    (
+        "headers.json",
+        include_str!("../templates/crud/headers.json"),
+    ),
+    ("list.kuiper", include_str!("../templates/crud/list.kuiper")),
+    (
+        "create.kuiper",
+        include_str!("../templates/crud/create.kuiper"),
+    ),
+    ("get.kuiper", include_str!("../templates/crud/get.kuiper")),
+    (
+        "update.kuiper",
+        include_str!("../templates/crud/update.kuiper"),
+    ),
+    (
+        "delete.kuiper",
+        include_str!("../templates/crud/delete.kuiper"),
+    ),
+];
+
+/// The names of the templates that are built into kuiper.
+pub const BUILTIN: &[&str] = &["crud"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    /// File contents keyed by their path relative to the generated directory, with `/` as the
+    /// separator.
+    files: BTreeMap<String, String>,
+}
+
+impl Template {
+    /// Returns the built-in template called `name`.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let files = match name {
+            "crud" => CRUD,
+            _ => return None,
+        };
+        Some(Self {
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect(),
+        })
+    }
+
+    /// Loads a user-defined template from every file in `dir` and its subdirectories.
+    pub fn load(dir: impl AsRef<Path>) -> KuiperResult<Self> {
+        let root = dir.as_ref();
+        let mut files = BTreeMap::new();
+        let mut dirs = VecDeque::from([root.to_path_buf()]);
+        while let Some(dir) = dirs.pop_front() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push_back(path);
+                } else if let Ok(relative) = path.strip_prefix(root) {
+                    let key = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    files.insert(key, fs::read_to_string(&path)?);
+                }
+            }
+        }
+        if files.is_empty() {
+            return Err(KuiperError::InvalidTemplate(format!(
+                "{} contains no files",
+                root.display()
+            )));
+        }
+        Ok(Self { files })
+    }
+
+    /// The paths of the files the template generates, before `{{resource}}` is replaced.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// Writes the files of the template into `dir`, with `{{resource}}` replaced by `resource`,
+    /// and returns their paths. Nothing is written if any of the files already exists.
+    pub fn generate(&self, resource: &str, dir: impl AsRef<Path>) -> KuiperResult<Vec<PathBuf>> {
+        if resource.is_empty() || resource.contains(['/', '\\']) || resource.starts_with('.') {
+            return Err(KuiperError::InvalidTemplate(format!(
+                "'{resource}' is not a valid resource name"
+            )));
+        }
+
+        let dir = dir.as_ref();
+        let mut targets = Vec::with_capacity(self.files.len());
+        for (key, contents) in &self.files {
+            let key = key.replace(RESOURCE_PLACEHOLDER, resource);
+            let relative = Path::new(&key);
+            if !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Err(KuiperError::InvalidTemplate(format!(
+                    "path '{key}' is not inside the generated directory"
+                )));
+            }
+            let target = dir.join(relative);
+            if target.exists() {
+                return Err(KuiperError::InvalidTemplate(format!(
+                    "'{}' already exists",
+                    target.display()
+                )));
+            }
+            targets.push((target, contents.replace(RESOURCE_PLACEHOLDER, resource)));
+        }
+
+        let mut written = Vec::with_capacity(targets.len());
+        for (target, contents) in targets {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, contents)?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+    use test_log::test;
+
+    #[test]
+    fn crud_test() {
+        let template = Template::builtin("crud").unwrap();
+        assert!(Template::builtin("missing").is_none());
+
+        let dir = std::env::temp_dir().join(format!("kuiper-template-{}", uuid::Uuid::new_v4()));
+        let written = template.generate("orders", dir.join("orders")).unwrap();
+        assert_eq!(written.len(), 6);
+
+        let get = Request::find_uninterpolated(dir.join("orders/get.kuiper")).unwrap();
+        assert_eq!(get.uri(), "{{env:BASE_URL}}/orders/{{capture:orders_id}}");
+        assert_eq!(get.headers()["Accept"].as_deref(), Some("application/json"));
+        let order = Request::dependency_order(dir.join("orders/delete.kuiper")).unwrap();
+        assert!(order[0].ends_with("orders/create.kuiper"));
+
+        assert!(matches!(
+            template.generate("orders", dir.join("orders")),
+            Err(KuiperError::InvalidTemplate(_))
+        ));
+        assert!(template.generate("../orders", &dir).is_err());
+
+        let custom = dir.join("custom");
+        fs::create_dir_all(custom.join("nested")).unwrap();
+        fs::write(custom.join("nested/{{resource}}.kuiper"), "{{resource}}").unwrap();
+        let template = Template::load(&custom).unwrap();
+        assert_eq!(
+            template.files().collect::<Vec<_>>(),
+            vec!["nested/{{resource}}.kuiper"]
+        );
+        let written = template.generate("users", dir.join("users")).unwrap();
+        assert_eq!(fs::read_to_string(&written[0]).unwrap(), "users");
+        assert!(written[0].ends_with("users/nested/users.kuiper"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+//! The TLS library that connections are made with, and the versions of TLS it can be limited to.
+
+use crate::{client::TlsVersion, KuiperError, KuiperResult};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// A TLS library that connections can be made with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// The platform's TLS library: SChannel on Windows, Secure Transport on macOS and OpenSSL
+    /// elsewhere.
+    #[default]
+    #[serde(rename = "native-tls")]
+    NativeTls,
+    /// rustls, when built with the `rustls` feature.
+    #[serde(rename = "rustls")]
+    Rustls,
+}
+
+impl FromStr for TlsBackend {
+    type Err = KuiperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native-tls" => Ok(TlsBackend::NativeTls),
+            "rustls" => Ok(TlsBackend::Rustls),
+            _ => Err(KuiperError::UnsupportedTls(format!(
+                "unknown backend '{s}', expected native-tls or rustls"
+            ))),
+        }
+    }
+}
+
+impl Display for TlsBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TlsBackend::NativeTls => "native-tls",
+                TlsBackend::Rustls => "rustls",
+            }
+        )
+    }
+}
+
+/// The minimum and maximum versions that `backend` is limited to for a `min_tls_version` of
+/// `min` and a `max_tls_version` of `max`, leaving out the limits it always meets. Fails for
+/// limits it can't enforce.
+pub(crate) fn versions(
+    backend: TlsBackend,
+    min: Option<TlsVersion>,
+    max: Option<TlsVersion>,
+) -> KuiperResult<(Option<TlsVersion>, Option<TlsVersion>)> {
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(KuiperError::UnsupportedTls(format!(
+                "min_tls_version {min} is newer than max_tls_version {max}"
+            )));
+        }
+    }
+    // no version newer than 1.3 exists, so a maximum of 1.3 is no limit
+    let max = max.filter(|max| *max < TlsVersion::Tls1_3);
+    match backend {
+        TlsBackend::NativeTls => {
+            if min == Some(TlsVersion::Tls1_3) {
+                return Err(KuiperError::UnsupportedTls(
+                    "native-tls can't require TLS 1.3, use the rustls backend for a \
+                     min_tls_version of 1.3"
+                        .to_string(),
+                ));
+            }
+            Ok((min, max))
+        }
+        TlsBackend::Rustls => {
+            if let Some(max) = max.filter(|max| *max < TlsVersion::Tls1_2) {
+                return Err(KuiperError::UnsupportedTls(format!(
+                    "rustls only supports TLS 1.2 and 1.3, so max_tls_version can't be {max}"
+                )));
+            }
+            // and rustls never connects with a version older than 1.2
+            Ok((min.filter(|min| *min > TlsVersion::Tls1_2), max))
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub use with_rustls::Session;
+#[cfg(feature = "rustls")]
+pub(crate) use with_rustls::{install_provider, negotiate, system_roots};
+
+#[cfg(feature = "rustls")]
+mod with_rustls {
+    use super::versions;
+    use crate::{client::TlsVersion, ClientConfig, KuiperError, KuiperResult};
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{ring, CryptoProvider},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        ClientConnection, DigitallySignedStruct, ProtocolVersion, SignatureScheme,
+    };
+    use std::{
+        fmt::Display,
+        net::{TcpStream, ToSocketAddrs},
+        path::PathBuf,
+        sync::Arc,
+        time::Duration,
+    };
+
+    /// Where the CA certificates of the system are found, on Debian and Ubuntu, Fedora and RHEL,
+    /// openSUSE, and macOS and Alpine.
+    const SYSTEM_ROOTS: &[&str] = &[
+        "/etc/ssl/certs/ca-certificates.crt",
+        "/etc/pki/tls/certs/ca-bundle.crt",
+        "/etc/ssl/ca-bundle.pem",
+        "/etc/ssl/cert.pem",
+    ];
+
+    /// How long checking the session with a server waits for it, unless `connect_timeout_ms`
+    /// is set.
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Makes ring the crypto provider of rustls, unless one has been installed already.
+    pub(crate) fn install_provider() {
+        let _ = ring::default_provider().install_default();
+    }
+
+    /// The CA certificates that rustls trusts, read from `SSL_CERT_FILE` or the system's bundle.
+    pub(crate) fn system_roots() -> KuiperResult<Vec<reqwest::Certificate>> {
+        let path = std::env::var_os("SSL_CERT_FILE")
+            .map(PathBuf::from)
+            .or_else(|| {
+                SYSTEM_ROOTS
+                    .iter()
+                    .map(PathBuf::from)
+                    .find(|path| path.is_file())
+            })
+            .ok_or_else(|| {
+                KuiperError::UnsupportedTls(
+                    "no CA certificates found for rustls, set SSL_CERT_FILE to a PEM bundle"
+                        .to_string(),
+                )
+            })?;
+        let pem = std::fs::read(&path)
+            .map_err(|e| KuiperError::InvalidCertificate(format!("{}: {e}", path.display())))?;
+        reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| KuiperError::InvalidCertificate(format!("{}: {e}", path.display())))
+    }
+
+    /// The TLS version and cipher suite negotiated with a server.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Session {
+        version: TlsVersion,
+        /// The name of the cipher suite, like `TLS13_AES_256_GCM_SHA384`.
+        cipher: String,
+    }
+
+    impl Session {
+        pub fn version(&self) -> TlsVersion {
+            self.version
+        }
+
+        pub fn cipher(&self) -> &str {
+            &self.cipher
+        }
+    }
+
+    impl Display for Session {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TLS {} with {}", self.version, self.cipher)
+        }
+    }
+
+    /// Connects to the server of `url` with rustls and the versions `config` allows, and returns
+    /// the session negotiated with it. Nothing is sent once the handshake is done.
+    ///
+    /// The connection is made directly, without a proxy, and the certificate of the server is
+    /// not checked, since the requests to it check it.
+    pub(crate) fn negotiate(url: &reqwest::Url, config: &ClientConfig) -> KuiperResult<Session> {
+        let failed = |reason: String| KuiperError::TlsHandshakeFailed(reason);
+        let host = url
+            .host_str()
+            .ok_or_else(|| failed(format!("'{url}' has no host")))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| failed(format!("'{url}' has no port")))?;
+        let (min, max) = versions(
+            config.tls_backend(),
+            config.min_tls_version(),
+            config.max_tls_version(),
+        )?;
+        let protocols: Vec<_> = [
+            (TlsVersion::Tls1_2, &rustls::version::TLS12),
+            (TlsVersion::Tls1_3, &rustls::version::TLS13),
+        ]
+        .into_iter()
+        .filter(|(version, _)| min.is_none_or(|min| *version >= min))
+        .filter(|(version, _)| max.is_none_or(|max| *version <= max))
+        .map(|(_, protocol)| protocol)
+        .collect();
+
+        let provider = Arc::new(ring::default_provider());
+        let tls = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&protocols)
+            .map_err(|e| failed(e.to_string()))?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Unverified(provider)))
+            .with_no_client_auth();
+        let name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+            .map_err(|e| failed(e.to_string()))?;
+        let mut connection =
+            ClientConnection::new(Arc::new(tls), name).map_err(|e| failed(e.to_string()))?;
+
+        let timeout = config.connect_timeout().unwrap_or(HANDSHAKE_TIMEOUT);
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| failed(format!("'{host}' did not resolve to an address")))?;
+        let mut socket = TcpStream::connect_timeout(&addr, timeout)?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.set_write_timeout(Some(timeout))?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut socket)?;
+        }
+
+        let version = match connection.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => TlsVersion::Tls1_2,
+            Some(ProtocolVersion::TLSv1_3) => TlsVersion::Tls1_3,
+            version => return Err(failed(format!("unexpected version {version:?}"))),
+        };
+        let cipher = connection
+            .negotiated_cipher_suite()
+            .ok_or_else(|| failed("no cipher suite was negotiated".to_string()))?;
+        Ok(Session {
+            version,
+            cipher: format!("{:?}", cipher.suite()),
+        })
+    }
+
+    /// Accepts any certificate, while still checking the signatures of the handshake.
+    #[derive(Debug)]
+    struct Unverified(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for Unverified {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn versions_test() {
+        use TlsVersion::*;
+
+        assert_eq!(
+            versions(TlsBackend::NativeTls, Some(Tls1_2), Some(Tls1_3)).unwrap(),
+            (Some(Tls1_2), None)
+        );
+        assert!(matches!(
+            versions(TlsBackend::NativeTls, Some(Tls1_3), None),
+            Err(KuiperError::UnsupportedTls(_))
+        ));
+        assert_eq!(
+            versions(TlsBackend::Rustls, Some(Tls1_3), Some(Tls1_3)).unwrap(),
+            (Some(Tls1_3), None)
+        );
+        assert_eq!(
+            versions(TlsBackend::Rustls, Some(Tls1_0), None).unwrap(),
+            (None, None)
+        );
+        assert!(matches!(
+            versions(TlsBackend::Rustls, None, Some(Tls1_1)),
+            Err(KuiperError::UnsupportedTls(_))
+        ));
+        assert!(matches!(
+            versions(TlsBackend::NativeTls, Some(Tls1_2), Some(Tls1_1)),
+            Err(KuiperError::UnsupportedTls(_))
+        ));
+        assert_eq!("rustls".parse::<TlsBackend>().unwrap(), TlsBackend::Rustls);
+        assert!("openssl".parse::<TlsBackend>().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Splitting text that is interpolated into literal text, escaped braces and placeholders.
+
+use crate::InterpolationError;
+
+/// How many characters around the offset of a format error are shown on either side of it.
+const SNIPPET_CHARS: usize = 20;
+
+/// A piece of text that is interpolated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// Text that is used as it is.
+    Text(&'a str),
+    /// `{{{{` or `}}}}`, which stand for a literal `{{` or `}}`.
+    Escaped(&'static str),
+    /// A placeholder without its braces, which may contain other placeholders, like
+    /// `expr:base64({{env:USER}})`.
+    Placeholder(&'a str),
+}
+
+/// The tokens of `input` and the offsets they start at, or an error at the first `{{` that is
+/// never closed.
+pub(crate) fn tokenize(input: &str) -> Result<Vec<(usize, Token<'_>)>, InterpolationError> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut idx = 0;
+    while idx < input.len() {
+        let Some(token) = token_at(input, idx) else {
+            idx += 1;
+            continue;
+        };
+        let (token, len) = token?;
+        if text_start < idx {
+            tokens.push((text_start, Token::Text(&input[text_start..idx])));
+        }
+        tokens.push((idx, token));
+        idx += len;
+        text_start = idx;
+    }
+    if text_start < input.len() {
+        tokens.push((text_start, Token::Text(&input[text_start..])));
+    }
+    Ok(tokens)
+}
+
+/// Every placeholder in `input`, without its braces, and the offset of its `{{`, including the
+/// placeholders nested in other placeholders, which follow the placeholder they are nested in.
+/// Braces that are never closed are skipped, so that the placeholders after them are found while
+/// they are still being typed.
+pub(crate) fn placeholders(input: &str) -> Vec<(usize, &str)> {
+    let mut placeholders = Vec::new();
+    let mut idx = 0;
+    while idx < input.len() {
+        match token_at(input, idx) {
+            Some(Ok((Token::Placeholder(inner), len))) => {
+                placeholders.push((idx, inner));
+                placeholders.extend(
+                    self::placeholders(inner)
+                        .into_iter()
+                        .map(|(start, nested)| (idx + 2 + start, nested)),
+                );
+                idx += len;
+            }
+            Some(Ok((_, len))) => idx += len,
+            Some(Err(_)) => idx += 2,
+            None => idx += 1,
+        }
+    }
+    placeholders
+}
+
+/// An [`InterpolationError::InvalidFormat`] at `offset` of `input`.
+pub(crate) fn invalid_format(input: &str, offset: usize) -> InterpolationError {
+    let start = input[..offset]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CHARS - 1)
+        .map_or(0, |(idx, _)| idx);
+    let end = input[offset..]
+        .char_indices()
+        .nth(SNIPPET_CHARS)
+        .map_or(input.len(), |(idx, _)| offset + idx);
+    InterpolationError::InvalidFormat {
+        offset,
+        snippet: input[start..end].to_string(),
+    }
+}
+
+/// The token that starts at `idx` of `input` and its length, if an escape or placeholder starts
+/// there.
+fn token_at(input: &str, idx: usize) -> Option<Result<(Token<'_>, usize), InterpolationError>> {
+    let rest = &input.as_bytes()[idx..];
+    if rest.starts_with(b"{{{{") {
+        return Some(Ok((Token::Escaped("{{"), 4)));
+    }
+    if rest.starts_with(b"}}}}") {
+        return Some(Ok((Token::Escaped("}}"), 4)));
+    }
+    if !rest.starts_with(b"{{") {
+        return None;
+    }
+    Some(match closing_braces(rest) {
+        Some(end) => Ok((Token::Placeholder(&input[idx + 2..idx + end]), end + 2)),
+        None => Err(invalid_format(input, idx)),
+    })
+}
+
+/// The offset of the `}}` that closes the `{{` that `input` starts with, skipping the placeholders
+/// nested in it.
+fn closing_braces(input: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx + 1 < input.len() {
+        match &input[idx..idx + 2] {
+            b"{{" => {
+                depth += 1;
+                idx += 2;
+            }
+            b"}}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+                idx += 2;
+            }
+            _ => idx += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn tokenize_test() {
+        assert_eq!(
+            tokenize("a {{env:HOST}}/{{expr:base64({{env:USER}})}} {{{{b}}}}").unwrap(),
+            vec![
+                (0, Token::Text("a ")),
+                (2, Token::Placeholder("env:HOST")),
+                (14, Token::Text("/")),
+                (15, Token::Placeholder("expr:base64({{env:USER}})")),
+                (44, Token::Text(" ")),
+                (45, Token::Escaped("{{")),
+                (49, Token::Text("b")),
+                (50, Token::Escaped("}}")),
+            ]
+        );
+        assert_eq!(tokenize("").unwrap(), vec![]);
+        assert_eq!(tokenize("}} }").unwrap(), vec![(0, Token::Text("}} }"))]);
+
+        assert_eq!(
+            tokenize("http://localhost/users/{{env:ID/orders"),
+            Err(InterpolationError::InvalidFormat {
+                offset: 23,
+                snippet: "p://localhost/users/{{env:ID/orders".to_string()
+            })
+        );
+        let long = format!("{}{{{{env:ü", "é".repeat(30));
+        assert_eq!(
+            tokenize(&long),
+            Err(InterpolationError::InvalidFormat {
+                offset: 60,
+                snippet: format!("{}{{{{env:ü", "é".repeat(20))
+            })
+        );
+    }
+
+    #[test]
+    fn placeholders_test() {
+        assert_eq!(
+            placeholders("{{expr:base64({{env:USER}})}} {{ {{var:a}} {{{{x}}}}"),
+            vec![
+                (0, "expr:base64({{env:USER}})"),
+                (14, "env:USER"),
+                (33, "var:a"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Normalization of interpolated uris, since joining a base url and a path easily produces uris
+//! like `https://example.com//api/users`, which some gateways don't route.
+
+use serde::{Deserialize, Serialize};
+
+/// Whether the path of a uri ends with a slash.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailingSlash {
+    /// `/users` becomes `/users/`.
+    Add,
+    /// `/users/` becomes `/users`. The root path `/` is left alone.
+    Remove,
+}
+
+/// How uris are normalized after they are interpolated, read from the `normalize_uri` section of
+/// `kuiper.toml`. Nothing is changed by default.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UriNormalization {
+    /// Replaces repeated slashes in the path by a single slash.
+    #[serde(default)]
+    collapse_slashes: bool,
+    /// Percent-encodes the characters of the path and query that aren't allowed in uris, like
+    /// spaces, and `%` signs that don't start an escape.
+    #[serde(default)]
+    encode: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trailing_slash: Option<TrailingSlash>,
+}
+
+impl UriNormalization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_collapse_slashes(mut self, collapse: bool) -> Self {
+        self.collapse_slashes = collapse;
+        self
+    }
+
+    pub fn with_encode(mut self, encode: bool) -> Self {
+        self.encode = encode;
+        self
+    }
+
+    pub fn with_trailing_slash(mut self, trailing_slash: Option<TrailingSlash>) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Normalizes the path and query of `uri`. The scheme, host and fragment are left alone.
+    pub fn normalize(&self, uri: &str) -> String {
+        if *self == Self::default() {
+            return uri.to_string();
+        }
+        let (fragment_start, query_start, path_start) = split(uri);
+        let (origin, path) = (&uri[..path_start], &uri[path_start..query_start]);
+        let (query, fragment) = (&uri[query_start..fragment_start], &uri[fragment_start..]);
+
+        let mut path = path.to_string();
+        if self.collapse_slashes {
+            while path.contains("//") {
+                path = path.replace("//", "/");
+            }
+        }
+        match self.trailing_slash {
+            Some(TrailingSlash::Add) if !path.ends_with('/') => path.push('/'),
+            Some(TrailingSlash::Remove) => {
+                while path.len() > 1 && path.ends_with('/') {
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        if self.encode {
+            format!("{origin}{}{}{fragment}", encode(&path), encode(query))
+        } else {
+            format!("{origin}{path}{query}{fragment}")
+        }
+    }
+}
+
+/// The offsets of the fragment, query and path of `uri`, which are `uri.len()` if it has none.
+fn split(uri: &str) -> (usize, usize, usize) {
+    let fragment = uri.find('#').unwrap_or(uri.len());
+    let query = uri[..fragment].find('?').unwrap_or(fragment);
+    let authority = uri[..query].find("://").map_or(0, |idx| idx + 3);
+    let path = uri[authority..query]
+        .find('/')
+        .map_or(query, |idx| authority + idx);
+    (fragment, query, path)
+}
+
+/// Percent-encodes the characters of `input` that can't appear in the path or query of a uri.
+fn encode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut encoded = String::with_capacity(input.len());
+    for (idx, byte) in bytes.iter().enumerate() {
+        let escape = bytes
+            .get(idx + 1..idx + 3)
+            .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        match byte {
+            b'%' if escape => encoded.push('%'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(char::from(*byte)),
+            b'-' | b'.' | b'_' | b'~' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+'
+            | b',' | b';' | b'=' | b':' | b'@' | b'/' | b'?' => encoded.push(char::from(*byte)),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn normalize_test() {
+        let uri = "https://example.com//api//users/?q=a b#top";
+        assert_eq!(UriNormalization::new().normalize(uri), uri);
+
+        let collapse = UriNormalization::new().with_collapse_slashes(true);
+        assert_eq!(
+            collapse.normalize(uri),
+            "https://example.com/api/users/?q=a b#top"
+        );
+        assert_eq!(
+            collapse
+                .with_trailing_slash(Some(TrailingSlash::Remove))
+                .normalize(uri),
+            "https://example.com/api/users?q=a b#top"
+        );
+        assert_eq!(
+            collapse
+                .with_trailing_slash(Some(TrailingSlash::Add))
+                .normalize("http://localhost:8080?page=2"),
+            "http://localhost:8080/?page=2"
+        );
+        assert_eq!(
+            UriNormalization::new()
+                .with_trailing_slash(Some(TrailingSlash::Remove))
+                .normalize("http://localhost/"),
+            "http://localhost/"
+        );
+
+        let encode = UriNormalization::new().with_encode(true);
+        assert_eq!(
+            encode.normalize("https://example.com/files/my report%20v2/ü?name=50%&x=a|b"),
+            "https://example.com/files/my%20report%20v2/%C3%BC?name=50%25&x=a%7Cb"
+        );
+    }
+}
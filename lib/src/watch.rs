@@ -0,0 +1,80 @@
+//! Noticing changes to the files that long-running frontends, like `kuiper lsp`, read when they
+//! start, such as env files, so they can read them again instead of being restarted.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// When a file was last modified and how long it was, or `None` if it didn't exist.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// Files whose changes are noticed by polling [`Watch::changed`], such as before each request is
+/// sent.
+#[derive(Clone, Debug, Default)]
+pub struct Watch {
+    files: Vec<(PathBuf, Stamp)>,
+}
+
+impl Watch {
+    /// Watches `paths` from now on. Paths that don't exist yet are watched for being created.
+    pub fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.into();
+                let stamp = stamp(&path);
+                (path, stamp)
+            })
+            .collect();
+        Self { files }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// The files that were changed, created or removed since the previous call, or since the
+    /// watch was created.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, previous) in &mut self.files {
+            let current = stamp(path);
+            if current != *previous {
+                *previous = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn watch_test() {
+        let path = std::env::temp_dir().join(format!("kuiper-{}.env", uuid::Uuid::new_v4()));
+        let mut watch = Watch::new([&path]);
+        assert!(watch.changed().is_empty());
+
+        fs::write(&path, "HOST=localhost\n").unwrap();
+        assert_eq!(watch.changed(), vec![path.clone()]);
+        assert!(watch.changed().is_empty());
+
+        fs::write(&path, "HOST=dev.example.com\n").unwrap();
+        assert_eq!(watch.changed(), vec![path.clone()]);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(watch.changed(), vec![path]);
+        assert!(watch.changed().is_empty());
+    }
+}